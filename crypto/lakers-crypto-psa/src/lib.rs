@@ -2,10 +2,14 @@
 
 use lakers_shared::{Crypto as CryptoTrait, *};
 use psa_crypto::operations::hash::hash_compute;
-use psa_crypto::operations::{aead, key_agreement, key_management, other::generate_random};
-use psa_crypto::types::algorithm::Hash;
-use psa_crypto::types::algorithm::{Aead, AeadWithDefaultLengthTag, KeyAgreement, RawKeyAgreement};
-use psa_crypto::types::key::{Attributes, EccFamily, Lifetime, Policy, Type, UsageFlags};
+use psa_crypto::operations::{
+    aead, asym_signature, key_agreement, key_management, mac, other::generate_random,
+};
+use psa_crypto::types::algorithm::{
+    Aead, AeadWithDefaultLengthTag, AsymmetricSignature, FullLengthMac, Hash, KeyAgreement, Mac,
+    RawKeyAgreement, SignHash,
+};
+use psa_crypto::types::key::{Attributes, EccFamily, Id, Lifetime, Policy, Type, UsageFlags};
 
 #[no_mangle]
 pub extern "C" fn mbedtls_hardware_poll(
@@ -23,15 +27,70 @@ pub extern "C" fn mbedtls_hardware_poll(
 #[derive(Debug)]
 pub struct Crypto;
 
-impl CryptoTrait for Crypto {
-    fn sha256_digest(&mut self, message: &BytesMaxBuffer, message_len: usize) -> BytesHashLen {
-        let hash_alg = Hash::Sha256;
-        let mut hash: [u8; SHA256_DIGEST_LEN] = [0; SHA256_DIGEST_LEN];
-        psa_crypto::init().unwrap();
-        hash_compute(hash_alg, &message[..message_len], &mut hash).unwrap();
+/// Incremental SHA-256 through a PSA multi-part hash operation, e.g. for
+/// [X509CertificateParseStream](lakers_shared::X509CertificateParseStream)
+pub struct Sha256Hasher(psa_crypto::ffi::psa_hash_operation_t);
+
+impl Default for Sha256Hasher {
+    fn default() -> Self {
+        psa_crypto::init().expect("PSA Crypto initializes");
+        // all bits zero is a valid initial value of any PSA operation object
+        let mut operation: psa_crypto::ffi::psa_hash_operation_t = unsafe { core::mem::zeroed() };
+        let status =
+            unsafe { psa_crypto::ffi::psa_hash_setup(&mut operation, Hash::Sha256.into()) };
+        // PSA_SUCCESS
+        assert_eq!(
+            status, 0,
+            "SHA-256 is available in every PSA implementation"
+        );
+        Sha256Hasher(operation)
+    }
+}
+
+impl core::fmt::Debug for Sha256Hasher {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("Sha256Hasher")
+    }
+}
 
+impl Sha256Stream for Sha256Hasher {
+    fn update(&mut self, data: &[u8]) {
+        let status =
+            unsafe { psa_crypto::ffi::psa_hash_update(&mut self.0, data.as_ptr(), data.len()) };
+        assert_eq!(status, 0, "a set up hash operation accepts more input");
+    }
+
+    fn finish(mut self) -> BytesHashLen {
+        let mut hash: BytesHashLen = [0; SHA256_DIGEST_LEN];
+        let mut hash_len = 0;
+        let status = unsafe {
+            psa_crypto::ffi::psa_hash_finish(
+                &mut self.0,
+                hash.as_mut_ptr(),
+                hash.len(),
+                &mut hash_len,
+            )
+        };
+        assert_eq!(status, 0, "the hash buffer fits a SHA-256 digest");
         hash
     }
+}
+
+impl Drop for Sha256Hasher {
+    fn drop(&mut self) {
+        // releases the operation if it was not finished; a no-op otherwise
+        unsafe { psa_crypto::ffi::psa_hash_abort(&mut self.0) };
+    }
+}
+
+impl CryptoTrait for Crypto {
+    fn sha256_digest(&mut self, message: &BytesMaxBuffer, message_len: usize) -> BytesHashLen {
+        self.hash(HashAlg::Sha256, &message[..message_len])
+            .expect("SHA-256 is available in every PSA implementation")
+            .as_slice()
+            .try_into()
+            .unwrap()
+    }
 
     fn hkdf_expand(
         &mut self,
@@ -40,47 +99,23 @@ impl CryptoTrait for Crypto {
         info_len: usize,
         length: usize,
     ) -> BytesMaxBuffer {
-        // Implementation of HKDF-Expand as per RFC5869
-
-        let mut output: [u8; MAX_BUFFER_LEN] = [0; MAX_BUFFER_LEN];
-
-        let mut n = 0;
-
-        // N = ceil(L/HashLen)
-        if length % SHA256_DIGEST_LEN == 0 {
-            n = length / SHA256_DIGEST_LEN;
-        } else {
-            n = length / SHA256_DIGEST_LEN + 1;
-        }
-
-        let mut message: [u8; MAX_INFO_LEN + SHA256_DIGEST_LEN + 1] =
-            [0; MAX_INFO_LEN + SHA256_DIGEST_LEN + 1];
-        message[..info_len].copy_from_slice(&info[..info_len]);
-        message[info_len] = 0x01;
-        let mut t_i = self.hmac_sha256(&message[..info_len + 1], prk);
-        output[..SHA256_DIGEST_LEN].copy_from_slice(&t_i);
-
-        for i in 2..n {
-            message[..SHA256_DIGEST_LEN].copy_from_slice(&t_i);
-            message[SHA256_DIGEST_LEN..SHA256_DIGEST_LEN + info_len]
-                .copy_from_slice(&info[..info_len]);
-            message[SHA256_DIGEST_LEN + info_len] = i as u8;
-            t_i = self.hmac_sha256(&message[..SHA256_DIGEST_LEN + info_len + 1], prk);
-            output[i * SHA256_DIGEST_LEN..(i + 1) * SHA256_DIGEST_LEN].copy_from_slice(&t_i);
-        }
-
-        output[length..].fill(0x00);
-
+        let mut output: BytesMaxBuffer = [0; MAX_BUFFER_LEN];
+        self.hkdf_expand_with(
+            HashAlg::Sha256,
+            prk,
+            &info[..info_len],
+            &mut output[..length],
+        )
+        .expect("HMAC-SHA256 is available in every PSA implementation");
         output
     }
 
     fn hkdf_extract(&mut self, salt: &BytesHashLen, ikm: &BytesP256ElemLen) -> BytesHashLen {
-        // Implementation of HKDF-Extract as per RFC 5869
-
-        // TODO generalize if salt is not provided
-        let output = self.hmac_sha256(ikm, salt);
-
-        output
+        self.hkdf_extract_with(HashAlg::Sha256, salt, ikm)
+            .expect("HMAC-SHA256 is available in every PSA implementation")
+            .as_slice()
+            .try_into()
+            .unwrap()
     }
 
     fn aes_ccm_encrypt_tag_8(
@@ -90,8 +125,6 @@ impl CryptoTrait for Crypto {
         ad: &[u8],
         plaintext: &BufferPlaintext3,
     ) -> BufferCiphertext3 {
-        psa_crypto::init().unwrap();
-
         let alg = Aead::AeadWithShortenedTag {
             aead_alg: AeadWithDefaultLengthTag::Ccm,
             tag_length: 8,
@@ -108,18 +141,19 @@ impl CryptoTrait for Crypto {
                 permitted_algorithms: alg.into(),
             },
         };
-        let my_key = key_management::import(attributes, None, &key[..]).unwrap();
         let mut output_buffer: BufferCiphertext3 = BufferCiphertext3::new();
 
-        aead::encrypt(
-            my_key,
-            alg,
-            iv,
-            ad,
-            plaintext.as_slice(),
-            &mut output_buffer.content,
-        )
-        .unwrap();
+        with_key(attributes, key, |my_key| {
+            aead::encrypt(
+                my_key,
+                alg,
+                iv,
+                ad,
+                plaintext.as_slice(),
+                &mut output_buffer.content,
+            )
+        })
+        .expect("AES-CCM-16-64-128 is available in every PSA implementation");
 
         output_buffer.len = plaintext.len + AES_CCM_TAG_LEN;
         output_buffer
@@ -132,7 +166,9 @@ impl CryptoTrait for Crypto {
         ad: &[u8],
         ciphertext: &BufferCiphertext3,
     ) -> Result<BufferPlaintext3, EDHOCError> {
-        psa_crypto::init().unwrap();
+        if ciphertext.len < AES_CCM_TAG_LEN {
+            return Err(EDHOCError::MacVerificationFailed);
+        }
 
         let alg = Aead::AeadWithShortenedTag {
             aead_alg: AeadWithDefaultLengthTag::Ccm,
@@ -150,36 +186,50 @@ impl CryptoTrait for Crypto {
                 permitted_algorithms: alg.into(),
             },
         };
-        let my_key = key_management::import(attributes, None, &key[..]).unwrap();
         let mut output_buffer: BufferPlaintext3 = BufferPlaintext3::new();
 
-        match aead::decrypt(
-            my_key,
-            alg,
-            iv,
-            ad,
-            &ciphertext.as_slice(),
-            &mut output_buffer.content,
-        ) {
+        match with_key(attributes, key, |my_key| {
+            aead::decrypt(
+                my_key,
+                alg,
+                iv,
+                ad,
+                ciphertext.as_slice(),
+                &mut output_buffer.content,
+            )
+        }) {
             Ok(_) => {
                 output_buffer.len = ciphertext.len - AES_CCM_TAG_LEN;
                 Ok(output_buffer)
             }
-            Err(_) => Err(EDHOCError::MacVerificationFailed),
+            Err(psa_crypto::types::status::Error::InvalidSignature) => {
+                Err(EDHOCError::MacVerificationFailed)
+            }
+            Err(status) => Err(map_status(status)),
         }
     }
 
+    /// Returns all zeros if PSA rejects the operation (e.g. because the peer's key is not on the
+    /// curve), which is never a valid shared secret and is rejected as such by lakers.
     fn p256_ecdh(
         &mut self,
         private_key: &BytesP256ElemLen,
         public_key: &BytesP256ElemLen,
     ) -> BytesP256ElemLen {
-        let mut peer_public_key: [u8; 33] = [0; 33];
-        peer_public_key[0] = 0x02; // sign does not matter for ECDH operation
-        peer_public_key[1..33].copy_from_slice(&public_key[..]);
+        raw_ecdh(private_key, public_key).unwrap_or([0; P256_ELEM_LEN])
+    }
 
+    fn get_random_byte(&mut self) -> u8 {
+        psa_crypto::init().expect("PSA Crypto initializes");
+        let mut buffer = [0u8; 1];
+        generate_random(&mut buffer).expect("the PSA random number generator is available");
+        buffer[0]
+    }
+
+    fn p256_generate_key_pair(&mut self) -> (BytesP256ElemLen, BytesP256ElemLen) {
         let alg = RawKeyAgreement::Ecdh;
-        let mut usage_flags: UsageFlags = Default::default();
+        let mut usage_flags: UsageFlags = UsageFlags::default();
+        usage_flags.set_export();
         usage_flags.set_derive();
         let attributes = Attributes {
             key_type: Type::EccKeyPair {
@@ -193,28 +243,105 @@ impl CryptoTrait for Crypto {
             },
         };
 
-        psa_crypto::init().unwrap();
-        let my_key = key_management::import(attributes, None, private_key).unwrap();
-        let mut output_buffer: [u8; P256_ELEM_LEN] = [0; P256_ELEM_LEN];
+        psa_crypto::init().expect("PSA Crypto initializes");
 
-        key_agreement::raw_key_agreement(alg, my_key, &peer_public_key, &mut output_buffer)
-            .unwrap();
+        let key_id =
+            key_management::generate(attributes, None).expect("P-256 keys can be generated");
+        let mut private_key: [u8; P256_ELEM_LEN] = [0; P256_ELEM_LEN];
+        let mut public_key: [u8; P256_ELEM_LEN * 2 + 1] = [0; P256_ELEM_LEN * 2 + 1]; // allocate buffer for: sign, x, and y coordinates
+        let exported = key_management::export(key_id, &mut private_key)
+            .and_then(|_| key_management::export_public(key_id, &mut public_key));
+        destroy(key_id);
+        exported.expect("generated P-256 keys can be exported");
+        let public_key: [u8; P256_ELEM_LEN] = public_key[1..33].try_into().unwrap(); // return only the x coordinate
 
-        output_buffer
+        (private_key, public_key)
     }
 
-    fn get_random_byte(&mut self) -> u8 {
-        psa_crypto::init().unwrap();
-        let mut buffer = [0u8; 1];
-        let _ = generate_random(&mut buffer); // TODO: check return value
-        buffer[0]
+    fn p256_validate_public_key(&mut self, public_key: &BytesP256ElemLen) -> bool {
+        // PSA has no way of decompressing a point, but it checks that the peer's point is on the
+        // curve before key agreement. With the private key 1, the agreement just yields x again.
+        let mut one: BytesP256ElemLen = [0; P256_ELEM_LEN];
+        one[P256_ELEM_LEN - 1] = 1;
+        raw_ecdh(&one, public_key).is_ok()
     }
 
-    fn p256_generate_key_pair(&mut self) -> (BytesP256ElemLen, BytesP256ElemLen) {
-        let alg = RawKeyAgreement::Ecdh;
-        let mut usage_flags: UsageFlags = UsageFlags::default();
-        usage_flags.set_export();
-        usage_flags.set_derive();
+    fn p256_public_from_private(
+        &mut self,
+        private_key: &BytesP256ElemLen,
+    ) -> Result<BytesP256ElemLen, EDHOCError> {
+        let attributes = Attributes {
+            key_type: Type::EccKeyPair {
+                curve_family: EccFamily::SecpR1,
+            },
+            bits: 256,
+            lifetime: Lifetime::Volatile,
+            policy: Policy {
+                usage_flags: UsageFlags::default(),
+                permitted_algorithms: KeyAgreement::Raw(RawKeyAgreement::Ecdh).into(),
+            },
+        };
+
+        let mut public_key: [u8; P256_ELEM_LEN * 2 + 1] = [0; P256_ELEM_LEN * 2 + 1];
+        with_key(attributes, private_key, |my_key| {
+            key_management::export_public(my_key, &mut public_key)
+        })
+        .map_err(|_| EDHOCError::InvalidParameter)?;
+
+        Ok(public_key[1..33].try_into().unwrap())
+    }
+
+    fn p256_ecdsa_verify(
+        &mut self,
+        public_key_x: &BytesP256ElemLen,
+        public_key_y: &BytesP256ElemLen,
+        message: &[u8],
+        signature: &BytesP256Signature,
+    ) -> bool {
+        let Ok(hash) = self.hash(HashAlg::Sha256, message) else {
+            return false;
+        };
+
+        let alg = AsymmetricSignature::Ecdsa {
+            hash_alg: SignHash::Specific(Hash::Sha256),
+        };
+        let mut usage_flags: UsageFlags = Default::default();
+        usage_flags.set_verify_hash();
+        let attributes = Attributes {
+            key_type: Type::EccPublicKey {
+                curve_family: EccFamily::SecpR1,
+            },
+            bits: 256,
+            lifetime: Lifetime::Volatile,
+            policy: Policy {
+                usage_flags,
+                permitted_algorithms: alg.into(),
+            },
+        };
+
+        let mut public_key: [u8; P256_ELEM_LEN * 2 + 1] = [0; P256_ELEM_LEN * 2 + 1];
+        public_key[0] = 0x04; // uncompressed point
+        public_key[1..33].copy_from_slice(public_key_x);
+        public_key[33..].copy_from_slice(public_key_y);
+
+        with_key(attributes, &public_key, |my_key| {
+            asym_signature::verify_hash(my_key, alg, hash.as_slice(), signature)
+        })
+        .is_ok()
+    }
+
+    fn p256_ecdsa_sign(
+        &mut self,
+        private_key: &BytesP256ElemLen,
+        message: &[u8],
+    ) -> Result<BytesP256Signature, EDHOCError> {
+        let hash = self.hash(HashAlg::Sha256, message)?;
+
+        let alg = AsymmetricSignature::Ecdsa {
+            hash_alg: SignHash::Specific(Hash::Sha256),
+        };
+        let mut usage_flags: UsageFlags = Default::default();
+        usage_flags.set_sign_hash();
         let attributes = Attributes {
             key_type: Type::EccKeyPair {
                 curve_family: EccFamily::SecpR1,
@@ -223,72 +350,185 @@ impl CryptoTrait for Crypto {
             lifetime: Lifetime::Volatile,
             policy: Policy {
                 usage_flags,
-                permitted_algorithms: KeyAgreement::Raw(alg).into(),
+                permitted_algorithms: alg.into(),
             },
         };
 
-        psa_crypto::init().unwrap();
+        let mut signature: BytesP256Signature = [0; P256_SIGNATURE_LEN];
+        with_key(attributes, private_key, |my_key| {
+            asym_signature::sign_hash(my_key, alg, hash.as_slice(), &mut signature)
+        })
+        .map_err(|_| EDHOCError::InvalidKey)?;
 
-        let key_id = key_management::generate(attributes, None).unwrap();
-        let mut private_key: [u8; P256_ELEM_LEN] = [0; P256_ELEM_LEN];
-        key_management::export(key_id, &mut private_key).unwrap();
+        Ok(signature)
+    }
 
-        let mut public_key: [u8; P256_ELEM_LEN * 2 + 1] = [0; P256_ELEM_LEN * 2 + 1]; // allocate buffer for: sign, x, and y coordinates
-        key_management::export_public(key_id, &mut public_key).unwrap();
-        let public_key: [u8; P256_ELEM_LEN] = public_key[1..33].try_into().unwrap(); // return only the x coordinate
+    fn hash(&mut self, alg: HashAlg, message: &[u8]) -> Result<HashOutput, EDHOCError> {
+        psa_crypto::init().map_err(map_status)?;
 
-        (private_key, public_key)
+        let mut hash: [u8; MAX_HASH_LEN] = [0; MAX_HASH_LEN];
+        let hash_len = hash_compute(psa_hash(alg), message, &mut hash).map_err(map_status)?;
+        HashOutput::new_from_slice(&hash[..hash_len])
+    }
+
+    fn hkdf_extract_with(
+        &mut self,
+        alg: HashAlg,
+        salt: &[u8],
+        ikm: &[u8],
+    ) -> Result<HashOutput, EDHOCError> {
+        // Implementation of HKDF-Extract as per RFC 5869; an absent salt is a string of HashLen
+        // zeros, which PSA (unlike HMAC) does not accept as an empty key
+        let zeros: [u8; MAX_HASH_LEN] = [0; MAX_HASH_LEN];
+        let salt = if salt.is_empty() {
+            &zeros[..alg.digest_len()]
+        } else {
+            salt
+        };
+        self.hmac(alg, salt, ikm)
+    }
+
+    fn hkdf_expand_with(
+        &mut self,
+        alg: HashAlg,
+        prk: &[u8],
+        info: &[u8],
+        okm: &mut [u8],
+    ) -> Result<(), EDHOCError> {
+        // Implementation of HKDF-Expand as per RFC 5869
+        let hash_len = alg.digest_len();
+        if prk.len() != hash_len || info.len() > MAX_INFO_LEN || okm.len() > 255 * hash_len {
+            return Err(EDHOCError::InvalidParameter);
+        }
+
+        // T(i) = HMAC-Hash(PRK, T(i-1) | info | i), with T(0) empty
+        let mut message: [u8; MAX_HASH_LEN + MAX_INFO_LEN + 1] =
+            [0; MAX_HASH_LEN + MAX_INFO_LEN + 1];
+        let mut previous_len = 0;
+        for (i, chunk) in okm.chunks_mut(hash_len).enumerate() {
+            message[previous_len..previous_len + info.len()].copy_from_slice(info);
+            message[previous_len + info.len()] = i as u8 + 1;
+            let t_i = self.hmac(alg, prk, &message[..previous_len + info.len() + 1])?;
+            chunk.copy_from_slice(&t_i.as_slice()[..chunk.len()]);
+            message[..hash_len].copy_from_slice(t_i.as_slice());
+            previous_len = hash_len;
+        }
+
+        Ok(())
     }
 }
 
 impl Crypto {
     pub fn hmac_sha256(&mut self, message: &[u8], key: &[u8; SHA256_DIGEST_LEN]) -> BytesHashLen {
-        // implementation of HMAC as per RFC2104
-
-        const IPAD: [u8; 64] = [0x36; 64];
-        const OPAD: [u8; 64] = [0x5C; 64];
-
-        //    (1) append zeros to the end of K to create a B byte string
-        //        (e.g., if K is of length 20 bytes and B=64, then K will be
-        //         appended with 44 zero bytes 0x00)
-        let mut b: [u8; MAX_BUFFER_LEN] = [0; MAX_BUFFER_LEN];
-        b[0..SHA256_DIGEST_LEN].copy_from_slice(&key[..]);
-
-        //    (2) XOR (bitwise exclusive-OR) the B byte string computed in step
-        //        (1) with ipad
-        let mut s2: [u8; MAX_BUFFER_LEN] = [0; MAX_BUFFER_LEN];
-        for i in 0..64 {
-            s2[i] = b[i] ^ IPAD[i];
-        }
+        self.hmac(HashAlg::Sha256, key, message)
+            .expect("HMAC-SHA256 is available in every PSA implementation")
+            .as_slice()
+            .try_into()
+            .unwrap()
+    }
 
-        //    (3) append the stream of data 'text' to the B byte string resulting
-        //        from step (2)
-        s2[64..64 + message.len()].copy_from_slice(message);
+    /// HMAC as per RFC 2104, through the PSA MAC API
+    pub fn hmac(
+        &mut self,
+        alg: HashAlg,
+        key: &[u8],
+        message: &[u8],
+    ) -> Result<HashOutput, EDHOCError> {
+        let mac_alg = Mac::FullLength(FullLengthMac::Hmac {
+            hash_alg: psa_hash(alg),
+        });
+        let mut usage_flags: UsageFlags = Default::default();
+        usage_flags.set_sign_message();
+        let attributes = Attributes {
+            key_type: Type::Hmac,
+            bits: 0,
+            lifetime: Lifetime::Volatile,
+            policy: Policy {
+                usage_flags,
+                permitted_algorithms: mac_alg.into(),
+            },
+        };
 
-        //    (4) apply H to the stream generated in step (3)
-        let ih = self.sha256_digest(&s2, 64 + message.len());
+        let mut output: [u8; MAX_HASH_LEN] = [0; MAX_HASH_LEN];
+        let output_len = with_key(attributes, key, |my_key| {
+            mac::compute_mac(my_key, mac_alg, message, &mut output)
+        })
+        .map_err(map_status)?;
+        HashOutput::new_from_slice(&output[..output_len])
+    }
+}
 
-        //    (5) XOR (bitwise exclusive-OR) the B byte string computed in
-        //        step (1) with opad
-        let mut s5: [u8; MAX_BUFFER_LEN] = [0; MAX_BUFFER_LEN];
-        for i in 0..64 {
-            s5[i] = b[i] ^ OPAD[i];
-        }
-        //    (6) append the H result from step (4) to the B byte string
-        //        resulting from step (5)
-        s5[64..64 + SHA256_DIGEST_LEN].copy_from_slice(&ih);
+fn raw_ecdh(
+    private_key: &BytesP256ElemLen,
+    public_key: &BytesP256ElemLen,
+) -> Result<BytesP256ElemLen, psa_crypto::types::status::Error> {
+    let mut peer_public_key: [u8; 33] = [0; 33];
+    peer_public_key[0] = 0x02; // sign does not matter for ECDH operation
+    peer_public_key[1..33].copy_from_slice(&public_key[..]);
+
+    let alg = RawKeyAgreement::Ecdh;
+    let mut usage_flags: UsageFlags = Default::default();
+    usage_flags.set_derive();
+    let attributes = Attributes {
+        key_type: Type::EccKeyPair {
+            curve_family: EccFamily::SecpR1,
+        },
+        bits: 256,
+        lifetime: Lifetime::Volatile,
+        policy: Policy {
+            usage_flags,
+            permitted_algorithms: KeyAgreement::Raw(alg).into(),
+        },
+    };
+
+    let mut output_buffer: [u8; P256_ELEM_LEN] = [0; P256_ELEM_LEN];
+    with_key(attributes, private_key, |my_key| {
+        key_agreement::raw_key_agreement(alg, my_key, &peer_public_key, &mut output_buffer)
+    })?;
+    Ok(output_buffer)
+}
 
-        //    (7) apply H to the stream generated in step (6) and output
-        //        the result
-        let oh = self.sha256_digest(&s5, 3 * SHA256_DIGEST_LEN);
+fn psa_hash(alg: HashAlg) -> Hash {
+    match alg {
+        HashAlg::Sha256 => Hash::Sha256,
+        HashAlg::Sha384 => Hash::Sha384,
+    }
+}
 
-        oh
+/// Maps a PSA status to the closest [EDHOCError]
+fn map_status(status: psa_crypto::types::status::Error) -> EDHOCError {
+    use psa_crypto::types::status::Error;
+    match status {
+        Error::NotSupported => EDHOCError::UnsupportedCipherSuite,
+        Error::InvalidArgument | Error::BufferTooSmall => EDHOCError::InvalidParameter,
+        Error::InvalidSignature => EDHOCError::MacVerificationFailed,
+        _ => EDHOCError::UnknownError,
     }
 }
 
+/// Imports a volatile key, runs `operation` with it and destroys the key again, so that
+/// handshakes do not exhaust the key slots of the PSA implementation
+fn with_key<T>(
+    attributes: Attributes,
+    key: &[u8],
+    operation: impl FnOnce(Id) -> Result<T, psa_crypto::types::status::Error>,
+) -> Result<T, psa_crypto::types::status::Error> {
+    psa_crypto::init()?;
+    let key_id = key_management::import(attributes, None, key)?;
+    let result = operation(key_id);
+    destroy(key_id);
+    result
+}
+
+fn destroy(key_id: Id) {
+    // SAFETY: the key is volatile and local to the operation that imported or generated it
+    let _ = unsafe { key_management::destroy(key_id) };
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use hexlit::hex;
 
     #[test]
     fn test_hmac_sha256() {
@@ -312,4 +552,172 @@ mod tests {
         let result_2 = Crypto.hmac_sha256(&MESSAGE_2, &KEY);
         assert_eq!(result_2, RESULT_2_TV);
     }
+
+    #[test]
+    fn test_hmac_rfc4231() {
+        // test case 1
+        let key = [0x0b; 20];
+        assert_eq!(
+            Crypto
+                .hmac(HashAlg::Sha256, &key, b"Hi There")
+                .unwrap()
+                .as_slice(),
+            hex!("b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7")
+        );
+        assert_eq!(
+            Crypto.hmac(HashAlg::Sha384, &key, b"Hi There").unwrap().as_slice(),
+            hex!("afd03944d84895626b0825f4ab46907f15f9dadbe4101ec682aa034c7cebc59cfaea9ea9076ede7f4af152e8b2fa9cb6")
+        );
+
+        // test case 2
+        let message = b"what do ya want for nothing?";
+        assert_eq!(
+            Crypto
+                .hmac(HashAlg::Sha256, b"Jefe", message)
+                .unwrap()
+                .as_slice(),
+            hex!("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+        );
+        assert_eq!(
+            Crypto.hmac(HashAlg::Sha384, b"Jefe", message).unwrap().as_slice(),
+            hex!("af45d2e376484031617f78d2b58a6b1b9c7ef464f5a01b47e42ec3736322445e8e2240ca5e69e2c78b3239ecfab21649")
+        );
+    }
+
+    #[test]
+    fn test_hash() {
+        // FIPS 180-2 example "abc"
+        assert_eq!(
+            Crypto.hash(HashAlg::Sha384, b"abc").unwrap().as_slice(),
+            hex!("cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed8086072ba1e7cc2358baeca134c825a7")
+        );
+        assert_eq!(
+            Crypto.hash(HashAlg::Sha256, b"abc").unwrap().as_slice(),
+            hex!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+    }
+
+    #[test]
+    fn test_hkdf_rfc5869() {
+        // test case 1, and its inputs with SHA-384 (as in lakers-crypto-rustcrypto)
+        const IKM: &[u8] = &hex!("0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b");
+        const SALT: &[u8] = &hex!("000102030405060708090a0b0c");
+        const INFO: &[u8] = &hex!("f0f1f2f3f4f5f6f7f8f9");
+
+        let prk = Crypto
+            .hkdf_extract_with(HashAlg::Sha256, SALT, IKM)
+            .unwrap();
+        assert_eq!(
+            prk.as_slice(),
+            hex!("077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5")
+        );
+        let mut okm = [0u8; 42];
+        Crypto
+            .hkdf_expand_with(HashAlg::Sha256, prk.as_slice(), INFO, &mut okm)
+            .unwrap();
+        assert_eq!(
+            okm,
+            hex!("3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865")
+        );
+
+        // the fixed-size trait methods agree, also beyond the first block
+        let mut info: BytesMaxInfoBuffer = [0; MAX_INFO_LEN];
+        info[..INFO.len()].copy_from_slice(INFO);
+        let expanded =
+            Crypto.hkdf_expand(&prk.as_slice().try_into().unwrap(), &info, INFO.len(), 42);
+        assert_eq!(expanded[..42], okm);
+        assert!(expanded[42..].iter().all(|byte| *byte == 0));
+
+        let prk = Crypto
+            .hkdf_extract_with(HashAlg::Sha384, SALT, IKM)
+            .unwrap();
+        assert_eq!(
+            prk.as_slice(),
+            hex!("704b39990779ce1dc548052c7dc39f303570dd13fb39f7acc564680bef80e8dec70ee9a7e1f3e293ef68eceb072a5ade")
+        );
+        Crypto
+            .hkdf_expand_with(HashAlg::Sha384, prk.as_slice(), INFO, &mut okm)
+            .unwrap();
+        assert_eq!(
+            okm,
+            hex!("9b5097a86038b805309076a44b3a9f38063e25b516dcbf369f394cfab43685f748b6457763e4f0204fc5")
+        );
+
+        // a PRK of the wrong length is rejected
+        assert_eq!(
+            Crypto.hkdf_expand_with(HashAlg::Sha384, &prk.as_slice()[..32], INFO, &mut okm),
+            Err(EDHOCError::InvalidParameter)
+        );
+    }
+
+    #[test]
+    fn test_aes_ccm_rfc3610() {
+        // packet vector #1
+        const KEY: BytesCcmKeyLen = hex!("c0c1c2c3c4c5c6c7c8c9cacbcccdcecf");
+        const NONCE: BytesCcmIvLen = hex!("00000003020100a0a1a2a3a4a5");
+        const AD: &[u8] = &hex!("0001020304050607");
+        const PLAINTEXT: &[u8] = &hex!("08090a0b0c0d0e0f101112131415161718191a1b1c1d1e");
+        const CIPHERTEXT: &[u8] =
+            &hex!("588c979a61c663d2f066d0c2c0f989806d5f6b61dac38417e8d12cfdf926e0");
+
+        let plaintext = BufferPlaintext3::new_from_slice(PLAINTEXT).unwrap();
+        let ciphertext = Crypto.aes_ccm_encrypt_tag_8(&KEY, &NONCE, AD, &plaintext);
+        assert_eq!(ciphertext.as_slice(), CIPHERTEXT);
+        assert_eq!(
+            Crypto
+                .aes_ccm_decrypt_tag_8(&KEY, &NONCE, AD, &ciphertext)
+                .unwrap()
+                .as_slice(),
+            PLAINTEXT
+        );
+
+        let mut tampered = ciphertext;
+        tampered.content[0] ^= 0x01;
+        assert_eq!(
+            Crypto.aes_ccm_decrypt_tag_8(&KEY, &NONCE, AD, &tampered),
+            Err(EDHOCError::MacVerificationFailed)
+        );
+        let short = BufferCiphertext3::new_from_slice(&CIPHERTEXT[..4]).unwrap();
+        assert_eq!(
+            Crypto.aes_ccm_decrypt_tag_8(&KEY, &NONCE, AD, &short),
+            Err(EDHOCError::MacVerificationFailed)
+        );
+    }
+
+    #[test]
+    fn test_p256() {
+        // from the EDHOC test vectors (RFC 9529, chapter 2)
+        const X: BytesP256ElemLen =
+            hex!("368ec1f69aeb659ba37d5a8d45b21bdc0299dceaa8ef235f3ca42ce3530f9525");
+        const G_Y: BytesP256ElemLen =
+            hex!("419701d7f00a26c2dc587a36dd752549f33763c893422c8ea0f955a13a4ff5d5");
+        const G_XY: BytesP256ElemLen =
+            hex!("2f0cb7e860ba538fbf5c8bded009f6259b4b628fe1eb7dbe9378e5ecf7a824ba");
+        const R: BytesP256ElemLen =
+            hex!("72cc4761dbd4c78f758931aa589d348d1ef874a7e303ede2f140dcf3e6aa4aac");
+        const G_R: BytesP256ElemLen =
+            hex!("bbc34960526ea4d32e940cad2a234148ddc21791a12afbcbac93622046dd44f0");
+        const G_R_Y: BytesP256ElemLen =
+            hex!("4519e257236b2a0ce2023f0931f1f386ca7afda64fcde0108c224c51eabf6072");
+        // x = 1 has no point on the curve
+        let mut not_on_curve: BytesP256ElemLen = [0; P256_ELEM_LEN];
+        not_on_curve[P256_ELEM_LEN - 1] = 1;
+
+        assert_eq!(Crypto.p256_ecdh(&X, &G_Y), G_XY);
+        assert_eq!(Crypto.p256_ecdh(&X, &not_on_curve), [0; P256_ELEM_LEN]);
+        assert!(Crypto.p256_validate_public_key(&G_Y));
+        assert!(!Crypto.p256_validate_public_key(&not_on_curve));
+        assert_eq!(Crypto.p256_public_from_private(&R), Ok(G_R));
+
+        let (private_key, public_key) = Crypto.p256_generate_key_pair();
+        assert_eq!(
+            Crypto.p256_public_from_private(&private_key),
+            Ok(public_key)
+        );
+
+        // signed by R over "abc"
+        let signature: BytesP256Signature = hex!("0cd5205fcb2912abc154b28b602833b911d0f0944ad9c712fab5238330df1f0b278fb75453d62c707cb697fcfe24a2c6c5a2f74c7c7b048131a7e91aa83eb828");
+        assert!(Crypto.p256_ecdsa_verify(&G_R, &G_R_Y, b"abc", &signature));
+        assert!(!Crypto.p256_ecdsa_verify(&G_R, &G_R_Y, b"abd", &signature));
+    }
 }
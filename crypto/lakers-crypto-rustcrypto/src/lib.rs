@@ -1,15 +1,19 @@
 #![no_std]
 
 use lakers_shared::{
-    BufferCiphertext3, BufferPlaintext3, BytesCcmIvLen, BytesCcmKeyLen, BytesHashLen,
-    BytesMaxBuffer, BytesMaxInfoBuffer, BytesP256ElemLen, Crypto as CryptoTrait, EDHOCError,
-    AES_CCM_TAG_LEN, MAX_BUFFER_LEN,
+    wipe, BufferCiphertext3, BufferPlaintext3, BytesCcmIvLen, BytesCcmKeyLen, BytesHashLen,
+    BytesMaxBuffer, BytesMaxInfoBuffer, BytesP256ElemLen, BytesP256Signature,
+    Crypto as CryptoTrait, EDHOCError, HashAlg, HashOutput, Sha256Stream, AES_CCM_TAG_LEN,
+    MAX_BUFFER_LEN, SHA256_DIGEST_LEN,
 };
 
 use ccm::AeadInPlace;
 use ccm::KeyInit;
+use hmac::Mac;
+use p256::ecdsa::signature::{Signer, Verifier};
 use p256::elliptic_curve::point::AffineCoordinates;
 use p256::elliptic_curve::point::DecompressPoint;
+use p256::elliptic_curve::sec1::FromEncodedPoint;
 use sha2::Digest;
 
 type AesCcm16_64_128 = ccm::Ccm<aes::Aes128, ccm::consts::U8, ccm::consts::U13>;
@@ -36,6 +40,21 @@ impl<Rng: rand_core::RngCore + rand_core::CryptoRng> core::fmt::Debug for Crypto
     }
 }
 
+/// Incremental SHA-256 of the [sha2] crate, e.g. for
+/// [X509CertificateParseStream](lakers_shared::X509CertificateParseStream)
+#[derive(Clone, Debug, Default)]
+pub struct Sha256Hasher(sha2::Sha256);
+
+impl Sha256Stream for Sha256Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finish(self) -> BytesHashLen {
+        self.0.finalize().into()
+    }
+}
+
 impl<Rng: rand_core::RngCore + rand_core::CryptoRng> CryptoTrait for Crypto<Rng> {
     fn sha256_digest(&mut self, message: &BytesMaxBuffer, message_len: usize) -> BytesHashLen {
         let mut hasher = sha2::Sha256::new();
@@ -58,6 +77,38 @@ impl<Rng: rand_core::RngCore + rand_core::CryptoRng> CryptoTrait for Crypto<Rng>
         output
     }
 
+    fn hkdf_expand_into(
+        &mut self,
+        prk: &BytesHashLen,
+        info: &BytesMaxInfoBuffer,
+        info_len: usize,
+        length: usize,
+        output: &mut dyn FnMut(&[u8]),
+    ) {
+        // T(i) = HMAC(PRK, T(i-1) | info | i) from RFC 5869, passed on one block at a time
+        let mut block: BytesHashLen = [0; SHA256_DIGEST_LEN];
+        let mut written = 0;
+        let mut counter = 1u8;
+        while written < length {
+            let mut mac = <hmac::Hmac<sha2::Sha256> as Mac>::new_from_slice(prk)
+                .expect("HMAC takes keys of any length");
+            if counter > 1 {
+                mac.update(&block);
+            }
+            mac.update(&info[..info_len]);
+            mac.update(&[counter]);
+            hmac::digest::FixedOutput::finalize_into(
+                mac,
+                hmac::digest::generic_array::GenericArray::from_mut_slice(&mut block),
+            );
+            let part = (length - written).min(SHA256_DIGEST_LEN);
+            output(&block[..part]);
+            written += part;
+            counter += 1;
+        }
+        wipe(&mut block);
+    }
+
     fn hkdf_extract(&mut self, salt: &BytesHashLen, ikm: &BytesP256ElemLen) -> BytesHashLen {
         // While it'd be nice to just pass around an Hkdf, the extract output is not a type generic
         // of this trait (yet?).
@@ -136,4 +187,240 @@ impl<Rng: rand_core::RngCore + rand_core::CryptoRng> CryptoTrait for Crypto<Rng>
 
         (private_key.into(), public_key.into())
     }
+
+    fn p256_validate_public_key(&mut self, public_key: &BytesP256ElemLen) -> bool {
+        p256::AffinePoint::decompress(public_key.into(), 1.into())
+            .is_some()
+            .into()
+    }
+
+    fn p256_public_from_private(
+        &mut self,
+        private_key: &BytesP256ElemLen,
+    ) -> Result<BytesP256ElemLen, EDHOCError> {
+        let Ok(secret) = p256::SecretKey::from_bytes(private_key.as_slice().into()) else {
+            return Err(EDHOCError::InvalidParameter);
+        };
+
+        Ok(secret.public_key().as_affine().x().into())
+    }
+
+    fn p256_ecdsa_verify(
+        &mut self,
+        public_key_x: &BytesP256ElemLen,
+        public_key_y: &BytesP256ElemLen,
+        message: &[u8],
+        signature: &BytesP256Signature,
+    ) -> bool {
+        let point = p256::EncodedPoint::from_affine_coordinates(
+            public_key_x.into(),
+            public_key_y.into(),
+            false,
+        );
+        let Some(public) =
+            Option::<p256::PublicKey>::from(p256::PublicKey::from_encoded_point(&point))
+        else {
+            return false;
+        };
+        let Ok(signature) = p256::ecdsa::Signature::from_slice(signature) else {
+            return false;
+        };
+
+        p256::ecdsa::VerifyingKey::from(public)
+            .verify(message, &signature)
+            .is_ok()
+    }
+
+    fn p256_ecdsa_sign(
+        &mut self,
+        private_key: &BytesP256ElemLen,
+        message: &[u8],
+    ) -> Result<BytesP256Signature, EDHOCError> {
+        let Ok(key) = p256::ecdsa::SigningKey::from_bytes(private_key.into()) else {
+            return Err(EDHOCError::InvalidKey);
+        };
+        // deterministic (RFC 6979), so no randomness is drawn
+        let signature: p256::ecdsa::Signature = key.sign(message);
+
+        Ok(signature.to_bytes().into())
+    }
+
+    fn hash(&mut self, alg: HashAlg, message: &[u8]) -> Result<HashOutput, EDHOCError> {
+        match alg {
+            HashAlg::Sha256 => HashOutput::new_from_slice(&sha2::Sha256::digest(message)),
+            HashAlg::Sha384 => HashOutput::new_from_slice(&sha2::Sha384::digest(message)),
+        }
+    }
+
+    fn hkdf_extract_with(
+        &mut self,
+        alg: HashAlg,
+        salt: &[u8],
+        ikm: &[u8],
+    ) -> Result<HashOutput, EDHOCError> {
+        match alg {
+            HashAlg::Sha256 => {
+                HashOutput::new_from_slice(&hkdf::Hkdf::<sha2::Sha256>::extract(Some(salt), ikm).0)
+            }
+            HashAlg::Sha384 => {
+                HashOutput::new_from_slice(&hkdf::Hkdf::<sha2::Sha384>::extract(Some(salt), ikm).0)
+            }
+        }
+    }
+
+    fn hkdf_expand_with(
+        &mut self,
+        alg: HashAlg,
+        prk: &[u8],
+        info: &[u8],
+        okm: &mut [u8],
+    ) -> Result<(), EDHOCError> {
+        let expanded = match alg {
+            HashAlg::Sha256 => hkdf::Hkdf::<sha2::Sha256>::from_prk(prk)
+                .map_err(|_| EDHOCError::InvalidParameter)?
+                .expand(info, okm),
+            HashAlg::Sha384 => hkdf::Hkdf::<sha2::Sha384>::from_prk(prk)
+                .map_err(|_| EDHOCError::InvalidParameter)?
+                .expand(info, okm),
+        };
+        expanded.map_err(|_| EDHOCError::InvalidParameter)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hexlit::hex;
+    use lakers_shared::MAX_INFO_LEN;
+
+    // RFC 5869 test case 1 inputs
+    const IKM: &[u8] = &hex!("0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b");
+    const SALT: &[u8] = &hex!("000102030405060708090a0b0c");
+    const INFO: &[u8] = &hex!("f0f1f2f3f4f5f6f7f8f9");
+
+    fn crypto() -> Crypto<rand_core::OsRng> {
+        Crypto::new(rand_core::OsRng)
+    }
+
+    #[test]
+    fn test_hash() {
+        // FIPS 180-2 example "abc"
+        assert_eq!(
+            crypto().hash(HashAlg::Sha384, b"abc").unwrap().as_slice(),
+            hex!("cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed8086072ba1e7cc2358baeca134c825a7")
+        );
+
+        let mut message: BytesMaxBuffer = [0x00; MAX_BUFFER_LEN];
+        message[..3].copy_from_slice(b"abc");
+        assert_eq!(
+            crypto().hash(HashAlg::Sha256, b"abc").unwrap().as_slice(),
+            crypto().sha256_digest(&message, 3)
+        );
+    }
+
+    #[test]
+    fn test_hkdf_sha256() {
+        // RFC 5869 test case 1
+        let prk = crypto()
+            .hkdf_extract_with(HashAlg::Sha256, SALT, IKM)
+            .unwrap();
+        assert_eq!(
+            prk.as_slice(),
+            hex!("077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5")
+        );
+        let mut okm = [0u8; 42];
+        crypto()
+            .hkdf_expand_with(HashAlg::Sha256, prk.as_slice(), INFO, &mut okm)
+            .unwrap();
+        assert_eq!(
+            okm,
+            hex!("3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865")
+        );
+    }
+
+    #[test]
+    fn test_hkdf_expand_into() {
+        // RFC 5869 test case 1, in blocks of the hash length
+        let prk = hex!("077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5");
+        let mut info: BytesMaxInfoBuffer = [0x00; MAX_INFO_LEN];
+        info[..INFO.len()].copy_from_slice(INFO);
+        let mut okm = [0u8; 42];
+        let mut part_lens = [0usize; 3];
+        let mut parts = 0;
+        crypto().hkdf_expand_into(&prk, &info, INFO.len(), okm.len(), &mut |part| {
+            let written: usize = part_lens.iter().sum();
+            okm[written..written + part.len()].copy_from_slice(part);
+            part_lens[parts] = part.len();
+            parts += 1;
+        });
+        assert_eq!(part_lens, [32, 10, 0]);
+        assert_eq!(
+            okm,
+            hex!("3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865")
+        );
+        assert_eq!(okm, crypto().hkdf_expand(&prk, &info, INFO.len(), 42)[..42]);
+    }
+
+    #[test]
+    fn test_hkdf_sha384() {
+        // Project Wycheproof, hkdf_sha384_test.json
+        for (ikm, salt, info, expected) in [
+            (
+                &hex!("60ab7f45b0ad534683b3a6c020d4f775")[..],
+                &[][..],
+                &[][..],
+                &hex!("3f8b0e4a7b2bff01a26a18f1e07c0218897a324e")[..],
+            ),
+            (
+                &hex!("5d3db20e8238a90b62a600fa57fdb318"),
+                &hex!("1d6f3b38a1e607b5e6bcd4af1800a9d3"),
+                &hex!("2bc5f39032b6fc87da69ba8711ce735b169646fd"),
+                &hex!("6724e716f6a953aab112b61e29d921fec0f8e806841d5ccd3aa567574b502904d04ae707d244187fec52"),
+            ),
+        ] {
+            let prk = crypto()
+                .hkdf_extract_with(HashAlg::Sha384, salt, ikm)
+                .unwrap();
+            assert_eq!(prk.as_slice().len(), 48);
+            let mut okm = [0u8; 42];
+            let okm = &mut okm[..expected.len()];
+            crypto()
+                .hkdf_expand_with(HashAlg::Sha384, prk.as_slice(), info, okm)
+                .unwrap();
+            assert_eq!(okm, expected);
+        }
+
+        let prk = crypto()
+            .hkdf_extract_with(HashAlg::Sha384, SALT, IKM)
+            .unwrap();
+        let mut okm = [0u8; 42];
+
+        // a PRK of the wrong length is rejected
+        assert_eq!(
+            crypto().hkdf_expand_with(HashAlg::Sha384, &prk.as_slice()[..32], INFO, &mut okm),
+            Err(EDHOCError::InvalidParameter)
+        );
+    }
+
+    #[test]
+    fn test_ecdsa() {
+        // RFC 6979, Section A.2.5, with SHA-256
+        const KEY: BytesP256ElemLen =
+            hex!("c9afa9d845ba75166b5c215767b1d6934e50c3db36e89b127b8a622b120f6721");
+        const UX: BytesP256ElemLen =
+            hex!("60fed4ba255a9d31c961eb74c6356d68c049b8923b61fa6ce669622e60f29fb6");
+        const UY: BytesP256ElemLen =
+            hex!("7903fe1008b8bc99a41ae9e95628bc64f2f1b20c2d7e9f5177a3c294d4462299");
+        const SIGNATURE: BytesP256Signature = hex!("efd48b2aacb6a8fd1140dd9cd45e81d69d2c877b56aaf991c34d0ea84eaf3716f7cb1c942d657c41d436c7a1b6e29f65f3e900dbb9aff4064dc4ab2f843acda8");
+
+        let signature = crypto().p256_ecdsa_sign(&KEY, b"sample").unwrap();
+        assert_eq!(signature, SIGNATURE);
+        assert!(crypto().p256_ecdsa_verify(&UX, &UY, b"sample", &signature));
+        assert!(!crypto().p256_ecdsa_verify(&UX, &UY, b"test", &signature));
+
+        assert_eq!(
+            crypto().p256_ecdsa_sign(&[0x00; 32], b"sample"),
+            Err(EDHOCError::InvalidKey)
+        );
+    }
 }
@@ -3,8 +3,68 @@ use lakers_shared::*;
 
 #[derive(Debug, Default)]
 pub struct ZeroTouchAuthenticator;
-#[derive(Debug, Default)]
-pub struct ZeroTouchAuthenticatorWaitVoucherResp;
+/// State of the authenticator between the voucher request and the voucher response
+///
+/// It is only created by [ZeroTouchAuthenticator::process_ead_1], as it holds the message_1 that
+/// the voucher response has to be for.
+#[derive(Debug)]
+pub struct ZeroTouchAuthenticatorWaitVoucherResp {
+    message_1: EdhocMessageBuffer, // to be echoed in the voucher response
+}
+
+/// Voucher response received from W
+#[derive(Debug, PartialEq)]
+pub struct VoucherResponse {
+    pub message_1: EdhocMessageBuffer,
+    pub voucher: BytesEncodedVoucher,
+    pub opaque_state: Option<EdhocMessageBuffer>,
+}
+
+impl VoucherResponse {
+    /// Decodes a voucher response, checking its shape before anything of it is used
+    ///
+    /// Voucher_Response = [ message_1: bstr, Voucher: bstr, ? opaque_state: bstr ], where the
+    /// Voucher is a MAC of exactly [MAC_LENGTH] bytes wrapped in a bstr, and nothing follows.
+    pub fn decode(voucher_response: &[u8]) -> Result<Self, EDHOCError> {
+        if voucher_response.len() > MAX_MESSAGE_SIZE_LEN {
+            return Err(EDHOCError::MessageTooLong);
+        }
+        let mut decoder = CBORDecoder::new(voucher_response);
+
+        let array_size = decoder.array()?;
+        if !(2..=3).contains(&array_size) {
+            return Err(EDHOCError::EADError);
+        }
+
+        let message_1 = EdhocMessageBuffer::new_from_slice(decoder.bytes()?)
+            .map_err(|_| EDHOCError::ParsingError)?;
+        let voucher: BytesEncodedVoucher = decoder
+            .bytes_sized(ENCODED_VOUCHER_LEN)?
+            .try_into()
+            .unwrap();
+        if voucher[0] != CBOR_MAJOR_BYTE_STRING | MAC_LENGTH as u8 {
+            return Err(EDHOCError::EADError);
+        }
+        let opaque_state = if array_size == 3 {
+            Some(
+                EdhocMessageBuffer::new_from_slice(decoder.bytes()?)
+                    .map_err(|_| EDHOCError::ParsingError)?,
+            )
+        } else {
+            None
+        };
+
+        if !decoder.finished() {
+            return Err(EDHOCError::TrailingData);
+        }
+
+        Ok(VoucherResponse {
+            message_1,
+            voucher,
+            opaque_state,
+        })
+    }
+}
 
 impl ZeroTouchAuthenticator {
     pub fn process_ead_1(
@@ -19,8 +79,51 @@ impl ZeroTouchAuthenticator {
         ),
         EDHOCError,
     > {
-        let opaque_state: Option<EdhocMessageBuffer> = None; // TODO: receive as parameter
+        self.process_voucher_request(ead_1, message_1, None)
+    }
 
+    /// Like [Self::process_ead_1], but puts `opaque_state` into the voucher request
+    ///
+    /// W returns the opaque_state untouched in the voucher response, so an authenticator (or a
+    /// proxy in front of it) can keep its context there, e.g. C_I and the responder endpoint. It is
+    /// handed back by [ZeroTouchAuthenticatorWaitVoucherResp::prepare_ead_2].
+    pub fn process_ead_1_with_opaque_state(
+        &self,
+        ead_1: &EADItem,
+        message_1: &EdhocMessageBuffer,
+        opaque_state: &[u8],
+    ) -> Result<
+        (
+            ZeroTouchAuthenticatorWaitVoucherResp,
+            EdhocMessageBuffer,
+            EdhocMessageBuffer,
+        ),
+        EDHOCError,
+    > {
+        // the opaque_state is encoded with a one-byte length, and W adds the voucher to it
+        if opaque_state.len() > u8::MAX as usize
+            || 6 + message_1.len + ENCODED_VOUCHER_LEN + opaque_state.len() > MAX_MESSAGE_SIZE_LEN
+        {
+            return Err(EDHOCError::InvalidParameter);
+        }
+        let opaque_state = EdhocMessageBuffer::new_from_slice(opaque_state)
+            .map_err(|_| EDHOCError::InvalidParameter)?;
+        self.process_voucher_request(ead_1, message_1, Some(opaque_state))
+    }
+
+    fn process_voucher_request(
+        &self,
+        ead_1: &EADItem,
+        message_1: &EdhocMessageBuffer,
+        opaque_state: Option<EdhocMessageBuffer>,
+    ) -> Result<
+        (
+            ZeroTouchAuthenticatorWaitVoucherResp,
+            EdhocMessageBuffer,
+            EdhocMessageBuffer,
+        ),
+        EDHOCError,
+    > {
         if ead_1.label != EAD_ZEROCONF_LABEL || ead_1.value.is_none() {
             return Err(EDHOCError::EADError);
         }
@@ -29,7 +132,9 @@ impl ZeroTouchAuthenticator {
         let voucher_request = encode_voucher_request(message_1, &opaque_state);
 
         Ok((
-            ZeroTouchAuthenticatorWaitVoucherResp::default(),
+            ZeroTouchAuthenticatorWaitVoucherResp {
+                message_1: *message_1,
+            },
             loc_w,
             voucher_request,
         ))
@@ -37,17 +142,25 @@ impl ZeroTouchAuthenticator {
 }
 
 impl ZeroTouchAuthenticatorWaitVoucherResp {
+    /// Returns EAD_2 along with the opaque_state that W sent back, if any
+    ///
+    /// The voucher response has to be for the message_1 that was processed.
     pub fn prepare_ead_2(
         &self,
-        voucher_response: &EdhocMessageBuffer,
-    ) -> Result<EADItem, EDHOCError> {
-        let (_message_1, voucher, _opaque_state) = parse_voucher_response(&voucher_response)?;
+        voucher_response: &VoucherResponse,
+    ) -> Result<(EADItem, Option<EdhocMessageBuffer>), EDHOCError> {
+        if voucher_response.message_1 != self.message_1 {
+            return Err(EDHOCError::EADError);
+        }
 
-        Ok(EADItem {
-            label: EAD_ZEROCONF_LABEL,
-            is_critical: true,
-            value: Some(voucher[..].try_into().unwrap()),
-        })
+        Ok((
+            EADItem {
+                label: EAD_ZEROCONF_LABEL,
+                is_critical: true,
+                value: Some(voucher_response.voucher[..].try_into().unwrap()),
+            },
+            voucher_response.opaque_state,
+        ))
     }
 }
 
@@ -78,37 +191,6 @@ pub fn encode_voucher_request(
     output
 }
 
-fn parse_voucher_response(
-    voucher_response: &EdhocMessageBuffer,
-) -> Result<
-    (
-        EdhocMessageBuffer,
-        BytesEncodedVoucher,
-        Option<EdhocMessageBuffer>,
-    ),
-    EDHOCError,
-> {
-    let mut decoder = CBORDecoder::new(voucher_response.as_slice());
-
-    let array_size = decoder.array()?;
-    if !(2..=3).contains(&array_size) {
-        return Err(EDHOCError::EADError);
-    }
-
-    let message_1: EdhocMessageBuffer = decoder.bytes()?.try_into().unwrap();
-    let voucher: BytesEncodedVoucher = decoder
-        .bytes_sized(ENCODED_VOUCHER_LEN)?
-        .try_into()
-        .unwrap();
-
-    if array_size == 3 {
-        let opaque_state: EdhocMessageBuffer = decoder.bytes()?.try_into().unwrap();
-        return Ok((message_1, voucher, Some(opaque_state)));
-    } else {
-        return Ok((message_1, voucher, None));
-    }
-}
-
 #[cfg(test)]
 mod test_authenticator {
     use super::*;
@@ -150,33 +232,105 @@ mod test_authenticator {
     }
 
     #[test]
-    fn test_parse_voucher_response() {
-        let voucher_response_tv: EdhocMessageBuffer = VOUCHER_RESPONSE_TV.try_into().unwrap();
+    fn test_decode_voucher_response() {
         let message_1_tv: EdhocMessageBuffer = MESSAGE_1_WITH_EAD_TV.try_into().unwrap();
         let voucher_tv: BytesEncodedVoucher = VOUCHER_TV.try_into().unwrap();
 
-        let res = parse_voucher_response(&voucher_response_tv);
+        let res = VoucherResponse::decode(VOUCHER_RESPONSE_TV);
         assert!(res.is_ok());
-        let (message_1, voucher, opaque_state) = res.unwrap();
-        assert_eq!(message_1.content, message_1_tv.content);
-        assert_eq!(voucher, voucher_tv);
-        assert!(opaque_state.is_none());
+        let voucher_response = res.unwrap();
+        assert_eq!(voucher_response.message_1.content, message_1_tv.content);
+        assert_eq!(voucher_response.voucher, voucher_tv);
+        assert!(voucher_response.opaque_state.is_none());
+    }
+
+    #[test]
+    fn test_decode_voucher_response_malformed() {
+        // truncated, cutting into the voucher
+        assert_eq!(
+            VoucherResponse::decode(&VOUCHER_RESPONSE_TV[..VOUCHER_RESPONSE_TV.len() - 1]),
+            Err(EDHOCError::ParsingError)
+        );
+
+        // trailing data after the array
+        let mut trailing = [0u8; VOUCHER_RESPONSE_TV.len() + 1];
+        trailing[..VOUCHER_RESPONSE_TV.len()].copy_from_slice(VOUCHER_RESPONSE_TV);
+        assert_eq!(
+            VoucherResponse::decode(&trailing),
+            Err(EDHOCError::TrailingData)
+        );
+
+        // longer than any message could be
+        assert_eq!(
+            VoucherResponse::decode(&[0u8; MAX_MESSAGE_SIZE_LEN + 1]),
+            Err(EDHOCError::MessageTooLong)
+        );
+
+        // a voucher that is one byte too long, so it would not fit EAD_2
+        let voucher_header = VOUCHER_RESPONSE_TV.len() - ENCODED_VOUCHER_LEN - 1;
+        let mut long_voucher = [0u8; VOUCHER_RESPONSE_TV.len() + 1];
+        long_voucher[..VOUCHER_RESPONSE_TV.len()].copy_from_slice(VOUCHER_RESPONSE_TV);
+        long_voucher[voucher_header] += 1;
+        assert_eq!(
+            VoucherResponse::decode(&long_voucher),
+            Err(EDHOCError::ParsingError)
+        );
+
+        // a voucher of the right length that does not hold a MAC
+        let mut not_a_mac = [0u8; VOUCHER_RESPONSE_TV.len()];
+        not_a_mac.copy_from_slice(VOUCHER_RESPONSE_TV);
+        not_a_mac[voucher_header + 1] = 0x00;
+        assert_eq!(
+            VoucherResponse::decode(&not_a_mac),
+            Err(EDHOCError::EADError)
+        );
+
+        // not an array of 2 or 3 items
+        let mut long_array = [0u8; VOUCHER_RESPONSE_TV.len()];
+        long_array.copy_from_slice(VOUCHER_RESPONSE_TV);
+        long_array[0] = 0x84;
+        assert_eq!(
+            VoucherResponse::decode(&long_array),
+            Err(EDHOCError::EADError)
+        );
     }
 
     #[test]
     fn test_r_prepare_ead_2() {
-        let voucher_response_tv: EdhocMessageBuffer = VOUCHER_RESPONSE_TV.try_into().unwrap();
+        let ead_1 = EADItem {
+            label: EAD_ZEROCONF_LABEL,
+            is_critical: true,
+            value: Some(EAD1_VALUE_TV.try_into().unwrap()),
+        };
         let ead_2_value_tv: EdhocMessageBuffer = EAD2_VALUE_TV.try_into().unwrap();
 
-        let ead_authenticator = ZeroTouchAuthenticatorWaitVoucherResp::default();
-
-        let ead_2 = ead_authenticator
-            .prepare_ead_2(&voucher_response_tv)
+        let (ead_authenticator, _loc_w, _voucher_request) = ZeroTouchAuthenticator::default()
+            .process_ead_1(&ead_1, &MESSAGE_1_WITH_EAD_TV.try_into().unwrap())
             .unwrap();
+
+        let voucher_response = VoucherResponse::decode(VOUCHER_RESPONSE_TV).unwrap();
+        let (ead_2, opaque_state) = ead_authenticator.prepare_ead_2(&voucher_response).unwrap();
+        assert!(opaque_state.is_none());
         assert_eq!(ead_2.label, EAD_ZEROCONF_LABEL);
         assert_eq!(ead_2.is_critical, true);
         assert_eq!(ead_2.value.unwrap().content, ead_2_value_tv.content);
     }
+
+    #[test]
+    fn test_r_prepare_ead_2_wrong_message_1() {
+        // a response for a message_1 other than the one that was processed
+        let ead_authenticator = ZeroTouchAuthenticatorWaitVoucherResp {
+            message_1: EdhocMessageBuffer::new_from_slice(&[0x03, 0x02]).unwrap(),
+        };
+
+        let voucher_response = VoucherResponse::decode(VOUCHER_RESPONSE_TV).unwrap();
+        assert_eq!(
+            ead_authenticator
+                .prepare_ead_2(&voucher_response)
+                .unwrap_err(),
+            EDHOCError::EADError
+        );
+    }
 }
 
 #[cfg(test)]
@@ -196,16 +350,18 @@ mod test_responder_stateless_operation {
 
     #[test]
     fn test_slo_parse_voucher_response() {
-        let voucher_response_tv: EdhocMessageBuffer = SLO_VOUCHER_RESPONSE_TV.try_into().unwrap();
         let message_1_tv: EdhocMessageBuffer = MESSAGE_1_WITH_EAD_TV.try_into().unwrap();
         let voucher_tv: BytesEncodedVoucher = VOUCHER_TV.try_into().unwrap();
         let opaque_state_tv: EdhocMessageBuffer = SLO_OPAQUE_STATE_TV.try_into().unwrap();
 
-        let res = parse_voucher_response(&voucher_response_tv);
+        let res = VoucherResponse::decode(SLO_VOUCHER_RESPONSE_TV);
         assert!(res.is_ok());
-        let (message_1, voucher, opaque_state) = res.unwrap();
-        assert_eq!(message_1.content, message_1_tv.content);
-        assert_eq!(voucher, voucher_tv);
-        assert_eq!(opaque_state.unwrap().content, opaque_state_tv.content);
+        let voucher_response = res.unwrap();
+        assert_eq!(voucher_response.message_1.content, message_1_tv.content);
+        assert_eq!(voucher_response.voucher, voucher_tv);
+        assert_eq!(
+            voucher_response.opaque_state.unwrap().content,
+            opaque_state_tv.content
+        );
     }
 }
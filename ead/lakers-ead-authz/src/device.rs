@@ -14,7 +14,7 @@ pub struct ZeroTouchDevice {
 #[repr(C)]
 pub struct ZeroTouchDeviceWaitEAD2 {
     prk: BytesHashLen,
-    pub h_message_1: BytesHashLen,
+    ss: u8, // suite that ENC_ID is bound to
 }
 
 #[derive(Default, Debug)]
@@ -23,9 +23,50 @@ pub struct ZeroTouchDeviceDone {
     pub voucher: BytesMac,
 }
 
+// message_1 without EAD_1, for the longest SUITES_I and a one-byte C_I
+const MESSAGE_1_MAX_LEN_WITHOUT_EAD: usize = 1 + 1 + SUITES_LEN + 2 + P256_ELEM_LEN + 1;
+// message_1 is embedded in the voucher response next to the voucher, which limits it the most
+const VOUCHER_RESPONSE_OVERHEAD: usize = 4 + ENCODED_VOUCHER_LEN;
+/// Budget for the encoded EAD_1 item (label and value) so that message_1 fits all buffers
+pub const MAX_EAD_1_LEN: usize =
+    MAX_MESSAGE_SIZE_LEN - VOUCHER_RESPONSE_OVERHEAD - MESSAGE_1_MAX_LEN_WITHOUT_EAD;
+// ENC_ID is encoded with a one-byte bstr header
+const MAX_ID_U_LEN: usize = CBOR_UINT_1BYTE as usize - 1 - 1 - AES_CCM_TAG_LEN;
+
+/// Length of the encoded EAD_1 item: label, then bstr(tstr LOC_W, bstr ENC_ID)
+const fn ead_1_len(id_u_len: usize, loc_w_len: usize) -> usize {
+    let enc_id_len = 1 + id_u_len + AES_CCM_TAG_LEN;
+    1 + 2 + 2 + loc_w_len + 1 + enc_id_len
+}
+
 impl ZeroTouchDevice {
-    pub fn new(id_u: EdhocMessageBuffer, g_w: BytesP256ElemLen, loc_w: EdhocMessageBuffer) -> Self {
-        ZeroTouchDevice { id_u, g_w, loc_w }
+    /// Creates a device, checking that the EAD_1 it produces will fit into message_1
+    pub fn new(
+        id_u: EdhocMessageBuffer,
+        g_w: BytesP256ElemLen,
+        loc_w: EdhocMessageBuffer,
+    ) -> Result<Self, ZeroTouchError> {
+        let device = ZeroTouchDevice { id_u, g_w, loc_w };
+        device.check_ead_1_len()?;
+        Ok(device)
+    }
+
+    /// Longest LOC_W that fits into message_1 for any ID_U
+    ///
+    /// A shorter ID_U leaves room for a slightly longer LOC_W, which [ZeroTouchDevice::new]
+    /// accepts.
+    pub const fn max_loc_w_len() -> usize {
+        MAX_EAD_1_LEN - ead_1_len(MAX_ID_U_LEN, 0)
+    }
+
+    fn check_ead_1_len(&self) -> Result<(), ZeroTouchError> {
+        if self.id_u.len > MAX_ID_U_LEN {
+            Err(ZeroTouchError::IdUTooLong)
+        } else if ead_1_len(self.id_u.len, self.loc_w.len) > MAX_EAD_1_LEN {
+            Err(ZeroTouchError::EADTooLong)
+        } else {
+            Ok(())
+        }
     }
 
     pub fn prepare_ead_1<Crypto: CryptoTrait>(
@@ -33,41 +74,43 @@ impl ZeroTouchDevice {
         crypto: &mut Crypto,
         secret: BytesP256ElemLen,
         ss: u8,
-    ) -> (ZeroTouchDeviceWaitEAD2, EADItem) {
+    ) -> Result<(ZeroTouchDeviceWaitEAD2, EADItem), ZeroTouchError> {
+        // the fields are public, so they may have changed since construction
+        self.check_ead_1_len()?;
+
         // PRK = EDHOC-Extract(salt, IKM)
         let prk = compute_prk_from_secret(crypto, &secret);
 
         // plaintext = (ID_U: bstr)
-        let encoded_id_u = encode_id_u(&self.id_u);
+        let encoded_id_u = encode_id_u(&self.id_u)?;
         let enc_id = encrypt_enc_id(crypto, &prk, &encoded_id_u, ss);
-        let value = Some(encode_ead_1_value(&self.loc_w, &enc_id));
+        let value = encode_ead_1_value(&self.loc_w, &enc_id)?;
+        if 1 + value.len > MAX_EAD_1_LEN {
+            return Err(ZeroTouchError::EADTooLong);
+        }
 
         let ead_1 = EADItem {
             label: EAD_ZEROCONF_LABEL,
             is_critical: true,
-            value,
+            value: Some(value),
         };
 
-        (
-            ZeroTouchDeviceWaitEAD2 {
-                prk,
-                h_message_1: [0; SHA256_DIGEST_LEN],
-            },
-            ead_1,
-        )
+        Ok((ZeroTouchDeviceWaitEAD2 { prk, ss }, ead_1))
     }
 }
 
 impl ZeroTouchDeviceWaitEAD2 {
-    pub fn set_h_message_1(&mut self, h_message_1: BytesHashLen) {
-        self.h_message_1 = h_message_1;
-    }
-
+    /// Verifies the voucher in EAD_2 against `message_1`, the message_1 that carried EAD_1
+    ///
+    /// The voucher covers H(message_1), and thus the suite that message_1 selects; that suite has
+    /// to be the one ENC_ID was bound to in [ZeroTouchDevice::prepare_ead_1], or this fails with
+    /// [ZeroTouchError::SuiteMismatch].
     pub fn process_ead_2<Crypto: CryptoTrait>(
         &self,
         crypto: &mut Crypto,
         ead_2: EADItem,
         cred_v: &[u8],
+        message_1: &EdhocMessageBuffer,
     ) -> Result<ZeroTouchDeviceDone, ZeroTouchError> {
         if ead_2.label != EAD_ZEROCONF_LABEL {
             return Err(ZeroTouchError::InvalidEADLabel);
@@ -78,21 +121,36 @@ impl ZeroTouchDeviceWaitEAD2 {
         let mut ead_2_value: BytesEncodedVoucher = Default::default();
         ead_2_value[..].copy_from_slice(&ead_2_value_buffer.content[..ENCODED_VOUCHER_LEN]);
 
-        match verify_voucher(crypto, &ead_2_value, &self.h_message_1, cred_v, &self.prk) {
+        let selected_suite = parse_message_1(message_1).ok().and_then(
+            |(_method, suites_i, suites_i_len, _g_x, _c_i, _ead_1)| {
+                suites_i[..suites_i_len].last().copied()
+            },
+        );
+        if selected_suite != Some(self.ss) {
+            return Err(ZeroTouchError::SuiteMismatch);
+        }
+        let mut message_1_buf: BytesMaxBuffer = [0x00; MAX_BUFFER_LEN];
+        message_1_buf[..message_1.len].copy_from_slice(message_1.as_slice());
+        let h_message_1 = crypto.sha256_digest(&message_1_buf, message_1.len);
+
+        match verify_voucher(crypto, &ead_2_value, &h_message_1, cred_v, &self.prk) {
             Ok(voucher) => Ok(ZeroTouchDeviceDone { voucher }),
             Err(error) => Err(error),
         }
     }
 }
 
-fn encode_id_u(id_u: &EdhocMessageBuffer) -> EdhocMessageBuffer {
+fn encode_id_u(id_u: &EdhocMessageBuffer) -> Result<EdhocMessageBuffer, ZeroTouchError> {
+    if id_u.len > MAX_ID_U_LEN {
+        return Err(ZeroTouchError::IdUTooLong);
+    }
     // plaintext = (ID_U: bstr)
     let mut plaintext = EdhocMessageBuffer::new();
     plaintext.content[0] = CBOR_MAJOR_BYTE_STRING + id_u.len as u8;
     plaintext.content[1..1 + id_u.len].copy_from_slice(id_u.as_slice());
     plaintext.len = 1 + id_u.len;
 
-    plaintext
+    Ok(plaintext)
 }
 
 fn encrypt_enc_id<Crypto: CryptoTrait>(
@@ -113,7 +171,14 @@ fn encrypt_enc_id<Crypto: CryptoTrait>(
 fn encode_ead_1_value(
     loc_w: &EdhocMessageBuffer,
     enc_id: &EdhocMessageBuffer,
-) -> EdhocMessageBuffer {
+) -> Result<EdhocMessageBuffer, ZeroTouchError> {
+    if enc_id.len > CBOR_UINT_1BYTE as usize - 1 {
+        return Err(ZeroTouchError::IdUTooLong);
+    }
+    // the outer bstr and LOC_W both carry a one-byte length
+    if 5 + loc_w.len + enc_id.len > MAX_MESSAGE_SIZE_LEN.min(2 + u8::MAX as usize) {
+        return Err(ZeroTouchError::EADTooLong);
+    }
     let mut output = EdhocMessageBuffer::new();
 
     output.content[0] = CBOR_BYTE_STRING;
@@ -129,7 +194,7 @@ fn encode_ead_1_value(
     output.len = 5 + loc_w.len + enc_id.len;
     output.content[1] = (output.len - 2) as u8;
 
-    output
+    Ok(output)
 }
 
 pub(crate) fn verify_voucher<Crypto: CryptoTrait>(
@@ -176,15 +241,61 @@ mod test_device {
             ID_U_TV.try_into().unwrap(),
             G_W_TV.try_into().unwrap(),
             LOC_W_TV.try_into().unwrap(),
-        );
+        )
+        .unwrap();
 
-        let (_ead_device, ead_1) =
-            ead_device.prepare_ead_1(&mut default_crypto(), G_XW_TV.try_into().unwrap(), SS_TV);
+        let (_ead_device, ead_1) = ead_device
+            .prepare_ead_1(&mut default_crypto(), G_XW_TV.try_into().unwrap(), SS_TV)
+            .unwrap();
         assert_eq!(ead_1.label, EAD_ZEROCONF_LABEL);
         assert_eq!(ead_1.is_critical, true);
         assert_eq!(ead_1.value.unwrap().content, ead_1_value_tv.content);
     }
 
+    #[test]
+    fn test_loc_w_too_long() {
+        let loc_w: EdhocMessageBuffer = [b'a'; 120][..].try_into().unwrap();
+        assert_eq!(
+            ZeroTouchDevice::new(
+                ID_U_TV.try_into().unwrap(),
+                G_W_TV.try_into().unwrap(),
+                loc_w
+            )
+            .unwrap_err(),
+            ZeroTouchError::EADTooLong
+        );
+
+        // the longest LOC_W fits even with the longest ID_U, and the whole EAD_1 is accounted for
+        let id_u: EdhocMessageBuffer = [0xa1; MAX_ID_U_LEN][..].try_into().unwrap();
+        let loc_w: EdhocMessageBuffer = [b'a'; ZeroTouchDevice::max_loc_w_len()][..]
+            .try_into()
+            .unwrap();
+        let mut ead_device = ZeroTouchDevice::new(id_u, G_W_TV.try_into().unwrap(), loc_w).unwrap();
+        let (_ead_device, ead_1) = ead_device
+            .prepare_ead_1(&mut default_crypto(), G_XW_TV.try_into().unwrap(), SS_TV)
+            .unwrap();
+        assert_eq!(1 + ead_1.value.unwrap().len, MAX_EAD_1_LEN);
+
+        ead_device.loc_w.len += 1;
+        assert_eq!(
+            ead_device
+                .prepare_ead_1(&mut default_crypto(), G_XW_TV.try_into().unwrap(), SS_TV)
+                .unwrap_err(),
+            ZeroTouchError::EADTooLong
+        );
+
+        let id_u: EdhocMessageBuffer = [0xa1; MAX_ID_U_LEN + 1][..].try_into().unwrap();
+        assert_eq!(
+            ZeroTouchDevice::new(
+                id_u,
+                G_W_TV.try_into().unwrap(),
+                LOC_W_TV.try_into().unwrap()
+            )
+            .unwrap_err(),
+            ZeroTouchError::IdUTooLong
+        );
+    }
+
     #[test]
     fn test_verify_voucher() {
         let mut voucher_tv = VOUCHER_TV.try_into().unwrap();
@@ -223,16 +334,34 @@ mod test_device {
 
         let ead_device = ZeroTouchDeviceWaitEAD2 {
             prk: PRK_TV.try_into().unwrap(),
-            h_message_1: H_MESSAGE_1_TV.try_into().unwrap(),
+            ss: SS_TV,
         };
 
         let res = ead_device.process_ead_2(
             &mut default_crypto(),
-            ead_2_tv,
+            ead_2_tv.clone(),
             CRED_V_TV.try_into().unwrap(),
+            &MESSAGE_1_WITH_EAD_TV.try_into().unwrap(),
         );
         assert!(res.is_ok());
         let ead_device = res.unwrap();
         assert_eq!(ead_device.voucher, VOUCHER_MAC_TV); // TODO: maybe should use the encoded voucher instead?
+
+        // ENC_ID bound to another suite than the one message_1 selects
+        let ead_device = ZeroTouchDeviceWaitEAD2 {
+            prk: PRK_TV.try_into().unwrap(),
+            ss: 0x03,
+        };
+        assert_eq!(
+            ead_device
+                .process_ead_2(
+                    &mut default_crypto(),
+                    ead_2_tv,
+                    CRED_V_TV.try_into().unwrap(),
+                    &MESSAGE_1_WITH_EAD_TV.try_into().unwrap(),
+                )
+                .unwrap_err(),
+            ZeroTouchError::SuiteMismatch
+        );
     }
 }
@@ -7,9 +7,13 @@ mod shared;
 #[cfg(test)]
 mod test_vectors;
 
-pub use authenticator::{ZeroTouchAuthenticator, ZeroTouchAuthenticatorWaitVoucherResp};
+pub use authenticator::{
+    VoucherResponse, ZeroTouchAuthenticator, ZeroTouchAuthenticatorWaitVoucherResp,
+};
 pub use device::{ZeroTouchDevice, ZeroTouchDeviceDone, ZeroTouchDeviceWaitEAD2};
-pub use server::{ZeroTouchServer, ZeroTouchServerUserAcl};
+#[cfg(feature = "introspection")]
+pub use server::VoucherRequestIntrospection;
+pub use server::{ZeroTouchServer, ZeroTouchServerUserAcl, MAX_W_KEYS};
 
 #[derive(PartialEq, Debug)]
 #[repr(C)]
@@ -17,16 +21,61 @@ pub enum ZeroTouchError {
     InvalidEADLabel,
     EmptyEADValue,
     VoucherVerificationFailed,
+    /// ID_U is too long to be encrypted into ENC_ID
+    IdUTooLong,
+    /// EAD_1 would not fit into message_1 (see [ZeroTouchDevice::max_loc_w_len])
+    EADTooLong,
+    /// message_1 selects another suite than the one ENC_ID is bound to
+    SuiteMismatch,
 }
 
 #[cfg(test)]
 mod test_authz {
     use crate::{
-        authenticator::ZeroTouchAuthenticator, device::ZeroTouchDevice, server::ZeroTouchServer,
+        authenticator::{VoucherResponse, ZeroTouchAuthenticator},
+        device::ZeroTouchDevice,
+        server::ZeroTouchServer,
         test_vectors::*,
     };
     use lakers_crypto::default_crypto;
-    use lakers_shared::EDHOCError;
+    use lakers_shared::{Crypto as _, EADItem, EDHOCError, EdhocMessageBuffer};
+
+    /// Runs the zero-touch flow for a device provisioned with `g_w`, returning the W key index
+    fn enroll_device(
+        server: &ZeroTouchServer,
+        g_w: &[u8],
+        g_xw: [u8; 32],
+    ) -> Result<usize, EDHOCError> {
+        let device = ZeroTouchDevice::new(
+            ID_U_TV.try_into().unwrap(),
+            g_w.try_into().unwrap(),
+            LOC_W_TV.try_into().unwrap(),
+        )
+        .unwrap();
+        let authenticator = ZeroTouchAuthenticator::default();
+
+        let (device, ead_1) = device
+            .prepare_ead_1(&mut default_crypto(), g_xw, SS_TV)
+            .unwrap();
+
+        // message_1 of the traces, up to and including the EAD label, followed by the new EAD value
+        let mut message_1: EdhocMessageBuffer = MESSAGE_1_WITH_EAD_TV[..40].try_into().unwrap();
+        message_1
+            .extend_from_slice(ead_1.value.as_ref().unwrap().as_slice())
+            .unwrap();
+
+        let (authenticator, _loc_w, voucher_request) =
+            authenticator.process_ead_1(&ead_1, &message_1)?;
+        let (voucher_response, w_index) =
+            server.handle_voucher_request_tagged(&mut default_crypto(), &voucher_request)?;
+        let voucher_response = VoucherResponse::decode(voucher_response.as_slice())?;
+        let (ead_2, _opaque_state) = authenticator.prepare_ead_2(&voucher_response)?;
+
+        assert!(device
+            .process_ead_2(&mut default_crypto(), ead_2, CRED_V_TV, &message_1)
+            .is_ok());
+        Ok(w_index)
+    }
 
     #[test]
     fn test_complete_flow() {
@@ -34,7 +83,8 @@ mod test_authz {
             ID_U_TV.try_into().unwrap(),
             G_W_TV.try_into().unwrap(),
             LOC_W_TV.try_into().unwrap(),
-        );
+        )
+        .unwrap();
         let authenticator = ZeroTouchAuthenticator::default();
         let server = ZeroTouchServer::new(
             W_TV.try_into().unwrap(),
@@ -44,9 +94,9 @@ mod test_authz {
 
         // using .unwrap below since detailed errors are tested in each entity's tests
 
-        let (mut device, ead_1) =
-            device.prepare_ead_1(&mut default_crypto(), G_XW_TV.try_into().unwrap(), SS_TV);
-        device.set_h_message_1(H_MESSAGE_1_TV.try_into().unwrap());
+        let (device, ead_1) = device
+            .prepare_ead_1(&mut default_crypto(), G_XW_TV.try_into().unwrap(), SS_TV)
+            .unwrap();
 
         // ead_1 will be transported within message_1
 
@@ -56,25 +106,82 @@ mod test_authz {
 
         // network request would be: let Ok(voucher_response) = auth_client.post(loc_w, voucher_request)
 
-        let voucher_response = server
+        let (voucher_response, _opaque_state) = server
             .handle_voucher_request(&mut default_crypto(), &voucher_request)
             .unwrap();
 
-        let ead_2 = authenticator.prepare_ead_2(&voucher_response).unwrap();
+        let voucher_response = VoucherResponse::decode(voucher_response.as_slice()).unwrap();
+        let (ead_2, _opaque_state) = authenticator.prepare_ead_2(&voucher_response).unwrap();
 
         // ead_2 will be transported within message_2
 
-        let result = device.process_ead_2(&mut default_crypto(), ead_2, CRED_V_TV);
+        let result = device.process_ead_2(
+            &mut default_crypto(),
+            ead_2,
+            CRED_V_TV,
+            &MESSAGE_1_WITH_EAD_TV.try_into().unwrap(),
+        );
         assert!(result.is_ok());
     }
 
     #[test]
-    fn test_complete_flow_unauthorized() {
+    fn test_complete_flow_opaque_state() {
         let device = ZeroTouchDevice::new(
             ID_U_TV.try_into().unwrap(),
             G_W_TV.try_into().unwrap(),
             LOC_W_TV.try_into().unwrap(),
+        )
+        .unwrap();
+        let authenticator = ZeroTouchAuthenticator::default();
+        let server = ZeroTouchServer::new(
+            W_TV.try_into().unwrap(),
+            CRED_V_TV,
+            Some(ACL_TV.try_into().unwrap()),
+        );
+        // e.g. C_I and the address of the responder endpoint
+        let opaque_state: [u8; 40] = core::array::from_fn(|i| i as u8);
+
+        let (device, ead_1) = device
+            .prepare_ead_1(&mut default_crypto(), G_XW_TV.try_into().unwrap(), SS_TV)
+            .unwrap();
+
+        let (authenticator, _loc_w, voucher_request) = authenticator
+            .process_ead_1_with_opaque_state(
+                &ead_1,
+                &MESSAGE_1_WITH_EAD_TV.try_into().unwrap(),
+                &opaque_state,
+            )
+            .unwrap();
+
+        let (voucher_response, server_opaque_state) = server
+            .handle_voucher_request(&mut default_crypto(), &voucher_request)
+            .unwrap();
+        assert_eq!(server_opaque_state.unwrap().as_slice(), opaque_state);
+
+        let voucher_response = VoucherResponse::decode(voucher_response.as_slice()).unwrap();
+        let (ead_2, returned_opaque_state) =
+            authenticator.prepare_ead_2(&voucher_response).unwrap();
+        assert_eq!(returned_opaque_state.unwrap().as_slice(), opaque_state);
+        // the voucher does not depend on the opaque_state
+        assert_eq!(ead_2.value.unwrap().as_slice(), EAD2_VALUE_TV);
+
+        let result = device.process_ead_2(
+            &mut default_crypto(),
+            ead_2,
+            CRED_V_TV,
+            &MESSAGE_1_WITH_EAD_TV.try_into().unwrap(),
         );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_complete_flow_unauthorized() {
+        let device = ZeroTouchDevice::new(
+            ID_U_TV.try_into().unwrap(),
+            G_W_TV.try_into().unwrap(),
+            LOC_W_TV.try_into().unwrap(),
+        )
+        .unwrap();
         let authenticator = ZeroTouchAuthenticator::default();
         let server = ZeroTouchServer::new(
             W_TV.try_into().unwrap(),
@@ -82,9 +189,9 @@ mod test_authz {
             Some(ACL_INVALID_TV.try_into().unwrap()),
         );
 
-        let (mut device, ead_1) =
-            device.prepare_ead_1(&mut default_crypto(), G_XW_TV.try_into().unwrap(), SS_TV);
-        device.set_h_message_1(H_MESSAGE_1_TV.try_into().unwrap());
+        let (_device, ead_1) = device
+            .prepare_ead_1(&mut default_crypto(), G_XW_TV.try_into().unwrap(), SS_TV)
+            .unwrap();
 
         let (_authenticator, _loc_w, voucher_request) = authenticator
             .process_ead_1(&ead_1, &MESSAGE_1_WITH_EAD_TV.try_into().unwrap())
@@ -94,4 +201,69 @@ mod test_authz {
             server.handle_voucher_request(&mut default_crypto(), &voucher_request);
         assert_eq!(voucher_response.unwrap_err(), EDHOCError::EADError);
     }
+
+    #[test]
+    fn test_complete_flow_rotated_w_keys() {
+        let server = ZeroTouchServer::new_with_w_keys(
+            &[W_NEW_TV.try_into().unwrap(), W_TV.try_into().unwrap()],
+            CRED_V_TV,
+            Some(ACL_TV.try_into().unwrap()),
+        )
+        .unwrap();
+
+        // a device provisioned before the rotation is served with the old key
+        let w_index = enroll_device(&server, G_W_TV, G_XW_TV.try_into().unwrap());
+        assert_eq!(w_index, Ok(1));
+
+        // and one provisioned after it with the current key
+        let g_xw_new = default_crypto().p256_ecdh(&X_TV, &G_W_NEW_TV.try_into().unwrap());
+        let w_index = enroll_device(&server, G_W_NEW_TV, g_xw_new);
+        assert_eq!(w_index, Ok(0));
+
+        // a device provisioned with a key the server does not hold is rejected
+        let server = ZeroTouchServer::new(W_NEW_TV.try_into().unwrap(), CRED_V_TV, None);
+        let w_index = enroll_device(&server, G_W_TV, G_XW_TV.try_into().unwrap());
+        assert_eq!(w_index, Err(EDHOCError::EADError));
+    }
+
+    #[test]
+    fn test_complete_flow_suite_downgrade() {
+        let device = ZeroTouchDevice::new(
+            ID_U_TV.try_into().unwrap(),
+            G_W_TV.try_into().unwrap(),
+            LOC_W_TV.try_into().unwrap(),
+        )
+        .unwrap();
+        let server = ZeroTouchServer::new(W_TV.try_into().unwrap(), CRED_V_TV, None);
+        let voucher_request = |ead_1: &EADItem, message_1: &EdhocMessageBuffer| {
+            let (_authenticator, _loc_w, voucher_request) = ZeroTouchAuthenticator::default()
+                .process_ead_1(ead_1, message_1)
+                .unwrap();
+            voucher_request
+        };
+
+        // ENC_ID bound to suite 3, sent in a message_1 that selects suite 2
+        let (_device, ead_1) = device
+            .prepare_ead_1(&mut default_crypto(), G_XW_TV.try_into().unwrap(), 0x03)
+            .unwrap();
+        let mut message_1: EdhocMessageBuffer = MESSAGE_1_WITH_EAD_TV[..40].try_into().unwrap();
+        message_1
+            .extend_from_slice(ead_1.value.as_ref().unwrap().as_slice())
+            .unwrap();
+        assert_eq!(
+            server
+                .handle_voucher_request(&mut default_crypto(), &voucher_request(&ead_1, &message_1))
+                .unwrap_err(),
+            EDHOCError::EADError
+        );
+
+        // message_1 that selects suite 3 (SUITES_I = [6, 3]), which the server does not support
+        message_1.content[3] = 0x03;
+        assert_eq!(
+            server
+                .handle_voucher_request(&mut default_crypto(), &voucher_request(&ead_1, &message_1))
+                .unwrap_err(),
+            EDHOCError::UnsupportedCipherSuite
+        );
+    }
 }
@@ -1,19 +1,70 @@
 use super::shared::*;
 use lakers_shared::{Crypto as CryptoTrait, *};
 
+/// Maximum number of W private keys a [ZeroTouchServer] can hold at the same time
+pub const MAX_W_KEYS: usize = 4;
+
 /// This server also stores an ACL
 #[derive(PartialEq, Debug, Copy, Clone)]
 pub struct ZeroTouchServer {
-    w: BytesP256ElemLen,            // private key of the enrollment server (W)
+    // private keys of the enrollment server (W), ordered from the current one to the oldest one
+    w: [BytesP256ElemLen; MAX_W_KEYS],
+    w_len: usize,
     pub cred_v: EdhocMessageBuffer, // credential of the authenticator (V)
     // access control list, each device identified by an u8 kid (this is arbitrary, it is not specified in the draft)
     pub acl: Option<EdhocMessageBuffer>,
 }
 
+/// Intermediate values of the decryption of a voucher request, for conformance testing
+#[cfg(any(test, feature = "introspection"))]
+#[derive(PartialEq, Debug)]
+pub struct VoucherRequestIntrospection {
+    /// Index of the W key that authenticated ENC_ID
+    pub w_index: usize,
+    pub prk: BytesHashLen,
+    pub k_1: BytesCcmKeyLen,
+    pub iv_1: BytesCcmIvLen,
+    /// The decrypted ENC_ID, i.e. the bstr-encoded ID_U
+    pub plaintext: EdhocMessageBuffer,
+}
+
 impl ZeroTouchServer {
     pub fn new(w: BytesP256ElemLen, cred_v: &[u8], acl: Option<EdhocMessageBuffer>) -> Self {
         let cred_v: EdhocMessageBuffer = cred_v.try_into().unwrap();
-        ZeroTouchServer { w, cred_v, acl }
+        let mut w_keys = [[0u8; P256_ELEM_LEN]; MAX_W_KEYS];
+        w_keys[0] = w;
+        ZeroTouchServer {
+            w: w_keys,
+            w_len: 1,
+            cred_v,
+            acl,
+        }
+    }
+
+    /// Creates a server holding several W keys, e.g. during a key rotation
+    ///
+    /// The keys are ordered from the current one to the oldest one, and are attempted in that
+    /// order when decrypting a voucher request. Fails with [EDHOCError::InvalidParameter] unless
+    /// between 1 and [MAX_W_KEYS] keys are given, and with [EDHOCError::MessageTooLong] if cred_v
+    /// does not fit into a buffer.
+    pub fn new_with_w_keys(
+        w_keys: &[BytesP256ElemLen],
+        cred_v: &[u8],
+        acl: Option<EdhocMessageBuffer>,
+    ) -> Result<Self, EDHOCError> {
+        if w_keys.is_empty() || w_keys.len() > MAX_W_KEYS {
+            return Err(EDHOCError::InvalidParameter);
+        }
+        let mut w = [[0u8; P256_ELEM_LEN]; MAX_W_KEYS];
+        w[..w_keys.len()].copy_from_slice(w_keys);
+        let cred_v: EdhocMessageBuffer =
+            cred_v.try_into().map_err(|_| EDHOCError::MessageTooLong)?;
+        Ok(ZeroTouchServer {
+            w,
+            w_len: w_keys.len(),
+            cred_v,
+            acl,
+        })
     }
 
     pub fn authorized(self, kid: u8) -> bool {
@@ -25,17 +76,48 @@ impl ZeroTouchServer {
         }
     }
 
+    /// Returns the voucher response along with the opaque_state of the voucher request, if any
+    ///
+    /// The opaque_state is also echoed in the voucher response; it is returned here so that it
+    /// can be logged, e.g. to correlate enrollments with the EDHOC sessions of the authenticator.
     pub fn handle_voucher_request<Crypto: CryptoTrait>(
         &self,
         crypto: &mut Crypto,
         vreq: &EdhocMessageBuffer,
-    ) -> Result<EdhocMessageBuffer, EDHOCError> {
-        let (message_1, opaque_state) = parse_voucher_request(vreq)?;
-        let (_method, _suites_i, _suites_i_len, g_x, _c_i, ead_1) = parse_message_1(&message_1)?;
-        let prk = compute_prk(crypto, &self.w, &g_x);
+    ) -> Result<(EdhocMessageBuffer, Option<EdhocMessageBuffer>), EDHOCError> {
+        let (voucher_response, opaque_state, _w_index) =
+            self.handle_voucher_request_all(crypto, vreq)?;
+        Ok((voucher_response, opaque_state))
+    }
 
-        let (_loc_w, enc_id) = parse_ead_1_value(&ead_1.unwrap().value.unwrap())?;
-        let id_u_encoded = decrypt_enc_id(crypto, &prk, &enc_id, EDHOC_SUPPORTED_SUITES[0])?;
+    /// Like [Self::handle_voucher_request], but also returns the index of the W key that was used
+    ///
+    /// An index other than 0 means the device was provisioned with an older G_W.
+    pub fn handle_voucher_request_tagged<Crypto: CryptoTrait>(
+        &self,
+        crypto: &mut Crypto,
+        vreq: &EdhocMessageBuffer,
+    ) -> Result<(EdhocMessageBuffer, usize), EDHOCError> {
+        let (voucher_response, _opaque_state, w_index) =
+            self.handle_voucher_request_all(crypto, vreq)?;
+        Ok((voucher_response, w_index))
+    }
+
+    fn handle_voucher_request_all<Crypto: CryptoTrait>(
+        &self,
+        crypto: &mut Crypto,
+        vreq: &EdhocMessageBuffer,
+    ) -> Result<(EdhocMessageBuffer, Option<EdhocMessageBuffer>, usize), EDHOCError> {
+        let (message_1, opaque_state) = parse_voucher_request(vreq)?;
+        let (_method, suites_i, suites_i_len, g_x, _c_i, ead_1) = parse_message_1(&message_1)?;
+        let ss = selected_suite(&suites_i[..suites_i_len])?;
+        let ead_1 = ead_1.iter().find(|ead_1| ead_1.label == EAD_ZEROCONF_LABEL);
+        let Some(ead_1_value) = ead_1.and_then(|ead_1| ead_1.value) else {
+            return Err(EDHOCError::EADError);
+        };
+
+        let (_loc_w, enc_id) = parse_ead_1_value(&ead_1_value)?;
+        let (w_index, prk, id_u_encoded) = self.decrypt_enc_id_any_w(crypto, &g_x, &enc_id, ss)?;
         let id_u = decode_id_u(id_u_encoded)?;
 
         if self.acl.is_none() || self.authorized(id_u.content[3]) {
@@ -46,11 +128,56 @@ impl ZeroTouchServer {
 
             let voucher = prepare_voucher(crypto, &h_message_1, &self.cred_v.as_slice(), &prk);
             let voucher_response = encode_voucher_response(&message_1, &voucher, &opaque_state);
-            Ok(voucher_response)
+            Ok((voucher_response, opaque_state, w_index))
         } else {
             Err(EDHOCError::EADError)
         }
     }
+
+    /// Exposes the intermediate values of decrypting the ENC_ID of a voucher request
+    #[cfg(any(test, feature = "introspection"))]
+    pub fn introspect_voucher_request<Crypto: CryptoTrait>(
+        &self,
+        crypto: &mut Crypto,
+        vreq: &EdhocMessageBuffer,
+    ) -> Result<VoucherRequestIntrospection, EDHOCError> {
+        let (message_1, _opaque_state) = parse_voucher_request(vreq)?;
+        let (_method, suites_i, suites_i_len, g_x, _c_i, ead_1) = parse_message_1(&message_1)?;
+        let ss = selected_suite(&suites_i[..suites_i_len])?;
+        let ead_1 = ead_1.iter().find(|ead_1| ead_1.label == EAD_ZEROCONF_LABEL);
+        let Some(ead_1_value) = ead_1.and_then(|ead_1| ead_1.value) else {
+            return Err(EDHOCError::EADError);
+        };
+
+        let (_loc_w, enc_id) = parse_ead_1_value(&ead_1_value)?;
+        let (w_index, prk, plaintext) = self.decrypt_enc_id_any_w(crypto, &g_x, &enc_id, ss)?;
+        let (k_1, iv_1) = compute_k_1_iv_1(crypto, &prk);
+
+        Ok(VoucherRequestIntrospection {
+            w_index,
+            prk,
+            k_1,
+            iv_1,
+            plaintext,
+        })
+    }
+
+    /// Attempts each W key in turn until one authenticates ENC_ID for the selected suite `ss`
+    fn decrypt_enc_id_any_w<Crypto: CryptoTrait>(
+        &self,
+        crypto: &mut Crypto,
+        g_x: &BytesP256ElemLen,
+        enc_id: &EdhocMessageBuffer,
+        ss: u8,
+    ) -> Result<(usize, BytesHashLen, EdhocMessageBuffer), EDHOCError> {
+        for (w_index, w) in self.w[..self.w_len].iter().enumerate() {
+            let prk = compute_prk(crypto, w, g_x);
+            if let Ok(id_u_encoded) = decrypt_enc_id(crypto, &prk, enc_id, ss) {
+                return Ok((w_index, prk, id_u_encoded));
+            }
+        }
+        Err(EDHOCError::EADError)
+    }
 }
 
 /// This server can be used when the ACL is stored in the application layer
@@ -72,11 +199,13 @@ impl ZeroTouchServerUserAcl {
         vreq: &EdhocMessageBuffer,
     ) -> Result<EdhocMessageBuffer, EDHOCError> {
         let (message_1, _opaque_state) = parse_voucher_request(vreq)?;
-        let (_method, _suites_i, _suites_i_len, g_x, _c_i, ead_1) = parse_message_1(&message_1)?;
+        let (_method, suites_i, suites_i_len, g_x, _c_i, ead_1) = parse_message_1(&message_1)?;
+        let ss = selected_suite(&suites_i[..suites_i_len])?;
         let prk = compute_prk(crypto, &self.w, &g_x);
 
+        let ead_1 = ead_1.iter().find(|ead_1| ead_1.label == EAD_ZEROCONF_LABEL);
         let (_loc_w, enc_id) = parse_ead_1_value(&ead_1.unwrap().value.unwrap())?;
-        let id_u_encoded = decrypt_enc_id(crypto, &prk, &enc_id, EDHOC_SUPPORTED_SUITES[0])?;
+        let id_u_encoded = decrypt_enc_id(crypto, &prk, &enc_id, ss)?;
 
         decode_id_u(id_u_encoded)
     }
@@ -120,6 +249,19 @@ fn parse_voucher_request(
     }
 }
 
+/// The suite selected in message_1, which ENC_ID is bound to through SS
+///
+/// ENC_ID only decrypts with the suite the device passed to [ZeroTouchDevice::prepare_ead_1], so
+/// a device that bound another suite than the one its EDHOC session uses is rejected.
+///
+/// [ZeroTouchDevice::prepare_ead_1]: crate::ZeroTouchDevice::prepare_ead_1
+fn selected_suite(suites_i: &[u8]) -> Result<u8, EDHOCError> {
+    match suites_i.last() {
+        Some(ss) if EDHOC_SUPPORTED_SUITES.contains(ss) => Ok(*ss),
+        _ => Err(EDHOCError::UnsupportedCipherSuite),
+    }
+}
+
 fn decrypt_enc_id<Crypto: CryptoTrait>(
     crypto: &mut Crypto,
     prk: &BytesHashLen,
@@ -242,8 +384,9 @@ mod test_enrollment_server {
             &VOUCHER_REQUEST_TV.try_into().unwrap(),
         );
         assert!(res.is_ok());
-        let voucher_response = res.unwrap();
+        let (voucher_response, opaque_state) = res.unwrap();
         assert_eq!(voucher_response.content, voucher_response_tv.content);
+        assert!(opaque_state.is_none());
     }
 
     #[test]
@@ -261,8 +404,9 @@ mod test_enrollment_server {
             &VOUCHER_REQUEST_TV.try_into().unwrap(),
         );
         assert!(res.is_ok());
-        let voucher_response = res.unwrap();
+        let (voucher_response, opaque_state) = res.unwrap();
         assert_eq!(voucher_response.content, voucher_response_tv.content);
+        assert!(opaque_state.is_none());
     }
 
     #[test]
@@ -279,6 +423,45 @@ mod test_enrollment_server {
         );
         assert_eq!(res.unwrap_err(), EDHOCError::EADError);
     }
+
+    #[test]
+    fn test_new_with_w_keys_count() {
+        let w: BytesP256ElemLen = W_TV.try_into().unwrap();
+        let res = ZeroTouchServer::new_with_w_keys(&[], CRED_V_TV, None);
+        assert_eq!(res.unwrap_err(), EDHOCError::InvalidParameter);
+        let res = ZeroTouchServer::new_with_w_keys(&[w; MAX_W_KEYS + 1], CRED_V_TV, None);
+        assert_eq!(res.unwrap_err(), EDHOCError::InvalidParameter);
+        assert!(ZeroTouchServer::new_with_w_keys(&[w; MAX_W_KEYS], CRED_V_TV, None).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod test_enrollment_server_introspection {
+    use super::*;
+    use crate::test_vectors::*;
+    use lakers_crypto::default_crypto;
+
+    #[test]
+    fn test_introspect_voucher_request() {
+        let ead_server = ZeroTouchServer::new_with_w_keys(
+            &[W_NEW_TV.try_into().unwrap(), W_TV.try_into().unwrap()],
+            CRED_V_TV,
+            None,
+        )
+        .unwrap();
+
+        let introspection = ead_server
+            .introspect_voucher_request(
+                &mut default_crypto(),
+                &VOUCHER_REQUEST_TV.try_into().unwrap(),
+            )
+            .unwrap();
+        assert_eq!(introspection.w_index, 1);
+        assert_eq!(introspection.prk, PRK_TV);
+        assert_eq!(introspection.k_1, K_1_TV);
+        assert_eq!(introspection.iv_1, IV_1_TV);
+        assert_eq!(introspection.plaintext.as_slice(), ID_U_ENCODED_TV);
+    }
 }
 
 #[cfg(test)]
@@ -342,7 +525,8 @@ mod test_server_stateless_operation {
             &SLO_VOUCHER_REQUEST_TV.try_into().unwrap(),
         );
         assert!(res.is_ok());
-        let voucher_response = res.unwrap();
+        let (voucher_response, opaque_state) = res.unwrap();
         assert_eq!(voucher_response.content, voucher_response_tv.content);
+        assert_eq!(opaque_state.unwrap().as_slice(), SLO_OPAQUE_STATE_TV);
     }
 }
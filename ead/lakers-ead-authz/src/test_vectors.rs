@@ -15,6 +15,11 @@ pub const CRED_V_TV: &[u8] = &hex!("a2026b6578616d706c652e65647508a101a501020241
 // W
 pub const W_TV: &[u8] = &hex!("4E5E15AB35008C15B89E91F9F329164D4AACD53D9923672CE0019F9ACD98573F");
 pub const G_W_TV: &[u8] = &hex!("FFA4F102134029B3B156890B88C9D9619501196574174DCB68A07DB0588E4D41");
+// W after a key rotation, reusing the static DH key pair of R from the EDHOC traces
+pub const W_NEW_TV: &[u8] =
+    &hex!("72cc4761dbd4c78f758931aa589d348d1ef874a7e303ede2f140dcf3e6aa4aac");
+pub const G_W_NEW_TV: &[u8] =
+    &hex!("bbc34960526ea4d32e940cad2a234148ddc21791a12afbcbac93622046dd44f0");
 pub const LOC_W_TV: &[u8] = &hex!("636F61703A2F2F656E726F6C6C6D656E742E736572766572"); // coap://enrollment.server
 
 pub const ACL_TV: &[u8] = &hex!("2b"); // kid from ID_U_TV
@@ -0,0 +1,112 @@
+//! Remote attestation over EDHOC (draft-ietf-lake-ra), in the background-check model
+//!
+//! The Initiator is the attester: it sends a nonce and the evidence formats it can produce in
+//! EAD_1, and the evidence in EAD_3. The Responder is the verifier (or relays to one): it picks up
+//! the evidence for appraisal after checking that it is bound to the nonce and to TH_3 of the
+//! handshake.
+//! Producing and appraising evidence is up to the application.
+#![no_std]
+
+mod requester;
+mod shared;
+mod verifier;
+
+pub use requester::{AttestationRequester, AttestationRequesterWaitEAD3};
+pub use shared::{MAX_EVIDENCE_FORMATS, RA_NONCE_LEN};
+pub use verifier::{AttestationVerifier, AttestationVerifierDone, AttestationVerifierWaitEAD3};
+
+#[derive(PartialEq, Debug)]
+#[repr(C)]
+pub enum AttestationError {
+    InvalidEADLabel,
+    EmptyEADValue,
+    InvalidEADValue,
+    /// No formats, or more than [MAX_EVIDENCE_FORMATS]
+    InvalidFormats,
+    /// The evidence format was not offered, or is not accepted
+    UnsupportedFormat,
+    EADTooLong,
+    /// The evidence is not bound to the nonce of EAD_1 and to TH_3 of this handshake
+    BindingMismatch,
+}
+
+#[cfg(test)]
+mod test_ra {
+    use crate::*;
+    use lakers_crypto::default_crypto;
+
+    const FORMATS_TV: &[u16] = &[263, 60];
+    const EVIDENCE_TV: &[u8] = b"stub evidence";
+    // stands in for TH_3 of a handshake
+    const TH_3_TV: [u8; 32] = [0x3a; 32];
+
+    #[test]
+    fn test_complete_flow() {
+        let requester = AttestationRequester::new(FORMATS_TV).unwrap();
+        let verifier = AttestationVerifier::new(&[60]).unwrap();
+
+        let (requester, ead_1) = requester.prepare_ead_1(&mut default_crypto()).unwrap();
+        assert!(!ead_1.is_critical);
+
+        let verifier = verifier.process_ead_1(&ead_1).unwrap();
+
+        let ead_3 = requester
+            .prepare_ead_3(&mut default_crypto(), &TH_3_TV, 60, EVIDENCE_TV)
+            .unwrap();
+        let done = verifier
+            .process_ead_3(&mut default_crypto(), &TH_3_TV, &ead_3)
+            .unwrap();
+        assert_eq!(done.format, 60);
+        assert_eq!(done.evidence.as_slice(), EVIDENCE_TV);
+    }
+
+    #[test]
+    fn test_unsupported_format() {
+        let requester = AttestationRequester::new(FORMATS_TV).unwrap();
+        let (requester, ead_1) = requester.prepare_ead_1(&mut default_crypto()).unwrap();
+
+        // none of the offered formats is accepted
+        let verifier = AttestationVerifier::new(&[10000]).unwrap();
+        assert_eq!(
+            verifier.process_ead_1(&ead_1).unwrap_err(),
+            AttestationError::UnsupportedFormat
+        );
+
+        // evidence in a format that was offered, but is not accepted
+        let verifier = AttestationVerifier::new(&[60])
+            .unwrap()
+            .process_ead_1(&ead_1)
+            .unwrap();
+        let ead_3 = requester
+            .prepare_ead_3(&mut default_crypto(), &TH_3_TV, 263, EVIDENCE_TV)
+            .unwrap();
+        assert_eq!(
+            verifier
+                .process_ead_3(&mut default_crypto(), &TH_3_TV, &ead_3)
+                .unwrap_err(),
+            AttestationError::UnsupportedFormat
+        );
+    }
+
+    #[test]
+    fn test_binding_mismatch() {
+        let requester = AttestationRequester::new(FORMATS_TV).unwrap();
+        let (requester, ead_1) = requester.prepare_ead_1(&mut default_crypto()).unwrap();
+
+        // evidence produced in another session, which answers the same EAD_1
+        let ead_3 = requester
+            .prepare_ead_3(&mut default_crypto(), &[0x00; 32], 263, EVIDENCE_TV)
+            .unwrap();
+
+        let verifier = AttestationVerifier::new(FORMATS_TV)
+            .unwrap()
+            .process_ead_1(&ead_1)
+            .unwrap();
+        assert_eq!(
+            verifier
+                .process_ead_3(&mut default_crypto(), &TH_3_TV, &ead_3)
+                .unwrap_err(),
+            AttestationError::BindingMismatch
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use super::shared::*;
+use crate::AttestationError;
+use lakers_shared::{Crypto as CryptoTrait, *};
+
+/// The attester (EDHOC Initiator), which asks the Responder to verify its evidence
+#[derive(Debug)]
+#[repr(C)]
+pub struct AttestationRequester {
+    formats: Formats,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct AttestationRequesterWaitEAD3 {
+    nonce: BytesRaNonce,
+    formats: Formats,
+}
+
+impl AttestationRequester {
+    /// Creates a requester offering the evidence `formats` (CoAP content formats), most preferred
+    /// first
+    pub fn new(formats: &[u16]) -> Result<Self, AttestationError> {
+        Ok(AttestationRequester {
+            formats: Formats::new(formats)?,
+        })
+    }
+
+    /// Draws a fresh nonce and encodes it into EAD_1 together with the offered formats
+    pub fn prepare_ead_1<Crypto: CryptoTrait>(
+        &self,
+        crypto: &mut Crypto,
+    ) -> Result<(AttestationRequesterWaitEAD3, EADItem), AttestationError> {
+        let mut nonce: BytesRaNonce = [0; RA_NONCE_LEN];
+        for byte in nonce.iter_mut() {
+            *byte = crypto.get_random_byte();
+        }
+
+        let ead_1 = EADItem {
+            label: EAD_RA_LABEL,
+            is_critical: false,
+            value: Some(encode_ead_1_value(&nonce, &self.formats)?),
+        };
+
+        Ok((
+            AttestationRequesterWaitEAD3 {
+                nonce,
+                formats: self.formats,
+            },
+            ead_1,
+        ))
+    }
+}
+
+impl AttestationRequesterWaitEAD3 {
+    /// Nonce sent in EAD_1, which the evidence is expected to include
+    pub fn nonce(&self) -> &[u8] {
+        &self.nonce
+    }
+
+    /// Encodes the `evidence` supplied by the application into EAD_3
+    ///
+    /// `format` has to be one of the offered formats; the evidence is bound to the nonce and to
+    /// `th_3`, the TH_3 of the handshake that carries EAD_3.
+    pub fn prepare_ead_3<Crypto: CryptoTrait>(
+        self,
+        crypto: &mut Crypto,
+        th_3: &BytesHashLen,
+        format: u16,
+        evidence: &[u8],
+    ) -> Result<EADItem, AttestationError> {
+        if !self.formats.contains(format) {
+            return Err(AttestationError::UnsupportedFormat);
+        }
+
+        let binding = compute_binding(crypto, &self.nonce, th_3);
+
+        Ok(EADItem {
+            label: EAD_RA_LABEL,
+            is_critical: false,
+            value: Some(encode_ead_3_value(format, evidence, &binding)?),
+        })
+    }
+}
@@ -0,0 +1,244 @@
+use crate::AttestationError;
+use lakers_shared::{Crypto as CryptoTrait, *};
+
+/// Length of the nonce the evidence is bound to
+pub const RA_NONCE_LEN: usize = 16;
+/// Largest number of evidence formats that can be offered in EAD_1
+pub const MAX_EVIDENCE_FORMATS: usize = 4;
+
+pub(crate) type BytesRaNonce = [u8; RA_NONCE_LEN];
+
+/// Evidence formats (CoAP content formats), in order of preference
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Formats {
+    formats: [u16; MAX_EVIDENCE_FORMATS],
+    len: usize,
+}
+
+impl Formats {
+    pub(crate) fn new(formats: &[u16]) -> Result<Self, AttestationError> {
+        if formats.is_empty() || formats.len() > MAX_EVIDENCE_FORMATS {
+            return Err(AttestationError::InvalidFormats);
+        }
+        let mut result = Formats::default();
+        result.formats[..formats.len()].copy_from_slice(formats);
+        result.len = formats.len();
+        Ok(result)
+    }
+
+    pub(crate) fn as_slice(&self) -> &[u16] {
+        &self.formats[..self.len]
+    }
+
+    pub(crate) fn contains(&self, format: u16) -> bool {
+        self.as_slice().contains(&format)
+    }
+
+    /// Formats of `self` that are also in `other`, keeping the order of `self`
+    pub(crate) fn intersection(&self, other: &Formats) -> Formats {
+        let mut result = Formats::default();
+        for format in self.as_slice() {
+            if other.contains(*format) {
+                result.formats[result.len] = *format;
+                result.len += 1;
+            }
+        }
+        result
+    }
+}
+
+/// Wraps the CBOR `payload` of an EAD value into a bstr
+fn wrap_ead_value(payload: &[u8]) -> Result<EdhocMessageBuffer, AttestationError> {
+    let mut value = EdhocMessageBuffer::new();
+    let mut encoder = CBOREncoder::new(&mut value.content);
+    encoder
+        .bytes(payload)
+        .map_err(|_| AttestationError::EADTooLong)?;
+    value.len = encoder.position();
+    Ok(value)
+}
+
+/// EAD_1 value = bstr .cbor [ nonce: bstr, formats: [ + uint ] ]
+pub(crate) fn encode_ead_1_value(
+    nonce: &BytesRaNonce,
+    formats: &Formats,
+) -> Result<EdhocMessageBuffer, AttestationError> {
+    let mut payload = [0u8; MAX_MESSAGE_SIZE_LEN];
+    let mut encoder = CBOREncoder::new(&mut payload);
+    encode_ead_1_payload(&mut encoder, nonce, formats).map_err(|_| AttestationError::EADTooLong)?;
+    let len = encoder.position();
+    wrap_ead_value(&payload[..len])
+}
+
+fn encode_ead_1_payload(
+    encoder: &mut CBOREncoder,
+    nonce: &BytesRaNonce,
+    formats: &Formats,
+) -> Result<(), MessageBufferError> {
+    encoder.array(2)?;
+    encoder.bytes(nonce)?;
+    encoder.array(formats.len)?;
+    for format in formats.as_slice() {
+        encoder.u16(*format)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn decode_ead_1_value(
+    value: &EdhocMessageBuffer,
+) -> Result<(BytesRaNonce, Formats), AttestationError> {
+    decode_ead_1_payload(value.as_slice()).map_err(|_| AttestationError::InvalidEADValue)
+}
+
+fn decode_ead_1_payload(value: &[u8]) -> Result<(BytesRaNonce, Formats), CBORError> {
+    let mut decoder = unwrap_ead_value(value)?;
+    if decoder.array()? != 2 {
+        return Err(CBORError::DecodingError);
+    }
+    let nonce: BytesRaNonce = decoder.bytes_sized(RA_NONCE_LEN)?.try_into().unwrap();
+    let formats_len = decoder.array()?;
+    if formats_len == 0 || formats_len > MAX_EVIDENCE_FORMATS {
+        return Err(CBORError::DecodingError);
+    }
+    let mut formats = Formats::default();
+    for format in formats.formats[..formats_len].iter_mut() {
+        *format = decoder.u16()?;
+    }
+    formats.len = formats_len;
+    decoder.ensure_finished()?;
+    Ok((nonce, formats))
+}
+
+/// EAD_3 value = bstr .cbor [ format: uint, evidence: bstr, binding: bstr ]
+pub(crate) fn encode_ead_3_value(
+    format: u16,
+    evidence: &[u8],
+    binding: &BytesHashLen,
+) -> Result<EdhocMessageBuffer, AttestationError> {
+    let mut payload = [0u8; MAX_MESSAGE_SIZE_LEN];
+    let mut encoder = CBOREncoder::new(&mut payload);
+    encode_ead_3_payload(&mut encoder, format, evidence, binding)
+        .map_err(|_| AttestationError::EADTooLong)?;
+    let len = encoder.position();
+    wrap_ead_value(&payload[..len])
+}
+
+fn encode_ead_3_payload(
+    encoder: &mut CBOREncoder,
+    format: u16,
+    evidence: &[u8],
+    binding: &BytesHashLen,
+) -> Result<(), MessageBufferError> {
+    encoder.array(3)?;
+    encoder.u16(format)?;
+    encoder.bytes(evidence)?;
+    encoder.bytes(binding)
+}
+
+pub(crate) fn decode_ead_3_value(
+    value: &EdhocMessageBuffer,
+) -> Result<(u16, &[u8], BytesHashLen), AttestationError> {
+    decode_ead_3_payload(value.as_slice()).map_err(|_| AttestationError::InvalidEADValue)
+}
+
+fn decode_ead_3_payload(value: &[u8]) -> Result<(u16, &[u8], BytesHashLen), CBORError> {
+    let mut decoder = unwrap_ead_value(value)?;
+    if decoder.array()? != 3 {
+        return Err(CBORError::DecodingError);
+    }
+    let format = decoder.u16()?;
+    let evidence = decoder.bytes()?;
+    let binding: BytesHashLen = decoder.bytes_sized(SHA256_DIGEST_LEN)?.try_into().unwrap();
+    decoder.ensure_finished()?;
+    Ok((format, evidence, binding))
+}
+
+/// Returns a decoder for the CBOR payload that is wrapped in the bstr of an EAD value
+fn unwrap_ead_value(value: &[u8]) -> Result<CBORDecoder<'_>, CBORError> {
+    let mut outer = CBORDecoder::new(value);
+    let payload = outer.bytes()?;
+    outer.ensure_finished()?;
+    Ok(CBORDecoder::new(payload))
+}
+
+/// binding = H(nonce | TH_3)
+///
+/// Both peers know TH_3 by the time EAD_3 is processed. Unlike H(message_1), which an attacker
+/// can reproduce by replaying message_1, TH_3 covers the ephemeral key of the Responder, so
+/// evidence from one session does not verify in another.
+pub(crate) fn compute_binding<Crypto: CryptoTrait>(
+    crypto: &mut Crypto,
+    nonce: &BytesRaNonce,
+    th_3: &BytesHashLen,
+) -> BytesHashLen {
+    let mut message: BytesMaxBuffer = [0x00; MAX_BUFFER_LEN];
+    message[..RA_NONCE_LEN].copy_from_slice(nonce);
+    message[RA_NONCE_LEN..RA_NONCE_LEN + SHA256_DIGEST_LEN].copy_from_slice(th_3);
+    crypto.sha256_digest(&message, RA_NONCE_LEN + SHA256_DIGEST_LEN)
+}
+
+#[cfg(test)]
+mod test_encoding {
+    use super::*;
+
+    const NONCE_TV: BytesRaNonce = [0xaa; RA_NONCE_LEN];
+
+    #[test]
+    fn test_ead_1_value() {
+        let formats = Formats::new(&[263, 60]).unwrap();
+        let value = encode_ead_1_value(&NONCE_TV, &formats).unwrap();
+        // << [h'aa...', [263, 60]] >>
+        assert_eq!(value.as_slice()[..4], [0x58, 0x18, 0x82, 0x50]);
+        assert_eq!(
+            value.as_slice()[4 + RA_NONCE_LEN..],
+            [0x82, 0x19, 0x01, 0x07, 0x18, 0x3c]
+        );
+        assert_eq!(decode_ead_1_value(&value), Ok((NONCE_TV, formats)));
+
+        // a value that is not wrapped in a bstr
+        let unwrapped = EdhocMessageBuffer::new_from_slice(&value.as_slice()[2..]).unwrap();
+        assert_eq!(
+            decode_ead_1_value(&unwrapped),
+            Err(AttestationError::InvalidEADValue)
+        );
+        // a nonce of the wrong length
+        let mut short_nonce = value;
+        short_nonce.content[3] = 0x4f;
+        assert_eq!(
+            decode_ead_1_value(&short_nonce),
+            Err(AttestationError::InvalidEADValue)
+        );
+
+        assert_eq!(Formats::new(&[]), Err(AttestationError::InvalidFormats));
+        assert_eq!(
+            Formats::new(&[1; MAX_EVIDENCE_FORMATS + 1]),
+            Err(AttestationError::InvalidFormats)
+        );
+    }
+
+    #[test]
+    fn test_ead_3_value() {
+        let binding = [0xbb; SHA256_DIGEST_LEN];
+        let value = encode_ead_3_value(263, b"evidence", &binding).unwrap();
+        assert_eq!(
+            decode_ead_3_value(&value),
+            Ok((263, &b"evidence"[..], binding))
+        );
+
+        // trailing data after the array
+        let mut trailing = EdhocMessageBuffer::new();
+        trailing.push(value.content[0]).unwrap();
+        trailing.push(value.content[1] + 1).unwrap();
+        trailing.extend_from_slice(&value.as_slice()[2..]).unwrap();
+        trailing.push(0x00).unwrap();
+        assert_eq!(
+            decode_ead_3_value(&trailing),
+            Err(AttestationError::InvalidEADValue)
+        );
+
+        assert_eq!(
+            encode_ead_3_value(263, &[0; MAX_MESSAGE_SIZE_LEN], &binding),
+            Err(AttestationError::EADTooLong)
+        );
+    }
+}
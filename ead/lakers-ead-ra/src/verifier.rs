@@ -0,0 +1,92 @@
+use super::shared::*;
+use crate::AttestationError;
+use lakers_shared::{Crypto as CryptoTrait, *};
+
+/// The verifier (EDHOC Responder), which checks that the evidence answers its nonce
+#[derive(Debug)]
+#[repr(C)]
+pub struct AttestationVerifier {
+    formats: Formats,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct AttestationVerifierWaitEAD3 {
+    nonce: BytesRaNonce,
+    /// Offered formats that are also accepted
+    formats: Formats,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct AttestationVerifierDone {
+    /// Format the evidence is encoded in
+    pub format: u16,
+    /// Evidence as supplied by the attester, for the application to appraise
+    pub evidence: EdhocMessageBuffer,
+}
+
+impl AttestationVerifier {
+    /// Creates a verifier accepting evidence in the given `formats` (CoAP content formats)
+    pub fn new(formats: &[u16]) -> Result<Self, AttestationError> {
+        Ok(AttestationVerifier {
+            formats: Formats::new(formats)?,
+        })
+    }
+
+    /// Processes the attestation request in EAD_1
+    ///
+    /// Fails with [AttestationError::UnsupportedFormat] if none of the offered formats is
+    /// accepted; the item is not critical, so the handshake can go on without attestation.
+    pub fn process_ead_1(
+        &self,
+        ead_1: &EADItem,
+    ) -> Result<AttestationVerifierWaitEAD3, AttestationError> {
+        if ead_1.label != EAD_RA_LABEL {
+            return Err(AttestationError::InvalidEADLabel);
+        }
+        let Some(ead_1_value) = &ead_1.value else {
+            return Err(AttestationError::EmptyEADValue);
+        };
+
+        let (nonce, offered) = decode_ead_1_value(ead_1_value)?;
+        let formats = offered.intersection(&self.formats);
+        if formats.as_slice().is_empty() {
+            return Err(AttestationError::UnsupportedFormat);
+        }
+
+        Ok(AttestationVerifierWaitEAD3 { nonce, formats })
+    }
+}
+
+impl AttestationVerifierWaitEAD3 {
+    /// Checks that the evidence in EAD_3 is in an offered and accepted format and bound to the
+    /// nonce of EAD_1 and to `th_3`, the TH_3 of this handshake, and hands it out for appraisal
+    pub fn process_ead_3<Crypto: CryptoTrait>(
+        self,
+        crypto: &mut Crypto,
+        th_3: &BytesHashLen,
+        ead_3: &EADItem,
+    ) -> Result<AttestationVerifierDone, AttestationError> {
+        if ead_3.label != EAD_RA_LABEL {
+            return Err(AttestationError::InvalidEADLabel);
+        }
+        let Some(ead_3_value) = &ead_3.value else {
+            return Err(AttestationError::EmptyEADValue);
+        };
+
+        let (format, evidence, binding) = decode_ead_3_value(ead_3_value)?;
+        if !self.formats.contains(format) {
+            return Err(AttestationError::UnsupportedFormat);
+        }
+        if binding != compute_binding(crypto, &self.nonce, th_3) {
+            return Err(AttestationError::BindingMismatch);
+        }
+
+        Ok(AttestationVerifierDone {
+            format,
+            evidence: EdhocMessageBuffer::new_from_slice(evidence)
+                .map_err(|_| AttestationError::EADTooLong)?,
+        })
+    }
+}
@@ -2,3 +2,6 @@
 
 #[cfg(feature = "ead-authz")]
 pub use lakers_ead_authz::*;
+
+#[cfg(feature = "ead-ra")]
+pub use lakers_ead_ra::*;
@@ -33,8 +33,8 @@ fn client_handshake() -> Result<(), EDHOCError> {
     let initiator = EdhocInitiator::new(lakers_crypto::default_crypto());
 
     // Send Message 1 over CoAP and convert the response to byte
-    let mut msg_1_buf = Vec::from([0xf5u8]); // EDHOC message_1 when transported over CoAP is prepended with CBOR true
-    let c_i = generate_connection_identifier_cbor(&mut lakers_crypto::default_crypto());
+    let mut msg_1_buf = Vec::from([CBOR_TRUE]); // EDHOC message_1 when transported over CoAP is prepended with CBOR true
+    let c_i = generate_connection_identifier_cbor(&mut lakers_crypto::default_crypto())?;
     let (initiator, message_1) = initiator.prepare_message_1(Some(c_i), &None)?;
     msg_1_buf.extend_from_slice(message_1.as_slice());
     println!("message_1 len = {}", msg_1_buf.len());
@@ -63,23 +63,25 @@ fn client_handshake() -> Result<(), EDHOCError> {
     println!("EDHOC exchange successfully completed");
     println!("PRK_out: {:02x?}", prk_out);
 
-    let mut oscore_secret = initiator.edhoc_exporter(0u8, &[], 16); // label is 0
-    let mut oscore_salt = initiator.edhoc_exporter(1u8, &[], 8); // label is 1
+    let mut oscore_secret = initiator.edhoc_exporter(0u8, &[], 16).unwrap(); // label is 0
+    let mut oscore_salt = initiator.edhoc_exporter(1u8, &[], 8).unwrap(); // label is 1
 
     println!("OSCORE secret: {:02x?}", oscore_secret);
     println!("OSCORE salt: {:02x?}", oscore_salt);
 
     // context of key update is a test vector from draft-ietf-lake-traces
-    let prk_out_new = initiator.edhoc_key_update(&[
-        0xa0, 0x11, 0x58, 0xfd, 0xb8, 0x20, 0x89, 0x0c, 0xd6, 0xbe, 0x16, 0x96, 0x02, 0xb8, 0xbc,
-        0xea,
-    ]);
+    let prk_out_new = initiator
+        .edhoc_key_update(&[
+            0xa0, 0x11, 0x58, 0xfd, 0xb8, 0x20, 0x89, 0x0c, 0xd6, 0xbe, 0x16, 0x96, 0x02, 0xb8,
+            0xbc, 0xea,
+        ])
+        .unwrap();
 
     println!("PRK_out after key update: {:02x?}?", prk_out_new);
 
     // compute OSCORE secret and salt after key update
-    oscore_secret = initiator.edhoc_exporter(0u8, &[], 16); // label is 0
-    oscore_salt = initiator.edhoc_exporter(1u8, &[], 8); // label is 1
+    oscore_secret = initiator.edhoc_exporter(0u8, &[], 16).unwrap(); // label is 0
+    oscore_salt = initiator.edhoc_exporter(1u8, &[], 8).unwrap(); // label is 1
 
     println!("OSCORE secret after key update: {:02x?}", oscore_secret);
     println!("OSCORE salt after key update: {:02x?}", oscore_salt);
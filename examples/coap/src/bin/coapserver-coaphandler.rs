@@ -38,7 +38,7 @@ fn too_small(_e: MessageBufferError) -> Error {
 ///
 /// Places using this function may be simplified if From/Into is specified (possibly after
 /// enlarging the Error type)
-fn render_error(_e: EDHOCError) -> Error {
+fn render_error(_e: impl Into<EDHOCError>) -> Error {
     Error::bad_request()
 }
 
@@ -93,8 +93,10 @@ impl coap_handler::Handler for EdhocHandler {
 
         request.options().ignore_elective_others()?;
 
-        let first_byte = request.payload().get(0).ok_or_else(Error::bad_request)?;
-        let starts_with_true = first_byte == &0xf5;
+        if request.payload().is_empty() {
+            return Err(Error::bad_request());
+        }
+        let starts_with_true = CBORDecoder::new(request.payload()).bool() == Ok(true);
 
         if starts_with_true {
             let cred_r =
@@ -103,6 +105,7 @@ impl coap_handler::Handler for EdhocHandler {
 
             let (responder, _ead_1) =
                 EdhocResponder::new(lakers_crypto::default_crypto(), &R, cred_r)
+                    .expect("Static private key is invalid")
                     .process_message_1(
                         &EdhocMessageBuffer::new_from_slice(&request.payload()[1..])
                             .map_err(too_small)?,
@@ -148,21 +151,23 @@ impl coap_handler::Handler for EdhocHandler {
             println!("EDHOC exchange successfully completed");
             println!("PRK_out: {:02x?}", prk_out);
 
-            let mut _oscore_secret = responder.edhoc_exporter(0u8, &[], 16); // label is 0
+            let mut _oscore_secret = responder.edhoc_exporter(0u8, &[], 16).unwrap(); // label is 0
             println!("OSCORE secret: {:02x?}", _oscore_secret);
-            let mut _oscore_salt = responder.edhoc_exporter(1u8, &[], 8); // label is 1
+            let mut _oscore_salt = responder.edhoc_exporter(1u8, &[], 8).unwrap(); // label is 1
             println!("OSCORE salt: {:02x?}", _oscore_salt);
 
             // context of key update is a test vector from draft-ietf-lake-traces
-            let prk_out_new = responder.edhoc_key_update(&[
-                0xa0, 0x11, 0x58, 0xfd, 0xb8, 0x20, 0x89, 0x0c, 0xd6, 0xbe, 0x16, 0x96, 0x02, 0xb8,
-                0xbc, 0xea,
-            ]);
+            let prk_out_new = responder
+                .edhoc_key_update(&[
+                    0xa0, 0x11, 0x58, 0xfd, 0xb8, 0x20, 0x89, 0x0c, 0xd6, 0xbe, 0x16, 0x96, 0x02,
+                    0xb8, 0xbc, 0xea,
+                ])
+                .unwrap();
             println!("PRK_out after key update: {:02x?}?", prk_out_new);
 
-            _oscore_secret = responder.edhoc_exporter(0u8, &[], 16); // label is 0
+            _oscore_secret = responder.edhoc_exporter(0u8, &[], 16).unwrap(); // label is 0
             println!("OSCORE secret after key update: {:02x?}", _oscore_secret);
-            _oscore_salt = responder.edhoc_exporter(1u8, &[], 8); // label is 1
+            _oscore_salt = responder.edhoc_exporter(1u8, &[], 8).unwrap(); // label is 1
             println!("OSCORE salt after key update: {:02x?}", _oscore_salt);
 
             Ok(EdhocResponse::Message3Processed)
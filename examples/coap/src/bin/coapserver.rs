@@ -39,9 +39,10 @@ fn main() {
         if path == ".well-known/edhoc" {
             println!("Received message from {}", src);
             // This is an EDHOC message
-            if request.message.payload[0] == 0xf5 {
+            if CBORDecoder::new(&request.message.payload).bool() == Ok(true) {
                 let cred_r = CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap();
-                let responder = EdhocResponder::new(lakers_crypto::default_crypto(), &R, cred_r);
+                let responder =
+                    EdhocResponder::new(lakers_crypto::default_crypto(), &R, cred_r).unwrap();
 
                 let message_1: EdhocMessageBuffer = request.message.payload[1..]
                     .try_into()
@@ -50,23 +51,29 @@ fn main() {
 
                 if let Ok((responder, ead_1)) = result {
                     let c_r =
-                        generate_connection_identifier_cbor(&mut lakers_crypto::default_crypto());
-                    let ead_2 = if let Some(ead_1) = ead_1 {
+                        generate_connection_identifier_cbor(&mut lakers_crypto::default_crypto())
+                            .unwrap();
+                    let ead_2 = if let Some(ead_1) = ead_1.first() {
                         let authenticator = ZeroTouchAuthenticator::default();
                         let (authenticator, _loc_w, voucher_request) =
-                            authenticator.process_ead_1(&ead_1, &message_1).unwrap();
+                            authenticator.process_ead_1(ead_1, &message_1).unwrap();
 
                         // mock a request to the server
-                        let voucher_response = server
+                        let (voucher_response, _opaque_state) = server
                             .handle_voucher_request(
                                 &mut lakers_crypto::default_crypto(),
                                 &voucher_request,
                             )
                             .unwrap();
+                        let voucher_response =
+                            VoucherResponse::decode(voucher_response.as_slice()).unwrap();
 
                         let res = authenticator.prepare_ead_2(&voucher_response);
                         assert!(res.is_ok());
-                        authenticator.prepare_ead_2(&voucher_response).ok()
+                        authenticator
+                            .prepare_ead_2(&voucher_response)
+                            .ok()
+                            .map(|(ead_2, _opaque_state)| ead_2)
                     } else {
                         None
                     };
@@ -110,21 +117,23 @@ fn main() {
                 println!("EDHOC exchange successfully completed");
                 println!("PRK_out: {:02x?}", prk_out);
 
-                let mut _oscore_secret = responder.edhoc_exporter(0u8, &[], 16); // label is 0
+                let mut _oscore_secret = responder.edhoc_exporter(0u8, &[], 16).unwrap(); // label is 0
                 println!("OSCORE secret: {:02x?}", _oscore_secret);
-                let mut _oscore_salt = responder.edhoc_exporter(1u8, &[], 8); // label is 1
+                let mut _oscore_salt = responder.edhoc_exporter(1u8, &[], 8).unwrap(); // label is 1
                 println!("OSCORE salt: {:02x?}", _oscore_salt);
 
                 // context of key update is a test vector from draft-ietf-lake-traces
-                let prk_out_new = responder.edhoc_key_update(&[
-                    0xa0, 0x11, 0x58, 0xfd, 0xb8, 0x20, 0x89, 0x0c, 0xd6, 0xbe, 0x16, 0x96, 0x02,
-                    0xb8, 0xbc, 0xea,
-                ]);
+                let prk_out_new = responder
+                    .edhoc_key_update(&[
+                        0xa0, 0x11, 0x58, 0xfd, 0xb8, 0x20, 0x89, 0x0c, 0xd6, 0xbe, 0x16, 0x96,
+                        0x02, 0xb8, 0xbc, 0xea,
+                    ])
+                    .unwrap();
                 println!("PRK_out after key update: {:02x?}?", prk_out_new);
 
-                _oscore_secret = responder.edhoc_exporter(0u8, &[], 16); // label is 0
+                _oscore_secret = responder.edhoc_exporter(0u8, &[], 16).unwrap(); // label is 0
                 println!("OSCORE secret after key update: {:02x?}", _oscore_secret);
-                _oscore_salt = responder.edhoc_exporter(1u8, &[], 8); // label is 1
+                _oscore_salt = responder.edhoc_exporter(1u8, &[], 8).unwrap(); // label is 1
                 println!("OSCORE salt after key update: {:02x?}", _oscore_salt);
             }
             response.set_status(ResponseType::Changed);
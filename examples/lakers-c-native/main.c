@@ -131,7 +131,6 @@ int main(void)
     EdhocMessageBuffer message_1;
 #ifdef LAKERS_EAD_AUTHZ
     int res = initiator_prepare_message_1(&initiator, NULL, &ead_1, &message_1);
-    memcpy(device.wait_ead2.h_message_1, initiator.wait_m2.h_message_1, SHA256_DIGEST_LEN);
 #else
     int res = initiator_prepare_message_1(&initiator, NULL, NULL, &message_1);
 #endif
@@ -161,7 +160,7 @@ int main(void)
     }
 #ifdef LAKERS_EAD_AUTHZ
     puts("processing ead2");
-    res = authz_device_process_ead_2(&device, &ead_2, cred_r);
+    res = authz_device_process_ead_2(&device, &ead_2, cred_r, &message_1);
     if (res != 0) {
         printf("Error process ead2 (authz): %d\n", res);
         return 1;
@@ -72,6 +72,10 @@ fn main() -> ! {
     const _G_R: &[u8] = &hex!("bbc34960526ea4d32e940cad2a234148ddc21791a12afbcbac93622046dd44f0");
     const _C_R_TV: [u8; 1] = hex!("27");
 
+    // the crypto back-end has to pass its known-answer tests before it is used
+    self_test(&mut default_crypto()).unwrap();
+    println!("Crypto self-test passed.");
+
     fn test_new_initiator() {
         let _initiator = EdhocInitiator::new(lakers_crypto::default_crypto());
     }
@@ -95,7 +99,7 @@ fn main() -> ! {
         let mut initiator = EdhocInitiator::new(lakers_crypto::default_crypto());
 
         let c_i: u8 =
-            generate_connection_identifier_cbor(&mut lakers_crypto::default_crypto()).into();
+            generate_connection_identifier_cbor(&mut lakers_crypto::default_crypto()).unwrap();
         let message_1 = initiator.prepare_message_1(None, &None);
         assert!(message_1.is_ok());
     }
@@ -108,7 +112,8 @@ fn main() -> ! {
         let cred_r = CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap();
 
         let mut initiator = EdhocInitiator::new(lakers_crypto::default_crypto());
-        let responder = EdhocResponder::new(lakers_crypto::default_crypto(), R, cred_r.clone());
+        let responder =
+            EdhocResponder::new(lakers_crypto::default_crypto(), R, cred_r.clone()).unwrap();
 
         let (initiator, message_1) = initiator.prepare_message_1(None, &None).unwrap();
 
@@ -133,11 +138,11 @@ fn main() -> ! {
         assert_eq!(i_prk_out, r_prk_out);
 
         // derive OSCORE secret and salt at both sides and compare
-        let i_oscore_secret = initiator.edhoc_exporter(0u8, &[], 16); // label is 0
-        let i_oscore_salt = initiator.edhoc_exporter(1u8, &[], 8); // label is 1
+        let i_oscore_secret = initiator.edhoc_exporter(0u8, &[], 16).unwrap(); // label is 0
+        let i_oscore_salt = initiator.edhoc_exporter(1u8, &[], 8).unwrap(); // label is 1
 
-        let r_oscore_secret = responder.edhoc_exporter(0u8, &[], 16); // label is 0
-        let r_oscore_salt = responder.edhoc_exporter(1u8, &[], 8); // label is 1
+        let r_oscore_secret = responder.edhoc_exporter(0u8, &[], 16).unwrap(); // label is 0
+        let r_oscore_salt = responder.edhoc_exporter(1u8, &[], 8).unwrap(); // label is 1
 
         assert_eq!(i_oscore_secret, r_oscore_secret);
         assert_eq!(i_oscore_salt, r_oscore_salt);
@@ -0,0 +1,56 @@
+#![no_main]
+//! Drives the high-level API with a message_1 and with parameters of arbitrary lengths, which
+//! must be rejected with an error rather than panic
+
+use lakers::doctest_helpers::{credential_i, credential_r, I, R};
+use lakers::*;
+use lakers_crypto::default_crypto;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // the first byte splits the input into a parameter and a message
+    let Some((&split, data)) = data.split_first() else {
+        return;
+    };
+    let (parameter, message) = data.split_at(usize::from(split).min(data.len()));
+    let length = message.len();
+
+    let _ = EdhocResponder::new(default_crypto(), parameter, credential_r());
+    let _ = EADItem::with_value(1, false, parameter);
+
+    if let Ok(message_1) = EdhocMessageBuffer::new_from_slice(message) {
+        let responder = EdhocResponder::new(default_crypto(), &R, credential_r()).unwrap();
+        let _ = responder.process_message_1(&message_1);
+    }
+
+    let (cred_i, cred_r) = (credential_i(), credential_r());
+    let (initiator, message_1) = EdhocInitiator::new(default_crypto())
+        .prepare_message_1(None, &None)
+        .unwrap();
+    let (mut responder, _ead_1) = EdhocResponder::new(default_crypto(), &R, cred_r)
+        .unwrap()
+        .process_message_1(&message_1)
+        .unwrap();
+    let _ = responder.derive_ead_key(0, parameter, length);
+    let (mut responder, message_2) = responder
+        .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+        .unwrap();
+    let _ = responder.early_exporter(0, parameter, length);
+    let (mut initiator, _c_r, _id_cred_r, _ead_2) = initiator.parse_message_2(&message_2).unwrap();
+    let _ = initiator.derive_ead_key(0, parameter, length);
+    let mut initiator = initiator.verify_message_2(&I, cred_i, cred_r).unwrap();
+    let _ = initiator.derive_ead_key(0, parameter, length);
+    let (mut initiator, message_3, _prk_out) = initiator
+        .prepare_message_3(CredentialTransfer::ByReference, &None)
+        .unwrap();
+    let (mut responder, _id_cred_i, _ead_3) = responder.parse_message_3(&message_3).unwrap();
+    let _ = responder.derive_ead_key(0, parameter, length);
+    let (mut responder, _prk_out) = responder.verify_message_3(cred_i).unwrap();
+
+    for label in [0, u8::MAX] {
+        let _ = initiator.edhoc_exporter(label, parameter, length);
+        let _ = responder.edhoc_exporter(label, parameter, length);
+    }
+    let _ = initiator.edhoc_key_update(parameter);
+    let _ = responder.edhoc_key_update(parameter);
+});
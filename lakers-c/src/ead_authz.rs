@@ -28,11 +28,13 @@ pub unsafe extern "C" fn authz_device_new(
         return -1;
     };
 
-    (*device_c).start.id_u = id_u;
-    (*device_c).start.g_w = *g_w;
-    (*device_c).start.loc_w = loc_w;
-
-    0
+    match ZeroTouchDevice::new(id_u, *g_w, loc_w) {
+        Ok(device) => {
+            (*device_c).start = device;
+            0
+        }
+        Err(_) => -1,
+    }
 }
 
 #[no_mangle]
@@ -45,11 +47,14 @@ pub unsafe extern "C" fn authz_device_prepare_ead_1(
     ead_1_c_out: *mut EADItemC,
 ) -> i8 {
     let crypto = &mut default_crypto();
-    let (device, ead_1) = (*device_c).start.prepare_ead_1(crypto, *secret, ss);
-    (*device_c).wait_ead2 = device;
-    EADItemC::copy_into_c(ead_1, ead_1_c_out);
-
-    0
+    match (*device_c).start.prepare_ead_1(crypto, *secret, ss) {
+        Ok((device, ead_1)) => {
+            (*device_c).wait_ead2 = device;
+            EADItemC::copy_into_c(ead_1, ead_1_c_out);
+            0
+        }
+        Err(_) => -1,
+    }
 }
 
 #[no_mangle]
@@ -58,12 +63,15 @@ pub unsafe extern "C" fn authz_device_process_ead_2(
     device_c: *mut EadAuthzDevice,
     ead_2_c: *mut EADItemC,
     cred_v: CredentialRPK,
+    message_1: *const EdhocMessageBuffer,
 ) -> i8 {
     let crypto = &mut default_crypto();
-    match (*device_c)
-        .wait_ead2
-        .process_ead_2(crypto, (*ead_2_c).to_rust(), cred_v.value.as_slice())
-    {
+    match (*device_c).wait_ead2.process_ead_2(
+        crypto,
+        (*ead_2_c).to_rust(),
+        cred_v.value.as_slice(),
+        &*message_1,
+    ) {
         Ok(device) => {
             (*device_c).done = device;
             0
@@ -22,17 +22,9 @@ pub struct EdhocInitiator {
 #[no_mangle]
 pub unsafe extern "C" fn initiator_new(initiator: *mut EdhocInitiator) -> i8 {
     // we only support a single cipher suite which is already CBOR-encoded
-    let mut suites_i: BytesSuites = [0x0; SUITES_LEN];
-    let suites_i_len = EDHOC_SUPPORTED_SUITES.len();
-    suites_i[0..suites_i_len].copy_from_slice(&EDHOC_SUPPORTED_SUITES[..]);
     let (x, g_x) = default_crypto().p256_generate_key_pair();
 
-    (*initiator).start = InitiatorStart {
-        x,
-        g_x,
-        suites_i,
-        suites_i_len,
-    };
+    (*initiator).start = InitiatorStart::with_supported_suites(x, g_x);
 
     0
 }
@@ -52,7 +44,10 @@ pub unsafe extern "C" fn initiator_prepare_message_1(
     let crypto = &mut default_crypto();
 
     let c_i = if c_i.is_null() {
-        generate_connection_identifier_cbor(crypto)
+        match generate_connection_identifier_cbor(crypto) {
+            Ok(c_i) => c_i,
+            Err(err) => return err as i8,
+        }
     } else {
         *c_i
     };
@@ -105,6 +100,8 @@ pub unsafe extern "C" fn initiator_parse_message_2(
     let state = core::ptr::read(&(*initiator_c).wait_m2);
 
     let result = match i_parse_message_2(&state, crypto, &(*message_2)) {
+        // a single EAD_2 item is handed out, and kept for verifying MAC_2
+        Ok((_state, _c_r, _id_cred_r, ead_2)) if ead_2.len() > 1 => EDHOCError::EADError as i8,
         Ok((state, c_r, id_cred_r, ead_2)) => {
             ProcessingM2C::copy_into_c(state, &mut (*initiator_c).processing_m2);
             *c_r_out = c_r;
@@ -116,7 +113,7 @@ pub unsafe extern "C" fn initiator_parse_message_2(
             };
             *valid_cred_r_out = valid_cred_r;
 
-            if let Some(ead_2) = ead_2 {
+            if let Some(ead_2) = ead_2.first().cloned() {
                 EADItemC::copy_into_c(ead_2, ead_2_c_out);
                 (*initiator_c).processing_m2.ead_2 = ead_2_c_out;
             }
@@ -170,8 +167,6 @@ pub unsafe extern "C" fn initiator_prepare_message_3(
     }
     let crypto = &mut default_crypto();
 
-    let mut state = core::ptr::read(&(*initiator_c).processed_m2);
-
     let ead_3 = if ead_3_c.is_null() {
         None
     } else {
@@ -180,7 +175,7 @@ pub unsafe extern "C" fn initiator_prepare_message_3(
     };
 
     match i_prepare_message_3(
-        &mut state,
+        &mut (*initiator_c).processed_m2,
         crypto,
         *(*initiator_c).cred_i,
         cred_transfer,
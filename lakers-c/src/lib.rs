@@ -23,7 +23,7 @@ static HEAP: Heap = Heap::empty();
 #[derive(Default, Clone, Debug)]
 #[repr(C)]
 pub struct EADItemC {
-    pub label: u8,
+    pub label: u16,
     pub is_critical: bool,
     pub value: EdhocMessageBuffer,
 }
@@ -51,7 +51,7 @@ impl EADItemC {
 #[derive(Debug)]
 #[repr(C)]
 pub struct ProcessingM2C {
-    pub mac_2: BytesMac2,
+    pub mac_2: SignatureOrMac,
     pub prk_2e: BytesHashLen,
     pub th_2: BytesHashLen,
     pub x: BytesP256ElemLen,
@@ -59,6 +59,8 @@ pub struct ProcessingM2C {
     pub plaintext_2: EdhocMessageBuffer,
     pub c_r: u8,
     pub ead_2: *mut EADItemC,
+    pub selected_suite: u8,
+    pub method: Method,
 }
 
 impl Default for ProcessingM2C {
@@ -72,26 +74,30 @@ impl Default for ProcessingM2C {
             plaintext_2: Default::default(),
             c_r: Default::default(),
             ead_2: core::ptr::null_mut(),
+            selected_suite: Default::default(),
+            method: Default::default(),
         }
     }
 }
 
 impl ProcessingM2C {
     pub fn to_rust(&self) -> ProcessingM2 {
-        ProcessingM2 {
-            mac_2: self.mac_2,
-            prk_2e: self.prk_2e,
-            th_2: self.th_2,
-            x: self.x,
-            g_y: self.g_y,
-            plaintext_2: self.plaintext_2,
-            c_r: self.c_r,
-            ead_2: if self.ead_2.is_null() {
+        ProcessingM2::new(
+            self.mac_2,
+            self.prk_2e,
+            self.th_2,
+            self.x,
+            self.g_y,
+            self.plaintext_2,
+            self.c_r,
+            EadItems::from(if self.ead_2.is_null() {
                 None
             } else {
                 Some(unsafe { (*self.ead_2).to_rust() })
-            },
-        }
+            }),
+            self.selected_suite,
+            self.method,
+        )
     }
 
     /// note that it is a shallow copy (ead_2 is handled separately by the caller)
@@ -107,6 +113,8 @@ impl ProcessingM2C {
         (*processing_m2_c).g_y = processing_m2.g_y;
         (*processing_m2_c).plaintext_2 = processing_m2.plaintext_2;
         (*processing_m2_c).c_r = processing_m2.c_r;
+        (*processing_m2_c).selected_suite = processing_m2.selected_suite;
+        (*processing_m2_c).method = processing_m2.method;
     }
 }
 
@@ -117,7 +125,10 @@ pub unsafe extern "C" fn credential_rpk_new(
     value_len: usize,
 ) -> i8 {
     let value = core::slice::from_raw_parts(value, value_len);
-    match CredentialRPK::new(EdhocMessageBuffer::new_from_slice(value).unwrap()) {
+    let Ok(value) = EdhocMessageBuffer::new_from_slice(value) else {
+        return -1;
+    };
+    match CredentialRPK::new(value) {
         Ok(cred_rpk) => {
             *cred = cred_rpk;
             0
@@ -1,6 +1,7 @@
 use lakers::*;
 use lakers_ead::*;
 use pyo3::{
+    exceptions::PyValueError,
     prelude::*,
     types::{PyBytes, PyString},
 };
@@ -8,7 +9,7 @@ use pyo3::{
 #[pyclass(name = "AuthzAutenticator")]
 pub struct PyAuthzAutenticator {
     authenticator: ZeroTouchAuthenticator,
-    authenticator_wait: ZeroTouchAuthenticatorWaitVoucherResp,
+    authenticator_wait: Option<ZeroTouchAuthenticatorWaitVoucherResp>,
 }
 
 #[pymethods]
@@ -17,7 +18,7 @@ impl PyAuthzAutenticator {
     fn new() -> Self {
         Self {
             authenticator: ZeroTouchAuthenticator::default(),
-            authenticator_wait: ZeroTouchAuthenticatorWaitVoucherResp::default(),
+            authenticator_wait: None,
         }
     }
 
@@ -30,7 +31,7 @@ impl PyAuthzAutenticator {
         let message_1 = EdhocMessageBuffer::new_from_slice(message_1.as_slice())?;
         let (state, loc_w, voucher_request) =
             self.authenticator.process_ead_1(&ead_1, &message_1)?;
-        self.authenticator_wait = state;
+        self.authenticator_wait = Some(state);
         let loc_w = std::str::from_utf8(loc_w.as_slice()).unwrap();
         Ok((
             PyString::new(py, loc_w),
@@ -39,7 +40,11 @@ impl PyAuthzAutenticator {
     }
 
     pub fn prepare_ead_2(&self, voucher_response: Vec<u8>) -> PyResult<EADItem> {
-        let voucher_response = EdhocMessageBuffer::new_from_slice(voucher_response.as_slice())?;
-        Ok(self.authenticator_wait.prepare_ead_2(&voucher_response)?)
+        let Some(authenticator_wait) = self.authenticator_wait.as_ref() else {
+            return Err(PyValueError::new_err("EAD_1 was not processed"));
+        };
+        let voucher_response = VoucherResponse::decode(voucher_response.as_slice())?;
+        let (ead_2, _opaque_state) = authenticator_wait.prepare_ead_2(&voucher_response)?;
+        Ok(ead_2)
     }
 }
@@ -1,7 +1,16 @@
 use lakers::*;
 use lakers_crypto::default_crypto;
 use lakers_ead::*;
-use pyo3::{exceptions::PyBaseException, prelude::*, types::PyBytes};
+use pyo3::{
+    exceptions::{PyBaseException, PyValueError},
+    prelude::*,
+    types::PyBytes,
+};
+
+/// Reports a constructor argument that the device cannot use as a `ValueError`
+fn invalid_parameter(error: ZeroTouchError) -> PyErr {
+    PyValueError::new_err(format!("{:?}", error))
+}
 
 #[pyclass(name = "AuthzDevice")]
 pub struct PyAuthzDevice {
@@ -13,20 +22,19 @@ pub struct PyAuthzDevice {
 #[pymethods]
 impl PyAuthzDevice {
     #[new]
-    fn new(id_u: Vec<u8>, g_w: Vec<u8>, loc_w: &str) -> Self {
-        let id_u = EdhocMessageBuffer::new_from_slice(id_u.as_slice()).unwrap();
-        let loc_w = EdhocMessageBuffer::new_from_slice(loc_w.as_bytes()).unwrap();
+    fn new(id_u: Vec<u8>, g_w: Vec<u8>, loc_w: &str) -> PyResult<Self> {
+        let id_u = EdhocMessageBuffer::new_from_slice(id_u.as_slice())
+            .map_err(|_| invalid_parameter(ZeroTouchError::IdUTooLong))?;
+        let loc_w = EdhocMessageBuffer::new_from_slice(loc_w.as_bytes())
+            .map_err(|_| invalid_parameter(ZeroTouchError::EADTooLong))?;
         let mut g_w_arr = BytesP256ElemLen::default();
         g_w_arr.copy_from_slice(&g_w[..]);
-        Self {
-            device: ZeroTouchDevice {
-                id_u,
-                g_w: g_w_arr,
-                loc_w,
-            },
+        let device = ZeroTouchDevice::new(id_u, g_w_arr, loc_w).map_err(invalid_parameter)?;
+        Ok(Self {
+            device,
             device_wait: ZeroTouchDeviceWaitEAD2::default(),
             device_done: ZeroTouchDeviceDone::default(),
-        }
+        })
     }
 
     pub fn prepare_ead_1(&mut self, secret: Vec<u8>, ss: u8) -> PyResult<EADItem> {
@@ -34,15 +42,22 @@ impl PyAuthzDevice {
         secret_arr.copy_from_slice(&secret[..]);
         let (device_wait, ead_1) = self
             .device
-            .prepare_ead_1(&mut default_crypto(), secret_arr, ss);
+            .prepare_ead_1(&mut default_crypto(), secret_arr, ss)
+            .map_err(|error| PyBaseException::new_err(error as i8))?;
         self.device_wait = device_wait;
         Ok(ead_1)
     }
 
-    pub fn process_ead_2(&mut self, ead_2: EADItem, cred_v: &[u8]) -> PyResult<bool> {
+    pub fn process_ead_2(
+        &mut self,
+        ead_2: EADItem,
+        cred_v: &[u8],
+        message_1: Vec<u8>,
+    ) -> PyResult<bool> {
+        let message_1 = EdhocMessageBuffer::new_from_slice(message_1.as_slice())?;
         match self
             .device_wait
-            .process_ead_2(&mut default_crypto(), ead_2, cred_v)
+            .process_ead_2(&mut default_crypto(), ead_2, cred_v, &message_1)
         {
             Ok(device_done) => {
                 self.device_done = device_done;
@@ -52,12 +67,6 @@ impl PyAuthzDevice {
         }
     }
 
-    pub fn set_h_message_1(&mut self, h_message_1: Vec<u8>) {
-        let mut h_message_1_arr = BytesHashLen::default();
-        h_message_1_arr.copy_from_slice(&h_message_1[..]);
-        self.device_wait.set_h_message_1(h_message_1_arr);
-    }
-
     pub fn get_g_w<'a>(&self, py: Python<'a>) -> PyResult<&'a PyBytes> {
         Ok(PyBytes::new(py, &self.device.g_w[..]))
     }
@@ -31,7 +31,9 @@ impl PyAuthzEnrollmentServer {
             .server
             .handle_voucher_request(&mut default_crypto(), &vreq)
         {
-            Ok(voucher_response) => Ok(PyBytes::new(py, voucher_response.as_slice())),
+            Ok((voucher_response, _opaque_state)) => {
+                Ok(PyBytes::new(py, voucher_response.as_slice()))
+            }
             Err(error) => Err(error.into()),
         }
     }
@@ -1,6 +1,7 @@
+use super::single_ead_item;
 use lakers::*;
 use lakers_crypto::{default_crypto, CryptoTrait};
-use pyo3::{prelude::*, types::PyBytes};
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyBytes};
 
 #[pyclass(name = "EdhocInitiator")]
 pub struct PyEdhocInitiator {
@@ -8,7 +9,7 @@ pub struct PyEdhocInitiator {
     start: InitiatorStart,
     wait_m2: WaitM2,
     processing_m2: ProcessingM2,
-    processed_m2: ProcessedM2,
+    processed_m2: Option<ProcessedM2>,
     completed: Completed,
 }
 
@@ -17,22 +18,14 @@ impl PyEdhocInitiator {
     #[new]
     fn new() -> Self {
         // we only support a single cipher suite which is already CBOR-encoded
-        let mut suites_i: BytesSuites = [0x0; SUITES_LEN];
-        let suites_i_len = EDHOC_SUPPORTED_SUITES.len();
-        suites_i[0..suites_i_len].copy_from_slice(&EDHOC_SUPPORTED_SUITES[..]);
         let (x, g_x) = default_crypto().p256_generate_key_pair();
 
         Self {
             cred_i: None,
-            start: InitiatorStart {
-                x,
-                g_x,
-                suites_i,
-                suites_i_len,
-            },
+            start: InitiatorStart::with_supported_suites(x, g_x),
             wait_m2: WaitM2::default(),
             processing_m2: ProcessingM2::default(),
-            processed_m2: ProcessedM2::default(),
+            processed_m2: None,
             completed: Completed::default(),
         }
     }
@@ -45,7 +38,7 @@ impl PyEdhocInitiator {
     ) -> PyResult<&'a PyBytes> {
         let c_i = match c_i {
             Some(c_i) => c_i,
-            None => generate_connection_identifier_cbor(&mut default_crypto()),
+            None => generate_connection_identifier_cbor(&mut default_crypto())?,
         };
 
         match i_prepare_message_1(&self.start, &mut default_crypto(), c_i, &ead_1) {
@@ -65,6 +58,7 @@ impl PyEdhocInitiator {
 
         match i_parse_message_2(&self.wait_m2, &mut default_crypto(), &message_2) {
             Ok((state, c_r, id_cred_r, ead_2)) => {
+                let ead_2 = single_ead_item(&ead_2)?;
                 self.processing_m2 = state;
                 let id_cred_r = if id_cred_r.reference_only() {
                     Vec::from([id_cred_r.kid])
@@ -83,22 +77,17 @@ impl PyEdhocInitiator {
         cred_i: Vec<u8>,
         valid_cred_r: Vec<u8>,
     ) -> PyResult<()> {
-        let cred_i =
-            CredentialRPK::new(EdhocMessageBuffer::new_from_slice(&cred_i.as_slice()).unwrap())?;
-        let valid_cred_r = CredentialRPK::new(
-            EdhocMessageBuffer::new_from_slice(&valid_cred_r.as_slice()).unwrap(),
-        )?;
-
-        match i_verify_message_2(
-            &self.processing_m2,
-            &mut default_crypto(),
-            valid_cred_r,
-            i.as_slice()
-                .try_into()
-                .expect("Wrong length of initiator private key"),
-        ) {
+        let cred_i = CredentialRPK::new(EdhocMessageBuffer::new_from_slice(&cred_i.as_slice())?)?;
+        let valid_cred_r = CredentialRPK::new(EdhocMessageBuffer::new_from_slice(
+            &valid_cred_r.as_slice(),
+        )?)?;
+
+        let Ok(i) = i.as_slice().try_into() else {
+            return Err(EDHOCError::InvalidKey.into());
+        };
+        match i_verify_message_2(&self.processing_m2, &mut default_crypto(), valid_cred_r, i) {
             Ok(state) => {
-                self.processed_m2 = state;
+                self.processed_m2 = Some(state);
                 self.cred_i = Some(cred_i);
                 Ok(())
             }
@@ -112,8 +101,11 @@ impl PyEdhocInitiator {
         cred_transfer: CredentialTransfer,
         ead_3: Option<EADItem>,
     ) -> PyResult<(&'a PyBytes, [u8; SHA256_DIGEST_LEN])> {
+        let Some(processed_m2) = self.processed_m2.as_mut() else {
+            return Err(PyValueError::new_err("message_2 was not verified"));
+        };
         match i_prepare_message_3(
-            &mut self.processed_m2,
+            processed_m2,
             &mut default_crypto(),
             self.cred_i.unwrap(),
             cred_transfer,
@@ -134,6 +126,10 @@ impl PyEdhocInitiator {
         context: Vec<u8>,
         length: usize,
     ) -> PyResult<&'a PyBytes> {
+        check_parameter_len(&context, MAX_EXPORTER_CONTEXT_LEN)?;
+        if length > MAX_EXPORTER_OUTPUT_LEN {
+            return Err(EDHOCError::InvalidParameter.into());
+        }
         let mut context_buf: BytesMaxContextBuffer = [0x00u8; MAX_KDF_CONTEXT_LEN];
         context_buf[..context.len()].copy_from_slice(context.as_slice());
 
@@ -153,6 +149,7 @@ impl PyEdhocInitiator {
         py: Python<'a>,
         context: Vec<u8>,
     ) -> PyResult<&'a PyBytes> {
+        check_parameter_len(&context, MAX_EXPORTER_CONTEXT_LEN)?;
         let mut context_buf = [0x00u8; MAX_KDF_CONTEXT_LEN];
         context_buf[..context.len()].copy_from_slice(context.as_slice());
 
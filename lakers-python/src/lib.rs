@@ -22,9 +22,9 @@ pub fn py_credential_check_or_fetch<'a>(
     cred_expected: Option<Vec<u8>>,
 ) -> PyResult<&'a PyBytes> {
     let cred_expected = if let Some(cred_expected) = cred_expected {
-        Some(CredentialRPK::new(
-            EdhocMessageBuffer::new_from_slice(cred_expected.as_slice()).unwrap(),
-        )?)
+        Some(CredentialRPK::new(EdhocMessageBuffer::new_from_slice(
+            cred_expected.as_slice(),
+        )?)?)
     } else {
         None
     };
@@ -35,19 +35,31 @@ pub fn py_credential_check_or_fetch<'a>(
                 kid: id_cred_received[0],
                 value: Default::default(),
                 public_key: Default::default(),
+                has_kid: true,
             },
         )?
     } else {
         credential_check_or_fetch(
             cred_expected,
-            CredentialRPK::new(
-                EdhocMessageBuffer::new_from_slice(id_cred_received.as_slice()).unwrap(),
-            )?,
+            CredentialRPK::new(EdhocMessageBuffer::new_from_slice(
+                id_cred_received.as_slice(),
+            )?)?,
         )?
     };
     Ok(PyBytes::new(py, valid_cred.value.as_slice()))
 }
 
+/// The EAD item of a received message, for the Python API that hands out at most one
+///
+/// Fails with [EDHOCError::EADError] for more than one item, instead of dropping the others, which
+/// may be critical ones that have to be processed.
+fn single_ead_item(ead: &EadItems) -> Result<Option<EADItem>, EDHOCError> {
+    if ead.len() > 1 {
+        return Err(EDHOCError::EADError);
+    }
+    Ok(ead.first().cloned())
+}
+
 /// this function is useful to test the python bindings
 #[pyfunction]
 fn p256_generate_key_pair<'a>(py: Python<'a>) -> PyResult<(&'a PyBytes, &'a PyBytes)> {
@@ -69,6 +81,8 @@ fn lakers_python(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<responder::PyEdhocResponder>()?;
     m.add_class::<lakers::CredentialTransfer>()?;
     m.add_class::<lakers::EADItem>()?;
+    m.add_class::<lakers::CredentialRPK>()?;
+    m.add("EdhocError", _py.get_type::<lakers::EdhocError>())?;
     // ead-authz items
     m.add_class::<ead_authz::PyAuthzDevice>()?;
     m.add_class::<ead_authz::PyAuthzAutenticator>()?;
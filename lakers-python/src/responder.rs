@@ -1,3 +1,4 @@
+use super::single_ead_item;
 use lakers::*;
 use lakers_crypto::{default_crypto, CryptoTrait};
 use pyo3::{prelude::*, types::PyBytes};
@@ -6,7 +7,6 @@ use pyo3::{prelude::*, types::PyBytes};
 pub struct PyEdhocResponder {
     r: Vec<u8>,
     cred_r: CredentialRPK,
-    start: ResponderStart,
     processing_m1: ProcessingM1,
     wait_m3: WaitM3,
     processing_m3: ProcessingM3,
@@ -16,26 +16,28 @@ pub struct PyEdhocResponder {
 #[pymethods]
 impl PyEdhocResponder {
     #[new]
-    fn new(r: Vec<u8>, cred_r: Vec<u8>) -> Self {
-        let (y, g_y) = default_crypto().p256_generate_key_pair();
-
-        Self {
+    fn new(r: Vec<u8>, cred_r: Vec<u8>) -> PyResult<Self> {
+        if r.len() != P256_ELEM_LEN {
+            return Err(EDHOCError::InvalidKey.into());
+        }
+        Ok(Self {
             r,
-            cred_r: CredentialRPK::new(
-                EdhocMessageBuffer::new_from_slice(&cred_r.as_slice()).unwrap(),
-            )
-            .unwrap(),
-            start: ResponderStart { y, g_y },
+            cred_r: CredentialRPK::new(EdhocMessageBuffer::new_from_slice(&cred_r.as_slice())?)?,
             processing_m1: ProcessingM1::default(),
             wait_m3: WaitM3::default(),
             processing_m3: ProcessingM3::default(),
             completed: Completed::default(),
-        }
+        })
     }
 
     fn process_message_1(&mut self, message_1: Vec<u8>) -> PyResult<Option<EADItem>> {
+        prevalidate_message_1(message_1.as_slice())?;
         let message_1 = EdhocMessageBuffer::new_from_slice(message_1.as_slice())?;
-        let (state, ead_1) = r_process_message_1(&self.start, &mut default_crypto(), &message_1)?;
+        let mut crypto = default_crypto();
+        let (y, g_y) = crypto.p256_generate_key_pair();
+        let (state, ead_1) =
+            r_process_message_1(&ResponderStart::new(y, g_y), &mut crypto, &message_1)?;
+        let ead_1 = single_ead_item(&ead_1)?;
         self.processing_m1 = state;
 
         Ok(ead_1)
@@ -50,7 +52,7 @@ impl PyEdhocResponder {
     ) -> PyResult<&'a PyBytes> {
         let c_r = match c_r {
             Some(c_r) => c_r,
-            None => generate_connection_identifier_cbor(&mut default_crypto()),
+            None => generate_connection_identifier_cbor(&mut default_crypto())?,
         };
         let mut r = BytesP256ElemLen::default();
         r.copy_from_slice(self.r.as_slice());
@@ -76,6 +78,7 @@ impl PyEdhocResponder {
         let message_3 = EdhocMessageBuffer::new_from_slice(message_3.as_slice())?;
         match r_parse_message_3(&mut self.wait_m3, &mut default_crypto(), &message_3) {
             Ok((state, id_cred_i, ead_3)) => {
+                let ead_3 = single_ead_item(&ead_3)?;
                 self.processing_m3 = state;
                 let id_cred_i = if id_cred_i.reference_only() {
                     Vec::from([id_cred_i.kid])
@@ -89,9 +92,9 @@ impl PyEdhocResponder {
     }
 
     pub fn verify_message_3(&mut self, valid_cred_i: Vec<u8>) -> PyResult<[u8; SHA256_DIGEST_LEN]> {
-        let valid_cred_i = CredentialRPK::new(
-            EdhocMessageBuffer::new_from_slice(&valid_cred_i.as_slice()).unwrap(),
-        )?;
+        let valid_cred_i = CredentialRPK::new(EdhocMessageBuffer::new_from_slice(
+            &valid_cred_i.as_slice(),
+        )?)?;
         match r_verify_message_3(&mut self.processing_m3, &mut default_crypto(), valid_cred_i) {
             Ok((state, prk_out)) => {
                 self.completed = state;
@@ -108,6 +111,10 @@ impl PyEdhocResponder {
         context: Vec<u8>,
         length: usize,
     ) -> PyResult<&'a PyBytes> {
+        check_parameter_len(&context, MAX_EXPORTER_CONTEXT_LEN)?;
+        if length > MAX_EXPORTER_OUTPUT_LEN {
+            return Err(EDHOCError::InvalidParameter.into());
+        }
         let mut context_buf: BytesMaxContextBuffer = [0x00u8; MAX_KDF_CONTEXT_LEN];
         context_buf[..context.len()].copy_from_slice(context.as_slice());
 
@@ -127,6 +134,7 @@ impl PyEdhocResponder {
         py: Python<'a>,
         context: Vec<u8>,
     ) -> PyResult<&'a PyBytes> {
+        check_parameter_len(&context, MAX_EXPORTER_CONTEXT_LEN)?;
         let mut context_buf = [0x00u8; MAX_KDF_CONTEXT_LEN];
         context_buf[..context.len()].copy_from_slice(context.as_slice());
 
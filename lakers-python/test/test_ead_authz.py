@@ -35,6 +35,11 @@ def test_authenticator_and_server():
     assert ead_2.is_critical() == True
     assert ead_2.value() == EAD_2_VALUE
 
+def test_authenticator_without_ead_1():
+    authenticator = lakers.AuthzAutenticator()
+    with pytest.raises(ValueError):
+        authenticator.prepare_ead_2(VOUCHER_RESPONSE)
+
 def test_authenticator_and_server():
     VOUCHER_REQUEST_TV = bytes.fromhex("8158520382060258208af6f430ebe18d34184017a9a11bf511c8dff8f834730b96c1b7c8dbca2fc3b6370158287818636f61703a2f2f656e726f6c6c6d656e742e7365727665724dda9784962883c96ed01ff122c3")
     enrollment_server = lakers.AuthzServerUserAcl(W, CRED_V)
@@ -66,7 +71,6 @@ def test_handshake_with_authz():
         initiator.selected_cipher_suite(),
     )
     message_1 = initiator.prepare_message_1(c_i=None, ead_1=ead_1)
-    device.set_h_message_1(initiator.get_h_message_1())
 
     # responder
     ead_1 = responder.process_message_1(message_1)
@@ -79,7 +83,7 @@ def test_handshake_with_authz():
     # initiator
     c_r, id_cred_r, ead_2 = initiator.parse_message_2(message_2)
     valid_cred_r = lakers.credential_check_or_fetch(id_cred_r, CRED_V)
-    assert device.process_ead_2(ead_2, CRED_V) # voucher is valid!
+    assert device.process_ead_2(ead_2, CRED_V, message_1) # voucher is valid!
     initiator.verify_message_2(I, CRED_I, valid_cred_r)
     message_3, i_prk_out = initiator.prepare_message_3(lakers.CredentialTransfer.ByReference, None)
     assert type(message_3) == bytes
@@ -91,3 +95,7 @@ def test_handshake_with_authz():
     r_prk_out = responder.verify_message_3(valid_cred_i)
 
     assert i_prk_out == r_prk_out
+
+def test_device_loc_w_too_long():
+    with pytest.raises(ValueError, match="EADTooLong"):
+        lakers.AuthzDevice(ID_U, G_W, "coap://" + "a" * 113)
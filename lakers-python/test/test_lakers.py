@@ -67,9 +67,66 @@ def test_edhoc_error():
     with pytest.raises(ValueError) as err:
         _ = responder.process_message_1([1, 2, 3])
     assert str(err.value) == "EDHOCError::ParsingError"
+    assert isinstance(err.value, lakers.EdhocError)
+    assert err.value.code == 5
+    assert err.value.name == "ParsingError"
+
+def test_several_ead_items():
+    initiator = lakers.EdhocInitiator()
+    responder = lakers.EdhocResponder(R, CRED_R)
+    message_1 = initiator.prepare_message_1(c_i=None, ead_1=None)
+    # two EAD_1 items, of which the second is critical
+    with pytest.raises(lakers.EdhocError) as err:
+        _ = responder.process_message_1(message_1 + bytes([0x01, 0x41, 0xcc, 0x22, 0x41, 0xdd]))
+    assert err.value.name == "EADError"
 
 def test_buffer_error():
     initiator = lakers.EdhocInitiator()
     with pytest.raises(ValueError) as err:
         _ = initiator.parse_message_2([1] * 1000)
     assert str(err.value) == "MessageBufferError::SliceTooLong"
+
+def test_credential_from_ccs():
+    cred_r = lakers.CredentialRPK.from_ccs(CRED_R)
+    assert cred_r.kid == bytes.fromhex("0a")
+    assert cred_r.public_key == CRED_R[-2 * 32 - 3:-32 - 3]
+    assert cred_r.value == CRED_R
+
+    with pytest.raises(lakers.EdhocError) as err:
+        _ = lakers.CredentialRPK.from_ccs(CRED_R[:-1])
+    assert "malformed CCS" in str(err.value)
+    assert err.value.name == "ParsingError"
+
+def test_credential_build_ccs():
+    # rebuilding CRED_R from its parts gives the same bytes
+    x_y = CRED_R[-2 * 32 - 3:-32 - 3] + CRED_R[-32:]
+    assert lakers.CredentialRPK.build_ccs("", bytes.fromhex("0a"), x_y).value == CRED_R
+
+    with pytest.raises(ValueError) as err:
+        _ = lakers.CredentialRPK.build_ccs("", bytes.fromhex("0a0b"), x_y)
+    assert str(err.value) == "kid must be a single byte, got 2 bytes"
+    with pytest.raises(ValueError) as err:
+        _ = lakers.CredentialRPK.build_ccs("", bytes.fromhex("0a"), x_y[:-1])
+    assert "got 63 bytes" in str(err.value)
+
+def test_handshake_with_built_credential():
+    r, g_r = lakers.p256_generate_key_pair()
+    cred_r = lakers.CredentialRPK.build_ccs("responder.example", bytes.fromhex("0b"), g_r)
+    assert cred_r.kid == bytes.fromhex("0b")
+    assert cred_r.public_key == g_r
+
+    initiator = lakers.EdhocInitiator()
+    responder = lakers.EdhocResponder(r, cred_r.value)
+
+    message_1 = initiator.prepare_message_1(c_i=None, ead_1=None)
+    ead_1 = responder.process_message_1(message_1)
+    message_2 = responder.prepare_message_2(lakers.CredentialTransfer.ByReference, None, ead_1)
+    _c_r, id_cred_r, _ead_2 = initiator.parse_message_2(message_2)
+    valid_cred_r = lakers.credential_check_or_fetch(id_cred_r, cred_r.value)
+    initiator.verify_message_2(I, CRED_I, valid_cred_r)
+    message_3, i_prk_out = initiator.prepare_message_3(lakers.CredentialTransfer.ByReference, None)
+    id_cred_i, _ead_3 = responder.parse_message_3(message_3)
+    valid_cred_i = lakers.credential_check_or_fetch(id_cred_i, CRED_I)
+    r_prk_out = responder.verify_message_3(valid_cred_i)
+
+    assert i_prk_out == r_prk_out
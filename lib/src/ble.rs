@@ -0,0 +1,288 @@
+//! Transport of EDHOC messages over BLE GATT with the default ATT MTU
+//!
+//! With the default MTU, a notification or write carries at most [GATT_FRAGMENT_LEN] bytes, which
+//! is less than most EDHOC messages. Messages are therefore split into fragments of one header byte
+//! followed by up to [GATT_PAYLOAD_LEN] bytes of payload:
+//!
+//! * The header holds the sequence number of the fragment in its lower 7 bits, and
+//!   [GATT_FINAL_FRAGMENT] if it is the last fragment of the message.
+//! * The payload of the first fragment starts with the total message length (2 bytes, network
+//!   byte order), so that the receiver can reject messages that would not fit before buffering
+//!   them.
+//!
+//! The [GattReassembler] only accepts fragments in order, and never buffers more than
+//! [MAX_MESSAGE_SIZE_LEN] bytes.
+
+use lakers_shared::*;
+
+/// Size of a fragment, which is the usable size of an ATT PDU with the default MTU of 23
+pub const GATT_FRAGMENT_LEN: usize = 20;
+/// Size of the payload of a fragment
+pub const GATT_PAYLOAD_LEN: usize = GATT_FRAGMENT_LEN - 1;
+/// Flag in the fragment header that marks the last fragment of a message
+pub const GATT_FINAL_FRAGMENT: u8 = 0x80;
+const GATT_SEQUENCE_MASK: u8 = 0x7f;
+const GATT_TOTAL_LEN_LEN: usize = 2;
+
+// every sequence number of the longest message has to fit in the header
+const _: () = assert!(
+    MAX_MESSAGE_SIZE_LEN + GATT_TOTAL_LEN_LEN
+        <= (GATT_SEQUENCE_MASK as usize + 1) * GATT_PAYLOAD_LEN
+);
+
+/// Error of the [GattReassembler]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum GattError {
+    /// The fragment is empty, too long, or inconsistent with the announced length
+    InvalidFragment,
+    /// A fragment was skipped; the expected sequence number is given
+    OutOfOrder(u8),
+    /// The message is longer than [MAX_MESSAGE_SIZE_LEN]
+    TooLong,
+}
+
+/// A single fragment, as sent in one GATT notification or write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GattFragment {
+    content: [u8; GATT_FRAGMENT_LEN],
+    len: usize,
+}
+
+impl GattFragment {
+    /// The fragment as it is sent, header included
+    pub fn as_slice(&self) -> &[u8] {
+        &self.content[..self.len]
+    }
+
+    /// Position of the fragment in its message, starting at 0
+    pub fn sequence_number(&self) -> u8 {
+        self.content[0] & GATT_SEQUENCE_MASK
+    }
+
+    /// Whether this is the last fragment of its message
+    pub fn is_final(&self) -> bool {
+        self.content[0] & GATT_FINAL_FRAGMENT != 0
+    }
+}
+
+/// Splits a message into [GattFragment]s, in order when used as an iterator
+///
+/// Single fragments can be produced again with [GattFragmenter::fragment], e.g. when the peer
+/// reports a [GattError::OutOfOrder].
+#[derive(Debug, Clone)]
+pub struct GattFragmenter {
+    message: EdhocMessageBuffer,
+    next: u8,
+}
+
+impl GattFragmenter {
+    /// Starts splitting `message`, which is copied
+    pub fn new(message: &EdhocMessageBuffer) -> Self {
+        GattFragmenter {
+            message: *message,
+            next: 0,
+        }
+    }
+
+    /// Number of fragments the message is split into
+    pub fn fragment_count(&self) -> u8 {
+        (GATT_TOTAL_LEN_LEN + self.message.len).div_ceil(GATT_PAYLOAD_LEN) as u8
+    }
+
+    /// Returns the fragment with the given sequence number, if the message has that many
+    pub fn fragment(&self, sequence_number: u8) -> Option<GattFragment> {
+        if sequence_number >= self.fragment_count() {
+            return None;
+        }
+        let mut fragment = GattFragment {
+            content: [0; GATT_FRAGMENT_LEN],
+            len: 1,
+        };
+        fragment.content[0] = sequence_number;
+        if sequence_number == self.fragment_count() - 1 {
+            fragment.content[0] |= GATT_FINAL_FRAGMENT;
+        }
+
+        let total_len = (self.message.len as u16).to_be_bytes();
+        let prefixed = total_len.iter().chain(self.message.as_slice());
+        for byte in prefixed
+            .skip(sequence_number as usize * GATT_PAYLOAD_LEN)
+            .take(GATT_PAYLOAD_LEN)
+        {
+            fragment.content[fragment.len] = *byte;
+            fragment.len += 1;
+        }
+        Some(fragment)
+    }
+}
+
+impl Iterator for GattFragmenter {
+    type Item = GattFragment;
+
+    fn next(&mut self) -> Option<GattFragment> {
+        let fragment = self.fragment(self.next)?;
+        self.next += 1;
+        Some(fragment)
+    }
+}
+
+/// Reassembles a message from [GattFragment]s received in order
+///
+/// Fragments that were received already (e.g. because the peer re-sent them) are ignored. After
+/// an error other than [GattError::OutOfOrder], the reassembler starts over with the next
+/// message.
+#[derive(Debug, Default)]
+pub struct GattReassembler {
+    message: EdhocMessageBuffer,
+    total_len: usize,
+    next: u8,
+}
+
+impl GattReassembler {
+    /// A reassembler waiting for the first fragment of a message
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Discards a partially received message
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Processes a received fragment, returning the message once its final fragment arrived
+    pub fn receive(&mut self, fragment: &[u8]) -> Result<Option<EdhocMessageBuffer>, GattError> {
+        let result = self.receive_in_order(fragment);
+        if matches!(result, Err(error) if error != GattError::OutOfOrder(self.next)) {
+            self.reset();
+        }
+        result
+    }
+
+    fn receive_in_order(
+        &mut self,
+        fragment: &[u8],
+    ) -> Result<Option<EdhocMessageBuffer>, GattError> {
+        let (&header, payload) = match fragment.split_first() {
+            Some(split) if fragment.len() <= GATT_FRAGMENT_LEN => split,
+            _ => return Err(GattError::InvalidFragment),
+        };
+        let sequence_number = header & GATT_SEQUENCE_MASK;
+        if sequence_number < self.next {
+            return Ok(None);
+        }
+        if sequence_number > self.next {
+            return Err(GattError::OutOfOrder(self.next));
+        }
+
+        let payload = if sequence_number == 0 {
+            let Some((total_len, rest)) = payload.split_first_chunk::<GATT_TOTAL_LEN_LEN>() else {
+                return Err(GattError::InvalidFragment);
+            };
+            self.total_len = u16::from_be_bytes(*total_len) as usize;
+            if self.total_len > MAX_MESSAGE_SIZE_LEN {
+                return Err(GattError::TooLong);
+            }
+            rest
+        } else {
+            payload
+        };
+        if self.message.len + payload.len() > self.total_len {
+            return Err(GattError::InvalidFragment);
+        }
+        self.message
+            .extend_from_slice(payload)
+            .map_err(|_| GattError::TooLong)?;
+
+        let complete = self.message.len == self.total_len;
+        if complete != (header & GATT_FINAL_FRAGMENT != 0) {
+            return Err(GattError::InvalidFragment);
+        }
+        if complete {
+            let message = self.message;
+            self.reset();
+            Ok(Some(message))
+        } else {
+            self.next += 1;
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn message(len: usize) -> EdhocMessageBuffer {
+        let mut message = EdhocMessageBuffer::new();
+        for i in 0..len {
+            message.push(i as u8).unwrap();
+        }
+        message
+    }
+
+    #[test]
+    fn test_fragment_layout() {
+        let fragments: [GattFragment; 2] = {
+            let mut fragmenter = GattFragmenter::new(&message(30));
+            assert_eq!(fragmenter.fragment_count(), 2);
+            [fragmenter.next().unwrap(), fragmenter.next().unwrap()]
+        };
+        assert_eq!(fragments[0].as_slice().len(), GATT_FRAGMENT_LEN);
+        assert_eq!(fragments[0].as_slice()[..4], [0x00, 0x00, 30, 0x00]);
+        assert!(!fragments[0].is_final());
+        assert_eq!(fragments[1].sequence_number(), 1);
+        assert!(fragments[1].is_final());
+        assert_eq!(
+            fragments[1].as_slice(),
+            &[0x81, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29]
+        );
+
+        // the longest message still fits
+        let mut reassembler = GattReassembler::new();
+        let mut result = None;
+        for fragment in GattFragmenter::new(&message(MAX_MESSAGE_SIZE_LEN)) {
+            result = reassembler.receive(fragment.as_slice()).unwrap();
+        }
+        assert_eq!(result, Some(message(MAX_MESSAGE_SIZE_LEN)));
+    }
+
+    #[test]
+    fn test_malicious_total_len() {
+        let mut reassembler = GattReassembler::new();
+        let total_len = (MAX_MESSAGE_SIZE_LEN as u16 + 1).to_be_bytes();
+        assert_eq!(
+            reassembler.receive(&[0x00, total_len[0], total_len[1], 0xaa]),
+            Err(GattError::TooLong)
+        );
+        assert_eq!(
+            reassembler.receive(&[0x00, 0xff, 0xff, 0xaa]),
+            Err(GattError::TooLong)
+        );
+
+        // a fragment with more data than announced
+        assert_eq!(
+            reassembler.receive(&[0x80, 0x00, 0x01, 0xaa, 0xbb]),
+            Err(GattError::InvalidFragment)
+        );
+        // the final fragment arrives early
+        assert_eq!(
+            reassembler.receive(&[0x80, 0x00, 0x03, 0xaa, 0xbb]),
+            Err(GattError::InvalidFragment)
+        );
+        // fragments longer than the MTU allows
+        assert_eq!(
+            reassembler.receive(&[0x00; GATT_FRAGMENT_LEN + 1]),
+            Err(GattError::InvalidFragment)
+        );
+        assert_eq!(reassembler.receive(&[]), Err(GattError::InvalidFragment));
+
+        // after all this, a proper message is still received
+        assert_eq!(
+            reassembler.receive(&[0x80, 0x00, 0x02, 0xaa, 0xbb]),
+            Ok(Some(
+                EdhocMessageBuffer::new_from_slice(&[0xaa, 0xbb]).unwrap()
+            ))
+        );
+    }
+}
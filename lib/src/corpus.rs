@@ -0,0 +1,242 @@
+//! Wire-format conformance corpus
+//!
+//! Runs deterministic handshakes (fixed ephemeral keys and connection identifiers) for every
+//! supported configuration and pins the messages and intermediate values in versioned files under
+//! `testdata/corpus/`, so that refactorings cannot change the wire format unnoticed.
+//!
+//! The corpus is only ever written on request, and the result is to be reviewed like code:
+//!
+//! ```text
+//! cargo test -p lakers corpus::generate_corpus -- --ignored
+//! ```
+//!
+//! A change that is meant to alter the wire format gets a new corpus version rather than an
+//! overwritten one.
+use super::test_vectors_common::*;
+use super::*;
+use hexlit::hex;
+use lakers_crypto::default_crypto;
+
+const CORPUS_VERSION: u32 = 1;
+
+// ephemeral keys and connection identifiers of the traces (RFC 9529, Section 3)
+const X: BytesP256ElemLen =
+    hex!("368ec1f69aeb659ba37d5a8d45b21bdc0299dceaa8ef235f3ca42ce3530f9525");
+const G_X: BytesP256ElemLen =
+    hex!("8af6f430ebe18d34184017a9a11bf511c8dff8f834730b96c1b7c8dbca2fc3b6");
+const Y: BytesP256ElemLen =
+    hex!("e2f4126777205e853b437d6eaca1e1f753cdcc3e2c69fa884b0a1a640977e418");
+const G_Y: BytesP256ElemLen =
+    hex!("419701d7f00a26c2dc587a36dd752549f33763c893422c8ea0f955a13a4ff5d5");
+const SUITES_I: &[u8] = &[0x06, 0x02];
+const C_I: u8 = 0x37;
+const C_R: u8 = 0x27;
+
+struct Configuration {
+    name: String,
+    description: String,
+    cred_transfer_i: CredentialTransfer,
+    cred_transfer_r: CredentialTransfer,
+    with_ead: bool,
+}
+
+fn configurations() -> Vec<Configuration> {
+    // Auto is not listed, as it only picks one of these
+    let transfers = [
+        ("ref", "by reference", CredentialTransfer::ByReference),
+        (
+            "map",
+            "by reference as {4: kid}",
+            CredentialTransfer::ByReferenceFullMap,
+        ),
+        ("val", "by value", CredentialTransfer::ByValue),
+    ];
+    let mut configurations = Vec::new();
+    for with_ead in [false, true] {
+        // CRED_I of the test vectors is too large to be sent by value: TH_4 would not fit into
+        // the hash input buffer
+        for (name_i, description_i, cred_transfer_i) in &transfers[..2] {
+            for (name_r, description_r, cred_transfer_r) in transfers {
+                configurations.push(Configuration {
+                    name: format!(
+                        "m3_s2_i-{name_i}_r-{name_r}{}",
+                        if with_ead { "_ead" } else { "" }
+                    ),
+                    description: format!(
+                        "method 3, suite 2, CRED_I {description_i}, CRED_R {description_r}, {}",
+                        if with_ead {
+                            "with EAD_1, EAD_2 and EAD_3"
+                        } else {
+                            "without EAD"
+                        }
+                    ),
+                    cred_transfer_i: *cred_transfer_i,
+                    cred_transfer_r,
+                    with_ead,
+                });
+            }
+        }
+    }
+    configurations
+}
+
+fn ead_item(label: u16, is_critical: bool) -> Option<EADItem> {
+    Some(EADItem {
+        label,
+        is_critical,
+        // h'cccccc'
+        value: Some(EdhocMessageBuffer::new_from_slice(&hex!("43cccccc")).unwrap()),
+    })
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Runs the handshake of `configuration` and renders what it put on the wire and derived
+fn render(configuration: &Configuration) -> String {
+    let mut crypto = default_crypto();
+    let cred_i = CredentialRPK::new(CRED_I.try_into().unwrap()).unwrap();
+    let cred_r = CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap();
+    let i: BytesP256ElemLen = I.try_into().unwrap();
+    let r: BytesP256ElemLen = R.try_into().unwrap();
+    let (ead_1, ead_2, ead_3) = if configuration.with_ead {
+        (ead_item(1, false), ead_item(2, true), ead_item(300, false))
+    } else {
+        (None, None, None)
+    };
+
+    let (wait_m2, message_1) = i_prepare_message_1(
+        &InitiatorStart::new(X, G_X, SUITES_I).unwrap(),
+        &mut crypto,
+        C_I,
+        &ead_1,
+    )
+    .unwrap();
+    let (processing_m1, _ead_1) =
+        r_process_message_1(&ResponderStart::new(Y, G_Y), &mut crypto, &message_1).unwrap();
+    let (mut wait_m3, message_2) = r_prepare_message_2(
+        &processing_m1,
+        &mut crypto,
+        cred_r,
+        &r,
+        C_R,
+        configuration.cred_transfer_r,
+        &ead_2,
+    )
+    .unwrap();
+    let (processing_m2, _c_r, _id_cred_r, _ead_2) =
+        i_parse_message_2(&wait_m2, &mut crypto, &message_2).unwrap();
+    let mut processed_m2 = i_verify_message_2(&processing_m2, &mut crypto, cred_r, &i).unwrap();
+    let th_3 = processed_m2.th_3;
+    let (i_completed, message_3, i_prk_out) = i_prepare_message_3(
+        &mut processed_m2,
+        &mut crypto,
+        cred_i,
+        configuration.cred_transfer_i,
+        &ead_3,
+    )
+    .unwrap();
+    let (mut processing_m3, _id_cred_i, _ead_3) =
+        r_parse_message_3(&mut wait_m3, &mut crypto, &message_3).unwrap();
+    let (r_completed, r_prk_out) =
+        r_verify_message_3(&mut processing_m3, &mut crypto, cred_i).unwrap();
+    assert_eq!(i_prk_out, r_prk_out);
+    assert_eq!(i_completed.th_4, r_completed.th_4);
+
+    let context: BytesMaxContextBuffer = [0x00; MAX_KDF_CONTEXT_LEN];
+    let oscore_secret = edhoc_exporter(&i_completed, &mut crypto, 0, &context, 0, 16);
+    let oscore_salt = edhoc_exporter(&i_completed, &mut crypto, 1, &context, 0, 8);
+
+    let mut rendered = format!(
+        "# lakers wire-format corpus, version {CORPUS_VERSION}\n# {}\n",
+        configuration.description
+    );
+    for (key, value) in [
+        ("message_1", message_1.as_slice()),
+        ("h_message_1", &wait_m2.h_message_1[..]),
+        ("th_2", &processing_m2.th_2[..]),
+        ("plaintext_2", processing_m2.plaintext_2.as_slice()),
+        ("message_2", message_2.as_slice()),
+        ("th_3", &th_3[..]),
+        ("plaintext_3", processing_m3.plaintext_3.as_slice()),
+        ("message_3", message_3.as_slice()),
+        ("th_4", &i_completed.th_4[..]),
+        ("prk_out", &i_prk_out[..]),
+        ("oscore_secret", &oscore_secret[..16]),
+        ("oscore_salt", &oscore_salt[..8]),
+    ] {
+        rendered.push_str(&format!("{key} = {}\n", to_hex(value)));
+    }
+    rendered
+}
+
+fn corpus_path(configuration: &Configuration) -> std::path::PathBuf {
+    std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("testdata/corpus")
+        .join(format!("v{CORPUS_VERSION}"))
+        .join(format!("{}.txt", configuration.name))
+}
+
+/// Lists the lines that differ, in the style of a unified diff
+fn line_diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut diff = String::new();
+    for line in 0..expected.len().max(actual.len()) {
+        match (expected.get(line), actual.get(line)) {
+            (Some(expected), Some(actual)) if expected == actual => {}
+            (expected, actual) => {
+                if let Some(expected) = expected {
+                    diff.push_str(&format!("-{expected}\n"));
+                }
+                if let Some(actual) = actual {
+                    diff.push_str(&format!("+{actual}\n"));
+                }
+            }
+        }
+    }
+    diff
+}
+
+// some configurations send credentials by value, which do not fit the buffers of limits-small
+#[cfg(not(feature = "limits-small"))]
+#[test]
+fn test_corpus_reproduced() {
+    let mut failures = String::new();
+    for configuration in configurations() {
+        let path = corpus_path(&configuration);
+        let expected = std::fs::read_to_string(&path)
+            .unwrap_or_else(|error| panic!("cannot read {}: {error}", path.display()));
+        let actual = render(&configuration);
+        if expected != actual {
+            failures.push_str(&format!(
+                "--- {}\n+++ current code\n{}",
+                path.display(),
+                line_diff(&expected, &actual)
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "the wire format differs from the committed corpus:\n{failures}"
+    );
+}
+
+#[test]
+fn test_corpus_anchored_to_traces() {
+    // message_1 only depends on values of the traces, so it has to be the one of the traces
+    let configuration = &configurations()[0];
+    let message_1 = format!("message_1 = {MESSAGE_1_TV}\n");
+    assert!(render(configuration).contains(&message_1));
+}
+
+#[test]
+#[ignore = "writes the corpus; run explicitly and review the result"]
+fn generate_corpus() {
+    for configuration in configurations() {
+        let path = corpus_path(&configuration);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, render(&configuration)).unwrap();
+    }
+}
@@ -0,0 +1,217 @@
+//! Fixed-capacity store of the credentials of known peers
+use super::*;
+
+/// Credentials of known peers, looked up by the kid that a peer sends in ID_CRED_X
+///
+/// # Timing
+///
+/// A kid is received before the peer is authenticated. If a lookup takes less time when the kid
+/// is unknown (or when it is found in an early slot), an attacker who can measure response times
+/// can probe kids and learn which identities are provisioned. With the `ct-lookups` feature,
+/// [CredentialStore::lookup] compares against every slot in constant time, so that its duration
+/// only depends on the capacity `N`; [credential_check_or_fetch] then also compares kids and
+/// credentials in constant time.
+///
+/// This does not hide what happens after the lookup: continuing the handshake with a found
+/// credential still takes a different path than rejecting an unknown one. Without the feature,
+/// lookups stop at the first match.
+///
+/// # Examples
+///
+/// ```
+/// use lakers::doctest_helpers::{credential_i, credential_r};
+/// use lakers::*;
+///
+/// # fn main() -> Result<(), EDHOCError> {
+/// let mut store = CredentialStore::<4>::new();
+/// store.insert(credential_i())?;
+/// store.insert(credential_r())?;
+///
+/// // ID_CRED_I as received in message_3, which only references the credential by its kid
+/// let id_cred_i = CredentialRPK::from_kid(0x2b);
+/// let valid_cred_i = store.check_or_fetch(id_cred_i)?;
+/// assert_eq!(valid_cred_i.value, credential_i().value);
+///
+/// assert_eq!(
+///     store.check_or_fetch(CredentialRPK::from_kid(0x99)).unwrap_err(),
+///     EDHOCError::UnknownPeer
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CredentialStore<const N: usize> {
+    credentials: [Option<CredentialRPK>; N],
+}
+
+impl<const N: usize> Default for CredentialStore<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> CredentialStore<N> {
+    /// An empty store
+    pub fn new() -> Self {
+        CredentialStore {
+            credentials: [None; N],
+        }
+    }
+
+    /// Adds a credential, replacing one with the same kid
+    ///
+    /// Fails with [EDHOCError::CredentialCannotBeReferenced] for a credential without kid, and
+    /// with [EDHOCError::InvalidParameter] when all `N` slots are taken.
+    pub fn insert(&mut self, credential: CredentialRPK) -> Result<(), EDHOCError> {
+        let Some(kid) = credential.kid_bytes() else {
+            return Err(EDHOCError::CredentialCannotBeReferenced);
+        };
+        let slot = match self.position(kid) {
+            Some(slot) => slot,
+            None => self
+                .credentials
+                .iter()
+                .position(Option::is_none)
+                .ok_or(EDHOCError::InvalidParameter)?,
+        };
+        self.credentials[slot] = Some(credential);
+        Ok(())
+    }
+
+    /// Removes the credential with the given kid, returning it if it was stored
+    pub fn remove(&mut self, kid: &[u8]) -> Option<CredentialRPK> {
+        let slot = self.position(kid)?;
+        self.credentials[slot].take()
+    }
+
+    /// The credential with the given kid, as the byte string of the COSE_Key (see
+    /// [CredentialRPK::kid_bytes])
+    pub fn lookup(&self, kid: &[u8]) -> Option<CredentialRPK> {
+        self.position(kid).and_then(|slot| self.credentials[slot])
+    }
+
+    /// Looks up the credential that `id_cred_received` refers to, and checks it against what was
+    /// received like [credential_check_or_fetch] does
+    pub fn check_or_fetch(
+        &self,
+        id_cred_received: CredentialRPK,
+    ) -> Result<CredentialRPK, EDHOCError> {
+        let cred_expected = id_cred_received
+            .kid_bytes()
+            .and_then(|kid| self.lookup(kid));
+        credential_check_or_fetch(cred_expected, id_cred_received)
+    }
+
+    /// Number of stored credentials
+    pub fn len(&self) -> usize {
+        self.credentials.iter().flatten().count()
+    }
+
+    /// Whether no credential is stored
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[cfg(not(feature = "ct-lookups"))]
+    fn position(&self, kid: &[u8]) -> Option<usize> {
+        self.credentials.iter().position(|credential| {
+            credential.is_some_and(|credential| credential.kid_bytes() == Some(kid))
+        })
+    }
+
+    // touches every slot, and selects the match without branching on it
+    #[cfg(feature = "ct-lookups")]
+    fn position(&self, kid: &[u8]) -> Option<usize> {
+        let mut found = 0usize;
+        let mut position = 0usize;
+        for (slot, credential) in self.credentials.iter().enumerate() {
+            let (stored_kid, occupied) = match credential {
+                Some(credential) => (credential.kid, credential.has_kid),
+                None => (0, false),
+            };
+            let is_match =
+                (constant_time_eq(core::slice::from_ref(&stored_kid), kid) & occupied) as usize;
+            // all ones for the first match, zero otherwise
+            let mask = (is_match & (found ^ 1)).wrapping_neg();
+            position |= slot & mask;
+            found |= is_match;
+        }
+        (found == 1).then_some(position)
+    }
+}
+
+impl<const N: usize> CredentialResolver for CredentialStore<N> {
+    fn resolve(&self, id_cred: &CredentialRPK) -> Option<CredentialRPK> {
+        id_cred.kid_bytes().and_then(|kid| self.lookup(kid))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test_vectors_common::*;
+    use super::*;
+
+    #[test]
+    fn test_credential_store() {
+        let cred_i = CredentialRPK::new(CRED_I.try_into().unwrap()).unwrap();
+        let cred_r = CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap();
+        let mut store = CredentialStore::<3>::new();
+        assert!(store.is_empty());
+        store.insert(cred_i).unwrap();
+        store.insert(cred_r).unwrap();
+        store.insert(cred_r).unwrap(); // replaces
+        assert_eq!(store.len(), 2);
+
+        // hits in every slot, and misses
+        for (slot, cred) in [cred_i, cred_r].iter().enumerate() {
+            let found = store.lookup(cred.kid_bytes().unwrap()).unwrap();
+            assert_eq!(found.value, cred.value, "slot {slot}");
+        }
+        assert!(store.lookup(&[0x99]).is_none());
+        assert!(store.lookup(&[]).is_none());
+        assert!(store.lookup(&[cred_i.kid, cred_i.kid]).is_none());
+
+        // by reference and by value
+        let by_reference = CredentialRPK::from_kid(cred_r.kid);
+        assert_eq!(
+            store.check_or_fetch(by_reference).unwrap().value,
+            cred_r.value
+        );
+        assert_eq!(store.check_or_fetch(cred_i).unwrap().value, cred_i.value);
+        assert_eq!(store.resolve(&by_reference).unwrap().value, cred_r.value);
+        assert!(store.resolve(&CredentialRPK::from_kid(0x99)).is_none());
+        assert_eq!(
+            store
+                .check_or_fetch(CredentialRPK::from_kid(0x99))
+                .unwrap_err(),
+            EDHOCError::UnknownPeer
+        );
+
+        // credentials without kid cannot be stored
+        let x: BytesP256ElemLen = cred_i.public_key;
+        let without_kid = CredentialRPK::build_ccs(None, None, &x, None).unwrap();
+        assert_eq!(
+            store.insert(without_kid),
+            Err(EDHOCError::CredentialCannotBeReferenced)
+        );
+
+        assert!(store.remove(cred_i.kid_bytes().unwrap()).is_some());
+        assert!(store.lookup(cred_i.kid_bytes().unwrap()).is_none());
+        assert_eq!(store.len(), 1);
+
+        let mut full = CredentialStore::<1>::new();
+        full.insert(cred_i).unwrap();
+        assert_eq!(full.insert(cred_r), Err(EDHOCError::InvalidParameter));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(&[], &[]));
+        assert!(constant_time_eq(CRED_I, CRED_I));
+        assert!(!constant_time_eq(CRED_I, CRED_R));
+        assert!(!constant_time_eq(&CRED_I[..4], CRED_I));
+        let mut last_differs = CRED_I.to_vec();
+        *last_differs.last_mut().unwrap() ^= 0x01;
+        assert!(!constant_time_eq(CRED_I, &last_differs));
+    }
+}
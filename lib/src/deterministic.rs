@@ -0,0 +1,149 @@
+//! Deterministic handshakes for regression testing and bisecting interoperability failures
+//!
+//! **Insecure**: everything a handshake would draw at random is derived from a seed, so whoever
+//! knows the seed knows the ephemeral key. This is only available with the `test-utils` feature
+//! and must never be used outside of tests.
+
+use super::*;
+
+/// A [Crypto](CryptoTrait) back-end whose randomness is derived from a fixed seed
+///
+/// Random bytes and ephemeral key pairs are taken from a stream that HKDF-Expand of `crypto`
+/// produces from the seed, in blocks of [SHA256_DIGEST_LEN] bytes. All other primitives
+/// are taken from `crypto`, so the same seed gives the same values with any back-end.
+#[derive(Debug)]
+pub struct InsecureSeededCrypto<C: CryptoTrait> {
+    crypto: C,
+    prk: BytesHashLen,
+    counter: u32,
+    block: BytesHashLen,
+    used: usize, // bytes of block that were handed out
+}
+
+impl<C: CryptoTrait> InsecureSeededCrypto<C> {
+    /// Derives the randomness of `crypto` from `seed`
+    pub fn new(mut crypto: C, seed: &[u8; SHA256_DIGEST_LEN]) -> Self {
+        let prk = crypto.hkdf_extract(&[0x00; SHA256_DIGEST_LEN], seed);
+        InsecureSeededCrypto {
+            crypto,
+            prk,
+            counter: 0,
+            block: [0x00; SHA256_DIGEST_LEN],
+            used: SHA256_DIGEST_LEN,
+        }
+    }
+
+    fn next_block(&mut self) -> BytesHashLen {
+        let mut info: BytesMaxInfoBuffer = [0x00; MAX_INFO_LEN];
+        info[..4].copy_from_slice(&self.counter.to_be_bytes());
+        self.counter += 1;
+        let output = self
+            .crypto
+            .hkdf_expand(&self.prk, &info, 4, SHA256_DIGEST_LEN);
+        output[..SHA256_DIGEST_LEN].try_into().unwrap()
+    }
+}
+
+impl<C: CryptoTrait> EdhocInitiator<'_, InsecureSeededCrypto<C>> {
+    /// Creates an Initiator whose ephemeral key and connection identifier (and any other random
+    /// value) are derived from `seed`, so that it produces the same message_1 in every run
+    ///
+    /// **Insecure**, see [InsecureSeededCrypto].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lakers::*;
+    /// use lakers_crypto::default_crypto;
+    ///
+    /// let seed = [0x01; SHA256_DIGEST_LEN];
+    /// let (_initiator, message_1) = EdhocInitiator::new_deterministic(default_crypto(), &seed)
+    ///     .prepare_message_1(None, &None)
+    ///     .unwrap();
+    /// let (_initiator, again) = EdhocInitiator::new_deterministic(default_crypto(), &seed)
+    ///     .prepare_message_1(None, &None)
+    ///     .unwrap();
+    /// assert_eq!(message_1, again);
+    /// ```
+    pub fn new_deterministic(crypto: C, seed: &[u8; SHA256_DIGEST_LEN]) -> Self {
+        EdhocInitiator::new(InsecureSeededCrypto::new(crypto, seed))
+    }
+}
+
+impl<C: CryptoTrait> CryptoTrait for InsecureSeededCrypto<C> {
+    fn get_random_byte(&mut self) -> u8 {
+        if self.used == SHA256_DIGEST_LEN {
+            self.block = self.next_block();
+            self.used = 0;
+        }
+        self.used += 1;
+        self.block[self.used - 1]
+    }
+    /// Takes the private key from the next block of the stream that is a valid scalar
+    ///
+    /// If none of [RNG_MAX_RETRIES] blocks is, the key pair is all zeros, which lakers rejects
+    /// with [EDHOCError::RngFailure].
+    fn p256_generate_key_pair(&mut self) -> (BytesP256ElemLen, BytesP256ElemLen) {
+        for _ in 0..RNG_MAX_RETRIES {
+            let private_key = self.next_block();
+            match self.crypto.p256_public_from_private(&private_key) {
+                Ok(public_key) if public_key != [0x00; P256_ELEM_LEN] => {
+                    return (private_key, public_key)
+                }
+                _ => continue,
+            }
+        }
+        ([0x00; P256_ELEM_LEN], [0x00; P256_ELEM_LEN])
+    }
+    forward_crypto!(
+        |this| this.crypto,
+        except get_random_byte,
+        p256_generate_key_pair
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test_vectors_common::*;
+    use super::*;
+    use lakers_crypto::default_crypto;
+
+    #[test]
+    fn test_new_deterministic() {
+        let message_1 = |seed| {
+            let (_initiator, message_1) =
+                EdhocInitiator::new_deterministic(default_crypto(), &[seed; SHA256_DIGEST_LEN])
+                    .prepare_message_1(None, &None)
+                    .unwrap();
+            message_1
+        };
+
+        assert_eq!(message_1(0x01), message_1(0x01));
+        assert_ne!(message_1(0x01), message_1(0x02));
+
+        // a deterministic Initiator still completes a handshake
+        let cred_i = CredentialRPK::new(CRED_I.try_into().unwrap()).unwrap();
+        let cred_r = CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap();
+        let (initiator, message_1) =
+            EdhocInitiator::new_deterministic(default_crypto(), &[0x01; SHA256_DIGEST_LEN])
+                .prepare_message_1(None, &None)
+                .unwrap();
+        let (responder, _ead_1) = EdhocResponder::new(default_crypto(), R, cred_r)
+            .unwrap()
+            .process_message_1(&message_1)
+            .unwrap();
+        let (responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+            .unwrap();
+        let (initiator, _c_r, id_cred_r, _ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        let valid_cred_r = credential_check_or_fetch(Some(cred_r), id_cred_r).unwrap();
+        let initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+        let (_initiator, message_3, i_prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+        let (responder, id_cred_i, _ead_3) = responder.parse_message_3(&message_3).unwrap();
+        let valid_cred_i = credential_check_or_fetch(Some(cred_i), id_cred_i).unwrap();
+        let (_responder, r_prk_out) = responder.verify_message_3(valid_cred_i).unwrap();
+        assert_eq!(i_prk_out, r_prk_out);
+    }
+}
@@ -0,0 +1,115 @@
+//! Credentials and keys for the `# Examples` of the documentation
+//!
+//! **Insecure**: the private keys are those of the traces in RFC 9529, Section 3, and
+//! [seeded_crypto] derives all randomness from a fixed seed. This is only available with the
+//! `doctest-helpers` feature and must never be used outside of examples and tests.
+//!
+//! ```
+//! use lakers::doctest_helpers::*;
+//! use lakers::*;
+//! use lakers_crypto::default_crypto;
+//!
+//! # fn main() -> Result<(), EDHOCError> {
+//! let cred_i = credential_i();
+//! assert_eq!(cred_i.kid_bytes(), Some(&[0x2b][..]));
+//! assert_eq!(credential_r().kid_bytes(), Some(&[0x0a][..]));
+//!
+//! let (mut initiator, mut responder) = handshake(default_crypto)?;
+//! assert_eq!(
+//!     initiator.edhoc_exporter(0, &[], 16)?[..16],
+//!     responder.edhoc_exporter(0, &[], 16)?[..16]
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use super::*;
+
+/// Private authentication key of the Initiator
+pub const I: BytesP256ElemLen =
+    decode_hex("fb13adeb6518cee5f88417660841142e830a81fe334380a953406a1305e8706b");
+/// CCS of the Initiator, with kid 0x2b
+pub const CRED_I: [u8; 107] = decode_hex("A2027734322D35302D33312D46462D45462D33372D33322D333908A101A5010202412B2001215820AC75E9ECE3E50BFC8ED60399889522405C47BF16DF96660A41298CB4307F7EB62258206E5DE611388A4B8A8211334AC7D37ECB52A387D257E6DB3C2A93DF21FF3AFFC8");
+/// Private authentication key of the Responder
+pub const R: BytesP256ElemLen =
+    decode_hex("72cc4761dbd4c78f758931aa589d348d1ef874a7e303ede2f140dcf3e6aa4aac");
+/// CCS of the Responder, with kid 0x0a
+pub const CRED_R: [u8; 84] = decode_hex("A2026008A101A5010202410A2001215820BBC34960526EA4D32E940CAD2A234148DDC21791A12AFBCBAC93622046DD44F02258204519E257236B2A0CE2023F0931F1F386CA7AFDA64FCDE0108C224C51EABF6072");
+
+/// The parsed [CRED_I]
+pub fn credential_i() -> CredentialRPK {
+    CredentialRPK::new(EdhocMessageBuffer::new_from_slice(&CRED_I).unwrap()).unwrap()
+}
+
+/// The parsed [CRED_R]
+pub fn credential_r() -> CredentialRPK {
+    CredentialRPK::new(EdhocMessageBuffer::new_from_slice(&CRED_R).unwrap()).unwrap()
+}
+
+/// Wraps `crypto` so that every random value is derived from a fixed seed, see
+/// [InsecureSeededCrypto]
+pub fn seeded_crypto<C: CryptoTrait>(crypto: C) -> InsecureSeededCrypto<C> {
+    InsecureSeededCrypto::new(crypto, &[0x01; SHA256_DIGEST_LEN])
+}
+
+/// Runs a complete handshake between an Initiator with [I] and [CRED_I] and a Responder with
+/// [R] and [CRED_R], in which both credentials are sent by reference, so that examples can start
+/// from the completed sessions
+///
+/// Each peer gets its own backend from `new_crypto`.
+pub fn handshake<C: CryptoTrait>(
+    mut new_crypto: impl FnMut() -> C,
+) -> Result<(EdhocInitiatorDone<C>, EdhocResponderDone<C>), EDHOCError> {
+    let initiator = EdhocInitiator::new(new_crypto());
+    let responder = EdhocResponder::new(new_crypto(), &R, credential_r())?;
+
+    let (initiator, message_1) = initiator.prepare_message_1(None, &None)?;
+    let (responder, _ead_1) = responder.process_message_1(&message_1)?;
+    let (responder, message_2) =
+        responder.prepare_message_2(CredentialTransfer::ByReference, None, &None)?;
+
+    let (initiator, _c_r, id_cred_r, _ead_2) = initiator.parse_message_2(&message_2)?;
+    let valid_cred_r = credential_check_or_fetch(Some(credential_r()), id_cred_r)?;
+    let initiator = initiator.verify_message_2(&I, credential_i(), valid_cred_r)?;
+    let (initiator, message_3, _prk_out) =
+        initiator.prepare_message_3(CredentialTransfer::ByReference, &None)?;
+
+    let (responder, id_cred_i, _ead_3) = responder.parse_message_3(&message_3)?;
+    let valid_cred_i = credential_check_or_fetch(Some(credential_i()), id_cred_i)?;
+    let (responder, _prk_out) = responder.verify_message_3(valid_cred_i)?;
+    Ok((initiator, responder))
+}
+
+const fn decode_hex<const N: usize>(hex: &str) -> [u8; N] {
+    const fn nibble(digit: u8) -> u8 {
+        match digit {
+            b'0'..=b'9' => digit - b'0',
+            b'a'..=b'f' => digit - b'a' + 10,
+            b'A'..=b'F' => digit - b'A' + 10,
+            _ => panic!("not a hex digit"),
+        }
+    }
+    let hex = hex.as_bytes();
+    assert!(hex.len() == 2 * N);
+    let mut bytes = [0u8; N];
+    let mut i = 0;
+    while i < N {
+        bytes[i] = nibble(hex[2 * i]) << 4 | nibble(hex[2 * i + 1]);
+        i += 1;
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test_vectors_common;
+    use super::*;
+
+    #[test]
+    fn test_helpers_match_traces() {
+        assert_eq!(&I[..], test_vectors_common::I);
+        assert_eq!(&R[..], test_vectors_common::R);
+        assert_eq!(&CRED_I[..], test_vectors_common::CRED_I);
+        assert_eq!(&CRED_R[..], test_vectors_common::CRED_R);
+    }
+}
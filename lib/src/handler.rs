@@ -0,0 +1,342 @@
+//! One-call handlers for a Responder that serves EDHOC requests, e.g. POSTs to
+//! /.well-known/edhoc in a callback-style CoAP server
+//!
+//! Each handler runs all steps of the Responder for one incoming message, with the decisions
+//! taken from a [ResponderPolicy], and returns the state to be parked in the session table until
+//! the next message. The granular API of [EdhocResponder] remains available for everything else.
+use super::*;
+
+/// Decisions that [respond_to_message_1] and [finish_with_message_3] take for the Responder
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResponderPolicy {
+    cred_transfer: CredentialTransfer,
+    suite_policy: SuitePolicy,
+    profile: ApplicationProfile,
+    peer_cred_by_value: bool,
+    reject_ead: bool,
+}
+
+impl Default for ResponderPolicy {
+    fn default() -> Self {
+        ResponderPolicy {
+            cred_transfer: CredentialTransfer::ByReference,
+            suite_policy: SuitePolicy::default(),
+            profile: ApplicationProfile::default(),
+            peer_cred_by_value: false,
+            reject_ead: false,
+        }
+    }
+}
+
+impl ResponderPolicy {
+    /// Sets how CRED_R is sent in message_2, which is [CredentialTransfer::ByReference] by default
+    pub fn with_credential_transfer(mut self, cred_transfer: CredentialTransfer) -> Self {
+        self.cred_transfer = cred_transfer;
+        self
+    }
+
+    /// See [EdhocResponder::with_suite_policy]
+    pub fn with_suite_policy(mut self, suite_policy: SuitePolicy) -> Self {
+        self.suite_policy = suite_policy;
+        self
+    }
+
+    /// See [EdhocResponder::with_application_profile]
+    pub fn with_application_profile(mut self, profile: ApplicationProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// See [EdhocResponder::require_peer_credential_by_value]
+    pub fn require_peer_credential_by_value(mut self, required: bool) -> Self {
+        self.peer_cred_by_value = required;
+        self
+    }
+
+    /// Sets whether EAD_1 and EAD_3 are rejected with [EDHOCError::EADError] even when they are
+    /// not critical
+    ///
+    /// The handlers do not process EAD, so critical items are always rejected; non-critical
+    /// ones are by default returned for the application to look at.
+    pub fn reject_ead(mut self, reject: bool) -> Self {
+        self.reject_ead = reject;
+        self
+    }
+
+    fn check_ead(&self, ead: &EadItems) -> Result<(), EDHOCError> {
+        if ead.iter().any(|ead| ead.is_critical || self.reject_ead) {
+            Err(EDHOCError::EADError)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Processes the message_1 in `payload` and prepares the message_2 that answers it
+///
+/// C_R is generated among the identifiers that `accept_c_r` accepts, given in their CBOR encoding
+/// like [EdhocResponderWaitM3::c_r], so that it can avoid those of the sessions that are parked
+/// already; this fails with [EDHOCError::InvalidParameter] if no acceptable one was found.
+/// message_2 is sent without EAD_2. Returns the state to be kept for the message_3 of the session,
+/// message_2, and the items of EAD_1, which are all non-critical unless the policy rejects them.
+///
+/// # Examples
+///
+/// A Responder that handles each message in one call, with an [EdhocInitiator] as its peer:
+///
+/// ```
+/// use lakers::doctest_helpers::{credential_i, credential_r, I, R};
+/// use lakers::*;
+/// use lakers_crypto::default_crypto;
+///
+/// # fn main() -> Result<(), EDHOCError> {
+/// let identity =
+///     ResponderIdentity::new(&mut default_crypto(), P256PrivateKey::new(R), credential_r())?;
+/// let policy = ResponderPolicy::default();
+///
+/// // the C_R of the sessions that are parked already
+/// let parked = [0x05, 0x2a];
+///
+/// let initiator = EdhocInitiator::new(default_crypto());
+/// let (initiator, message_1) = initiator.prepare_message_1(None, &None)?;
+/// let (responder, message_2, _ead_1) = respond_to_message_1(
+///     &identity,
+///     default_crypto(),
+///     message_1.as_slice(),
+///     &policy,
+///     |c_r| !parked.contains(&c_r),
+/// )?;
+/// assert!(!parked.contains(&responder.c_r()));
+///
+/// let (initiator, _c_r, id_cred_r, _ead_2) = initiator.parse_message_2(&message_2)?;
+/// let valid_cred_r = credential_check_or_fetch(Some(credential_r()), id_cred_r)?;
+/// let initiator = initiator.verify_message_2(&I, credential_i(), valid_cred_r)?;
+/// let (_initiator, message_3, i_prk_out) =
+///     initiator.prepare_message_3(CredentialTransfer::ByReference, &None)?;
+///
+/// // in between, the state is parked in the session table, e.g. under responder.c_r()
+/// let (_responder, r_prk_out, cred_i, _ead_3) = finish_with_message_3(
+///     responder,
+///     message_3.as_slice(),
+///     |_id_cred_i| Some(credential_i()),
+///     &policy,
+/// )?;
+/// assert_eq!(i_prk_out, r_prk_out);
+/// assert_eq!(cred_i.value, credential_i().value);
+/// # Ok(())
+/// # }
+/// ```
+pub fn respond_to_message_1<Crypto: CryptoTrait>(
+    identity: &ResponderIdentity,
+    crypto: Crypto,
+    payload: &[u8],
+    policy: &ResponderPolicy,
+    mut accept_c_r: impl FnMut(u8) -> bool,
+) -> Result<(EdhocResponderWaitM3<Crypto>, BufferMessage2, EadItems), EDHOCError> {
+    let message_1 =
+        BufferMessage1::new_from_slice(payload).map_err(|_| EDHOCError::MessageTooLong)?;
+    let (mut responder, ead_1) = EdhocResponder::from_identity(crypto, identity)
+        .with_suite_policy(policy.suite_policy)
+        .with_application_profile(policy.profile)
+        .require_peer_credential_by_value(policy.peer_cred_by_value)
+        .process_message_1(&message_1)
+        .map_err(|failure| failure.error)?;
+    policy.check_ead(&ead_1)?;
+    let c_r = generate_connection_identifier_with(&mut responder.crypto, |c_r| {
+        accept_c_r(encode_connection_identifier(c_r))
+    })
+    .map(encode_connection_identifier)?;
+    let (responder, message_2) = responder
+        .prepare_message_2(policy.cred_transfer, Some(c_r), &None)
+        .map_err(|failure| failure.error)?;
+    Ok((responder, message_2, ead_1))
+}
+
+/// Processes the message_3 in `payload` with the state parked by [respond_to_message_1], and
+/// completes the handshake
+///
+/// `lookup` is given ID_CRED_I as received and returns the expected credential of the
+/// Initiator, if known, which is then checked like in [credential_check_or_fetch]. Returns the
+/// completed state, PRK_out, the authenticated credential of the Initiator, and the items of
+/// EAD_3, which are all non-critical unless the policy rejects them.
+pub fn finish_with_message_3<Crypto: CryptoTrait>(
+    responder: EdhocResponderWaitM3<Crypto>,
+    payload: &[u8],
+    lookup: impl FnOnce(&CredentialRPK) -> Option<CredentialRPK>,
+    policy: &ResponderPolicy,
+) -> Result<
+    (
+        EdhocResponderDone<Crypto>,
+        BytesHashLen,
+        CredentialRPK,
+        EadItems,
+    ),
+    EDHOCError,
+> {
+    let message_3 =
+        BufferMessage3::new_from_slice(payload).map_err(|_| EDHOCError::MessageTooLong)?;
+    let (responder, id_cred_i, ead_3) = responder
+        .parse_message_3(&message_3)
+        .map_err(|failure| failure.error)?;
+    policy.check_ead(&ead_3)?;
+    let valid_cred_i = credential_check_or_fetch(lookup(&id_cred_i), id_cred_i)?;
+    let (responder, prk_out) = responder
+        .verify_message_3(valid_cred_i)
+        .map_err(|failure| failure.error)?;
+    Ok((responder, prk_out, valid_cred_i, ead_3))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test_vectors_common::*;
+    use super::*;
+    use lakers_crypto::{default_crypto, Crypto};
+
+    /// Sessions of a toy CoAP server, which only ever uses the one-call handlers
+    struct Server {
+        identity: ResponderIdentity,
+        policy: ResponderPolicy,
+        pending: Vec<EdhocResponderWaitM3<Crypto>>,
+        completed: Vec<(CredentialRPK, BytesHashLen)>,
+    }
+
+    impl Server {
+        /// Handles the payload of a POST to /.well-known/edhoc, which is message_1 prefixed with
+        /// CBOR true, or message_3 prefixed with C_R; returns the payload of the response
+        fn post(&mut self, payload: &[u8]) -> Result<Vec<u8>, EDHOCError> {
+            let (&prefix, message) = payload.split_first().ok_or(EDHOCError::ParsingError)?;
+            if prefix == CBOR_TRUE {
+                let pending = &self.pending;
+                let (responder, message_2, _ead_1) = respond_to_message_1(
+                    &self.identity,
+                    default_crypto(),
+                    message,
+                    &self.policy,
+                    |c_r| pending.iter().all(|responder| responder.c_r() != c_r),
+                )?;
+                self.pending.push(responder);
+                return Ok(message_2.as_slice().to_vec());
+            }
+
+            let session = self
+                .pending
+                .iter()
+                .position(|responder| responder.c_r() == prefix)
+                .ok_or(EDHOCError::UnknownPeer)?;
+            let responder = self.pending.swap_remove(session);
+            let known = |id_cred_i: &CredentialRPK| {
+                let cred_i = CredentialRPK::new(CRED_I.try_into().unwrap()).unwrap();
+                (id_cred_i.kid == cred_i.kid).then_some(cred_i)
+            };
+            let (_responder, prk_out, cred_i, _ead_3) =
+                finish_with_message_3(responder, message, known, &self.policy)?;
+            self.completed.push((cred_i, prk_out));
+            Ok(Vec::new())
+        }
+    }
+
+    fn initiate(ead_1: &Option<EADItem>) -> (EdhocInitiatorWaitM2<'static, Crypto>, Vec<u8>) {
+        let (initiator, message_1) = EdhocInitiator::new(default_crypto())
+            .prepare_message_1(None, ead_1)
+            .unwrap();
+        let mut payload = vec![CBOR_TRUE];
+        payload.extend_from_slice(message_1.as_slice());
+        (initiator, payload)
+    }
+
+    #[test]
+    fn test_handlers() {
+        let cred_i = CredentialRPK::new(CRED_I.try_into().unwrap()).unwrap();
+        let cred_r = CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap();
+        let identity = ResponderIdentity::new(
+            &mut default_crypto(),
+            P256PrivateKey::new(R.try_into().unwrap()),
+            cred_r,
+        )
+        .unwrap();
+        let mut server = Server {
+            identity,
+            policy: ResponderPolicy::default(),
+            pending: Vec::new(),
+            completed: Vec::new(),
+        };
+
+        // two interleaved handshakes
+        let (first, payload) = initiate(&None);
+        let first_message_2 = server.post(&payload).unwrap();
+        let (second, payload) = initiate(&None);
+        let second_message_2 = server.post(&payload).unwrap();
+        assert_eq!(server.pending.len(), 2);
+
+        let mut prk_outs = Vec::new();
+        for (initiator, message_2) in [(second, second_message_2), (first, first_message_2)] {
+            let message_2 = BufferMessage2::new_from_slice(&message_2).unwrap();
+            let (initiator, c_r, id_cred_r, _ead_2) =
+                initiator.parse_message_2(&message_2).unwrap();
+            assert!(id_cred_r.reference_only());
+            let valid_cred_r = credential_check_or_fetch(Some(cred_r), id_cred_r).unwrap();
+            let initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+            let (_initiator, message_3, prk_out) = initiator
+                .prepare_message_3(CredentialTransfer::ByReference, &None)
+                .unwrap();
+            let mut payload = vec![c_r];
+            payload.extend_from_slice(message_3.as_slice());
+            assert_eq!(server.post(&payload), Ok(Vec::new()));
+            prk_outs.push(prk_out);
+        }
+        assert!(server.pending.is_empty());
+        for ((peer, r_prk_out), i_prk_out) in server.completed.iter().zip(&prk_outs) {
+            assert_eq!(peer.value, cred_i.value);
+            assert_eq!(r_prk_out, i_prk_out);
+        }
+
+        // no C_R left to choose from
+        let (_initiator, payload) = initiate(&None);
+        assert_eq!(
+            respond_to_message_1(
+                &server.identity,
+                default_crypto(),
+                &payload[1..],
+                &server.policy,
+                |_c_r| false
+            )
+            .unwrap_err(),
+            EDHOCError::InvalidParameter
+        );
+
+        // a message_3 of no known session
+        assert_eq!(server.post(&[0x05, 0x40]), Err(EDHOCError::UnknownPeer));
+
+        // EAD_1 is returned if not critical, unless the policy rejects any
+        let ead_1 = EADItem {
+            label: 0x05,
+            is_critical: false,
+            value: None,
+        };
+        let (_initiator, payload) = initiate(&Some(ead_1.clone()));
+        assert!(server.post(&payload).is_ok());
+        server.policy = ResponderPolicy::default().reject_ead(true);
+        let (_initiator, payload) = initiate(&Some(ead_1.clone()));
+        assert_eq!(server.post(&payload), Err(EDHOCError::EADError));
+        let critical = EADItem {
+            is_critical: true,
+            ..ead_1.clone()
+        };
+        server.policy = ResponderPolicy::default();
+        let (_initiator, payload) = initiate(&Some(critical));
+        assert_eq!(server.post(&payload), Err(EDHOCError::EADError));
+
+        // how CRED_R is sent is up to the policy; by value, it does not fit the buffers of
+        // limits-small
+        if cfg!(feature = "limits-small") {
+            return;
+        }
+        server.policy =
+            ResponderPolicy::default().with_credential_transfer(CredentialTransfer::ByValue);
+        let (initiator, payload) = initiate(&None);
+        let message_2 = server.post(&payload).unwrap();
+        let message_2 = BufferMessage2::new_from_slice(&message_2).unwrap();
+        let (_initiator, _c_r, id_cred_r, _ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        assert!(!id_cred_r.reference_only());
+    }
+}
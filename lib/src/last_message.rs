@@ -0,0 +1,102 @@
+//! Recognition of a retransmitted message
+use super::*;
+
+/// Hash of the message processed last, to recognize a retransmission of it
+///
+/// Over a lossy link, a peer resends message_1 or message_3 when it did not receive an answer.
+/// Processing such a copy again fails, or worse, produces a different answer; a duplicate is
+/// instead to be dropped (or answered with the retained response, see
+/// [EdhocResponderWaitM3::retained_message_2_for]). This only holds a hash, so that it costs the
+/// same for every message size.
+///
+/// The hashes are compared in constant time, so that the time to reject a message does not
+/// reveal how much of it matched.
+///
+/// # Examples
+///
+/// ```
+/// use lakers::*;
+/// use lakers_crypto::default_crypto;
+///
+/// let mut crypto = default_crypto();
+/// let mut last = LastMessage::new();
+/// let message_3 = EdhocMessageBuffer::from_hex("52e562097bc417dd5919485ac7891ffd90a9fc");
+///
+/// assert!(!last.is_duplicate(&mut crypto, &message_3));
+/// last.record(&mut crypto, &message_3);
+/// assert!(last.is_duplicate(&mut crypto, &message_3));
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LastMessage {
+    hash: Option<BytesHashLen>,
+}
+
+impl LastMessage {
+    /// Nothing recorded yet, so that no message is a duplicate
+    pub fn new() -> Self {
+        LastMessage { hash: None }
+    }
+
+    /// Records `message` as the message processed last, replacing the one recorded before
+    pub fn record(&mut self, crypto: &mut impl CryptoTrait, message: &EdhocMessageBuffer) {
+        self.hash = Some(Self::hash(crypto, message));
+    }
+
+    /// Whether `message` is the same as the one recorded last
+    pub fn is_duplicate(
+        &self,
+        crypto: &mut impl CryptoTrait,
+        message: &EdhocMessageBuffer,
+    ) -> bool {
+        let Some(hash) = self.hash else {
+            return false;
+        };
+        constant_time_eq(&hash, &Self::hash(crypto, message))
+    }
+
+    /// Forgets the recorded message, e.g. once the handshake completed
+    pub fn clear(&mut self) {
+        self.hash = None;
+    }
+
+    fn hash(crypto: &mut impl CryptoTrait, message: &EdhocMessageBuffer) -> BytesHashLen {
+        let mut buffer: BytesMaxBuffer = [0x00; MAX_BUFFER_LEN];
+        buffer[..message.len].copy_from_slice(message.as_slice());
+        crypto.sha256_digest(&buffer, message.len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test_vectors_common::*;
+    use super::*;
+    use lakers_crypto::default_crypto;
+
+    #[test]
+    fn test_last_message() {
+        let mut crypto = default_crypto();
+        let message_1 = BufferMessage1::from_hex(MESSAGE_1_TV);
+        let other = BufferMessage1::from_hex(MESSAGE_1_TV_FIRST_TIME);
+        let mut last = LastMessage::new();
+        assert!(!last.is_duplicate(&mut crypto, &message_1));
+
+        last.record(&mut crypto, &message_1);
+        assert!(last.is_duplicate(&mut crypto, &message_1));
+        assert!(last.is_duplicate(&mut crypto, &message_1));
+        assert!(!last.is_duplicate(&mut crypto, &other));
+
+        // a single byte differs, or the message is a prefix
+        let mut tampered = message_1;
+        tampered.content[message_1.len - 1] ^= 0x01;
+        assert!(!last.is_duplicate(&mut crypto, &tampered));
+        let mut truncated = message_1;
+        truncated.len -= 1;
+        assert!(!last.is_duplicate(&mut crypto, &truncated));
+
+        last.record(&mut crypto, &other);
+        assert!(!last.is_duplicate(&mut crypto, &message_1));
+        assert!(last.is_duplicate(&mut crypto, &other));
+        last.clear();
+        assert!(!last.is_duplicate(&mut crypto, &other));
+    }
+}
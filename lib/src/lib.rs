@@ -2,7 +2,7 @@
 //! exchange for the Internet of Things.
 //!
 //! The crate provides a high-level interface through the [EdhocInitiator] and the [EdhocResponder]
-//! structs. Both these wrap the lower level [State] struct that is mainly used through internal
+//! structs. Both these wrap the lower level state structs that are mainly used through internal
 //! functions in the `edhoc` module. This separation is relevant because the lower level tools are
 //! subject of ongoing formal verification, whereas the high-level interfaces aim for good
 //! usability.
@@ -13,7 +13,8 @@
 //! respectively, through which the EDHOC key material can be obtained.
 //!
 //! [EDHOC]: https://datatracker.ietf.org/doc/draft-ietf-lake-edhoc/
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+#![warn(missing_docs)]
 
 pub use {lakers_shared::Crypto as CryptoTrait, lakers_shared::*};
 
@@ -23,266 +24,1586 @@ pub use lakers_ead::*;
 mod edhoc;
 pub use edhoc::*;
 
+mod self_test;
+pub use self_test::{self_test, SelfTestFailure};
+
+pub mod ble;
+
+mod report;
+use report::ReportRecorder;
+#[cfg(feature = "report")]
+pub use report::{
+    CredentialReport, EadReport, ExporterReport, HandshakeReport, Role, TrailingItemsReport,
+};
+
+mod handler;
+pub use handler::{finish_with_message_3, respond_to_message_1, ResponderPolicy};
+
+mod cred_store;
+pub use cred_store::CredentialStore;
+
+mod pool;
+pub use pool::{PoolStats, ResponderPool};
+
+mod last_message;
+pub use last_message::LastMessage;
+
+mod precompute;
+pub use precompute::{PreparedMessage1, MAX_SAVED_INITIATOR_LEN};
+
+#[cfg(any(test, feature = "test-utils"))]
+mod deterministic;
+#[cfg(any(test, feature = "test-utils"))]
+pub use deterministic::InsecureSeededCrypto;
+
+#[cfg(any(test, feature = "doctest-helpers"))]
+pub mod doctest_helpers;
+
 // TODO: clean these structs and remove the cred_x whre they are not needed anymore
 /// Starting point for performing EDHOC in the role of the Initiator.
+///
+/// # Examples
+///
+/// A complete handshake with an [EdhocResponder], in which both know the credential of the other
+/// in advance, so that credentials are sent by reference. Each step consumes the state it is
+/// called on, and the messages are passed on as they would be sent over the network:
+///
+/// ```
+/// use lakers::doctest_helpers::{credential_i, credential_r, I, R};
+/// use lakers::*;
+/// use lakers_crypto::default_crypto;
+///
+/// # fn main() -> Result<(), EDHOCError> {
+/// let (cred_i, cred_r) = (credential_i(), credential_r());
+/// let initiator = EdhocInitiator::new(default_crypto());
+/// let responder = EdhocResponder::new(default_crypto(), &R, cred_r)?;
+///
+/// let (initiator, message_1) = initiator.prepare_message_1(None, &None)?;
+/// let (responder, _ead_1) = responder.process_message_1(&message_1)?;
+/// let (responder, message_2) =
+///     responder.prepare_message_2(CredentialTransfer::ByReference, None, &None)?;
+///
+/// let (initiator, _c_r, id_cred_r, _ead_2) = initiator.parse_message_2(&message_2)?;
+/// let valid_cred_r = credential_check_or_fetch(Some(cred_r), id_cred_r)?;
+/// let initiator = initiator.verify_message_2(&I, cred_i, valid_cred_r)?;
+/// let (mut initiator, message_3, i_prk_out) =
+///     initiator.prepare_message_3(CredentialTransfer::ByReference, &None)?;
+///
+/// let (responder, id_cred_i, _ead_3) = responder.parse_message_3(&message_3)?;
+/// let valid_cred_i = credential_check_or_fetch(Some(cred_i), id_cred_i)?;
+/// let (mut responder, r_prk_out) = responder.verify_message_3(valid_cred_i)?;
+/// assert_eq!(i_prk_out, r_prk_out);
+///
+/// // e.g. the OSCORE Master Secret
+/// let secret = initiator.edhoc_exporter(0, &[], 16)?;
+/// assert_eq!(secret[..16], responder.edhoc_exporter(0, &[], 16)?[..16]);
+/// # Ok(())
+/// # }
+/// ```
 #[derive(Debug)]
-pub struct EdhocInitiator<Crypto: CryptoTrait> {
-    state: InitiatorStart, // opaque state
+pub struct EdhocInitiator<'a, Crypto: CryptoTrait> {
+    state: InitiatorStart,         // opaque state
+    preferred_suites: BytesSuites, // SUITES_I as configured, in order of preference
+    preferred_suites_len: usize,
+    attempted_suites: BytesSuites, // selected in earlier attempts, kept in SUITES_I
+    attempted_suites_len: usize,
+    profile: ApplicationProfile,
+    peer_cred_by_value: bool, // whether the peer has to send its credential by value
+    credential_resolver: Option<&'a dyn CredentialResolver>,
+    report: ReportRecorder,
+    progress_mark: u32, // application-defined, carried through transitions
     crypto: Crypto,
 }
 
+/// The Initiator once message_1 is prepared, waiting for message_2
 #[derive(Debug)]
-pub struct EdhocInitiatorWaitM2<Crypto: CryptoTrait> {
-    state: WaitM2, // opaque state
+pub struct EdhocInitiatorWaitM2<'a, Crypto: CryptoTrait> {
+    state: WaitM2,             // opaque state
+    message_1: BufferMessage1, // retained for retransmission
+    suites_i: BytesSuites,     // as sent in message_1
+    suites_i_len: usize,
+    preferred_suites: BytesSuites, // see EdhocInitiator
+    preferred_suites_len: usize,
+    attempted_suites: BytesSuites, // see EdhocInitiator
+    attempted_suites_len: usize,
+    bytes_exchanged: usize, // of the EDHOC messages so far
+    c_i: ConnectionId,
+    profile: ApplicationProfile,
+    peer_cred_by_value: bool, // whether the peer has to send its credential by value
+    credential_resolver: Option<&'a dyn CredentialResolver>,
+    report: ReportRecorder,
+    progress_mark: u32, // application-defined, carried through transitions
     crypto: Crypto,
 }
 
+/// The Initiator once message_2 is decrypted, waiting for the credential of the Responder to be
+/// verified against
 #[derive(Debug)]
 pub struct EdhocInitiatorProcessingM2<Crypto: CryptoTrait> {
-    state: ProcessingM2, // opaque state
+    state: ProcessingM2,             // opaque state
+    ciphertext_2: BufferCiphertext2, // as received, for decryption outside of the library
+    bytes_exchanged: usize,          // of the EDHOC messages so far
+    c_i: ConnectionId,
+    profile: ApplicationProfile,
+    report: ReportRecorder,
+    progress_mark: u32, // application-defined, carried through transitions
     crypto: Crypto,
 }
 
+/// The Initiator once message_2 is verified, ready to prepare message_3
 #[derive(Debug)]
 pub struct EdhocInitiatorProcessedM2<Crypto: CryptoTrait> {
-    state: ProcessedM2,    // opaque state
-    cred_i: CredentialRPK, // I's full credential
+    state: ProcessedM2,     // opaque state
+    cred_i: CredentialRPK,  // I's full credential
+    cred_r: CredentialRPK,  // R's verified credential
+    bytes_exchanged: usize, // of the EDHOC messages so far
+    c_i: ConnectionId,
+    c_r: ConnectionId,
+    selected_suite: u8,
+    profile: ApplicationProfile,
+    report: ReportRecorder,
+    progress_mark: u32, // application-defined, carried through transitions
     crypto: Crypto,
 }
 
+/// Final state of the Initiator.
+///
+/// The Initiator reaches this state as soon as message_3 is produced, which does not mean that the
+/// Responder received it. Until [EdhocInitiatorDone::confirm_peer_completion] is called, message_3
+/// is retained so that it can be resent in case it got lost.
 #[derive(Debug)]
 pub struct EdhocInitiatorDone<Crypto: CryptoTrait> {
     state: Completed,
+    message_3: Option<BufferMessage3>, // retained for retransmission, until discarded
+    completion: CompletionHint,
+    message_4_pending: bool, // the profile requires a message_4 that was not processed yet
+    cred_transfer: CredentialTransfer, // how CRED_I was sent in message_3
+    cred_i: CredentialRPK,
+    cred_r: CredentialRPK,  // verified in message_2
+    bytes_exchanged: usize, // of the EDHOC messages
+    c_i: ConnectionId,
+    c_r: ConnectionId,
+    selected_suite: u8,
+    report: ReportRecorder,
+    progress_mark: u32, // application-defined, carried through transitions
     crypto: Crypto,
 }
 
+/// Looks up the credential of the Responder that ID_CRED_R (as received in message_2) refers to,
+/// for [EdhocInitiatorWaitM2::parse_and_verify_message_2]
+///
+/// The Initiator only borrows the resolver, so that it can be stored without an allocator; as the
+/// resolver is [Sync], the Initiator can still be moved between threads. It is implemented for
+/// functions and closures that take ID_CRED_R, and by credential stores that keep state of their
+/// own.
+pub trait CredentialResolver: Sync {
+    /// Returns the credential that `id_cred` refers to, or None if it is not known
+    fn resolve(&self, id_cred: &CredentialRPK) -> Option<CredentialRPK>;
+}
+
+impl<F: Fn(&CredentialRPK) -> Option<CredentialRPK> + Sync> CredentialResolver for F {
+    fn resolve(&self, id_cred: &CredentialRPK) -> Option<CredentialRPK> {
+        self(id_cred)
+    }
+}
+
+impl core::fmt::Debug for dyn CredentialResolver + '_ {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("CredentialResolver")
+    }
+}
+
+/// A connection identifier along with the peer that chose it, so that C_I and C_R can not be
+/// mixed up unnoticed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ConnectionId {
+    id: u8,
+    chosen_by_initiator: bool,
+}
+
+impl ConnectionId {
+    fn c_i(id: u8) -> Self {
+        ConnectionId {
+            id,
+            chosen_by_initiator: true,
+        }
+    }
+
+    fn c_r(id: u8) -> Self {
+        ConnectionId {
+            id,
+            chosen_by_initiator: false,
+        }
+    }
+}
+
+/// Knowledge of the Initiator about whether the Responder completed the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionHint {
+    /// message_3 was produced, but nothing was received from the Responder since
+    Unconfirmed,
+    /// a message_4 or a protected application message from the Responder was processed
+    Confirmed,
+}
+
 /// Starting point for performing EDHOC in the role of the Responder.
+///
+/// The private authentication key of R is either given as raw bytes, or held by a [Signer] `S`.
+///
+/// # Examples
+///
+/// See [EdhocInitiator] for a complete handshake. A message_1 that selects a cipher suite the
+/// Responder does not support is rejected, and can be answered with an EDHOC error message:
+///
+/// ```
+/// use lakers::doctest_helpers::{credential_r, R};
+/// use lakers::*;
+/// use lakers_crypto::default_crypto;
+///
+/// let responder = EdhocResponder::new(default_crypto(), &R, credential_r()).unwrap();
+/// // message_1 of RFC 9529, Section 3, with SUITES_I [6, 2] changed into [2, 6]
+/// let message_1 = EdhocMessageBuffer::from_hex(
+///     "0382020658208af6f430ebe18d34184017a9a11bf511c8dff8f834730b96c1b7c8dbca2fc3b637",
+/// );
+/// let failure = responder.process_message_1(&message_1).unwrap_err();
+/// assert_eq!(failure.error, EDHOCError::UnsupportedCipherSuite);
+///
+/// let error_message = prepare_error_wrong_selected_suite();
+/// # assert!(error_message.len > 0);
+/// ```
 #[derive(Debug)]
-pub struct EdhocResponder<'a, Crypto: CryptoTrait> {
-    state: ResponderStart, // opaque state
-    r: &'a [u8],           // private authentication key of R
-    cred_r: CredentialRPK, // R's full credential
+pub struct EdhocResponder<'a, Crypto: CryptoTrait, S: Signer = NoSigner> {
+    r: ResponderKey<'a, S>, // private authentication key of R
+    cred_r: CredentialRPK,  // R's full credential
+    method: Method,         // the kind of key `r` is
+    suite_policy: SuitePolicy,
+    profile: ApplicationProfile,
+    peer_cred_by_value: bool, // whether the peer has to send its credential by value
+    unknown_trailing_data: UnknownTrailingData,
+    report: ReportRecorder,
+    progress_mark: u32, // application-defined, carried through transitions
     crypto: Crypto,
 }
 
+/// The Responder once message_1 is processed, ready to prepare message_2
 #[derive(Debug)]
-pub struct EdhocResponderProcessedM1<'a, Crypto: CryptoTrait> {
-    state: ProcessingM1,   // opaque state
-    r: &'a [u8],           // private authentication key of R
-    cred_r: CredentialRPK, // R's full credential
+pub struct EdhocResponderProcessedM1<'a, Crypto: CryptoTrait, S: Signer = NoSigner> {
+    state: ProcessingM1,                   // opaque state
+    r: ResponderKey<'a, S>,                // private authentication key of R
+    cred_r: CredentialRPK,                 // R's full credential
+    trailing_items: Option<TrailingItems>, // skipped at the end of message_1
+    profile: ApplicationProfile,
+    peer_cred_by_value: bool, // whether the peer has to send its credential by value
+    report: ReportRecorder,
+    progress_mark: u32, // application-defined, carried through transitions
     crypto: Crypto,
 }
 
+/// The Responder once message_2 is prepared
+///
+/// [EdhocResponderProcessedM1::prepare_message_2] moves to this state as soon as it returns,
+/// regardless of when the transport actually sends message_2. A message_3 that arrives before the
+/// transport confirmed message_2 (e.g. over a fast link while sending is queued) is therefore
+/// passed to [EdhocResponderWaitM3::parse_message_3] right away, and needs no buffering.
 #[derive(Debug)]
 pub struct EdhocResponderWaitM3<Crypto: CryptoTrait> {
     state: WaitM3, // opaque state
+    c_i: u8,
+    c_r: u8,
+    g_x: BytesP256ElemLen, // of the Initiator, to recognize a resent message_1
+    message_2: Option<BufferMessage2>, // retained for retransmission, until discarded
+    cred_transfer: CredentialTransfer, // how CRED_R was sent in message_2
+    early_exported: bool,
+    profile: ApplicationProfile,
+    peer_cred_by_value: bool, // whether the peer has to send its credential by value
+    report: ReportRecorder,
+    progress_mark: u32, // application-defined, carried through transitions
     crypto: Crypto,
 }
 
+/// The Responder once message_3 is decrypted, waiting for the credential of the Initiator to be
+/// verified against
 #[derive(Debug)]
 pub struct EdhocResponderProcessingM3<Crypto: CryptoTrait> {
     state: ProcessingM3, // opaque state
+    c_i: u8,
+    early_exported: bool,
+    message_4_required: bool,
+    report: ReportRecorder,
+    progress_mark: u32, // application-defined, carried through transitions
     crypto: Crypto,
 }
 
+/// Final state of the Responder, through which the keys of the handshake are obtained
 #[derive(Debug)]
 pub struct EdhocResponderDone<Crypto: CryptoTrait> {
     state: Completed,
+    c_i: u8,
+    message_4_pending: bool, // the profile requires a message_4 that was not prepared yet
+    report: ReportRecorder,
+    progress_mark: u32, // application-defined, carried through transitions
     crypto: Crypto,
 }
 
+/// How the Responder treats a message_1 whose selected cipher suite it supports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SuitePolicy {
+    /// Any supported selected cipher suite is accepted
+    #[default]
+    AcceptAnySupported,
+    /// The selected cipher suite is rejected if SUITES_I also offers one that the Responder
+    /// prefers, so that the Initiator retries with that one (see
+    /// [prepare_error_wrong_selected_suite])
+    RequireMostPreferred,
+}
+
+/// How the Responder treats items after EAD_1 in message_1, which future extensions of EDHOC
+/// may append
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownTrailingData {
+    /// Everything after C_I is parsed as EAD_1, so unknown items make message_1 fail to parse
+    /// (or end up in the value of EAD_1)
+    #[default]
+    Reject,
+    /// Well-formed items after EAD_1 are skipped and reported by
+    /// [EdhocResponderProcessedM1::unknown_trailing_items]
+    ///
+    /// EAD_1 then has to be a label with at most one bstr value. The skipped items are still
+    /// part of H(message_1), so they are authenticated like the rest of the message.
+    IgnoreAndReport,
+}
+
+/// Constraints of an EDHOC application profile, which both peers agree on out of band
+///
+/// See [RFC 9528 Section 3.9](https://www.rfc-editor.org/rfc/rfc9528#section-3.9). The default
+/// profile allows everything that is supported. A peer that does not adhere to the profile is
+/// rejected with [EDHOCError::UnsupportedMethod], [EDHOCError::UnsupportedCipherSuite] or
+/// [EDHOCError::ProfileViolation].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApplicationProfile {
+    methods: u8, // bit n is set if method n is allowed
+    suites: u32, // bit n is set if cipher suite n is allowed
+    by_reference: bool,
+    by_value: bool,
+    message_4: bool,
+}
+
+impl Default for ApplicationProfile {
+    fn default() -> Self {
+        ApplicationProfile {
+            methods: u8::MAX,
+            suites: u32::MAX,
+            by_reference: true,
+            by_value: true,
+            message_4: false,
+        }
+    }
+}
+
+impl ApplicationProfile {
+    /// Only allows the given methods, which are numbered from 0 to 3
+    pub fn with_methods(mut self, methods: &[u8]) -> Self {
+        self.methods = methods.iter().fold(0, |mask, method| {
+            mask | 1u8.checked_shl(*method as u32).unwrap_or(0)
+        });
+        self
+    }
+
+    /// Only allows the given cipher suites; suites above 31 are never allowed
+    pub fn with_cipher_suites(mut self, suites: &[u8]) -> Self {
+        self.suites = suites.iter().fold(0, |mask, suite| {
+            mask | 1u32.checked_shl(*suite as u32).unwrap_or(0)
+        });
+        self
+    }
+
+    /// Sets whether credentials may be sent by reference and by value in ID_CRED_R and ID_CRED_I
+    pub fn with_credential_transfers(mut self, by_reference: bool, by_value: bool) -> Self {
+        self.by_reference = by_reference;
+        self.by_value = by_value;
+        self
+    }
+
+    /// Sets whether the profile uses message_4
+    ///
+    /// If it does, the exporter and the key update of the completed handshake fail with
+    /// [EDHOCError::ProfileViolation] until the Responder prepared message_4 (see
+    /// [EdhocResponderDone::prepare_message_4]) and the Initiator processed it (see
+    /// [EdhocInitiatorDone::process_message_4]).
+    pub fn with_message_4(mut self, message_4: bool) -> Self {
+        self.message_4 = message_4;
+        self
+    }
+
+    /// Whether the authentication method numbered `method` is allowed
+    pub fn allows_method(&self, method: u8) -> bool {
+        method < u8::BITS as u8 && self.methods & (1 << method) != 0
+    }
+
+    /// Whether the cipher suite `suite` is allowed
+    pub fn allows_cipher_suite(&self, suite: u8) -> bool {
+        suite < u32::BITS as u8 && self.suites & (1 << suite) != 0
+    }
+
+    /// Whether a credential may be transferred as `cred_transfer`, which must be resolved already
+    pub fn allows_credential_transfer(&self, cred_transfer: CredentialTransfer) -> bool {
+        match cred_transfer {
+            CredentialTransfer::ByReference | CredentialTransfer::ByReferenceFullMap => {
+                self.by_reference
+            }
+            CredentialTransfer::ByValue => self.by_value,
+            CredentialTransfer::Auto => false,
+        }
+    }
+
+    /// Whether the profile uses message_4
+    pub fn uses_message_4(&self) -> bool {
+        self.message_4
+    }
+
+    /// Checks how the peer sent its credential, as returned when parsing message_2 or message_3
+    fn check_received_credential(&self, id_cred: &CredentialRPK) -> Result<(), EDHOCError> {
+        let cred_transfer = if id_cred.reference_only() {
+            CredentialTransfer::ByReference
+        } else {
+            CredentialTransfer::ByValue
+        };
+        if self.allows_credential_transfer(cred_transfer) {
+            Ok(())
+        } else {
+            Err(EDHOCError::ProfileViolation)
+        }
+    }
+
+    fn check_sent_credential(&self, cred_transfer: CredentialTransfer) -> Result<(), EDHOCError> {
+        if self.allows_credential_transfer(cred_transfer) {
+            Ok(())
+        } else {
+            Err(EDHOCError::ProfileViolation)
+        }
+    }
+}
+
+/// Whether an ephemeral key pair was produced, as back-ends that fail to return all zeros (see
+/// [CryptoTrait::p256_generate_key_pair])
+fn is_generated_key_pair(private_key: &BytesP256ElemLen, public_key: &BytesP256ElemLen) -> bool {
+    *private_key != [0x00; P256_ELEM_LEN] && *public_key != [0x00; P256_ELEM_LEN]
+}
+
+/// Phase of the handshake, as reported in an [EdhocFailure]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeStep {
+    /// [EdhocInitiator::prepare_message_1]
+    PrepareMessage1,
+    /// [EdhocResponder::process_message_1]
+    ProcessMessage1,
+    /// [EdhocResponderProcessedM1::prepare_message_2]
+    PrepareMessage2,
+    /// [EdhocInitiatorWaitM2::parse_message_2]
+    ParseMessage2,
+    /// [EdhocInitiatorProcessingM2::verify_message_2]
+    VerifyMessage2,
+    /// [EdhocInitiatorProcessedM2::prepare_message_3]
+    PrepareMessage3,
+    /// [EdhocResponderWaitM3::parse_message_3]
+    ParseMessage3,
+    /// [EdhocResponderProcessingM3::verify_message_3]
+    VerifyMessage3,
+}
+
+/// State of a handshake, as reported by the `stage` method of each state machine type
+///
+/// Initiator and responder go through different stages; only [HandshakeStage::Start] and
+/// [HandshakeStage::Done] are shared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeStage {
+    /// Nothing was sent or received yet
+    Start,
+    /// The Responder processed message_1
+    ProcessedM1,
+    /// The Initiator sent message_1
+    WaitM2,
+    /// The Initiator decrypted message_2
+    ProcessingM2,
+    /// The Initiator verified message_2
+    ProcessedM2,
+    /// The Responder sent message_2
+    WaitM3,
+    /// The Responder decrypted message_3
+    ProcessingM3,
+    /// The handshake completed
+    Done,
+}
+
+/// Error returned by the [EdhocInitiator] and [EdhocResponder] state machines
+///
+/// It carries the [EDHOCError] of the underlying function along with the step of the handshake
+/// that produced it.
+///
+/// # Examples
+///
+/// The step tells where a handshake failed, and `?` turns the failure into its [EDHOCError]:
+///
+/// ```
+/// use lakers::doctest_helpers::{credential_r, R};
+/// use lakers::*;
+/// use lakers_crypto::default_crypto;
+///
+/// fn respond(message_1: &BufferMessage1) -> Result<BufferMessage2, EDHOCError> {
+///     let responder = EdhocResponder::new(default_crypto(), &R, credential_r())?;
+///     let (responder, _ead_1) = responder.process_message_1(message_1)?;
+///     let (_responder, message_2) =
+///         responder.prepare_message_2(CredentialTransfer::ByReference, None, &None)?;
+///     Ok(message_2)
+/// }
+///
+/// // message_1 that ends within G_X
+/// let truncated = EdhocMessageBuffer::from_hex("03820602582000");
+/// assert_eq!(respond(&truncated), Err(EDHOCError::ParsingError));
+///
+/// let responder = EdhocResponder::new(default_crypto(), &R, credential_r()).unwrap();
+/// let failure = responder.process_message_1(&truncated).unwrap_err();
+/// assert_eq!(failure.step, HandshakeStep::ProcessMessage1);
+/// ```
+#[derive(Debug, PartialEq)]
+pub struct EdhocFailure {
+    /// What went wrong
+    pub error: EDHOCError,
+    /// Where it went wrong
+    pub step: HandshakeStep,
+}
+
+impl EdhocFailure {
+    /// A failure of `step` because of `error`
+    pub fn new(error: EDHOCError, step: HandshakeStep) -> Self {
+        Self { error, step }
+    }
+}
+
+impl From<EdhocFailure> for EDHOCError {
+    fn from(failure: EdhocFailure) -> Self {
+        failure.error
+    }
+}
+
+impl core::fmt::Display for EdhocFailure {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "EDHOC failed at {:?}", self.step)
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl std::error::Error for EdhocFailure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Implements `stage`, `set_progress_mark` and `progress_mark` for a state machine type.
+macro_rules! impl_progress {
+    ($ty:ty, $stage:ident $(, $generic:ident: $bound:path)?) => {
+        impl<'a, Crypto: CryptoTrait $(, $generic: $bound)?> $ty {
+            /// Stage of the handshake, e.g. for reporting sessions that are stuck
+            pub fn stage(&self) -> HandshakeStage {
+                HandshakeStage::$stage
+            }
+
+            /// Stores a value of the application's choice, such as a monotonic timestamp.
+            ///
+            /// The value is carried over to the state that the next step of the handshake returns.
+            pub fn set_progress_mark(&mut self, mark: u32) {
+                self.progress_mark = mark;
+            }
+
+            /// The value last passed to `set_progress_mark`, or 0 if there was none
+            pub fn progress_mark(&self) -> u32 {
+                self.progress_mark
+            }
+        }
+    };
+}
+
+impl_progress!(EdhocInitiator<'a, Crypto>, Start);
+impl_progress!(EdhocInitiatorWaitM2<'a, Crypto>, WaitM2);
+impl_progress!(EdhocInitiatorProcessingM2<Crypto>, ProcessingM2);
+impl_progress!(EdhocInitiatorProcessedM2<Crypto>, ProcessedM2);
+impl_progress!(EdhocInitiatorDone<Crypto>, Done);
+impl_progress!(EdhocResponder<'a, Crypto, S>, Start, S: Signer);
+impl_progress!(EdhocResponderProcessedM1<'a, Crypto, S>, ProcessedM1, S: Signer);
+impl_progress!(EdhocResponderWaitM3<Crypto>, WaitM3);
+impl_progress!(EdhocResponderProcessingM3<Crypto>, ProcessingM3);
+impl_progress!(EdhocResponderDone<Crypto>, Done);
+
+/// Label of a key derived through the EDHOC exporter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExporterLabel(pub u8);
+
+impl ExporterLabel {
+    /// The OSCORE Master Secret, see RFC 9528 Appendix A.1
+    pub const OSCORE_MASTER_SECRET: Self = ExporterLabel(0);
+    /// The OSCORE Master Salt, see RFC 9528 Appendix A.1
+    pub const OSCORE_MASTER_SALT: Self = ExporterLabel(1);
+}
+
+/// COSE key type of an exported key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CoseKty {
+    /// A symmetric key, as exported for OSCORE
+    Symmetric = 4,
+}
+
+/// Static identity of a Responder, to be shared by all the sessions it serves
+///
+/// The credential is parsed, and checked to match the private key, once when the identity is
+/// created; [EdhocResponder::from_identity] then only borrows it. The identity is never modified
+/// by a handshake, so a single instance can be used by any number of concurrent sessions, also
+/// across threads.
+#[derive(Clone)]
+pub struct ResponderIdentity {
+    r: P256PrivateKey,
+    cred_r: CredentialRPK,
+}
+
+// leaves out the private key, as identities end up in logs
+impl core::fmt::Debug for ResponderIdentity {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ResponderIdentity")
+            .field("cred_r", &self.cred_r)
+            .finish_non_exhaustive()
+    }
+}
+
+// sessions on different threads share one identity
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<ResponderIdentity>();
+};
+
+impl ResponderIdentity {
+    /// Returns [EDHOCError::InvalidParameter] if the public key of `cred_r` is not the one of `r`
+    pub fn new(
+        crypto: &mut impl CryptoTrait,
+        r: P256PrivateKey,
+        cred_r: CredentialRPK,
+    ) -> Result<Self, EDHOCError> {
+        let d: &BytesP256ElemLen = r
+            .as_slice()
+            .try_into()
+            .map_err(|_| EDHOCError::InvalidKey)?;
+        if crypto.p256_public_from_private(d)? != cred_r.public_key {
+            return Err(EDHOCError::InvalidParameter);
+        }
+        Ok(ResponderIdentity { r, cred_r })
+    }
+
+    /// The credential of the Responder
+    pub fn cred_r(&self) -> &CredentialRPK {
+        &self.cred_r
+    }
+}
+
+/// Private authentication key of a Responder
+#[derive(Debug)]
+enum ResponderKey<'a, S> {
+    Raw(&'a [u8]),
+    Signer(S),
+}
+
+/// [Signer] of a Responder whose private key is given as raw bytes, which is never used
+#[derive(Debug)]
+pub enum NoSigner {}
+
+impl Signer for NoSigner {
+    fn static_dh(&self, _peer_point: &BytesP256ElemLen) -> Result<BytesP256ElemLen, EDHOCError> {
+        match *self {}
+    }
+}
+
 impl<'a, Crypto: CryptoTrait> EdhocResponder<'a, Crypto> {
-    pub fn new(mut crypto: Crypto, r: &'a [u8], cred_r: CredentialRPK) -> Self {
-        assert!(r.len() == P256_ELEM_LEN);
-        let (y, g_y) = crypto.p256_generate_key_pair();
+    /// Starts a session of a Responder whose identity is shared with other sessions
+    pub fn from_identity(crypto: Crypto, identity: &'a ResponderIdentity) -> Self {
+        // the length of the identity key was checked when the identity was created
+        Self::from_key(
+            crypto,
+            ResponderKey::Raw(identity.r.as_slice()),
+            identity.cred_r,
+        )
+    }
+
+    /// The private key `r` is given as raw bytes, or as a [P256PrivateKey]
+    ///
+    /// This is cheap: the ephemeral key is only generated once a message_1 passed the checks of
+    /// [prevalidate_message_1].
+    ///
+    /// Fails with [EDHOCError::InvalidKey] if `r` is not [P256_ELEM_LEN] bytes long.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lakers::doctest_helpers::{credential_r, R};
+    /// use lakers::*;
+    /// use lakers_crypto::default_crypto;
+    ///
+    /// let responder = EdhocResponder::new(default_crypto(), &R[..31], credential_r());
+    /// assert_eq!(responder.unwrap_err(), EDHOCError::InvalidKey);
+    /// ```
+    pub fn new(
+        crypto: Crypto,
+        r: &'a (impl AsRef<[u8]> + ?Sized),
+        cred_r: CredentialRPK,
+    ) -> Result<Self, EDHOCError> {
+        let r = r.as_ref();
+        if r.len() != P256_ELEM_LEN {
+            return Err(EDHOCError::InvalidKey);
+        }
+
+        Ok(Self::from_key(crypto, ResponderKey::Raw(r), cred_r))
+    }
+}
+
+impl<'a, Crypto: CryptoTrait, S: Signer> EdhocResponder<'a, Crypto, S> {
+    /// The private key is held by `signer`, so that it does not need to be in memory
+    pub fn new_with_signer(crypto: Crypto, signer: S, cred_r: CredentialRPK) -> Self {
+        Self::from_key(crypto, ResponderKey::Signer(signer), cred_r)
+    }
 
+    fn from_key(crypto: Crypto, r: ResponderKey<'a, S>, cred_r: CredentialRPK) -> Self {
         EdhocResponder {
-            state: ResponderStart { y, g_y },
             r,
             cred_r,
+            method: Method::default(),
+            suite_policy: SuitePolicy::default(),
+            profile: ApplicationProfile::default(),
+            peer_cred_by_value: false,
+            unknown_trailing_data: UnknownTrailingData::default(),
+            report: ReportRecorder::new(),
+            progress_mark: 0,
             crypto,
         }
     }
 
+    /// Sets the authentication method, which is [Method::StaticStatic] by default
+    ///
+    /// The method has to fit the private key: if the Responder signs (see
+    /// [Method::responder_signs]), it is an ECDSA P-256 signing key, and otherwise a static DH key.
+    /// A message_1 that uses a different method is rejected with [EDHOCError::UnsupportedMethod].
+    pub fn with_method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// Sets how the cipher suite selected in message_1 is checked, which is
+    /// [SuitePolicy::AcceptAnySupported] by default
+    pub fn with_suite_policy(mut self, suite_policy: SuitePolicy) -> Self {
+        self.suite_policy = suite_policy;
+        self
+    }
+
+    /// Sets the application profile the handshake has to adhere to
+    pub fn with_application_profile(mut self, profile: ApplicationProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Sets whether the Initiator has to send its credential by value in message_3, which is not
+    /// required by default
+    ///
+    /// A message_3 that only references the credential is then rejected by
+    /// [EdhocResponderWaitM3::parse_message_3] with [EDHOCError::CredentialTransferPolicyViolation].
+    pub fn require_peer_credential_by_value(mut self, required: bool) -> Self {
+        self.peer_cred_by_value = required;
+        self
+    }
+
+    /// Sets how items after EAD_1 in message_1 are treated, which is
+    /// [UnknownTrailingData::Reject] by default
+    pub fn with_unknown_trailing_data(
+        mut self,
+        unknown_trailing_data: UnknownTrailingData,
+    ) -> Self {
+        self.unknown_trailing_data = unknown_trailing_data;
+        self
+    }
+
+    /// Applies the suite policy to the SUITES_I of a message_1, given the Responder's SUITES_R
+    fn check_selected_suite(&self, suites_i: &[u8], suites_r: &[u8]) -> Result<(), EDHOCError> {
+        if self.suite_policy != SuitePolicy::AcceptAnySupported
+            && !selected_suite_acceptable(self.suite_policy, suites_i, suites_r)
+        {
+            return Err(EDHOCError::UnsupportedCipherSuite);
+        }
+        Ok(())
+    }
+
+    /// Processes message_1, returning its EAD_1 along with the state that prepares message_2
+    ///
+    /// Fails with [EDHOCError::UnsupportedMethod] or [EDHOCError::UnsupportedCipherSuite] if the
+    /// method or the selected cipher suite are not supported or not allowed.
     pub fn process_message_1(
         mut self,
         message_1: &BufferMessage1,
-    ) -> Result<(EdhocResponderProcessedM1<'a, Crypto>, Option<EADItem>), EDHOCError> {
-        let (state, ead_1) = r_process_message_1(&self.state, &mut self.crypto, message_1)?;
+    ) -> Result<(EdhocResponderProcessedM1<'a, Crypto, S>, EadItems), EdhocFailure> {
+        let (known_len, trailing_items) = match self.unknown_trailing_data {
+            UnknownTrailingData::Reject => (message_1.len, TrailingItems::default()),
+            UnknownTrailingData::IgnoreAndReport => {
+                if message_1.len > MAX_MESSAGE_SIZE_LEN {
+                    return Err(EdhocFailure::new(
+                        EDHOCError::MessageTooLong,
+                        HandshakeStep::ProcessMessage1,
+                    ));
+                }
+                split_message_1_trailing_items(message_1.as_slice())
+                    .map_err(|error| EdhocFailure::new(error, HandshakeStep::ProcessMessage1))?
+            }
+        };
+        let trailing_items = (trailing_items.items > 0).then_some(trailing_items);
+
+        let summary = prevalidate_message_1(&message_1.as_slice()[..known_len])
+            .map_err(|error| EdhocFailure::new(error, HandshakeStep::ProcessMessage1))?;
+        if summary.method != self.method as u8 || !self.profile.allows_method(summary.method) {
+            return Err(EdhocFailure::new(
+                EDHOCError::UnsupportedMethod,
+                HandshakeStep::ProcessMessage1,
+            ));
+        }
+        if !self.profile.allows_cipher_suite(summary.selected_suite) {
+            return Err(EdhocFailure::new(
+                EDHOCError::UnsupportedCipherSuite,
+                HandshakeStep::ProcessMessage1,
+            ));
+        }
+        self.check_selected_suite(summary.suites_i(), &EDHOC_SUPPORTED_SUITES)
+            .map_err(|error| EdhocFailure::new(error, HandshakeStep::ProcessMessage1))?;
+
+        let (y, g_y) = self.crypto.p256_generate_key_pair();
+        if !is_generated_key_pair(&y, &g_y) {
+            return Err(EdhocFailure::new(
+                EDHOCError::RngFailure,
+                HandshakeStep::ProcessMessage1,
+            ));
+        }
+        let mut start = ResponderStart::new(y, g_y);
+        start.method = self.method;
+        let (state, ead_1) = r_process_message_1_ignoring_trailing_items(
+            &start,
+            &mut self.crypto,
+            message_1,
+            known_len,
+        )
+        .map_err(|error| EdhocFailure::new(error, HandshakeStep::ProcessMessage1))?;
+        self.report.message_1(message_1, trailing_items);
 
         Ok((
             EdhocResponderProcessedM1 {
                 state,
                 r: self.r,
                 cred_r: self.cred_r,
+                trailing_items,
+                profile: self.profile,
+                peer_cred_by_value: self.peer_cred_by_value,
+                report: self.report,
+                progress_mark: self.progress_mark,
                 crypto: self.crypto,
             },
             ead_1,
         ))
     }
+
+    /// Processes message_1 and prepares message_2 in one go, for responders that need neither EAD
+    /// nor a specific C_R.
+    ///
+    /// C_R is generated, and message_2 is sent without EAD. Critical EAD items in message_1 can
+    /// not be handled this way and result in an error; non-critical ones are ignored.
+    pub fn respond_to_message_1(
+        self,
+        message_1: &BufferMessage1,
+        cred_transfer: CredentialTransfer,
+    ) -> Result<(EdhocResponderWaitM3<Crypto>, BufferMessage2), EdhocFailure> {
+        let (responder, ead_1) = self.process_message_1(message_1)?;
+        if ead_1.iter().any(|ead_1| ead_1.is_critical) {
+            return Err(EdhocFailure::new(
+                EDHOCError::EADError,
+                HandshakeStep::ProcessMessage1,
+            ));
+        }
+        responder.prepare_message_2(cred_transfer, None, &None)
+    }
 }
 
-impl<'a, Crypto: CryptoTrait> EdhocResponderProcessedM1<'a, Crypto> {
+impl<'a, Crypto: CryptoTrait, S: Signer> EdhocResponderProcessedM1<'a, Crypto, S> {
+    /// Unknown items that were skipped at the end of message_1 (see
+    /// [UnknownTrailingData::IgnoreAndReport]), if there were any
+    pub fn unknown_trailing_items(&self) -> Option<TrailingItems> {
+        self.trailing_items
+    }
+
+    /// The cipher suites the Initiator offered in message_1, in its order of preference and with
+    /// the selected one last
+    ///
+    /// Suites preferred over the selected one indicate a downgrade if they are supported here,
+    /// and are what SUITES_R of an error message is built from.
+    pub fn offered_suites(&self) -> &[u8] {
+        &self.state.suites_i[..self.state.suites_i_len]
+    }
+
+    /// Derives a key for encrypting the value of a custom EAD_2 item.
+    ///
+    /// The derivation is specific to lakers and not interoperable, see [i_derive_ead_2_key].
+    /// The initiator obtains the same key from [EdhocInitiatorProcessingM2::derive_ead_key].
+    /// Fails like [EdhocResponderProcessedM1::prepare_message_2] would if the key exchange is
+    /// unusable.
+    pub fn derive_ead_key(
+        &mut self,
+        label: u8,
+        context: &[u8],
+        length: usize,
+    ) -> Result<[u8; MAX_BUFFER_LEN], EdhocFailure> {
+        check_kdf_parameters(context, length)
+            .map_err(|error| EdhocFailure::new(error, HandshakeStep::PrepareMessage2))?;
+        let mut context_buf: BytesMaxContextBuffer = [0x00u8; MAX_KDF_CONTEXT_LEN];
+        context_buf[..context.len()].copy_from_slice(context);
+
+        r_derive_ead_2_key(
+            &self.state,
+            &mut self.crypto,
+            label,
+            &context_buf,
+            context.len(),
+            length,
+        )
+        .map_err(|error| EdhocFailure::new(error, HandshakeStep::PrepareMessage2))
+    }
+
+    /// Prepares message_2, sending the credential of the Responder as `cred_transfer`
+    ///
+    /// C_R is generated if not given; [CONN_ID_EMPTY] may be given to use the empty byte string.
     pub fn prepare_message_2(
-        mut self,
+        self,
         cred_transfer: CredentialTransfer,
         c_r: Option<u8>,
         ead_2: &Option<EADItem>,
-    ) -> Result<(EdhocResponderWaitM3<Crypto>, BufferMessage2), EDHOCError> {
+    ) -> Result<(EdhocResponderWaitM3<Crypto>, BufferMessage2), EdhocFailure> {
+        self.prepare_message_2_with(cred_transfer, c_r, |_| ead_2.clone())
+    }
+
+    /// Like [Self::prepare_message_2], with EAD_2 computed by `ead_2` from values that are only
+    /// known while message_2 is prepared, such as TH_2 and the chosen C_R (see [Ead2Context])
+    ///
+    /// E.g. evidence for remote attestation can so be bound to TH_2, which the Initiator obtains
+    /// from [EdhocInitiatorProcessingM2::th_2].
+    pub fn prepare_message_2_with(
+        mut self,
+        cred_transfer: CredentialTransfer,
+        c_r: Option<u8>,
+        ead_2: impl FnOnce(&Ead2Context) -> Option<EADItem>,
+    ) -> Result<(EdhocResponderWaitM3<Crypto>, BufferMessage2), EdhocFailure> {
         let c_r = match c_r {
             Some(c_r) => c_r,
-            None => generate_connection_identifier_cbor(&mut self.crypto),
+            None => generate_connection_identifier_cbor(&mut self.crypto)
+                .map_err(|error| EdhocFailure::new(error, HandshakeStep::PrepareMessage2))?,
         };
+        let cred_transfer = resolve_credential_transfer(&self.cred_r, cred_transfer)
+            .and_then(|cred_transfer| {
+                self.profile.check_sent_credential(cred_transfer)?;
+                Ok(cred_transfer)
+            })
+            .map_err(|error| EdhocFailure::new(error, HandshakeStep::PrepareMessage2))?;
+        self.report.own_credential(&self.cred_r, cred_transfer);
 
-        match r_prepare_message_2(
-            &self.state,
-            &mut self.crypto,
-            self.cred_r,
-            self.r.try_into().expect("Wrong length of private key"),
-            c_r,
-            cred_transfer,
-            ead_2,
-        ) {
-            Ok((state, message_2)) => Ok((
-                EdhocResponderWaitM3 {
-                    state,
-                    crypto: self.crypto,
-                },
-                message_2,
-            )),
-            Err(error) => Err(error),
+        let prepared = match &self.r {
+            ResponderKey::Raw(r) => r_prepare_message_2_with_ead(
+                &self.state,
+                &mut self.crypto,
+                self.cred_r,
+                (*r).try_into().expect("Wrong length of private key"),
+                c_r,
+                cred_transfer,
+                ead_2,
+            ),
+            ResponderKey::Signer(signer) => r_prepare_message_2_with_signer_and_ead(
+                &self.state,
+                &mut self.crypto,
+                self.cred_r,
+                signer,
+                c_r,
+                cred_transfer,
+                ead_2,
+            ),
+        };
+        match prepared {
+            Ok((state, message_2, ead_2)) => {
+                self.report.message_2(&message_2, ead_2.as_slice());
+                Ok((
+                    EdhocResponderWaitM3 {
+                        state,
+                        c_i: self.state.c_i,
+                        c_r,
+                        g_x: self.state.g_x,
+                        message_2: Some(message_2),
+                        cred_transfer,
+                        early_exported: false,
+                        profile: self.profile,
+                        peer_cred_by_value: self.peer_cred_by_value,
+                        report: self.report,
+                        progress_mark: self.progress_mark,
+                        crypto: self.crypto,
+                    },
+                    message_2,
+                ))
+            }
+            Err(error) => Err(EdhocFailure::new(error, HandshakeStep::PrepareMessage2)),
         }
     }
 }
 
 impl<'a, Crypto: CryptoTrait> EdhocResponderWaitM3<Crypto> {
-    pub fn parse_message_3(
-        mut self,
-        message_3: &'a BufferMessage3,
-    ) -> Result<
-        (
-            EdhocResponderProcessingM3<Crypto>,
-            CredentialRPK,
-            Option<EADItem>,
-        ),
-        EDHOCError,
-    > {
-        match r_parse_message_3(&mut self.state, &mut self.crypto, message_3) {
-            Ok((state, id_cred_i, ead_3)) => Ok((
-                EdhocResponderProcessingM3 {
-                    state,
-                    crypto: self.crypto,
-                },
-                id_cred_i,
-                ead_3,
-            )),
-            Err(error) => Err(error),
-        }
+    /// C_R as chosen for message_2, in the form returned by [EdhocInitiatorWaitM2::parse_message_2]
+    ///
+    /// E.g. to find the session of a message_3 that a CoAP client prefixes with C_R.
+    pub fn c_r(&self) -> u8 {
+        self.c_r
     }
-}
 
-impl<'a, Crypto: CryptoTrait> EdhocResponderProcessingM3<Crypto> {
-    pub fn verify_message_3(
-        mut self,
-        cred_i: CredentialRPK,
-    ) -> Result<(EdhocResponderDone<Crypto>, [u8; SHA256_DIGEST_LEN]), EDHOCError> {
-        match r_verify_message_3(&mut self.state, &mut self.crypto, cred_i) {
-            Ok((state, prk_out)) => Ok((
-                EdhocResponderDone {
-                    state,
-                    crypto: self.crypto,
-                },
-                prk_out,
-            )),
-            Err(error) => Err(error),
+    /// How CRED_R was sent in message_2, i.e. what [CredentialTransfer::Auto] resolved to
+    pub fn credential_transfer(&self) -> CredentialTransfer {
+        self.cred_transfer
+    }
+
+    /// The message_2 that was sent, to be resent if the Initiator does not seem to have received it.
+    ///
+    /// Returns None once [EdhocResponderWaitM3::discard_retained] was called.
+    pub fn retained_message_2(&self) -> Option<&BufferMessage2> {
+        self.message_2.as_ref()
+    }
+
+    /// The retained message_2 if `message_1` is a resent copy of the message_1 it answers, i.e.
+    /// carries the same G_X.
+    ///
+    /// A duplicate message_1 is to be answered with this rather than be processed again: that
+    /// would produce a different message_2, which the Initiator can not tell apart from an
+    /// attack. Neither this nor [EdhocResponderWaitM3::retained_message_2] performs any
+    /// cryptographic operation.
+    pub fn retained_message_2_for(&self, message_1: &BufferMessage1) -> Option<&BufferMessage2> {
+        let (_method, _suites_i, _suites_i_len, g_x, _c_i, _ead_1) =
+            parse_message_1(message_1).ok()?;
+        if g_x != self.g_x {
+            return None;
         }
+        self.retained_message_2()
     }
-}
 
-impl<Crypto: CryptoTrait> EdhocResponderDone<Crypto> {
-    pub fn edhoc_exporter(
+    /// Drops the retained message_2 to reclaim its memory.
+    pub fn discard_retained(&mut self) {
+        self.message_2 = None;
+    }
+
+    /// Exports keying material before message_3 was received.
+    ///
+    /// **This is specific to lakers and not interoperable**: RFC 9528 defines no such exporter,
+    /// so the Initiator can only use these keys if it follows the same derivation (see
+    /// [r_early_exporter]).
+    ///
+    /// **The keys are not bound to an authenticated initiator**: until
+    /// [EdhocResponderProcessingM3::verify_message_3] succeeds, the peer may be anyone, and data
+    /// protected with these keys must be treated accordingly (e.g. only sending data that may be
+    /// disclosed to an unauthenticated party). The keys differ from those of
+    /// [EdhocResponderDone::edhoc_exporter] for the same label and context.
+    ///
+    /// Using this is recorded, see [EdhocResponderDone::early_exported].
+    ///
+    /// Fails with [EDHOCError::InvalidParameter] if `context` or `length` exceed
+    /// [MAX_EXPORTER_CONTEXT_LEN] or [MAX_EXPORTER_OUTPUT_LEN].
+    pub fn early_exporter(
         &mut self,
         label: u8,
         context: &[u8],
         length: usize,
-    ) -> [u8; MAX_BUFFER_LEN] {
+    ) -> Result<[u8; MAX_BUFFER_LEN], EDHOCError> {
+        check_kdf_parameters(context, length)?;
         let mut context_buf: BytesMaxContextBuffer = [0x00u8; MAX_KDF_CONTEXT_LEN];
         context_buf[..context.len()].copy_from_slice(context);
 
-        edhoc_exporter(
+        self.early_exported = true;
+        self.report.exporter(label, true);
+        Ok(r_early_exporter(
             &self.state,
             &mut self.crypto,
             label,
             &context_buf,
             context.len(),
             length,
-        )
+        ))
     }
 
-    pub fn edhoc_key_update(&mut self, context: &[u8]) -> [u8; SHA256_DIGEST_LEN] {
-        let mut context_buf = [0x00u8; MAX_KDF_CONTEXT_LEN];
-        context_buf[..context.len()].copy_from_slice(context);
-
-        edhoc_key_update(
+    /// Decrypts message_3, returning ID_CRED_I, which is to be resolved to the credential of the
+    /// Initiator (see [credential_check_or_fetch]), and EAD_3
+    pub fn parse_message_3(
+        mut self,
+        message_3: &'a BufferMessage3,
+    ) -> Result<(EdhocResponderProcessingM3<Crypto>, CredentialRPK, EadItems), EdhocFailure> {
+        match r_parse_message_3_with_transfer_policy(
             &mut self.state,
             &mut self.crypto,
-            &context_buf,
-            context.len(),
+            message_3,
+            self.peer_cred_by_value,
         )
-    }
-}
-
-impl<'a, Crypto: CryptoTrait> EdhocInitiator<Crypto> {
-    pub fn new(mut crypto: Crypto) -> Self {
-        // we only support a single cipher suite which is already CBOR-encoded
-        let mut suites_i: BytesSuites = [0x0; SUITES_LEN];
-        let suites_i_len = EDHOC_SUPPORTED_SUITES.len();
-        suites_i[0..suites_i_len].copy_from_slice(&EDHOC_SUPPORTED_SUITES[..]);
-        let (x, g_x) = crypto.p256_generate_key_pair();
-
-        EdhocInitiator {
-            state: InitiatorStart {
-                x,
-                g_x,
-                suites_i,
-                suites_i_len,
-            },
-            crypto,
+        .and_then(|(state, id_cred_i, ead_3)| {
+            self.profile.check_received_credential(&id_cred_i)?;
+            Ok((state, id_cred_i, ead_3))
+        }) {
+            Ok((state, id_cred_i, ead_3)) => {
+                self.report.peer_credential(&id_cred_i);
+                self.report.message_3(message_3, ead_3.as_slice());
+                Ok((
+                    EdhocResponderProcessingM3 {
+                        state,
+                        c_i: self.c_i,
+                        early_exported: self.early_exported,
+                        message_4_required: self.profile.uses_message_4(),
+                        report: self.report,
+                        progress_mark: self.progress_mark,
+                        crypto: self.crypto,
+                    },
+                    id_cred_i,
+                    ead_3,
+                ))
+            }
+            Err(error) => Err(EdhocFailure::new(error, HandshakeStep::ParseMessage3)),
         }
     }
 
-    pub fn prepare_message_1(
-        mut self,
-        c_i: Option<u8>,
-        ead_1: &Option<EADItem>,
-    ) -> Result<(EdhocInitiatorWaitM2<Crypto>, EdhocMessageBuffer), EDHOCError> {
-        let c_i = match c_i {
-            Some(c_i) => c_i,
-            None => generate_connection_identifier_cbor(&mut self.crypto),
-        };
-
-        match i_prepare_message_1(&self.state, &mut self.crypto, c_i, ead_1) {
-            Ok((state, message_1)) => Ok((
-                EdhocInitiatorWaitM2 {
-                    state,
-                    crypto: self.crypto,
-                },
-                message_1,
-            )),
-            Err(error) => Err(error),
-        }
+    /// Like [Self::parse_message_3], but for a message_3 that the transport wraps in an
+    /// additional CBOR byte string (see [unwrap_message_3])
+    ///
+    /// Which of the two forms is used is fixed by the transport, so a bare message_3 is not
+    /// accepted here, nor a wrapped one by [Self::parse_message_3].
+    pub fn parse_message_3_wrapped(
+        self,
+        message_3: &BufferMessage3,
+    ) -> Result<(EdhocResponderProcessingM3<Crypto>, CredentialRPK, EadItems), EdhocFailure> {
+        let message_3 = unwrap_message_3(message_3)
+            .map_err(|error| EdhocFailure::new(error, HandshakeStep::ParseMessage3))?;
+        self.parse_message_3(&message_3)
     }
+}
 
-    pub fn compute_ephemeral_secret(&mut self, g_a: &BytesP256ElemLen) -> BytesP256ElemLen {
-        self.crypto.p256_ecdh(&self.state.x, g_a)
+impl<'a, Crypto: CryptoTrait> EdhocResponderProcessingM3<Crypto> {
+    /// TH_3, e.g. to verify an EAD_3 item that is bound to it
+    pub fn th_3(&self) -> &BytesHashLen {
+        &self.state.th_3
     }
 
-    pub fn selected_cipher_suite(&self) -> u8 {
-        self.state.suites_i[self.state.suites_i_len - 1]
-    }
+    /// Derives a key for decrypting the value of a custom EAD_3 item.
+    ///
+    /// The derivation is specific to lakers and not interoperable, see [r_derive_ead_3_key].
+    /// The key is not bound to the initiator's identity until [Self::verify_message_3] succeeds.
+    ///
+    /// Fails with [EDHOCError::InvalidParameter] if `context` or `length` exceed
+    /// [MAX_EXPORTER_CONTEXT_LEN] or [MAX_EXPORTER_OUTPUT_LEN].
+    pub fn derive_ead_key(
+        &mut self,
+        label: u8,
+        context: &[u8],
+        length: usize,
+    ) -> Result<[u8; MAX_BUFFER_LEN], EDHOCError> {
+        check_kdf_parameters(context, length)?;
+        let mut context_buf: BytesMaxContextBuffer = [0x00u8; MAX_KDF_CONTEXT_LEN];
+        context_buf[..context.len()].copy_from_slice(context);
+
+        Ok(r_derive_ead_3_key(
+            &self.state,
+            &mut self.crypto,
+            label,
+            &context_buf,
+            context.len(),
+            length,
+        ))
+    }
+
+    /// Verifies message_3 against the credential of the Initiator, returning PRK_out
+    pub fn verify_message_3(
+        mut self,
+        cred_i: CredentialRPK,
+    ) -> Result<(EdhocResponderDone<Crypto>, [u8; SHA256_DIGEST_LEN]), EdhocFailure> {
+        match r_verify_message_3(&mut self.state, &mut self.crypto, cred_i) {
+            Ok((mut state, prk_out)) => {
+                state.early_exported = self.early_exported;
+                Ok((
+                    EdhocResponderDone {
+                        state,
+                        c_i: self.c_i,
+                        message_4_pending: self.message_4_required,
+                        report: self.report,
+                        progress_mark: self.progress_mark,
+                        crypto: self.crypto,
+                    },
+                    prk_out,
+                ))
+            }
+            Err(error) => Err(EdhocFailure::new(error, HandshakeStep::VerifyMessage3)),
+        }
+    }
+}
+
+impl<Crypto: CryptoTrait> EdhocResponderDone<Crypto> {
+    /// Whether [EdhocResponderWaitM3::early_exporter] was used during the handshake
+    pub fn early_exported(&self) -> bool {
+        self.state.early_exported
+    }
+
+    /// Prepares message_4, which confirms to the Initiator that the handshake completed
+    ///
+    /// message_4 is optional unless the [ApplicationProfile] uses it, in which case keys can only
+    /// be exported once it was prepared.
+    pub fn prepare_message_4(
+        &mut self,
+        ead_4: &Option<EADItem>,
+    ) -> Result<BufferMessage4, EDHOCError> {
+        let message_4 = r_prepare_message_4(&self.state, &mut self.crypto, ead_4)?;
+        self.message_4_pending = false;
+        Ok(message_4)
+    }
+
+    fn check_message_4(&self) -> Result<(), EDHOCError> {
+        if self.message_4_pending {
+            Err(EDHOCError::ProfileViolation)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The transcript hash TH_4 over the completed handshake, e.g. for channel binding
+    pub fn th_4(&self) -> &BytesHashLen {
+        &self.state.th_4
+    }
+
+    /// EDHOC_Exporter(label, context, length), of which the first `length` bytes are valid
+    ///
+    /// Fails with [EDHOCError::InvalidParameter] if `context` or `length` exceed
+    /// [MAX_EXPORTER_CONTEXT_LEN] or [MAX_EXPORTER_OUTPUT_LEN].
+    pub fn edhoc_exporter(
+        &mut self,
+        label: u8,
+        context: &[u8],
+        length: usize,
+    ) -> Result<[u8; MAX_BUFFER_LEN], EDHOCError> {
+        check_kdf_parameters(context, length)?;
+        self.check_message_4()?;
+        self.report.exporter(label, false);
+        edhoc_exporter_from_slice(&self.state, &mut self.crypto, label, context, length)
+    }
+
+    /// Like [Self::edhoc_exporter], but writes the key into `out` instead of returning it,
+    /// e.g. into memory that is reserved for keys
+    pub fn edhoc_exporter_into(
+        &mut self,
+        label: u8,
+        context: &[u8],
+        length: usize,
+        out: &mut impl KeyOutput,
+    ) -> Result<(), EDHOCError> {
+        check_kdf_parameters(context, length)?;
+        self.check_message_4()?;
+        self.report.exporter(label, false);
+        edhoc_exporter_into(&self.state, &mut self.crypto, label, context, length, out)
+    }
+
+    /// EDHOC_KeyUpdate(context), which changes all keys exported afterwards, returning the new
+    /// PRK_out
+    ///
+    /// Fails with [EDHOCError::InvalidParameter] if `context` is longer than
+    /// [MAX_EXPORTER_CONTEXT_LEN].
+    pub fn edhoc_key_update(
+        &mut self,
+        context: &[u8],
+    ) -> Result<[u8; SHA256_DIGEST_LEN], EDHOCError> {
+        check_kdf_parameters(context, SHA256_DIGEST_LEN)?;
+        self.check_message_4()?;
+        let mut context_buf = [0x00u8; MAX_KDF_CONTEXT_LEN];
+        context_buf[..context.len()].copy_from_slice(context);
+
+        Ok(edhoc_key_update(
+            &mut self.state,
+            &mut self.crypto,
+            &context_buf,
+            context.len(),
+        ))
+    }
+
+    /// Derives a key of `len` bytes through the exporter and writes it into `out` as a COSE_Key.
+    ///
+    /// The kid of the key is C_I, the connection identifier chosen by the Initiator, which is the
+    /// OSCORE Sender ID of the Responder. Returns the number of bytes written.
+    pub fn export_cose_key(
+        &mut self,
+        label: ExporterLabel,
+        kty: CoseKty,
+        len: usize,
+        out: &mut [u8],
+    ) -> Result<usize, EDHOCError> {
+        let key = self.edhoc_exporter(label.0, &[], len)?;
+        encode_cose_key(kty, self.c_i, &key[..len], out).or(Err(EDHOCError::InvalidParameter))
+    }
+
+    /// Ends the session, returning what was recorded about the handshake
+    #[cfg(feature = "report")]
+    pub fn into_report(self) -> Result<HandshakeReport, EDHOCError> {
+        self.report.finish(Role::Responder)
+    }
+}
+
+impl<'a, Crypto: CryptoTrait> EdhocInitiator<'a, Crypto> {
+    /// Creates an Initiator with a newly generated ephemeral key, which offers the
+    /// [EDHOC_SUPPORTED_SUITES] in message_1
+    pub fn new(mut crypto: Crypto) -> Self {
+        // we only support a single cipher suite which is already CBOR-encoded
+        let (x, g_x) = crypto.p256_generate_key_pair();
+
+        Self::from_start(crypto, InitiatorStart::with_supported_suites(x, g_x))
+    }
+
+    fn from_start(crypto: Crypto, state: InitiatorStart) -> Self {
+        EdhocInitiator {
+            preferred_suites: state.suites_i,
+            preferred_suites_len: state.suites_i_len,
+            state,
+            attempted_suites: [0x00; SUITES_LEN],
+            attempted_suites_len: 0,
+            profile: ApplicationProfile::default(),
+            peer_cred_by_value: false,
+            credential_resolver: None,
+            report: ReportRecorder::new(),
+            progress_mark: 0,
+            crypto,
+        }
+    }
+
+    /// Sets the application profile the handshake has to adhere to
+    pub fn with_application_profile(mut self, profile: ApplicationProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Sets the authentication method, which is [Method::StaticStatic] by default
+    ///
+    /// If the Initiator signs (see [Method::initiator_signs]), the private key given to
+    /// [EdhocInitiatorProcessingM2::verify_message_2] is an ECDSA P-256 signing key, and otherwise
+    /// a static DH key.
+    pub fn with_method(mut self, method: Method) -> Self {
+        self.state.method = method;
+        self
+    }
+
+    /// Sets whether the Responder has to send its credential by value in message_2, e.g. on first
+    /// contact; this is not required by default
+    ///
+    /// A message_2 that only references the credential is then rejected by
+    /// [EdhocInitiatorWaitM2::parse_message_2] with [EDHOCError::CredentialTransferPolicyViolation].
+    pub fn require_peer_credential_by_value(mut self, required: bool) -> Self {
+        self.peer_cred_by_value = required;
+        self
+    }
+
+    /// Sets how [EdhocInitiatorWaitM2::parse_and_verify_message_2] looks up the credential of the
+    /// Responder
+    pub fn with_credential_resolver(mut self, resolver: &'a dyn CredentialResolver) -> Self {
+        self.credential_resolver = Some(resolver);
+        self
+    }
+
+    /// C_I is generated if not given; [CONN_ID_EMPTY] may be given to use the empty byte string.
+    pub fn prepare_message_1(
+        mut self,
+        c_i: Option<u8>,
+        ead_1: &Option<EADItem>,
+    ) -> Result<(EdhocInitiatorWaitM2<'a, Crypto>, EdhocMessageBuffer), EdhocFailure> {
+        if !self.profile.allows_method(self.state.method as u8) {
+            return Err(EdhocFailure::new(
+                EDHOCError::UnsupportedMethod,
+                HandshakeStep::PrepareMessage1,
+            ));
+        }
+        if !self
+            .profile
+            .allows_cipher_suite(self.selected_cipher_suite())
+        {
+            return Err(EdhocFailure::new(
+                EDHOCError::UnsupportedCipherSuite,
+                HandshakeStep::PrepareMessage1,
+            ));
+        }
+        if !is_generated_key_pair(&self.state.x, &self.state.g_x) {
+            return Err(EdhocFailure::new(
+                EDHOCError::RngFailure,
+                HandshakeStep::PrepareMessage1,
+            ));
+        }
+        let c_i = match c_i {
+            Some(c_i) => c_i,
+            None => generate_connection_identifier_cbor(&mut self.crypto)
+                .map_err(|error| EdhocFailure::new(error, HandshakeStep::PrepareMessage1))?,
+        };
+
+        match i_prepare_message_1(&self.state, &mut self.crypto, c_i, ead_1) {
+            Ok((state, message_1)) => {
+                self.report.message_1(&message_1, None);
+                Ok((
+                    EdhocInitiatorWaitM2 {
+                        state,
+                        message_1,
+                        suites_i: self.state.suites_i,
+                        suites_i_len: self.state.suites_i_len,
+                        preferred_suites: self.preferred_suites,
+                        preferred_suites_len: self.preferred_suites_len,
+                        attempted_suites: self.attempted_suites,
+                        attempted_suites_len: self.attempted_suites_len,
+                        bytes_exchanged: message_1.len,
+                        c_i: ConnectionId::c_i(c_i),
+                        profile: self.profile,
+                        peer_cred_by_value: self.peer_cred_by_value,
+                        credential_resolver: self.credential_resolver,
+                        report: self.report,
+                        progress_mark: self.progress_mark,
+                        crypto: self.crypto,
+                    },
+                    message_1,
+                ))
+            }
+            Err(error) => Err(EdhocFailure::new(error, HandshakeStep::PrepareMessage1)),
+        }
+    }
+
+    /// ECDH of the own ephemeral private key X with `g_a`, e.g. G_XW for an EAD item that is
+    /// protected for a third party holding the private key of `g_a`
+    pub fn compute_ephemeral_secret(&mut self, g_a: &BytesP256ElemLen) -> BytesP256ElemLen {
+        self.crypto.p256_ecdh(&self.state.x, g_a)
+    }
+
+    /// The cipher suite selected in SUITES_I, i.e. the last one
+    pub fn selected_cipher_suite(&self) -> u8 {
+        self.state.suites_i[self.state.suites_i_len - 1]
+    }
+
+    /// Selects `suite` after the Responder rejected the one selected so far, e.g. with the error
+    /// message of [prepare_error_wrong_selected_suite]
+    ///
+    /// The order of preference is not changed by the error, as RFC 9528 (Section 5.2.2) requires:
+    /// SUITES_I lists the configured suites in their order, with only `suite` moved to the last
+    /// position, where it is the selected one. The rejected suite is recorded as attempted, and
+    /// attempted suites that were not configured stay in SUITES_I, in the order they were
+    /// attempted, before `suite`. This history survives [EdhocInitiatorWaitM2::recycle], and is
+    /// cleared by [Self::reset_negotiation_history].
+    ///
+    /// Fails with [EDHOCError::InvalidParameter] if SUITES_I would exceed [SUITES_LEN] suites.
+    pub fn restart_with_suite(mut self, suite: u8) -> Result<Self, EDHOCError> {
+        let rejected = self.selected_cipher_suite();
+        if !self.attempted_suites().contains(&rejected) {
+            if self.attempted_suites_len == SUITES_LEN {
+                return Err(EDHOCError::InvalidParameter);
+            }
+            self.attempted_suites[self.attempted_suites_len] = rejected;
+            self.attempted_suites_len += 1;
+        }
+        self.select_suite(suite)?;
+        Ok(self)
+    }
+
+    /// Forgets the suites attempted by earlier handshakes, so that SUITES_I only offers the
+    /// configured suites and the selected one; for a new session with a possibly different
+    /// Responder
+    pub fn reset_negotiation_history(&mut self) {
+        self.attempted_suites_len = 0;
+        let selected = self.selected_cipher_suite();
+        // cannot fail, as SUITES_I only loses the attempted suites that were not configured
+        let _ = self.select_suite(selected);
+    }
+
+    /// The suites recorded by [Self::restart_with_suite]
+    pub fn attempted_suites(&self) -> &[u8] {
+        &self.attempted_suites[..self.attempted_suites_len]
+    }
+
+    // SUITES_I: the configured suites other than `suite`, the attempted suites that are neither
+    // configured nor `suite`, then `suite`
+    fn select_suite(&mut self, suite: u8) -> Result<(), EDHOCError> {
+        let preferred = &self.preferred_suites[..self.preferred_suites_len];
+        let mut suites_i: BytesSuites = [0x00; SUITES_LEN];
+        let mut suites_i_len = 0;
+        let others = preferred.iter().chain(
+            self.attempted_suites()
+                .iter()
+                .filter(|attempted| !preferred.contains(attempted)),
+        );
+        for &other in others.filter(|&&other| other != suite).chain([&suite]) {
+            let Some(slot) = suites_i.get_mut(suites_i_len) else {
+                return Err(EDHOCError::InvalidParameter);
+            };
+            *slot = other;
+            suites_i_len += 1;
+        }
+        let method = self.state.method;
+        self.state = InitiatorStart::new(self.state.x, self.state.g_x, &suites_i[..suites_i_len])?;
+        self.state.method = method;
+        Ok(())
+    }
 }
 
-impl<'a, Crypto: CryptoTrait> EdhocInitiatorWaitM2<Crypto> {
+impl<'a, Crypto: CryptoTrait> EdhocInitiatorWaitM2<'a, Crypto> {
+    /// The cipher suite selected in message_1
+    pub fn selected_cipher_suite(&self) -> u8 {
+        self.state.selected_suite
+    }
+
+    /// The message_1 that was prepared, to be sent again if sending it failed or no message_2
+    /// arrived.
+    ///
+    /// This is the only safe way of reusing the ephemeral key: sending a different message_1 with
+    /// the same key would link the two handshakes and break the assumptions of the protocol, so a
+    /// new [EdhocInitiator] has to be created for anything but a retransmission.
+    pub fn message_1(&self) -> &BufferMessage1 {
+        &self.message_1
+    }
+
+    /// H(message_1), e.g. for EAD items that are bound to message_1
+    pub fn message_1_hash(&self) -> &BytesHashLen {
+        &self.state.h_message_1
+    }
+
+    /// The own ephemeral public key G_X, as sent in message_1
+    pub fn g_x(&self) -> &BytesP256ElemLen {
+        &self.state.g_x
+    }
+
+    /// Returns to the start of the handshake with a new ephemeral key, e.g. after the Responder
+    /// answered message_1 with an error message
+    ///
+    /// The configuration, SUITES_I of message_1 and the suites attempted before (see
+    /// [EdhocInitiator::restart_with_suite]) are kept; the report of this attempt is discarded.
+    pub fn recycle(mut self) -> EdhocInitiator<'a, Crypto> {
+        let (x, g_x) = self.crypto.p256_generate_key_pair();
+        // SUITES_I is offered again as it was accepted before
+        let mut state = InitiatorStart::with_supported_suites(x, g_x);
+        state.suites_i = self.suites_i;
+        state.suites_i_len = self.suites_i_len;
+        state.method = self.state.method;
+        EdhocInitiator {
+            state,
+            preferred_suites: self.preferred_suites,
+            preferred_suites_len: self.preferred_suites_len,
+            attempted_suites: self.attempted_suites,
+            attempted_suites_len: self.attempted_suites_len,
+            profile: self.profile,
+            peer_cred_by_value: self.peer_cred_by_value,
+            credential_resolver: self.credential_resolver,
+            report: ReportRecorder::new(),
+            progress_mark: self.progress_mark,
+            crypto: self.crypto,
+        }
+    }
+
+    /// Decrypts message_2, returning C_R, ID_CRED_R, which is to be resolved to the credential of
+    /// the Responder (see [credential_check_or_fetch]), and EAD_2
     pub fn parse_message_2(
         mut self,
         message_2: &'a BufferMessage2,
@@ -291,49 +1612,290 @@ impl<'a, Crypto: CryptoTrait> EdhocInitiatorWaitM2<Crypto> {
             EdhocInitiatorProcessingM2<Crypto>,
             u8,
             CredentialRPK,
-            Option<EADItem>,
+            EadItems,
         ),
-        EDHOCError,
+        EdhocFailure,
     > {
-        match i_parse_message_2(&self.state, &mut self.crypto, message_2) {
-            Ok((state, c_r, id_cred_r, ead_2)) => Ok((
-                EdhocInitiatorProcessingM2 {
-                    state,
-                    crypto: self.crypto,
-                },
-                c_r,
-                id_cred_r,
-                ead_2,
-            )),
-            Err(error) => Err(error),
+        match parse_message_2(message_2).and_then(|(g_y, ciphertext_2)| {
+            let (state, c_r, id_cred_r, ead_2) = i_parse_message_2_parts(
+                &self.state,
+                &mut self.crypto,
+                &g_y,
+                &ciphertext_2,
+                self.peer_cred_by_value,
+            )?;
+            self.profile.check_received_credential(&id_cred_r)?;
+            Ok((state, ciphertext_2, c_r, id_cred_r, ead_2))
+        }) {
+            Ok((state, ciphertext_2, c_r, id_cred_r, ead_2)) => {
+                self.report.peer_credential(&id_cred_r);
+                self.report.message_2(message_2, ead_2.as_slice());
+                Ok((
+                    EdhocInitiatorProcessingM2 {
+                        state,
+                        ciphertext_2,
+                        bytes_exchanged: self.bytes_exchanged + message_2.len,
+                        c_i: self.c_i,
+                        profile: self.profile,
+                        report: self.report,
+                        progress_mark: self.progress_mark,
+                        crypto: self.crypto,
+                    },
+                    c_r,
+                    id_cred_r,
+                    ead_2,
+                ))
+            }
+            Err(error) => Err(EdhocFailure::new(error, HandshakeStep::ParseMessage2)),
         }
     }
+
+    /// Parses and verifies message_2 in one go, returning C_R, the credential of the Responder
+    /// and EAD_2.
+    ///
+    /// The credential is looked up with the resolver set by
+    /// [EdhocInitiator::with_credential_resolver] and checked with [credential_check_or_fetch];
+    /// a credential that is not known fails with [EDHOCError::UnknownPeer] unless it was sent by
+    /// value. EAD_2 is only handed out after verification, so flows that have to process it
+    /// before (e.g. for fetching the credential) use [Self::parse_message_2] and
+    /// [EdhocInitiatorProcessingM2::verify_message_2] instead.
+    pub fn parse_and_verify_message_2(
+        self,
+        message_2: &'a BufferMessage2,
+        i: &'a (impl AsRef<[u8]> + ?Sized),
+        cred_i: CredentialRPK,
+    ) -> Result<
+        (
+            EdhocInitiatorProcessedM2<Crypto>,
+            u8,
+            CredentialRPK,
+            EadItems,
+        ),
+        EdhocFailure,
+    > {
+        let credential_resolver = self.credential_resolver;
+        let (initiator, c_r, id_cred_r, ead_2) = self.parse_message_2(message_2)?;
+        let cred_r = credential_resolver.and_then(|resolver| resolver.resolve(&id_cred_r));
+        let valid_cred_r = credential_check_or_fetch(cred_r, id_cred_r)
+            .map_err(|error| EdhocFailure::new(error, HandshakeStep::VerifyMessage2))?;
+        let initiator = initiator.verify_message_2(i, cred_i, valid_cred_r)?;
+        Ok((initiator, c_r, valid_cred_r, ead_2))
+    }
+
+    /// Like [Self::parse_and_verify_message_2], but awaits `fetch` for the credential of the
+    /// Responder, e.g. to resolve a reference through a remote directory
+    ///
+    /// `fetch` is given ID_CRED_R as received, and its result is checked like in
+    /// [credential_check_or_fetch]: None still accepts a credential that was sent by value. The
+    /// [EdhocInitiatorProcessingM2] held across the await is [Send] if `Crypto` is.
+    #[cfg(feature = "async")]
+    pub async fn parse_and_verify_message_2_async<F>(
+        self,
+        message_2: &'a BufferMessage2,
+        i: &'a (impl AsRef<[u8]> + ?Sized),
+        cred_i: CredentialRPK,
+        fetch: impl FnOnce(CredentialRPK) -> F,
+    ) -> Result<
+        (
+            EdhocInitiatorProcessedM2<Crypto>,
+            u8,
+            CredentialRPK,
+            EadItems,
+        ),
+        EdhocFailure,
+    >
+    where
+        F: core::future::Future<Output = Option<CredentialRPK>>,
+    {
+        let (initiator, c_r, id_cred_r, ead_2) = self.parse_message_2(message_2)?;
+        let cred_r = fetch(id_cred_r).await;
+        let valid_cred_r = credential_check_or_fetch(cred_r, id_cred_r)
+            .map_err(|error| EdhocFailure::new(error, HandshakeStep::VerifyMessage2))?;
+        let initiator = initiator.verify_message_2(i, cred_i, valid_cred_r)?;
+        Ok((initiator, c_r, valid_cred_r, ead_2))
+    }
 }
 
 impl<'a, Crypto: CryptoTrait> EdhocInitiatorProcessingM2<Crypto> {
+    /// The cipher suite selected in message_1
+    pub fn selected_cipher_suite(&self) -> u8 {
+        self.state.selected_suite
+    }
+
+    /// TH_2, e.g. to verify an EAD_2 item that is bound to it (see
+    /// [EdhocResponderProcessedM1::prepare_message_2_with])
+    pub fn th_2(&self) -> &BytesHashLen {
+        &self.state.th_2
+    }
+
+    /// The ciphertext_2 of the received message_2, e.g. to decrypt it in a separate environment
+    /// with the key from [Self::keystream_2]
+    pub fn ciphertext_2(&self) -> &BufferCiphertext2 {
+        &self.ciphertext_2
+    }
+
+    /// KEYSTREAM_2 for the length of [Self::ciphertext_2]
+    pub fn keystream_2(&mut self) -> BytesMaxBuffer {
+        i_compute_keystream_2(&self.state, &mut self.crypto, self.ciphertext_2.len)
+    }
+
+    /// The plaintext_2 in use, as decrypted when parsing message_2 or set by
+    /// [Self::set_plaintext_2]
+    pub fn plaintext_2(&self) -> &BufferPlaintext2 {
+        &self.state.plaintext_2
+    }
+
+    /// Uses a plaintext_2 that was decrypted outside of the library instead of the one decrypted
+    /// when parsing message_2, returning C_R, ID_CRED_R and EAD_2 as decoded from it.
+    pub fn set_plaintext_2(
+        &mut self,
+        plaintext_2: &BufferPlaintext2,
+    ) -> Result<(u8, CredentialRPK, EadItems), EdhocFailure> {
+        let (c_r, id_cred_r, ead_2) = i_set_plaintext_2(&mut self.state, plaintext_2)
+            .map_err(|error| EdhocFailure::new(error, HandshakeStep::ParseMessage2))?;
+        self.report.peer_credential(&id_cred_r);
+        self.report.ead(2, ead_2.as_slice());
+        Ok((c_r, id_cred_r, ead_2))
+    }
+
+    /// Derives a key for decrypting the value of a custom EAD_2 item.
+    ///
+    /// The derivation is specific to lakers and not interoperable, see [i_derive_ead_2_key].
+    /// The key is not bound to the responder's identity until [Self::verify_message_2] succeeds.
+    ///
+    /// Fails with [EDHOCError::InvalidParameter] if `context` or `length` exceed
+    /// [MAX_EXPORTER_CONTEXT_LEN] or [MAX_EXPORTER_OUTPUT_LEN].
+    pub fn derive_ead_key(
+        &mut self,
+        label: u8,
+        context: &[u8],
+        length: usize,
+    ) -> Result<[u8; MAX_BUFFER_LEN], EDHOCError> {
+        check_kdf_parameters(context, length)?;
+        let mut context_buf: BytesMaxContextBuffer = [0x00u8; MAX_KDF_CONTEXT_LEN];
+        context_buf[..context.len()].copy_from_slice(context);
+
+        Ok(i_derive_ead_2_key(
+            &self.state,
+            &mut self.crypto,
+            label,
+            &context_buf,
+            context.len(),
+            length,
+        ))
+    }
+
+    /// Verifies message_2 against the credential of the Responder, authenticating the Initiator
+    /// with its private key `i` (see [EdhocInitiator::with_method]) and credential `cred_i` later on
     pub fn verify_message_2(
         mut self,
-        i: &'a [u8],
+        i: &'a (impl AsRef<[u8]> + ?Sized),
         cred_i: CredentialRPK,
         valid_cred_r: CredentialRPK,
-    ) -> Result<EdhocInitiatorProcessedM2<Crypto>, EDHOCError> {
-        match i_verify_message_2(
-            &self.state,
-            &mut self.crypto,
-            valid_cred_r,
-            i.try_into().expect("Wrong length of initiator private key"),
-        ) {
+    ) -> Result<EdhocInitiatorProcessedM2<Crypto>, EdhocFailure> {
+        let Ok(i) = i.as_ref().try_into() else {
+            return Err(EdhocFailure::new(
+                EDHOCError::InvalidKey,
+                HandshakeStep::VerifyMessage2,
+            ));
+        };
+        match i_verify_message_2(&self.state, &mut self.crypto, valid_cred_r, i) {
             Ok(state) => Ok(EdhocInitiatorProcessedM2 {
                 state,
                 cred_i: cred_i,
+                cred_r: valid_cred_r,
+                bytes_exchanged: self.bytes_exchanged,
+                c_i: self.c_i,
+                c_r: ConnectionId::c_r(self.state.c_r),
+                selected_suite: self.state.selected_suite,
+                profile: self.profile,
+                report: self.report,
+                progress_mark: self.progress_mark,
                 crypto: self.crypto,
             }),
-            Err(error) => Err(error),
+            Err(error) => Err(EdhocFailure::new(error, HandshakeStep::VerifyMessage2)),
         }
     }
 }
 
 impl<'a, Crypto: CryptoTrait> EdhocInitiatorProcessedM2<Crypto> {
+    /// The cipher suite selected in message_1
+    pub fn selected_cipher_suite(&self) -> u8 {
+        self.selected_suite
+    }
+
+    /// TH_3, e.g. to bind an EAD_3 item to it (see [EdhocResponderProcessingM3::th_3])
+    pub fn th_3(&self) -> &BytesHashLen {
+        &self.state.th_3
+    }
+
+    /// Whether `c_r` is the C_R the Responder chose in message_2
+    pub fn expects_c_r(&self, c_r: u8) -> bool {
+        self.c_r.id == c_r
+    }
+
+    /// Length of the message_3 that [EdhocInitiatorProcessedM2::prepare_message_3] will produce
+    /// with the same arguments, e.g. to check it against the transport's MTU beforehand.
+    pub fn estimate_message_3_size(
+        &self,
+        cred_transfer: CredentialTransfer,
+        ead_3: &Option<EADItem>,
+    ) -> Result<usize, EDHOCError> {
+        i_estimate_message_3_size(&self.cred_i, cred_transfer, ead_3, self.state.method)
+    }
+
+    /// The PLAINTEXT_3 that [EdhocInitiatorProcessedM2::prepare_message_3] will encrypt with the
+    /// same arguments, and the length of its message_3, e.g. to show them for confirmation,
+    /// without advancing the handshake
+    ///
+    /// This returns PLAINTEXT_3 rather than the encrypted message_3: encrypting a preview would use
+    /// the one AEAD nonce of message_3 ahead of prepare_message_3 (see [i_preview_message_3]). As
+    /// nothing is encrypted, this may be called with any arguments before prepare_message_3.
+    pub fn preview_message_3(
+        &mut self,
+        cred_transfer: CredentialTransfer,
+        ead_3: &Option<EADItem>,
+    ) -> Result<(BufferPlaintext3, usize), EDHOCError> {
+        let cred_transfer = resolve_credential_transfer(&self.cred_i, cred_transfer)?;
+        self.profile.check_sent_credential(cred_transfer)?;
+        i_preview_message_3(
+            &self.state,
+            &mut self.crypto,
+            self.cred_i,
+            cred_transfer,
+            ead_3,
+        )
+    }
+
+    /// Derives a key for encrypting the value of a custom EAD_3 item.
+    ///
+    /// The derivation is specific to lakers and not interoperable, see [r_derive_ead_3_key].
+    /// The responder obtains the same key from [EdhocResponderProcessingM3::derive_ead_key].
+    ///
+    /// Fails with [EDHOCError::InvalidParameter] if `context` or `length` exceed
+    /// [MAX_EXPORTER_CONTEXT_LEN] or [MAX_EXPORTER_OUTPUT_LEN].
+    pub fn derive_ead_key(
+        &mut self,
+        label: u8,
+        context: &[u8],
+        length: usize,
+    ) -> Result<[u8; MAX_BUFFER_LEN], EDHOCError> {
+        check_kdf_parameters(context, length)?;
+        let mut context_buf: BytesMaxContextBuffer = [0x00u8; MAX_KDF_CONTEXT_LEN];
+        context_buf[..context.len()].copy_from_slice(context);
+
+        Ok(i_derive_ead_3_key(
+            &self.state,
+            &mut self.crypto,
+            label,
+            &context_buf,
+            context.len(),
+            length,
+        ))
+    }
+
+    /// Prepares message_3 with the credential of the Initiator sent as `cred_transfer` and EAD_3,
+    /// returning PRK_out
     pub fn prepare_message_3(
         mut self,
         cred_transfer: CredentialTransfer,
@@ -344,8 +1906,15 @@ impl<'a, Crypto: CryptoTrait> EdhocInitiatorProcessedM2<Crypto> {
             BufferMessage3,
             [u8; SHA256_DIGEST_LEN],
         ),
-        EDHOCError,
+        EdhocFailure,
     > {
+        let cred_transfer = resolve_credential_transfer(&self.cred_i, cred_transfer)
+            .and_then(|cred_transfer| {
+                self.profile.check_sent_credential(cred_transfer)?;
+                Ok(cred_transfer)
+            })
+            .map_err(|error| EdhocFailure::new(error, HandshakeStep::PrepareMessage3))?;
+        self.report.own_credential(&self.cred_i, cred_transfer);
         match i_prepare_message_3(
             &mut self.state,
             &mut self.crypto,
@@ -353,276 +1922,4243 @@ impl<'a, Crypto: CryptoTrait> EdhocInitiatorProcessedM2<Crypto> {
             cred_transfer,
             ead_3,
         ) {
-            Ok((state, message_3, prk_out)) => Ok((
-                EdhocInitiatorDone {
-                    state,
-                    crypto: self.crypto,
-                },
-                message_3,
-                prk_out,
-            )),
-            Err(error) => Err(error),
+            Ok((state, message_3, prk_out)) => {
+                self.report.message_3(&message_3, ead_3.as_slice());
+                Ok((
+                    EdhocInitiatorDone {
+                        state,
+                        message_3: Some(message_3),
+                        completion: CompletionHint::Unconfirmed,
+                        message_4_pending: self.profile.uses_message_4(),
+                        cred_transfer,
+                        cred_i: self.cred_i,
+                        cred_r: self.cred_r,
+                        bytes_exchanged: self.bytes_exchanged + message_3.len,
+                        c_i: self.c_i,
+                        c_r: self.c_r,
+                        selected_suite: self.selected_suite,
+                        report: self.report,
+                        progress_mark: self.progress_mark,
+                        crypto: self.crypto,
+                    },
+                    message_3,
+                    prk_out,
+                ))
+            }
+            Err(error) => Err(EdhocFailure::new(error, HandshakeStep::PrepareMessage3)),
         }
     }
 }
 
 impl<Crypto: CryptoTrait> EdhocInitiatorDone<Crypto> {
-    pub fn edhoc_exporter(
-        &mut self,
-        label: u8,
-        context: &[u8],
-        length: usize,
-    ) -> [u8; MAX_BUFFER_LEN] {
-        let mut context_buf: BytesMaxContextBuffer = [0x00u8; MAX_KDF_CONTEXT_LEN];
-        context_buf[..context.len()].copy_from_slice(context);
+    /// The cipher suite that was used for the handshake
+    pub fn selected_cipher_suite(&self) -> u8 {
+        self.selected_suite
+    }
 
-        edhoc_exporter(
-            &self.state,
-            &mut self.crypto,
-            label,
-            &context_buf,
-            context.len(),
-            length,
-        )
+    /// Whether `c_r` is the C_R the Responder chose in message_2, e.g. to correlate a combined
+    /// EDHOC + OSCORE response with this handshake
+    pub fn expects_c_r(&self, c_r: u8) -> bool {
+        self.c_r.id == c_r
+    }
+
+    /// Total length of message_1, message_2 and message_3, e.g. for metering
+    ///
+    /// Retransmissions and any message_4 are not included.
+    pub fn bytes_exchanged(&self) -> usize {
+        self.bytes_exchanged
+    }
+
+    /// The C_R the Responder chose in message_2, in the byte form of [oscore_id], e.g. for
+    /// persisting it along with the Responder's credential
+    pub fn responder_connection_id(&self) -> &[u8] {
+        oscore_id(&self.c_r.id)
+    }
+
+    /// Checks that C_I is held as the identifier the Initiator chose in message_1 and C_R as the
+    /// one the Responder chose in message_2
+    ///
+    /// This is a self-check against a swap of the two in the state, which would swap the OSCORE
+    /// Sender and Recipient IDs derived from them: the result would work in one direction only.
+    pub fn verify_connection_id_orientation(&self) -> Result<(), EDHOCError> {
+        if self.c_i.chosen_by_initiator && !self.c_r.chosen_by_initiator {
+            Ok(())
+        } else {
+            Err(EDHOCError::ConnectionIdentifierMismatch)
+        }
+    }
+
+    /// How CRED_I was sent in message_3, i.e. what [CredentialTransfer::Auto] resolved to
+    pub fn credential_transfer(&self) -> CredentialTransfer {
+        self.cred_transfer
+    }
+
+    /// The Responder's credential that message_2 was verified against, e.g. for logging or for
+    /// authorization decisions
+    pub fn peer_credential(&self) -> &CredentialRPK {
+        &self.cred_r
     }
 
-    pub fn edhoc_key_update(&mut self, context: &[u8]) -> [u8; SHA256_DIGEST_LEN] {
-        let mut context_buf = [0x00u8; MAX_KDF_CONTEXT_LEN];
-        context_buf[..context.len()].copy_from_slice(context);
+    /// The Initiator's own credential, as it was authenticated in message_3
+    pub fn own_credential(&self) -> &CredentialRPK {
+        &self.cred_i
+    }
+
+    /// The message_3 that was sent, to be resent if the Responder does not seem to have received it.
+    ///
+    /// Returns None once [EdhocInitiatorDone::discard_retained] was called.
+    pub fn message_3(&self) -> Option<&BufferMessage3> {
+        self.message_3.as_ref()
+    }
+
+    /// Whether the Responder is known to have completed the handshake.
+    ///
+    /// While this is [CompletionHint::Unconfirmed], keys obtained through the exporter may not yet
+    /// be usable by the Responder.
+    pub fn completion_hint(&self) -> CompletionHint {
+        self.completion
+    }
+
+    /// To be called after the first protected message from the Responder was successfully
+    /// processed.
+    ///
+    /// This does not drop the retained message_3; use [EdhocInitiatorDone::discard_retained] for that.
+    /// If the [ApplicationProfile] uses message_4, only [EdhocInitiatorDone::process_message_4]
+    /// allows the keys to be exported.
+    pub fn confirm_peer_completion(&mut self) {
+        self.completion = CompletionHint::Confirmed;
+    }
+
+    /// Processes message_4 and returns its EAD_4 items, confirming that the Responder completed
+    /// the handshake
+    ///
+    /// Fails with [EDHOCError::MacVerificationFailed] if message_4 was not protected with the keys
+    /// of this handshake.
+    pub fn process_message_4(
+        &mut self,
+        message_4: &BufferMessage4,
+    ) -> Result<EadItems, EDHOCError> {
+        let ead_4 = i_process_message_4(&self.state, &mut self.crypto, message_4)?;
+        self.message_4_pending = false;
+        self.completion = CompletionHint::Confirmed;
+        Ok(ead_4)
+    }
+
+    fn check_message_4(&self) -> Result<(), EDHOCError> {
+        if self.message_4_pending {
+            Err(EDHOCError::ProfileViolation)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Drops the retained message_3 to reclaim its memory.
+    pub fn discard_retained(&mut self) {
+        self.message_3 = None;
+    }
+
+    /// The transcript hash TH_4 over the completed handshake, e.g. for channel binding
+    pub fn th_4(&self) -> &BytesHashLen {
+        &self.state.th_4
+    }
+
+    /// Fails with [EDHOCError::InvalidParameter] if `context` or `length` exceed
+    /// [MAX_EXPORTER_CONTEXT_LEN] or [MAX_EXPORTER_OUTPUT_LEN].
+    pub fn edhoc_exporter(
+        &mut self,
+        label: u8,
+        context: &[u8],
+        length: usize,
+    ) -> Result<[u8; MAX_BUFFER_LEN], EDHOCError> {
+        check_kdf_parameters(context, length)?;
+        self.check_message_4()?;
+        self.report.exporter(label, false);
+        edhoc_exporter_from_slice(&self.state, &mut self.crypto, label, context, length)
+    }
+
+    /// Like [Self::edhoc_exporter], but writes the key into `out` instead of returning it,
+    /// e.g. into memory that is reserved for keys
+    pub fn edhoc_exporter_into(
+        &mut self,
+        label: u8,
+        context: &[u8],
+        length: usize,
+        out: &mut impl KeyOutput,
+    ) -> Result<(), EDHOCError> {
+        check_kdf_parameters(context, length)?;
+        self.check_message_4()?;
+        self.report.exporter(label, false);
+        edhoc_exporter_into(&self.state, &mut self.crypto, label, context, length, out)
+    }
+
+    /// Fails with [EDHOCError::InvalidParameter] if `context` is longer than
+    /// [MAX_EXPORTER_CONTEXT_LEN].
+    pub fn edhoc_key_update(
+        &mut self,
+        context: &[u8],
+    ) -> Result<[u8; SHA256_DIGEST_LEN], EDHOCError> {
+        check_kdf_parameters(context, SHA256_DIGEST_LEN)?;
+        self.check_message_4()?;
+        let mut context_buf = [0x00u8; MAX_KDF_CONTEXT_LEN];
+        context_buf[..context.len()].copy_from_slice(context);
+
+        Ok(edhoc_key_update(
+            &mut self.state,
+            &mut self.crypto,
+            &context_buf,
+            context.len(),
+        ))
+    }
+
+    /// Derives a key of `len` bytes through the exporter and writes it into `out` as a COSE_Key.
+    ///
+    /// The kid of the key is C_R, the connection identifier chosen by the Responder, which is the
+    /// OSCORE Sender ID of the Initiator. Returns the number of bytes written.
+    pub fn export_cose_key(
+        &mut self,
+        label: ExporterLabel,
+        kty: CoseKty,
+        len: usize,
+        out: &mut [u8],
+    ) -> Result<usize, EDHOCError> {
+        let key = self.edhoc_exporter(label.0, &[], len)?;
+        encode_cose_key(kty, self.c_r.id, &key[..len], out).or(Err(EDHOCError::InvalidParameter))
+    }
+
+    /// Ends the session, returning what was recorded about the handshake
+    #[cfg(feature = "report")]
+    pub fn into_report(self) -> Result<HandshakeReport, EDHOCError> {
+        self.report.finish(Role::Initiator)
+    }
+}
+
+/// Whether the cipher suite selected in SUITES_I is acceptable given the Responder's SUITES_R,
+/// which is ordered by preference
+fn selected_suite_acceptable(policy: SuitePolicy, suites_i: &[u8], suites_r: &[u8]) -> bool {
+    let Some(selected) = suites_i.last() else {
+        return false;
+    };
+    if !suites_r.contains(selected) {
+        return false;
+    }
+    match policy {
+        SuitePolicy::AcceptAnySupported => true,
+        SuitePolicy::RequireMostPreferred => {
+            suites_r.iter().find(|suite| suites_i.contains(suite)) == Some(selected)
+        }
+    }
+}
+
+/// What [prevalidate_message_1] found out about a message_1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Message1Summary {
+    /// The authentication method
+    pub method: u8,
+    /// The selected cipher suite, i.e. the last one of SUITES_I
+    pub selected_suite: u8,
+    /// SUITES_I, of which the first `suites_i_len` are valid (see [Message1Summary::suites_i])
+    pub suites_i: BytesSuites,
+    /// Number of cipher suites in SUITES_I
+    pub suites_i_len: usize,
+    /// The connection identifier C_I, in its one-byte encoding
+    pub c_i: u8,
+    /// Label of the first item of EAD_1 and whether it is critical, if message_1 carries EAD
+    pub ead_1: Option<(u16, bool)>,
+}
+
+impl Message1Summary {
+    /// SUITES_I, the last of which is the selected cipher suite
+    pub fn suites_i(&self) -> &[u8] {
+        &self.suites_i[..self.suites_i_len]
+    }
+}
+
+/// Checks whether a message_1 is worth processing, without any state or cryptographic operation.
+///
+/// This performs the checks of [EdhocResponder::process_message_1] that come before the
+/// Responder generates its ephemeral key, so that a front-end (e.g. one that rate-limits
+/// handshakes) can reject bogus messages cheaply: message_1 has to parse, use a supported method
+/// and cipher suite, and carry a G_X that is an element of the field. Whether G_X is a point on
+/// the curve is only checked when processing message_1.
+///
+/// Messages longer than [MAX_MESSAGE_SIZE_LEN] are rejected with [EDHOCError::MessageTooLong].
+///
+/// # Examples
+///
+/// ```
+/// use lakers::*;
+///
+/// // message_1 of RFC 9529, Section 3
+/// let message_1 = EdhocMessageBuffer::from_hex(
+///     "0382060258208af6f430ebe18d34184017a9a11bf511c8dff8f834730b96c1b7c8dbca2fc3b637",
+/// );
+/// let summary = prevalidate_message_1(message_1.as_slice()).unwrap();
+/// assert_eq!((summary.selected_suite, summary.c_i), (2, 0x37));
+///
+/// assert_eq!(
+///     prevalidate_message_1(&message_1.as_slice()[..10]),
+///     Err(EDHOCError::ParsingError)
+/// );
+/// ```
+pub fn prevalidate_message_1(message_1: &[u8]) -> Result<Message1Summary, EDHOCError> {
+    let message_1 =
+        BufferMessage1::new_from_slice(message_1).map_err(|_| EDHOCError::MessageTooLong)?;
+    let (method, suites_i, suites_i_len, g_x, c_i, ead_1) = parse_message_1(&message_1)?;
+    if Method::from_u8(method).is_none() {
+        return Err(EDHOCError::UnsupportedMethod);
+    }
+    let Some(&selected_suite) = suites_i[..suites_i_len].last() else {
+        return Err(EDHOCError::UnsupportedCipherSuite);
+    };
+    if !EDHOC_SUPPORTED_SUITES.contains(&selected_suite) {
+        return Err(EDHOCError::UnsupportedCipherSuite);
+    }
+    if g_x[..] >= P256_FIELD_PRIME[..] {
+        return Err(EDHOCError::InvalidKey);
+    }
+
+    Ok(Message1Summary {
+        method,
+        selected_suite,
+        suites_i,
+        suites_i_len,
+        c_i,
+        ead_1: ead_1.first().map(|ead_1| (ead_1.label, ead_1.is_critical)),
+    })
+}
+
+/// Returns method and selected cipher suite of a message_1, e.g. for routing it to a backend.
+///
+/// Only the first two items of message_1 are decoded, so this neither checks the rest of the
+/// message nor whether the method and suite are supported; see [prevalidate_message_1] for that.
+/// Messages longer than [MAX_MESSAGE_SIZE_LEN] are still rejected, with
+/// [EDHOCError::MessageTooLong], to not route what no backend would process.
+///
+/// # Examples
+///
+/// ```
+/// use lakers::*;
+///
+/// // message_1 of RFC 9529, Section 3
+/// let message_1 = EdhocMessageBuffer::from_hex(
+///     "0382060258208af6f430ebe18d34184017a9a11bf511c8dff8f834730b96c1b7c8dbca2fc3b637",
+/// );
+/// assert_eq!(peek_message_1_header(message_1.as_slice()), Ok((3, 2)));
+/// ```
+pub fn peek_message_1_header(message_1: &[u8]) -> Result<(u8, u8), EDHOCError> {
+    if message_1.len() > MAX_MESSAGE_SIZE_LEN {
+        return Err(EDHOCError::MessageTooLong);
+    }
+    let mut decoder = CBORDecoder::new(message_1);
+    let method = decoder.u8()?;
+    let (suites_i, suites_i_len, _decoder) = parse_suites_i(decoder)?;
+    Ok((method, suites_i[suites_i_len - 1]))
+}
+
+/// Encodes the EDHOC error message to send when message_1 was rejected with
+/// [EDHOCError::UnsupportedCipherSuite]: ERR_CODE 2 (wrong selected cipher suite) with SUITES_R.
+///
+/// # Examples
+///
+/// ```
+/// use lakers::*;
+///
+/// // ERR_CODE 2, and suite 2 as the only supported one
+/// assert_eq!(prepare_error_wrong_selected_suite().as_slice(), &[0x02, 0x02]);
+/// ```
+pub fn prepare_error_wrong_selected_suite() -> EdhocMessageBuffer {
+    let mut message = EdhocMessageBuffer::new();
+    let mut encoder = CBOREncoder::new(&mut message.content);
+    encoder.u8(ERR_CODE_WRONG_SELECTED_SUITE).unwrap();
+    // a single suite is encoded as int, several ones as array
+    if EDHOC_SUPPORTED_SUITES.len() > 1 {
+        encoder.array(EDHOC_SUPPORTED_SUITES.len()).unwrap();
+    }
+    for suite in EDHOC_SUPPORTED_SUITES {
+        encoder.u8(suite).unwrap();
+    }
+    message.len = encoder.position();
+    message
+}
+
+/// COSE algorithm identifiers of the application AEAD and application hash algorithm of an EDHOC
+/// cipher suite (RFC 9528, Section 10.2), as `(aead_alg, hash_alg)`.
+///
+/// These configure the OSCORE security context derived from the handshake (RFC 9528,
+/// Appendix A.1). Returns None for suites that are not registered, whether they are supported by
+/// this implementation or not.
+///
+/// # Examples
+///
+/// ```
+/// use lakers::*;
+///
+/// // AES-CCM-16-64-128 and SHA-256
+/// assert_eq!(cipher_suite_to_oscore_algs(2), Some((10, -16)));
+/// assert_eq!(cipher_suite_to_oscore_algs(7), None);
+/// ```
+pub fn cipher_suite_to_oscore_algs(suite: u8) -> Option<(i32, i32)> {
+    const AES_CCM_16_64_128: i32 = 10;
+    const A128GCM: i32 = 1;
+    const A256GCM: i32 = 3;
+    const CHACHA20_POLY1305: i32 = 24;
+    const SHA_256: i32 = -16;
+    const SHA_384: i32 = -43;
+    const SHAKE256: i32 = -45;
+
+    match suite {
+        0..=3 => Some((AES_CCM_16_64_128, SHA_256)),
+        4 | 5 => Some((CHACHA20_POLY1305, SHA_256)),
+        6 => Some((A128GCM, SHA_256)),
+        24 => Some((A256GCM, SHA_384)),
+        25 => Some((CHACHA20_POLY1305, SHAKE256)),
+        _ => None,
+    }
+}
+
+/// Generates an identifier like [generate_connection_identifier], in the single byte of its CBOR
+/// encoding
+///
+/// # Examples
+///
+/// ```
+/// use lakers::*;
+/// use lakers_crypto::default_crypto;
+///
+/// # fn main() -> Result<(), EDHOCError> {
+/// let c_i = generate_connection_identifier_cbor(&mut default_crypto())?;
+/// // 0x00 to 0x17 encode 0 to 23, and 0x20 to 0x37 encode -1 to -24
+/// assert!(c_i <= 0x17 || (0x20..=0x37).contains(&c_i));
+/// # Ok(())
+/// # }
+/// ```
+pub fn generate_connection_identifier_cbor<Crypto: CryptoTrait>(
+    crypto: &mut Crypto,
+) -> Result<u8, EDHOCError> {
+    Ok(encode_connection_identifier(
+        generate_connection_identifier(crypto)?,
+    ))
+}
+
+/// The single byte of the CBOR encoding of an identifier from [generate_connection_identifier]
+fn encode_connection_identifier(conn_id: i8) -> u8 {
+    if conn_id >= 0 && conn_id <= 23 {
+        conn_id as u8 // verbatim encoding of single byte integer
+    } else if conn_id < 0 && conn_id >= -24 {
+        // negative single byte integer encoding
+        CBOR_NEG_INT_1BYTE_START - 1 + conn_id.unsigned_abs()
+    } else {
+        0
+    }
+}
+
+/// generates an identifier that can be serialized as a single CBOR integer, i.e. -24 <= x <= 23
+///
+/// Fails with [EDHOCError::RngFailure] if none of [RNG_MAX_RETRIES] random bytes was in range.
+///
+/// # Examples
+///
+/// ```
+/// use lakers::*;
+/// use lakers_crypto::default_crypto;
+///
+/// # fn main() -> Result<(), EDHOCError> {
+/// let c_i = generate_connection_identifier(&mut default_crypto())?;
+/// assert!((-24..=23).contains(&c_i));
+/// # Ok(())
+/// # }
+/// ```
+pub fn generate_connection_identifier<Crypto: CryptoTrait>(
+    crypto: &mut Crypto,
+) -> Result<i8, EDHOCError> {
+    for _ in 0..RNG_MAX_RETRIES {
+        let conn_id = crypto.get_random_byte() as i8;
+        if conn_id >= -24 && conn_id <= 23 {
+            return Ok(conn_id);
+        }
+    }
+    Err(EDHOCError::RngFailure)
+}
+
+/// Number of random candidates drawn by [generate_connection_identifier_with] before it tries
+/// all identifiers in turn
+pub const CONNECTION_IDENTIFIER_ATTEMPTS: usize = 64;
+
+/// Generates an identifier like [generate_connection_identifier] that also satisfies `accept`
+///
+/// This allows constraints such as avoiding identifiers in use, or encoding a hint in the
+/// identifier. When none of [CONNECTION_IDENTIFIER_ATTEMPTS] random candidates was accepted, the
+/// first acceptable identifier is taken, so that the last free one is also found. Fails with
+/// [EDHOCError::InvalidParameter] if no identifier is accepted, or with
+/// [EDHOCError::RngFailure] if no candidate could be drawn.
+///
+/// # Examples
+///
+/// ```
+/// use lakers::*;
+/// use lakers_crypto::default_crypto;
+///
+/// # fn main() -> Result<(), EDHOCError> {
+/// let in_use = [0, 1, -1];
+/// let c_r = generate_connection_identifier_with(&mut default_crypto(), |c_r| {
+///     !in_use.contains(&c_r)
+/// })?;
+/// assert!(!in_use.contains(&c_r));
+///
+/// // no identifier is acceptable
+/// assert_eq!(
+///     generate_connection_identifier_with(&mut default_crypto(), |_| false),
+///     Err(EDHOCError::InvalidParameter)
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub fn generate_connection_identifier_with<Crypto: CryptoTrait>(
+    crypto: &mut Crypto,
+    mut accept: impl FnMut(i8) -> bool,
+) -> Result<i8, EDHOCError> {
+    for _ in 0..CONNECTION_IDENTIFIER_ATTEMPTS {
+        let conn_id = generate_connection_identifier(crypto)?;
+        if accept(conn_id) {
+            return Ok(conn_id);
+        }
+    }
+    (-24..=23)
+        .find(|conn_id| accept(*conn_id))
+        .ok_or(EDHOCError::InvalidParameter)
+}
+
+/// The OSCORE Sender or Recipient ID that corresponds to a connection identifier.
+///
+/// This is the CBOR encoding of a single-byte integer identifier, and nothing at all for
+/// [CONN_ID_EMPTY].
+///
+/// # Examples
+///
+/// ```
+/// use lakers::*;
+///
+/// assert_eq!(oscore_id(&0x37), &[0x37]);
+/// assert!(oscore_id(&CONN_ID_EMPTY).is_empty());
+/// ```
+pub fn oscore_id(conn_id: &u8) -> &[u8] {
+    if *conn_id == CONN_ID_EMPTY {
+        &[]
+    } else {
+        core::slice::from_ref(conn_id)
+    }
+}
+
+/// Writes the COSE_Key `{1: kty, 2: h'kid', -1: h'key'}` into `out`, returning its length.
+fn encode_cose_key(
+    kty: CoseKty,
+    kid: u8,
+    key: &[u8],
+    out: &mut [u8],
+) -> Result<usize, MessageBufferError> {
+    let mut encoder = CBOREncoder::new(out);
+    encoder.map(3)?;
+    encoder.u8(1)?; // kty
+    encoder.u8(kty as u8)?;
+    encoder.u8(2)?; // kid
+    encoder.bytes(oscore_id(&kid))?;
+    encoder.i8(-1)?; // k
+    encoder.bytes(key)?;
+    Ok(encoder.position())
+}
+
+/// Why [credential_check_or_fetch_detailed] rejected a received credential
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectionKind {
+    /// ID_CRED_X referenced a kid other than the one of the expected credential
+    KidMismatch,
+    /// ID_CRED_X carried a credential other than the expected one
+    ValueMismatch,
+    /// ID_CRED_X only referenced a credential, but no credential was given to resolve it against
+    ReferenceWithoutStore,
+}
+
+/// Details on a credential rejected by [credential_check_or_fetch_detailed], e.g. for logging
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CredentialRejection {
+    /// What was wrong with the received credential
+    pub kind: RejectionKind,
+    /// The kid of the received credential, if it has one
+    pub received_kid: Option<u8>,
+    /// The kid of the expected credential, if one was expected and it has a kid
+    pub expected_kid: Option<u8>,
+}
+
+impl From<CredentialRejection> for EDHOCError {
+    fn from(_rejection: CredentialRejection) -> Self {
+        EDHOCError::UnknownPeer
+    }
+}
+
+/// Like [credential_check_or_fetch_detailed], but reports every rejection as
+/// [EDHOCError::UnknownPeer]
+///
+/// # Examples
+///
+/// A credential received by reference is resolved to the expected one, while one received by
+/// value is taken as it is when none was expected (trust on first use):
+///
+/// ```
+/// use lakers::doctest_helpers::credential_r;
+/// use lakers::*;
+///
+/// # fn main() -> Result<(), EDHOCError> {
+/// let cred_r = credential_r();
+/// let by_reference = CredentialRPK::from_kid(cred_r.kid);
+/// let valid_cred_r = credential_check_or_fetch(Some(cred_r), by_reference)?;
+/// assert_eq!(valid_cred_r.value, cred_r.value);
+///
+/// let valid_cred_r = credential_check_or_fetch(None, cred_r)?;
+/// assert_eq!(valid_cred_r.value, cred_r.value);
+///
+/// // a reference cannot be resolved without the expected credential
+/// assert_eq!(
+///     credential_check_or_fetch(None, by_reference).unwrap_err(),
+///     EDHOCError::UnknownPeer
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub fn credential_check_or_fetch<'a>(
+    cred_expected: Option<CredentialRPK>,
+    id_cred_received: CredentialRPK,
+) -> Result<CredentialRPK, EDHOCError> {
+    credential_check_or_fetch_detailed(cred_expected, id_cred_received).map_err(EDHOCError::from)
+}
+
+/// Where the credential of a peer that only sent its kid can be fetched from, as sent in EAD_2
+///
+/// This lets an Initiator that does not know the kid of the Responder fetch the credential from a
+/// repository, as described in draft-tiloca-lake-implem-cons. The item uses the unregistered
+/// label [EAD_CRED_HINT_LABEL] and is not critical, so peers other than lakers ignore it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CredentialHint {
+    uri: EdhocMessageBuffer,
+}
+
+impl CredentialHint {
+    /// The hint carried in `ead_2`, if it is a well-formed credential hint item
+    pub fn from_ead_2(ead_2: &EADItem) -> Option<CredentialHint> {
+        if ead_2.label != EAD_CRED_HINT_LABEL {
+            return None;
+        }
+        let mut decoder = CBORDecoder::new(ead_2.value.as_ref()?.as_slice());
+        let uri = decoder.bytes().ok()?;
+        decoder.ensure_finished().ok()?;
+        Some(CredentialHint {
+            uri: EdhocMessageBuffer::new_from_slice(uri).ok()?,
+        })
+    }
+
+    /// EAD_2 item pointing the Initiator to `uri` for fetching the Responder's credential
+    pub fn to_ead_2(uri: &[u8]) -> Result<EADItem, EDHOCError> {
+        let mut value = EdhocMessageBuffer::new();
+        let mut encoder = CBOREncoder::new(&mut value.content);
+        encoder.bytes(uri).or(Err(EDHOCError::EadTooLongError))?;
+        value.len = encoder.position();
+        Ok(EADItem {
+            label: EAD_CRED_HINT_LABEL,
+            is_critical: false,
+            value: Some(value),
+        })
+    }
+
+    /// The URI the credential of the Responder can be fetched from
+    pub fn uri(&self) -> &[u8] {
+        self.uri.as_slice()
+    }
+}
+
+/// Coarse replay protection through a timestamp that the Initiator sends in EAD_1
+///
+/// The Responder rejects a message_1 whose timestamp is further than a window from its own clock,
+/// in either direction to allow for skew. This bounds how long a recorded message_1 can be
+/// replayed, but does not detect a replay within the window. Timestamps are in any unit that both
+/// peers agree on, e.g. seconds since the Unix epoch; the value is the timestamp as an 8-byte
+/// big-endian bstr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EadFreshness {
+    window: u64,
+}
+
+impl EadFreshness {
+    /// Accepts timestamps that differ by at most `window` from the Responder's time
+    pub fn new(window: u64) -> Self {
+        EadFreshness { window }
+    }
+
+    /// EAD_1 item carrying `timestamp`
+    pub fn to_ead_1(timestamp: u64) -> EADItem {
+        let mut value = EdhocMessageBuffer::new();
+        let mut encoder = CBOREncoder::new(&mut value.content);
+        encoder
+            .bytes(&timestamp.to_be_bytes())
+            .expect("fits any message buffer");
+        value.len = encoder.position();
+        EADItem {
+            label: EAD_FRESHNESS_LABEL,
+            is_critical: false,
+            value: Some(value),
+        }
+    }
+
+    /// The timestamp carried in `ead_1`, if it is a well-formed freshness item
+    pub fn timestamp(ead_1: &EADItem) -> Option<u64> {
+        if ead_1.label != EAD_FRESHNESS_LABEL {
+            return None;
+        }
+        let mut decoder = CBORDecoder::new(ead_1.value.as_ref()?.as_slice());
+        let timestamp = decoder.bytes_sized(8).ok()?;
+        decoder.ensure_finished().ok()?;
+        Some(u64::from_be_bytes(timestamp.try_into().ok()?))
+    }
+
+    /// Checks the EAD_1 returned by [EdhocResponder::process_message_1] at the time `now`
+    ///
+    /// Fails with [EDHOCError::EADError] if there is no freshness item among the items, or if its
+    /// timestamp is outside the window.
+    pub fn check_ead_1(&self, ead_1: &EadItems, now: u64) -> Result<(), EDHOCError> {
+        match ead_1.iter().find_map(Self::timestamp) {
+            Some(timestamp) if timestamp.abs_diff(now) <= self.window => Ok(()),
+            _ => Err(EDHOCError::EADError),
+        }
+    }
+}
+
+/// Outcome of [credential_check_or_request_fetch]
+#[derive(Debug, Clone, Copy)]
+pub enum CredentialLookup {
+    /// The credential to continue the handshake with
+    Valid(CredentialRPK),
+    /// The peer only sent the kid of a credential that is not at hand
+    NeedsFetch(CredentialFetchRequest),
+}
+
+/// Request to fetch the credential of a peer that only sent its kid
+#[derive(Debug, Clone, Copy)]
+pub struct CredentialFetchRequest {
+    kid: u8,
+    /// Where the credential can be fetched from, if the peer said so
+    pub hint: Option<CredentialHint>,
+}
+
+impl CredentialFetchRequest {
+    /// The kid to fetch the credential by, as the byte string of the COSE_Key
+    pub fn kid(&self) -> &[u8] {
+        core::slice::from_ref(&self.kid)
+    }
+
+    /// Checks the `fetched` credential against the kid that was received, returning the
+    /// credential to continue the handshake with
+    pub fn complete(&self, fetched: CredentialRPK) -> Result<CredentialRPK, CredentialRejection> {
+        credential_check_or_fetch_detailed(Some(fetched), CredentialRPK::from_kid(self.kid))
+    }
+}
+
+/// Like [credential_check_or_fetch_detailed], but asks for the credential to be fetched instead
+/// of rejecting a reference when no credential is expected
+///
+/// The `hint` is typically obtained through [CredentialHint::from_ead_2].
+pub fn credential_check_or_request_fetch(
+    cred_expected: Option<CredentialRPK>,
+    id_cred_received: CredentialRPK,
+    hint: Option<CredentialHint>,
+) -> Result<CredentialLookup, CredentialRejection> {
+    if cred_expected.is_none() && id_cred_received.reference_only() {
+        // a reference is always by kid
+        return Ok(CredentialLookup::NeedsFetch(CredentialFetchRequest {
+            kid: id_cred_received.kid,
+            hint,
+        }));
+    }
+    credential_check_or_fetch_detailed(cred_expected, id_cred_received).map(CredentialLookup::Valid)
+}
+
+/// Checks the parameters of an exporter or EAD key derivation against the limits of the API
+fn check_kdf_parameters(context: &[u8], length: usize) -> Result<(), EDHOCError> {
+    check_parameter_len(context, MAX_EXPORTER_CONTEXT_LEN)?;
+    if length > MAX_EXPORTER_OUTPUT_LEN {
+        return Err(EDHOCError::InvalidParameter);
+    }
+    Ok(())
+}
+
+/// Compares kids and credentials, in constant time with the `ct-lookups` feature (see
+/// [CredentialStore])
+fn credential_bytes_eq(a: &[u8], b: &[u8]) -> bool {
+    if cfg!(feature = "ct-lookups") {
+        constant_time_eq(a, b)
+    } else {
+        a == b
+    }
+}
+
+/// Checks a received ID_CRED_X against the expected credential, if any, according to
+/// draft-tiloca-lake-implem-cons, returning the credential to continue the handshake with
+///
+/// Without an expected credential, one received by value is trusted on first use. A rejection
+/// carries the details of the mismatch.
+pub fn credential_check_or_fetch_detailed(
+    cred_expected: Option<CredentialRPK>,
+    id_cred_received: CredentialRPK,
+) -> Result<CredentialRPK, CredentialRejection> {
+    let kid_of = |cred: &CredentialRPK| cred.has_kid.then_some(cred.kid);
+    let rejection = |kind, cred_expected: Option<&CredentialRPK>| CredentialRejection {
+        kind,
+        received_kid: kid_of(&id_cred_received),
+        expected_kid: cred_expected.and_then(kid_of),
+    };
+
+    // Processing of auth credentials according to draft-tiloca-lake-implem-cons
+    // Comments tagged with a number refer to steps in Section 4.3.1. of draft-tiloca-lake-implem-cons
+    if let Some(cred_expected) = cred_expected {
+        // 1. Does ID_CRED_X point to a stored authentication credential? YES
+        // IMPL: compare cred_i_expected with id_cred
+        //   IMPL: assume cred_i_expected is well formed
+        let mismatch = if id_cred_received.reference_only() {
+            (!credential_bytes_eq(
+                id_cred_received.kid_bytes().unwrap_or_default(),
+                cred_expected.kid_bytes().unwrap_or_default(),
+            ))
+            .then_some(RejectionKind::KidMismatch)
+        } else {
+            (!credential_bytes_eq(
+                id_cred_received.value.as_slice(),
+                cred_expected.value.as_slice(),
+            ))
+            .then_some(RejectionKind::ValueMismatch)
+        };
+
+        // 2. Is this authentication credential still valid?
+        // IMPL,TODO: check cred_r_expected is still valid
+
+        // Continue by considering CRED_X as the authentication credential of the other peer.
+        // IMPL: ready to proceed, including process ead_2
+
+        match mismatch {
+            None => Ok(cred_expected),
+            Some(kind) => Err(rejection(kind, Some(&cred_expected))),
+        }
+    } else {
+        // 1. Does ID_CRED_X point to a stored authentication credential? NO
+        // IMPL: cred_i_expected provided by application is None
+        //       id_cred must be a full credential
+        // 3. Is the trust model Pre-knowledge-only? NO (hardcoded to NO for now)
+        // 4. Is the trust model Pre-knowledge + TOFU? YES (hardcoded to YES for now)
+        // 6. Validate CRED_X. Generally a CCS has to be validated only syntactically and semantically, unlike a certificate or a CWT.
+        //    Is the validation successful?
+        // IMPL,NOTE: the credential has already been parsed with CredentialRPK::new in the *_parse_message_* function
+        // 5. Is the authentication credential authorized for use in the context of this EDHOC session?
+        // IMPL,TODO: we just skip this step for now
+        // 7. Store CRED_X as valid and trusted.
+        //   Pair it with consistent credential identifiers, for each supported type of credential identifier.
+
+        if id_cred_received.reference_only() {
+            return Err(rejection(RejectionKind::ReferenceWithoutStore, None));
+        }
+        Ok(id_cred_received)
+    }
+
+    // 8. Is this authentication credential good to use in the context of this EDHOC session?
+    // IMPL,TODO: we just skip this step for now
+}
+
+#[cfg(test)]
+mod test_vectors_common {
+    use hexlit::hex;
+
+    pub const ID_CRED_I: &[u8] = &hex!("a104412b");
+    pub const ID_CRED_R: &[u8] = &hex!("a104410a");
+    pub const CRED_I: &[u8] = &hex!("A2027734322D35302D33312D46462D45462D33372D33322D333908A101A5010202412B2001215820AC75E9ECE3E50BFC8ED60399889522405C47BF16DF96660A41298CB4307F7EB62258206E5DE611388A4B8A8211334AC7D37ECB52A387D257E6DB3C2A93DF21FF3AFFC8");
+    pub const I: &[u8] = &hex!("fb13adeb6518cee5f88417660841142e830a81fe334380a953406a1305e8706b");
+    pub const R: &[u8] = &hex!("72cc4761dbd4c78f758931aa589d348d1ef874a7e303ede2f140dcf3e6aa4aac");
+    pub const _G_I_Y_COORD: &[u8] =
+        &hex!("6e5de611388a4b8a8211334ac7d37ecb52a387d257e6db3c2a93df21ff3affc8"); // not used
+    pub const CRED_R: &[u8] = &hex!("A2026008A101A5010202410A2001215820BBC34960526EA4D32E940CAD2A234148DDC21791A12AFBCBAC93622046DD44F02258204519E257236B2A0CE2023F0931F1F386CA7AFDA64FCDE0108C224C51EABF6072");
+    // CRED_R without the kid parameter in its COSE_Key
+    pub const CRED_R_NO_KID: &[u8] = &hex!("A2026008A101A401022001215820BBC34960526EA4D32E940CAD2A234148DDC21791A12AFBCBAC93622046DD44F02258204519E257236B2A0CE2023F0931F1F386CA7AFDA64FCDE0108C224C51EABF6072");
+
+    // X.509 certificate for R's static key, issued by X5_ROOT
+    pub const X5_LEAF: &[u8] = &hex!("308201083081aea003020102020102300a06082a8648ce3d040302300f310d300b06035504030c04726f6f74301e170d3234303130313030303030305a170d3334303130313030303030305a300c310a300806035504030c01523059301306072a8648ce3d020106082a8648ce3d03010703420004bbc34960526ea4d32e940cad2a234148ddc21791a12afbcbac93622046dd44f04519e257236b2a0ce2023f0931f1f386ca7afda64fcde0108c224c51eabf6072300a06082a8648ce3d0403020349003046022100861fb47086fbf64bd336478c9df8b61319cf9b7a74dd8eb57d37c051b339c0a70221009955c3fc5e85fa38136042c986dd723cf6361ab519ae351855d07d5387327a07");
+    // self-signed root certificate
+    pub const X5_ROOT: &[u8] = &hex!("3082010a3081b1a003020102020101300a06082a8648ce3d040302300f310d300b06035504030c04726f6f74301e170d3234303130313030303030305a170d3334303130313030303030305a300f310d300b06035504030c04726f6f743059301306072a8648ce3d020106082a8648ce3d030107034200040217e617f0b6443928278f96999e69a23a4f2c152bdf6d6cdf66e5b80282d4ed194a7debcb97712d2dda3ca85aa8765a56f45fc758599652f2897c65306e5794300a06082a8648ce3d0403020348003045022100d795fe9fb2e717f292a7fdf9159b3fe7f93a4fa6ae48f35995ca3f7b617fe4d2022001a676290af26cf461c1d1424bfc0055f1c20c910d4c1540c5d7805d0be24004");
+    // self-signed X.509 certificate of 416 bytes, larger than any message buffer
+    pub const X5_LARGE: &[u8] = &hex!("3082019c30820143a003020102020103300a06082a8648ce3d040302303e311c301a060355040a0c136c616b657273207465737420766563746f7273311e301c06035504030c15726573706f6e6465722e6578616d706c652e636f6d301e170d3234303130313030303030305a170d3334303130313030303030305a303e311c301a060355040a0c136c616b657273207465737420766563746f7273311e301c06035504030c15726573706f6e6465722e6578616d706c652e636f6d3059301306072a8648ce3d020106082a8648ce3d0301070342000486052e205dc25d859908f394459bef39cde938777aca31cf87a2abc7ed208d72a6b5b6ffe0aef81a606a196c40200024c90c83820b27395937efd4c4fd52c404a332303030200603551d11041930178215726573706f6e6465722e6578616d706c652e636f6d300c0603551d130101ff04023000300a06082a8648ce3d040302034700304402204c063cd7ef03588e195c557aff37a27099741a8a5a0673eb6f6f945b4bcad93c0220062e823e4ca17c9b8fa04065a929fa96ea1086063ff991a3fe864fd3a707b3af");
+    pub const X5_LARGE_PUBLIC_KEY_X: [u8; 32] =
+        hex!("86052e205dc25d859908f394459bef39cde938777aca31cf87a2abc7ed208d72");
+    // CRED_R as the payload of a COSE_Sign1, signed with ES256 by the issuer below
+    pub const SIGNED_CWT_R: &[u8] = &hex!("d28443a10126a05854a2026008a101a5010202410a2001215820bbc34960526ea4d32e940cad2a234148ddc21791a12afbcbac93622046dd44f02258204519e257236b2a0ce2023f0931f1f386ca7afda64fcde0108c224c51eabf60725840530425448841a1c48c3dd6ad274150d2e4c30553568e1156baab59882f8b2b2933598b480d51710fcdf707b8ae1adc5f04be33e7ee865f8a5e47eb7de33859b9");
+    pub const CWT_ISSUER_X: [u8; 32] =
+        hex!("e4e3defa9c63371fdde11324e2ff4d318de4bc47524187702335c8276b7bb507");
+    pub const CWT_ISSUER_Y: [u8; 32] =
+        hex!("4226d280da1c86b43a01ca5ffccd929fb62292d8901319dbcfb45016fc597882");
+
+    pub const MESSAGE_1_TV_FIRST_TIME: &str =
+        "03065820741a13d7ba048fbb615e94386aa3b61bea5b3d8f65f32620b749bee8d278efa90e";
+    pub const MESSAGE_1_TV: &str =
+        "0382060258208af6f430ebe18d34184017a9a11bf511c8dff8f834730b96c1b7c8dbca2fc3b637";
+}
+
+#[cfg(test)]
+mod corpus;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lakers_crypto::default_crypto;
+    use test_vectors_common::*;
+
+    // The parties of the test vectors, and the steps of a handshake between them as most tests run
+    // it: without EAD, and with both credentials sent by reference. A test that changes one step
+    // runs that one by hand.
+
+    pub(super) fn cred_i() -> CredentialRPK {
+        CredentialRPK::new(CRED_I.try_into().unwrap()).unwrap()
+    }
+
+    pub(super) fn cred_r() -> CredentialRPK {
+        CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap()
+    }
+
+    pub(super) fn new_initiator() -> EdhocInitiator<'static, lakers_crypto::Crypto> {
+        EdhocInitiator::new(default_crypto())
+    }
+
+    pub(super) fn new_responder() -> EdhocResponder<'static, lakers_crypto::Crypto> {
+        EdhocResponder::new(default_crypto(), R, cred_r()).unwrap()
+    }
+
+    pub(super) fn exchange_message_1<'a, 'b, CI: CryptoTrait, CR: CryptoTrait, S: Signer>(
+        initiator: EdhocInitiator<'a, CI>,
+        responder: EdhocResponder<'b, CR, S>,
+    ) -> (
+        EdhocInitiatorWaitM2<'a, CI>,
+        EdhocResponderProcessedM1<'b, CR, S>,
+        BufferMessage1,
+    ) {
+        let (initiator, message_1) = initiator.prepare_message_1(None, &None).unwrap();
+        let (responder, _ead_1) = responder.process_message_1(&message_1).unwrap();
+        (initiator, responder, message_1)
+    }
+
+    /// The Initiator's side of message_2, which is checked against CRED_R
+    pub(super) fn receive_message_2<'a, C: CryptoTrait>(
+        initiator: EdhocInitiatorWaitM2<'a, C>,
+        message_2: &'a BufferMessage2,
+    ) -> EdhocInitiatorProcessedM2<C> {
+        let (initiator, _c_r, id_cred_r, _ead_2) = initiator.parse_message_2(message_2).unwrap();
+        let valid_cred_r = credential_check_or_fetch(Some(cred_r()), id_cred_r).unwrap();
+        initiator
+            .verify_message_2(I, cred_i(), valid_cred_r)
+            .unwrap()
+    }
+
+    pub(super) fn exchange_message_2<CI: CryptoTrait, CR: CryptoTrait, S: Signer>(
+        initiator: EdhocInitiatorWaitM2<'_, CI>,
+        responder: EdhocResponderProcessedM1<'_, CR, S>,
+    ) -> (
+        EdhocInitiatorProcessedM2<CI>,
+        EdhocResponderWaitM3<CR>,
+        BufferMessage2,
+    ) {
+        let (responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+            .unwrap();
+        (
+            receive_message_2(initiator, &message_2),
+            responder,
+            message_2,
+        )
+    }
+
+    /// The Responder's side of message_3, which is checked against CRED_I
+    pub(super) fn receive_message_3<C: CryptoTrait>(
+        responder: EdhocResponderWaitM3<C>,
+        message_3: &BufferMessage3,
+    ) -> (EdhocResponderDone<C>, [u8; SHA256_DIGEST_LEN]) {
+        let (responder, id_cred_i, _ead_3) = responder.parse_message_3(message_3).unwrap();
+        let valid_cred_i = credential_check_or_fetch(Some(cred_i()), id_cred_i).unwrap();
+        responder.verify_message_3(valid_cred_i).unwrap()
+    }
+
+    /// Also checks that both parties arrive at the same PRK_out
+    pub(super) fn exchange_message_3<CI: CryptoTrait, CR: CryptoTrait>(
+        initiator: EdhocInitiatorProcessedM2<CI>,
+        responder: EdhocResponderWaitM3<CR>,
+    ) -> (
+        EdhocInitiatorDone<CI>,
+        EdhocResponderDone<CR>,
+        BufferMessage3,
+    ) {
+        let (initiator, message_3, i_prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+        let (responder, r_prk_out) = receive_message_3(responder, &message_3);
+        assert_eq!(i_prk_out, r_prk_out);
+        (initiator, responder, message_3)
+    }
+
+    pub(super) fn handshake<CI: CryptoTrait, CR: CryptoTrait, S: Signer>(
+        initiator: EdhocInitiator<'_, CI>,
+        responder: EdhocResponder<'_, CR, S>,
+    ) -> (EdhocInitiatorDone<CI>, EdhocResponderDone<CR>) {
+        let (initiator, responder, _message_1) = exchange_message_1(initiator, responder);
+        let (initiator, responder, _message_2) = exchange_message_2(initiator, responder);
+        let (initiator, responder, _message_3) = exchange_message_3(initiator, responder);
+        (initiator, responder)
+    }
+
+    #[test]
+    fn test_new_initiator() {
+        let _initiator = EdhocInitiator::new(default_crypto());
+    }
+
+    #[test]
+    fn test_new_responder() {
+        let _responder = EdhocResponder::new(
+            default_crypto(),
+            R,
+            CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_prepare_message_1() {
+        let initiator = EdhocInitiator::new(default_crypto());
+
+        let c_i = generate_connection_identifier_cbor(&mut default_crypto()).unwrap();
+        let result = initiator.prepare_message_1(Some(c_i), &None);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_process_message_1() {
+        let message_1_tv_first_time = EdhocMessageBuffer::from_hex(MESSAGE_1_TV_FIRST_TIME);
+        let message_1_tv = EdhocMessageBuffer::from_hex(MESSAGE_1_TV);
+        let responder = EdhocResponder::new(
+            default_crypto(),
+            R,
+            CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap(),
+        )
+        .unwrap();
+
+        // process message_1 first time, when unsupported suite is selected
+        let error = responder.process_message_1(&message_1_tv_first_time);
+        assert!(error.is_err());
+        assert_eq!(
+            error.unwrap_err(),
+            EdhocFailure::new(
+                EDHOCError::UnsupportedCipherSuite,
+                HandshakeStep::ProcessMessage1
+            )
+        );
+
+        // We need to create a new responder -- no message is supposed to be processed twice by a
+        // responder or initiator
+        let responder = EdhocResponder::new(
+            default_crypto(),
+            R,
+            CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap(),
+        )
+        .unwrap();
+
+        // process message_1 second time
+        let error = responder.process_message_1(&message_1_tv);
+        assert!(error.is_ok());
+    }
+
+    #[test]
+    fn test_error_chain() {
+        fn process_message_1(message_1: &BufferMessage1) -> anyhow::Result<()> {
+            new_responder().process_message_1(message_1)?;
+            Ok(())
+        }
+
+        // message_1 of the traces, with C_I = -24 encoded as 0x3817 instead of 0x37
+        let mut message_1 = BufferMessage1::from_hex(MESSAGE_1_TV);
+        message_1.len -= 1;
+        message_1.extend_from_slice(&[0x38, 0x17]).unwrap();
+        let error = process_message_1(&message_1).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<EdhocFailure>().unwrap().step,
+            HandshakeStep::ProcessMessage1
+        );
+        assert_eq!(
+            error.chain().nth(1).unwrap().downcast_ref::<EDHOCError>(),
+            Some(&EDHOCError::NonCanonicalEncoding)
+        );
+        assert_eq!(
+            error.chain().nth(2).unwrap().downcast_ref::<CBORError>(),
+            Some(&CBORError::NonCanonicalEncoding)
+        );
+        assert_eq!(
+            format!("{:#}", error),
+            "EDHOC failed at ProcessMessage1: EDHOCError::NonCanonicalEncoding: \
+             CBORError::NonCanonicalEncoding"
+        );
+
+        // a ParsingError need not come from the CBOR decoder, so the chain ends there
+        let error = process_message_1(&EdhocMessageBuffer::from_hex("03")).unwrap_err();
+        assert_eq!(
+            error.chain().nth(1).unwrap().downcast_ref::<EDHOCError>(),
+            Some(&EDHOCError::ParsingError)
+        );
+        assert_eq!(error.chain().count(), 2);
+    }
+
+    #[test]
+    fn test_generate_connection_identifier() {
+        let conn_id = generate_connection_identifier(&mut default_crypto()).unwrap();
+        assert!(conn_id >= -24 && conn_id <= 23);
+    }
+
+    /// An RNG that only ever produces the same byte
+    pub(crate) struct StuckRng(pub(crate) u8);
+
+    impl rand_core::RngCore for StuckRng {
+        fn next_u32(&mut self) -> u32 {
+            u32::from_ne_bytes([self.0; 4])
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            u64::from_ne_bytes([self.0; 8])
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            dest.fill(self.0)
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+            dest.fill(self.0);
+            Ok(())
+        }
+    }
+
+    impl rand_core::CryptoRng for StuckRng {}
+
+    #[test]
+    fn test_rng_failure() {
+        // 0x7f is 127, which is not a single byte CBOR integer
+        let stuck = || lakers_crypto_rustcrypto::Crypto::new(StuckRng(0x7f));
+
+        assert_eq!(
+            generate_connection_identifier(&mut stuck()),
+            Err(EDHOCError::RngFailure)
+        );
+        assert_eq!(
+            generate_connection_identifier_cbor(&mut stuck()),
+            Err(EDHOCError::RngFailure)
+        );
+        assert_eq!(
+            generate_connection_identifier_with(&mut stuck(), |_| true),
+            Err(EDHOCError::RngFailure)
+        );
+
+        let (x, g_x) = default_crypto().p256_generate_key_pair();
+        let initiator = EdhocInitiator::from_start(
+            stuck(),
+            InitiatorStart::new(x, g_x, &EDHOC_SUPPORTED_SUITES).unwrap(),
+        );
+        assert_eq!(
+            initiator.prepare_message_1(None, &None).unwrap_err(),
+            EdhocFailure::new(EDHOCError::RngFailure, HandshakeStep::PrepareMessage1)
+        );
+
+        // with a working RNG, C_R is generated when preparing message_2
+        let responder = EdhocResponder::new(stuck(), R, cred_r()).unwrap();
+        let (_initiator, responder, _message_1) = exchange_message_1(new_initiator(), responder);
+        assert_eq!(
+            responder
+                .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+                .unwrap_err(),
+            EdhocFailure::new(EDHOCError::RngFailure, HandshakeStep::PrepareMessage2)
+        );
+    }
+
+    #[test]
+    fn test_non_canonical_c_i() {
+        // message_1 of the traces, with C_I = -24 encoded as 0x3817 instead of 0x37
+        let mut message_1 = BufferMessage1::from_hex(MESSAGE_1_TV);
+        message_1.len -= 1;
+        message_1.extend_from_slice(&[0x38, 0x17]).unwrap();
+
+        assert_eq!(
+            prevalidate_message_1(message_1.as_slice()),
+            Err(EDHOCError::NonCanonicalEncoding)
+        );
+        assert_eq!(
+            new_responder().process_message_1(&message_1).unwrap_err(),
+            EdhocFailure::new(
+                EDHOCError::NonCanonicalEncoding,
+                HandshakeStep::ProcessMessage1
+            )
+        );
+    }
+
+    #[test]
+    fn test_cipher_suite_to_oscore_algs() {
+        // AES-CCM-16-64-128 and SHA-256
+        assert_eq!(cipher_suite_to_oscore_algs(2), Some((10, -16)));
+        for suite in EDHOC_SUPPORTED_SUITES {
+            assert!(cipher_suite_to_oscore_algs(suite).is_some());
+        }
+        // the application AEAD of suites 1 and 3 is not their EDHOC AEAD
+        assert_eq!(cipher_suite_to_oscore_algs(3), Some((10, -16)));
+        assert_eq!(cipher_suite_to_oscore_algs(24), Some((3, -43)));
+        assert_eq!(cipher_suite_to_oscore_algs(7), None);
+    }
+
+    #[test]
+    fn test_prevalidate_message_1() {
+        let summary = prevalidate_message_1(&hexlit::hex!(
+            "0382060258208af6f430ebe18d34184017a9a11bf511c8dff8f834730b96c1b7c8dbca2fc3b637"
+        ))
+        .unwrap();
+        assert_eq!(summary.method, Method::default() as u8);
+        assert_eq!(summary.selected_suite, 0x02);
+        assert_eq!(summary.suites_i(), [0x06, 0x02]);
+        assert_eq!(summary.c_i, 0x37);
+        assert_eq!(summary.ead_1, None);
+
+        let ead_1 = EADItem {
+            label: 0x05,
+            is_critical: true,
+            value: None,
+        };
+        let (_initiator, message_1) = new_initiator()
+            .prepare_message_1(Some(0x0e), &Some(ead_1))
+            .unwrap();
+        let summary = prevalidate_message_1(message_1.as_slice()).unwrap();
+        assert_eq!(summary.c_i, 0x0e);
+        assert_eq!(summary.ead_1, Some((0x05, true)));
+
+        assert_eq!(
+            prevalidate_message_1(&[0x03; MAX_MESSAGE_SIZE_LEN + 1]),
+            Err(EDHOCError::MessageTooLong)
+        );
+    }
+
+    #[test]
+    fn test_peek_message_1_header() {
+        let message_1 = BufferMessage1::from_hex(MESSAGE_1_TV);
+        assert_eq!(peek_message_1_header(message_1.as_slice()), Ok((3, 0x02)));
+        let message_1 = BufferMessage1::from_hex(MESSAGE_1_TV_FIRST_TIME);
+        assert_eq!(peek_message_1_header(message_1.as_slice()), Ok((3, 0x06)));
+
+        // nothing after the suites is looked at
+        let message_1 = BufferMessage1::from_hex(MESSAGE_1_TV);
+        assert_eq!(
+            peek_message_1_header(&message_1.as_slice()[..4]),
+            Ok((3, 0x02))
+        );
+        let mut garbled = message_1;
+        garbled.content[4..garbled.len].fill(0xff);
+        assert_eq!(peek_message_1_header(garbled.as_slice()), Ok((3, 0x02)));
+
+        assert_eq!(
+            peek_message_1_header(&message_1.as_slice()[..3]),
+            Err(EDHOCError::ParsingError)
+        );
+        assert_eq!(peek_message_1_header(&[]), Err(EDHOCError::ParsingError));
+
+        let mut oversized = [0x00; MAX_MESSAGE_SIZE_LEN + 1];
+        oversized[..4].copy_from_slice(&message_1.as_slice()[..4]);
+        assert_eq!(
+            peek_message_1_header(&oversized[..MAX_MESSAGE_SIZE_LEN]),
+            Ok((3, 0x02))
+        );
+        assert_eq!(
+            peek_message_1_header(&oversized),
+            Err(EDHOCError::MessageTooLong)
+        );
+    }
+
+    /// A backend that counts the EC operations it performs
+    #[derive(Debug)]
+    struct CountingEc<C: CryptoTrait>(C, std::rc::Rc<core::cell::Cell<usize>>);
+
+    impl<C: CryptoTrait> CryptoTrait for CountingEc<C> {
+        forward_crypto!(
+            |this| {
+                this.1.set(this.1.get() + 1);
+                &mut this.0
+            },
+            only p256_ecdh,
+            p256_generate_key_pair,
+            p256_validate_public_key
+        );
+        forward_crypto!(
+            |this| this.0,
+            except p256_ecdh,
+            p256_generate_key_pair,
+            p256_validate_public_key
+        );
+    }
+
+    #[test]
+    fn test_reject_message_1_without_ec_operations() {
+        let ec_operations = std::rc::Rc::new(core::cell::Cell::new(0));
+        let responder = || {
+            EdhocResponder::new(
+                CountingEc(default_crypto(), ec_operations.clone()),
+                R,
+                cred_r(),
+            )
+            .unwrap()
+        };
+        let message_1 = BufferMessage1::from_hex(MESSAGE_1_TV);
+
+        let mut wrong_method = message_1;
+        wrong_method.content[0] = 0x04;
+        let mut wrong_suite = message_1;
+        wrong_suite.content[3] = 0x06;
+        let mut g_x_too_large = message_1;
+        g_x_too_large.content[6..6 + P256_ELEM_LEN].fill(0xff);
+        let mut truncated = message_1;
+        truncated.len = 20;
+        for (message_1, error) in [
+            (wrong_method, EDHOCError::UnsupportedMethod),
+            (wrong_suite, EDHOCError::UnsupportedCipherSuite),
+            (g_x_too_large, EDHOCError::InvalidKey),
+            (truncated, EDHOCError::ParsingError),
+        ] {
+            assert_eq!(prevalidate_message_1(message_1.as_slice()), Err(error));
+            assert_eq!(
+                responder().process_message_1(&message_1).unwrap_err(),
+                EdhocFailure::new(error, HandshakeStep::ProcessMessage1)
+            );
+        }
+        assert_eq!(ec_operations.get(), 0);
+
+        // a message_1 that passes costs the ephemeral key and the validation of G_X
+        responder().process_message_1(&message_1).unwrap();
+        assert_eq!(ec_operations.get(), 2);
+    }
+
+    #[test]
+    fn test_unknown_trailing_data() {
+        let responder = |unknown_trailing_data| {
+            new_responder().with_unknown_trailing_data(unknown_trailing_data)
+        };
+        // message_1 of the traces, followed by {1: 2} and 5
+        let mut message_1 = BufferMessage1::from_hex(MESSAGE_1_TV);
+        message_1
+            .extend_from_slice(&[0xa1, 0x01, 0x02, 0x05])
+            .unwrap();
+
+        assert_eq!(
+            responder(UnknownTrailingData::default())
+                .process_message_1(&message_1)
+                .unwrap_err(),
+            EdhocFailure::new(EDHOCError::ParsingError, HandshakeStep::ProcessMessage1)
+        );
+
+        let (processed, ead_1) = responder(UnknownTrailingData::IgnoreAndReport)
+            .process_message_1(&message_1)
+            .unwrap();
+        assert!(ead_1.is_empty());
+        assert_eq!(
+            processed.unknown_trailing_items(),
+            Some(TrailingItems { items: 2, bytes: 4 })
+        );
+
+        // nothing to skip in a message_1 that ends with EAD_1
+        let mut message_1_ead = BufferMessage1::from_hex(MESSAGE_1_TV);
+        message_1_ead
+            .extend_from_slice(&[0x01, 0x42, 0xcc, 0xcc])
+            .unwrap();
+        let (processed, ead_1) = responder(UnknownTrailingData::IgnoreAndReport)
+            .process_message_1(&message_1_ead)
+            .unwrap();
+        assert_eq!(ead_1.first().unwrap().label, 1);
+        assert_eq!(processed.unknown_trailing_items(), None);
+
+        // EAD_1 is kept when unknown items follow it
+        message_1_ead
+            .extend_from_slice(&[0x40, 0xa1, 0x01, 0x02])
+            .unwrap();
+        let (processed, ead_1) = responder(UnknownTrailingData::IgnoreAndReport)
+            .process_message_1(&message_1_ead)
+            .unwrap();
+        let ead_1 = ead_1.first().cloned().unwrap();
+        assert_eq!(ead_1.label, 1);
+        assert_eq!(ead_1.value.unwrap().as_slice(), &[0x42, 0xcc, 0xcc]);
+        assert_eq!(
+            processed.unknown_trailing_items(),
+            Some(TrailingItems { items: 2, bytes: 4 })
+        );
+
+        // all EAD_1 items are kept, so that a critical one after the first is not skipped
+        let mut message_1_eads = BufferMessage1::from_hex(MESSAGE_1_TV);
+        message_1_eads
+            .extend_from_slice(&[0x01, 0x42, 0xcc, 0xcc, 0x22, 0x41, 0xdd])
+            .unwrap();
+        let (processed, ead_1) = responder(UnknownTrailingData::IgnoreAndReport)
+            .process_message_1(&message_1_eads)
+            .unwrap();
+        assert_eq!(ead_1.len(), 2);
+        let critical = &ead_1.as_slice()[1];
+        assert_eq!((critical.label, critical.is_critical), (3, true));
+        assert_eq!(processed.unknown_trailing_items(), None);
+
+        // the transcript still covers the whole message_1
+        let mut crypto = default_crypto();
+        let (y, g_y) = crypto.p256_generate_key_pair();
+        let (state, _ead_1) = r_process_message_1_ignoring_trailing_items(
+            &ResponderStart::new(y, g_y),
+            &mut crypto,
+            &message_1,
+            MESSAGE_1_TV.len() / 2,
+        )
+        .unwrap();
+        let mut message_1_buf: BytesMaxBuffer = [0x00; MAX_BUFFER_LEN];
+        message_1_buf[..message_1.len].copy_from_slice(message_1.as_slice());
+        assert_eq!(
+            state.h_message_1,
+            crypto.sha256_digest(&message_1_buf, message_1.len)
+        );
+    }
+
+    /// An AES-CCM engine that can be switched off, counting the operations it performs
+    #[derive(Debug)]
+    struct MockEngine {
+        present: std::rc::Rc<core::cell::Cell<bool>>,
+        operations: std::rc::Rc<core::cell::Cell<usize>>,
+    }
+
+    impl AesCcmEngine for MockEngine {
+        fn available(&mut self) -> bool {
+            self.present.get()
+        }
+
+        forward_crypto!(
+            |this| {
+                this.operations.set(this.operations.get() + 1);
+                default_crypto()
+            },
+            only aes_ccm_encrypt_tag_8,
+            aes_ccm_decrypt_tag_8
+        );
+    }
+
+    #[test]
+    fn test_aes_ccm_dispatch() {
+        let present = std::rc::Rc::new(core::cell::Cell::new(true));
+        let operations = std::rc::Rc::new(core::cell::Cell::new(0));
+        let mut crypto = AesCcmDispatch::new(
+            default_crypto(),
+            MockEngine {
+                present: present.clone(),
+                operations: operations.clone(),
+            },
+        );
+        let key = [0x0b; AES_CCM_KEY_LEN];
+        let iv = [0x0c; AES_CCM_IV_LEN];
+        let plaintext = BufferPlaintext3::new_from_slice(b"plaintext").unwrap();
+
+        assert!(crypto.aes_ccm_hardware_available());
+        let hardware_ciphertext = crypto.aes_ccm_encrypt_tag_8(&key, &iv, b"ad", &plaintext);
+        assert_eq!(operations.get(), 1);
+
+        // the peripheral went away: the software fallback takes over with the same output
+        present.set(false);
+        assert!(!crypto.aes_ccm_hardware_available());
+        let software_ciphertext = crypto.aes_ccm_encrypt_tag_8(&key, &iv, b"ad", &plaintext);
+        assert_eq!(operations.get(), 1);
+        assert_eq!(hardware_ciphertext, software_ciphertext);
+        assert_eq!(
+            crypto.aes_ccm_decrypt_tag_8(&key, &iv, b"ad", &hardware_ciphertext),
+            Ok(plaintext)
+        );
+        assert_eq!(operations.get(), 1);
+
+        present.set(true);
+        assert_eq!(
+            crypto.aes_ccm_decrypt_tag_8(&key, &iv, b"ad", &software_ciphertext),
+            Ok(plaintext)
+        );
+        assert_eq!(operations.get(), 2);
+
+        // other back-ends report software
+        assert!(!default_crypto().aes_ccm_hardware_available());
+    }
+
+    /// A backend whose ECDH is broken and always produces an all-zero shared secret, and public
+    /// key as it takes those from the same point multiplication
+    #[derive(Debug)]
+    struct ZeroEcdh<C: CryptoTrait>(C);
+
+    impl<C: CryptoTrait> CryptoTrait for ZeroEcdh<C> {
+        fn p256_ecdh(
+            &mut self,
+            _private_key: &BytesP256ElemLen,
+            _public_key: &BytesP256ElemLen,
+        ) -> BytesP256ElemLen {
+            [0; P256_ELEM_LEN]
+        }
+
+        fn p256_public_from_private(
+            &mut self,
+            _private_key: &BytesP256ElemLen,
+        ) -> Result<BytesP256ElemLen, EDHOCError> {
+            Ok([0; P256_ELEM_LEN])
+        }
+
+        forward_crypto!(|this| this.0, except p256_ecdh, p256_public_from_private);
+    }
+
+    #[test]
+    fn test_all_zero_shared_secret() {
+        let (initiator, mut responder, message_1) = exchange_message_1(
+            EdhocInitiator::new(ZeroEcdh(default_crypto())),
+            EdhocResponder::new(ZeroEcdh(default_crypto()), R, cred_r()).unwrap(),
+        );
+        assert_eq!(
+            responder
+                .derive_ead_key(0, b"my-ead", AES_CCM_KEY_LEN)
+                .unwrap_err(),
+            EdhocFailure::new(EDHOCError::InvalidKey, HandshakeStep::PrepareMessage2)
+        );
+        assert_eq!(
+            responder
+                .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+                .unwrap_err(),
+            EdhocFailure::new(EDHOCError::InvalidKey, HandshakeStep::PrepareMessage2)
+        );
+
+        // the initiator does not derive keys from it either
+        let (_responder, message_2) = new_responder()
+            .process_message_1(&message_1)
+            .unwrap()
+            .0
+            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+            .unwrap();
+        assert_eq!(
+            initiator.parse_message_2(&message_2).unwrap_err(),
+            EdhocFailure::new(EDHOCError::InvalidKey, HandshakeStep::ParseMessage2)
+        );
+    }
+
+    #[test]
+    fn test_no_key_pair_generated() {
+        // all public keys are zero, so no seed gives a key pair
+        let seeded = || InsecureSeededCrypto::new(ZeroEcdh(default_crypto()), &[0x01; 32]);
+        assert_eq!(
+            seeded().p256_generate_key_pair(),
+            ([0x00; P256_ELEM_LEN], [0x00; P256_ELEM_LEN])
+        );
+
+        assert_eq!(
+            EdhocInitiator::new(seeded())
+                .prepare_message_1(None, &None)
+                .unwrap_err(),
+            EdhocFailure::new(EDHOCError::RngFailure, HandshakeStep::PrepareMessage1)
+        );
+        let (_initiator, message_1) = new_initiator().prepare_message_1(None, &None).unwrap();
+        assert_eq!(
+            EdhocResponder::new(seeded(), R, cred_r())
+                .unwrap()
+                .process_message_1(&message_1)
+                .unwrap_err(),
+            EdhocFailure::new(EDHOCError::RngFailure, HandshakeStep::ProcessMessage1)
+        );
+    }
+
+    #[test]
+    fn test_generate_connection_identifier_with() {
+        for _ in 0..16 {
+            let conn_id =
+                generate_connection_identifier_with(&mut default_crypto(), |id| id >= 0).unwrap();
+            assert!(conn_id >= 0 && conn_id <= 23);
+        }
+        // a single identifier left
+        assert_eq!(
+            generate_connection_identifier_with(&mut default_crypto(), |id| id == -7),
+            Ok(-7)
+        );
+
+        assert_eq!(
+            generate_connection_identifier_with(&mut default_crypto(), |_| false),
+            Err(EDHOCError::InvalidParameter)
+        );
+    }
+
+    #[test]
+    fn test_validate_x5chain() {
+        let chain = CredentialX509Chain::new(&[X5_LEAF, X5_ROOT]).unwrap();
+        assert!(chain.validate(&mut default_crypto(), &[X5_ROOT]).is_ok());
+        assert_eq!(chain.leaf_public_key().unwrap(), cred_r().public_key);
+
+        // the root does not need to be part of the chain
+        let chain = CredentialX509Chain::new(&[X5_LEAF]).unwrap();
+        assert!(chain.validate(&mut default_crypto(), &[X5_ROOT]).is_ok());
+
+        // chain does not end at a trust anchor
+        assert_eq!(
+            chain.validate(&mut default_crypto(), &[]),
+            Err(EDHOCError::CredentialVerificationFailed)
+        );
+
+        let chain = CredentialX509Chain::new(&[]).unwrap();
+        assert_eq!(
+            chain.validate(&mut default_crypto(), &[X5_ROOT]),
+            Err(EDHOCError::CredentialVerificationFailed)
+        );
+
+        // broken link: the leaf's signature does not verify with the root's key
+        let mut broken_leaf = [0u8; X5_LEAF.len()];
+        broken_leaf.copy_from_slice(X5_LEAF);
+        broken_leaf[X5_LEAF.len() - 1] ^= 0x01;
+        let chain = CredentialX509Chain::new(&[&broken_leaf, X5_ROOT]).unwrap();
+        assert_eq!(
+            chain.validate(&mut default_crypto(), &[X5_ROOT]),
+            Err(EDHOCError::CredentialVerificationFailed)
+        );
+    }
+
+    #[test]
+    fn test_x509_certificate_parse_stream() {
+        use lakers_crypto_rustcrypto::Sha256Hasher;
+        const CHUNK_LEN: usize = 16;
+
+        let stream_certificate = |certificate: &[u8], chunk_len: usize| {
+            let mut stream = X509CertificateParseStream::new(Sha256Hasher::default());
+            for chunk in certificate.chunks(chunk_len) {
+                stream.update(chunk)?;
+            }
+            stream.finish()
+        };
+
+        // the state and a chunk take less memory than the certificate, let alone a buffer for it
+        assert!(X5_LARGE.len() > MAX_MESSAGE_SIZE_LEN);
+        let memory = core::mem::size_of::<X509CertificateParseStream<Sha256Hasher>>() + CHUNK_LEN;
+        assert!(memory < X5_LARGE.len(), "{memory} bytes");
+
+        let streamed = stream_certificate(X5_LARGE, CHUNK_LEN).unwrap();
+        let thumbprint = default_crypto().hash(HashAlg::Sha256, X5_LARGE).unwrap();
+        assert_eq!(&streamed.thumbprint[..], thumbprint.as_slice());
+        assert_eq!(streamed.public_key_x, X5_LARGE_PUBLIC_KEY_X);
+        // the public key is the one the in-memory parser finds, and it verifies the signature
+        let chain = CredentialX509Chain::new(&[X5_LARGE]).unwrap();
+        assert_eq!(streamed.public_key_x, chain.leaf_public_key().unwrap());
+        let chain = CredentialX509Chain::new(&[X5_LARGE, X5_LARGE]).unwrap();
+        assert!(chain.validate(&mut default_crypto(), &[X5_LARGE]).is_ok());
+
+        // however the certificate is split up
+        assert_eq!(stream_certificate(X5_LARGE, 1), Ok(streamed));
+        assert_eq!(stream_certificate(X5_LARGE, X5_LARGE.len()), Ok(streamed));
+        let leaf = stream_certificate(X5_LEAF, 7).unwrap();
+        assert_eq!(leaf.public_key_x, cred_r().public_key);
+
+        // truncated, with trailing data, and with a length beyond the enclosing SEQUENCE
+        let truncated = &X5_LARGE[..X5_LARGE.len() - 1];
+        assert_eq!(
+            stream_certificate(truncated, CHUNK_LEN),
+            Err(EDHOCError::ParsingError)
+        );
+        let mut trailing = X5_LARGE.to_vec();
+        trailing.push(0x00);
+        assert_eq!(
+            stream_certificate(&trailing, CHUNK_LEN),
+            Err(EDHOCError::ParsingError)
+        );
+        let mut overlong = X5_LARGE.to_vec();
+        overlong[7] += 0x10; // length of the TBSCertificate
+        assert_eq!(
+            stream_certificate(&overlong, CHUNK_LEN),
+            Err(EDHOCError::ParsingError)
+        );
+
+        // the length of the TBSCertificate in two bytes, where one is enough, is not DER
+        assert_eq!(X5_LEAF[..7], [0x30, 0x82, 0x01, 0x08, 0x30, 0x81, 0xae]);
+        let mut non_minimal = vec![0x30, 0x82, 0x01, 0x09, 0x30, 0x82, 0x00, 0xae];
+        non_minimal.extend_from_slice(&X5_LEAF[7..]);
+        assert_eq!(
+            stream_certificate(&non_minimal, CHUNK_LEN),
+            Err(EDHOCError::ParsingError)
+        );
+        let chain = CredentialX509Chain::new(&[&non_minimal]).unwrap();
+        assert_eq!(chain.leaf_public_key(), Err(EDHOCError::ParsingError));
+
+        // the signature is not verified, so the key is only handed out for pinned certificates
+        assert_eq!(
+            streamed.check_thumbprint(&[leaf.thumbprint, streamed.thumbprint]),
+            Ok(X5_LARGE_PUBLIC_KEY_X)
+        );
+        assert_eq!(
+            streamed.check_thumbprint(&[leaf.thumbprint]),
+            Err(EDHOCError::CredentialVerificationFailed)
+        );
+    }
+
+    #[test]
+    fn test_responder_identity() {
+        let cred_r = cred_r();
+        let r = P256PrivateKey::new(R.try_into().unwrap());
+
+        assert_eq!(
+            ResponderIdentity::new(&mut default_crypto(), r.clone(), cred_i()).unwrap_err(),
+            EDHOCError::InvalidParameter
+        );
+        let identity = ResponderIdentity::new(&mut default_crypto(), r, cred_r).unwrap();
+        assert_eq!(identity.cred_r().value, cred_r.value);
+        let debug = format!("{identity:?}");
+        assert!(debug.contains("cred_r"));
+        assert!(!debug.contains(format!("{:?}", &R[..4]).trim_matches(['[', ']'])));
+
+        // many concurrent sessions with one identity
+        std::thread::scope(|scope| {
+            for _ in 0..16 {
+                scope.spawn(|| {
+                    for _ in 0..4 {
+                        let responder = EdhocResponder::from_identity(default_crypto(), &identity);
+                        handshake(new_initiator(), responder);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_handshake_with_signer() {
+        struct SoftwareSigner(BytesP256ElemLen);
+
+        impl Signer for SoftwareSigner {
+            fn static_dh(
+                &self,
+                peer_point: &BytesP256ElemLen,
+            ) -> Result<BytesP256ElemLen, EDHOCError> {
+                Ok(default_crypto().p256_ecdh(&self.0, peer_point))
+            }
+        }
+
+        let signer = SoftwareSigner(R.try_into().unwrap());
+        let responder = EdhocResponder::new_with_signer(default_crypto(), signer, cred_r());
+        handshake(new_initiator(), responder);
+
+        // what the signer returns is checked like the result of any other ECDH
+        struct BrokenSigner;
+
+        impl Signer for BrokenSigner {
+            fn static_dh(
+                &self,
+                _peer_point: &BytesP256ElemLen,
+            ) -> Result<BytesP256ElemLen, EDHOCError> {
+                Ok([0x00; P256_ELEM_LEN])
+            }
+        }
+
+        let responder = EdhocResponder::new_with_signer(default_crypto(), BrokenSigner, cred_r());
+        let (_initiator, responder, _message_1) = exchange_message_1(new_initiator(), responder);
+        let failure = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+            .unwrap_err();
+        assert_eq!(failure.error, EDHOCError::InvalidKey);
+        assert_eq!(failure.step, HandshakeStep::PrepareMessage2);
+    }
+
+    #[test]
+    fn test_parse_and_verify_message_2() {
+        let cred_i = cred_i();
+        fn known(id_cred: &CredentialRPK) -> Option<CredentialRPK> {
+            let cred_r = cred_r();
+            (id_cred.kid_bytes() == cred_r.kid_bytes()).then_some(cred_r)
+        }
+        fn unknown(_id_cred: &CredentialRPK) -> Option<CredentialRPK> {
+            None
+        }
+        let exchange = |resolver: Option<&'static dyn CredentialResolver>| {
+            let mut initiator = new_initiator();
+            if let Some(resolver) = resolver {
+                initiator = initiator.with_credential_resolver(resolver);
+            }
+            let (initiator, responder, _message_1) = exchange_message_1(initiator, new_responder());
+            let (responder, message_2) = responder
+                .prepare_message_2(CredentialTransfer::ByReference, Some(0x08), &None)
+                .unwrap();
+            (initiator, responder, message_2)
+        };
+
+        let (initiator, responder, message_2) = exchange(Some(&known));
+        let (initiator, c_r, valid_cred_r, ead_2) = initiator
+            .parse_and_verify_message_2(&message_2, I, cred_i)
+            .unwrap();
+        assert_eq!(c_r, 0x08);
+        assert_eq!(valid_cred_r.value, cred_r().value);
+        assert!(ead_2.is_empty());
+        exchange_message_3(initiator, responder);
+
+        // CRED_R is only referenced, so it has to be known
+        for resolver in [Some(&unknown as &dyn CredentialResolver), None] {
+            let (initiator, _responder, message_2) = exchange(resolver);
+            assert_eq!(
+                initiator
+                    .parse_and_verify_message_2(&message_2, I, cred_i)
+                    .unwrap_err(),
+                EdhocFailure::new(EDHOCError::UnknownPeer, HandshakeStep::VerifyMessage2)
+            );
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_parse_and_verify_message_2_async() {
+        use core::future::Future;
+        use core::pin::pin;
+        use core::task::{Context, Poll, Waker};
+
+        // a directory lookup that is not ready the first time it is polled
+        struct Directory(bool);
+        impl Future for Directory {
+            type Output = Option<CredentialRPK>;
+            fn poll(
+                mut self: core::pin::Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<Self::Output> {
+                if core::mem::replace(&mut self.0, true) {
+                    Poll::Ready(Some(cred_r()))
+                } else {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            }
+        }
+        fn block_on<T>(future: impl Future<Output = T>) -> T {
+            let mut future = pin!(future);
+            let mut cx = Context::from_waker(Waker::noop());
+            loop {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                    return output;
+                }
+            }
+        }
+        fn assert_send<T: Send>(value: T) -> T {
+            value
+        }
+
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+        let (initiator, responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+        let (responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+            .unwrap();
+
+        let mut fetched = None;
+        let verify =
+            initiator.parse_and_verify_message_2_async(&message_2, I, cred_i, |id_cred_r| {
+                fetched = Some(id_cred_r);
+                Directory(false)
+            });
+        let (initiator, _c_r, valid_cred_r, _ead_2) = block_on(assert_send(verify)).unwrap();
+        assert_eq!(fetched.unwrap().kid, cred_r.kid);
+        assert_eq!(valid_cred_r.value, cred_r.value);
+        exchange_message_3(initiator, responder);
+
+        // a directory that does not know the referenced credential
+        let (initiator, responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+        let (_responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+            .unwrap();
+        let verify =
+            initiator.parse_and_verify_message_2_async(&message_2, I, cred_i, |_| async { None });
+        assert_eq!(
+            block_on(verify).unwrap_err(),
+            EdhocFailure::new(EDHOCError::UnknownPeer, HandshakeStep::VerifyMessage2)
+        );
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_handshake() {
+        let cred_i = CredentialRPK::new(CRED_I.try_into().unwrap()).unwrap();
+        let cred_r = CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap();
+
+        let initiator = EdhocInitiator::new(default_crypto()); // can choose which identity to use after learning R's identity
+        let responder = EdhocResponder::new(default_crypto(), R, cred_r).unwrap(); // has to select an identity before learning who is I
+
+        // ---- begin initiator handling
+        // if needed: prepare ead_1
+        let (initiator, message_1) = initiator.prepare_message_1(None, &None).unwrap();
+        // ---- end initiator handling
+
+        // ---- begin responder handling
+        let (responder, _ead_1) = responder.process_message_1(&message_1).unwrap();
+        // if ead_1: process ead_1
+        // if needed: prepare ead_2
+        let (responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+            .unwrap();
+        // ---- end responder handling
+
+        // ---- being initiator handling
+        let (initiator, _c_r, id_cred_r, _ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        let valid_cred_r = credential_check_or_fetch(Some(cred_r), id_cred_r).unwrap();
+        let initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+
+        // if needed: prepare ead_3
+        let (mut initiator, message_3, i_prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+        assert_eq!(initiator.selected_cipher_suite(), EDHOC_SUPPORTED_SUITES[0]);
+        // ---- end initiator handling
+
+        // ---- begin responder handling
+        let (responder, id_cred_i, _ead_3) = responder.parse_message_3(&message_3).unwrap();
+        let valid_cred_i = credential_check_or_fetch(Some(cred_i), id_cred_i).unwrap();
+        // if ead_3: process ead_3
+        let (mut responder, r_prk_out) = responder.verify_message_3(valid_cred_i).unwrap();
+        // ---- end responder handling
+
+        // check that prk_out is equal at initiator and responder side
+        assert_eq!(i_prk_out, r_prk_out);
+
+        // derive OSCORE secret and salt at both sides and compare
+        let i_oscore_secret = initiator.edhoc_exporter(0u8, &[], 16); // label is 0
+        let i_oscore_salt = initiator.edhoc_exporter(1u8, &[], 8); // label is 1
+
+        let r_oscore_secret = responder.edhoc_exporter(0u8, &[], 16); // label is 0
+        let r_oscore_salt = responder.edhoc_exporter(1u8, &[], 8); // label is 1
+
+        assert_eq!(i_oscore_secret, r_oscore_secret);
+        assert_eq!(i_oscore_salt, r_oscore_salt);
+
+        // test key update with context from draft-ietf-lake-traces
+        let i_prk_out_new = initiator
+            .edhoc_key_update(&[
+                0xa0, 0x11, 0x58, 0xfd, 0xb8, 0x20, 0x89, 0x0c, 0xd6, 0xbe, 0x16, 0x96, 0x02, 0xb8,
+                0xbc, 0xea,
+            ])
+            .unwrap();
+        let r_prk_out_new = responder
+            .edhoc_key_update(&[
+                0xa0, 0x11, 0x58, 0xfd, 0xb8, 0x20, 0x89, 0x0c, 0xd6, 0xbe, 0x16, 0x96, 0x02, 0xb8,
+                0xbc, 0xea,
+            ])
+            .unwrap();
+
+        assert_eq!(i_prk_out_new, r_prk_out_new);
+
+        #[cfg(feature = "report")]
+        {
+            let i_report = initiator.into_report().unwrap();
+            let r_report = responder.into_report().unwrap();
+            assert_eq!(i_report.role, Role::Initiator);
+            assert_eq!(r_report.role, Role::Responder);
+            for report in [&i_report, &r_report] {
+                assert_eq!(report.method, Method::default() as u8);
+                assert_eq!(report.cipher_suite, EDHOC_SUPPORTED_SUITES[0]);
+                assert_eq!(report.ead, []);
+                assert_eq!(report.message_1_len, message_1.len);
+                assert_eq!(report.message_2_len, message_2.len);
+                assert_eq!(report.message_3_len, message_3.len);
+                assert_eq!(report.message_1_trailing_items, None);
+                assert_eq!(
+                    report.exporter_labels,
+                    [
+                        ExporterReport {
+                            label: 0,
+                            early: false
+                        },
+                        ExporterReport {
+                            label: 1,
+                            early: false
+                        }
+                    ]
+                );
+            }
+            let by_reference = |kid| CredentialReport {
+                kid: Some(kid),
+                transfer: CredentialTransfer::ByReference,
+            };
+            assert_eq!(i_report.own_credential, by_reference(0x2b));
+            assert_eq!(i_report.peer_credential, by_reference(0x0a));
+            assert_eq!(r_report.own_credential, by_reference(0x0a));
+            assert_eq!(r_report.peer_credential, by_reference(0x2b));
+        }
+    }
+
+    // the transcript of a signature and CRED_I does not fit the buffers of limits-small
+    #[cfg(not(feature = "limits-small"))]
+    #[test]
+    fn test_handshake_signature_signature() {
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+        // the private keys of the traces are used as ECDSA signing keys
+        let start = || {
+            let (initiator, responder, message_1) = exchange_message_1(
+                new_initiator().with_method(Method::SignatureSignature),
+                new_responder().with_method(Method::SignatureSignature),
+            );
+            assert_eq!(message_1.content[0], Method::SignatureSignature as u8);
+            let (responder, message_2) = responder
+                .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+                .unwrap();
+            (initiator, responder, message_2)
+        };
+
+        let (initiator, responder, message_2) = start();
+        let initiator = receive_message_2(initiator, &message_2);
+        let (mut initiator, mut responder, _message_3) = exchange_message_3(initiator, responder);
+        assert_eq!(
+            initiator.edhoc_exporter(0u8, &[], 16),
+            responder.edhoc_exporter(0u8, &[], 16)
+        );
+
+        // the last byte of ciphertext_2 is the last byte of the signature
+        let (initiator, _responder, mut message_2) = start();
+        message_2.content[message_2.len - 1] ^= 0x01;
+        let (initiator, _c_r, _id_cred_r, _ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        assert_eq!(
+            initiator
+                .verify_message_2(I, cred_i, cred_r)
+                .unwrap_err()
+                .error,
+            EDHOCError::MacVerificationFailed
+        );
+
+        // a signature does not verify with the key of a different credential
+        let (initiator, _responder, message_2) = start();
+        let (initiator, _c_r, _id_cred_r, _ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        assert_eq!(
+            initiator
+                .verify_message_2(I, cred_i, cred_i)
+                .unwrap_err()
+                .error,
+            EDHOCError::MacVerificationFailed
+        );
+    }
+
+    // the transcript of a signature and CRED_I does not fit the buffers of limits-small
+    #[cfg(not(feature = "limits-small"))]
+    #[test]
+    fn test_handshake_mixed_methods() {
+        // the private keys of the traces are used as ECDSA signing or static DH keys, as the
+        // method has it
+        let message_lengths = |method: Method| {
+            let (initiator, responder, message_1) = exchange_message_1(
+                new_initiator().with_method(method),
+                new_responder().with_method(method),
+            );
+            assert_eq!(message_1.content[0], method as u8);
+            let (initiator, responder, message_2) = exchange_message_2(initiator, responder);
+            let (_initiator, _responder, message_3) = exchange_message_3(initiator, responder);
+            (message_2.len, message_3.len)
+        };
+
+        // a peer that signs sends the same Signature_or_MAC as with method 0, else as with 3
+        let (signed_2, signed_3) = message_lengths(Method::SignatureSignature);
+        let (mac_2, mac_3) = message_lengths(Method::StaticStatic);
+        assert_eq!(message_lengths(Method::SignatureStatic), (mac_2, signed_3));
+        assert_eq!(message_lengths(Method::StaticSignature), (signed_2, mac_3));
+        // plus one byte, as the encoded length of a signature does not fit its bstr header
+        assert_eq!(signed_2 - mac_2, P256_SIGNATURE_LEN - MAC_LENGTH + 1);
+    }
+
+    #[test]
+    fn test_method_mismatch() {
+        let (_initiator, message_1) = new_initiator()
+            .with_method(Method::SignatureSignature)
+            .prepare_message_1(None, &None)
+            .unwrap();
+        assert_eq!(
+            prevalidate_message_1(message_1.as_slice()).unwrap().method,
+            Method::SignatureSignature as u8
+        );
+
+        // the key of a Responder fits one method only
+        let responder = new_responder();
+        assert_eq!(
+            responder.process_message_1(&message_1).unwrap_err(),
+            EdhocFailure::new(
+                EDHOCError::UnsupportedMethod,
+                HandshakeStep::ProcessMessage1
+            )
+        );
+        let (_initiator, message_1) = new_initiator().prepare_message_1(None, &None).unwrap();
+        let responder = new_responder().with_method(Method::SignatureSignature);
+        assert_eq!(
+            responder.process_message_1(&message_1).unwrap_err(),
+            EdhocFailure::new(
+                EDHOCError::UnsupportedMethod,
+                HandshakeStep::ProcessMessage1
+            )
+        );
+
+        // a Responder that only allows method 3 rejects method 1, also when its key would fit
+        let (_initiator, message_1) = new_initiator()
+            .with_method(Method::SignatureStatic)
+            .prepare_message_1(None, &None)
+            .unwrap();
+        let only_static_static = ApplicationProfile {
+            methods: 1 << Method::StaticStatic as u8,
+            ..ApplicationProfile::default()
+        };
+        for responder in [
+            new_responder(),
+            new_responder()
+                .with_method(Method::SignatureStatic)
+                .with_application_profile(only_static_static),
+        ] {
+            assert_eq!(
+                responder.process_message_1(&message_1).unwrap_err().error,
+                EDHOCError::UnsupportedMethod
+            );
+        }
+        let mut crypto = default_crypto();
+        let (y, g_y) = crypto.p256_generate_key_pair();
+        assert_eq!(
+            r_process_message_1(&ResponderStart::new(y, g_y), &mut crypto, &message_1).unwrap_err(),
+            EDHOCError::UnsupportedMethod
+        );
+    }
+
+    #[test]
+    fn test_handshake_erased_crypto() {
+        // initiators over different back-ends share one type
+        let mut initiators: Vec<EdhocInitiator<Box<dyn ErasedCrypto>>> = vec![
+            EdhocInitiator::new(Box::new(default_crypto())),
+            EdhocInitiator::new(Box::new(lakers_crypto_rustcrypto::Crypto::new(
+                rand_core::OsRng,
+            ))),
+        ];
+
+        let initiator = initiators.pop().unwrap();
+        let responder = EdhocResponder::new(
+            Box::new(default_crypto()) as Box<dyn ErasedCrypto>,
+            R,
+            cred_r(),
+        )
+        .unwrap();
+        handshake(initiator, responder);
+    }
+
+    /// A report must never give away any of the keys used in or obtained from the handshake
+    #[cfg(all(feature = "test-ead-none", feature = "report"))]
+    #[test]
+    fn test_report_contains_no_secrets() {
+        let (initiator, responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+        let (mut responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByValue, None, &None)
+            .unwrap();
+        let early_secret = responder.early_exporter(2, &[], 16).unwrap();
+        let initiator = receive_message_2(initiator, &message_2);
+        let (mut initiator, message_3, prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+        let (mut responder, _prk_out) = receive_message_3(responder, &message_3);
+
+        let oscore_secret = initiator.edhoc_exporter(0, &[], 16).unwrap();
+        let mut cose_key = [0u8; 64];
+        responder
+            .export_cose_key(
+                ExporterLabel::OSCORE_MASTER_SALT,
+                CoseKty::Symmetric,
+                8,
+                &mut cose_key,
+            )
+            .unwrap();
+        let oscore_salt = responder.edhoc_exporter(1, &[], 8).unwrap();
+
+        let r_report = responder.into_report().unwrap();
+        assert_eq!(
+            r_report.own_credential.transfer,
+            CredentialTransfer::ByValue
+        );
+        assert_eq!(
+            r_report.exporter_labels,
+            [
+                ExporterReport {
+                    label: 2,
+                    early: true
+                },
+                ExporterReport {
+                    label: 1,
+                    early: false
+                }
+            ]
+        );
+
+        let secrets: [&[u8]; 6] = [
+            I,
+            R,
+            &prk_out,
+            &early_secret[..16],
+            &oscore_secret[..16],
+            &oscore_salt[..8],
+        ];
+        for report in [initiator.into_report().unwrap(), r_report] {
+            let json = serde_json::to_string(&report).unwrap();
+            for secret in secrets {
+                let hex: String = secret.iter().map(|byte| format!("{byte:02x}")).collect();
+                let numbers: Vec<String> = secret.iter().map(|byte| byte.to_string()).collect();
+                assert!(!json.to_lowercase().contains(&hex), "{json}");
+                assert!(!json.contains(&numbers.join(",")), "{json}");
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "report")]
+    fn test_report_of_incomplete_handshake() {
+        // nothing was recorded about the credentials
+        assert_eq!(
+            ReportRecorder::new().finish(Role::Initiator),
+            Err(EDHOCError::InconsistentState)
+        );
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_expects_c_r() {
+        let c_r = 0x05;
+        let (initiator, responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+        let (_responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, Some(c_r), &None)
+            .unwrap();
+
+        let initiator = receive_message_2(initiator, &message_2);
+        assert!(initiator.expects_c_r(c_r));
+        assert!(!initiator.expects_c_r(0x06));
+
+        let (initiator, _message_3, _prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+        assert!(initiator.expects_c_r(c_r));
+        assert!(!initiator.expects_c_r(0x06));
+        assert!(!initiator.expects_c_r(CONN_ID_EMPTY));
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_responder_connection_id() {
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+
+        for (c_r, expected) in [(0x05, &[0x05][..]), (0x27, &[0x27]), (CONN_ID_EMPTY, &[])] {
+            let (initiator, responder, _message_1) =
+                exchange_message_1(new_initiator(), new_responder());
+            let (_responder, message_2) = responder
+                .prepare_message_2(CredentialTransfer::ByReference, Some(c_r), &None)
+                .unwrap();
+
+            let (initiator, c_r_rcvd, id_cred_r, _ead_2) =
+                initiator.parse_message_2(&message_2).unwrap();
+            assert_eq!(c_r_rcvd, c_r);
+            let valid_cred_r = credential_check_or_fetch(Some(cred_r), id_cred_r).unwrap();
+            let initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+            let (mut initiator, _message_3, _prk_out) = initiator
+                .prepare_message_3(CredentialTransfer::ByReference, &None)
+                .unwrap();
+            assert_eq!(initiator.responder_connection_id(), expected);
+
+            initiator.confirm_peer_completion();
+            assert_eq!(initiator.responder_connection_id(), oscore_id(&c_r_rcvd));
+        }
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_bytes_exchanged() {
+        // connection identifiers as in the traces of RFC 9529 chapter 3
+        let (initiator, message_1) = new_initiator()
+            .prepare_message_1(Some(0x37), &None)
+            .unwrap();
+        let (responder, _ead_1) = new_responder().process_message_1(&message_1).unwrap();
+        let (_responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, Some(0x27), &None)
+            .unwrap();
+        let initiator = receive_message_2(initiator, &message_2);
+        let (initiator, message_3, _prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+
+        assert_eq!(
+            initiator.bytes_exchanged(),
+            message_1.len + message_2.len + message_3.len
+        );
+        assert_eq!(initiator.bytes_exchanged(), 37 + 45 + 19);
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_verify_connection_id_orientation() {
+        let (initiator, message_1) = new_initiator()
+            .prepare_message_1(Some(0x37), &None)
+            .unwrap();
+        let (responder, _ead_1) = new_responder().process_message_1(&message_1).unwrap();
+        let (_responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, Some(0x27), &None)
+            .unwrap();
+        let initiator = receive_message_2(initiator, &message_2);
+        let (mut initiator, _message_3, _prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+
+        assert_eq!(initiator.verify_connection_id_orientation(), Ok(()));
+
+        // with the two swapped in the state, C_I would be used as the OSCORE Sender ID
+        core::mem::swap(&mut initiator.c_i, &mut initiator.c_r);
+        assert_eq!(initiator.responder_connection_id(), [0x37]);
+        assert_eq!(
+            initiator.verify_connection_id_orientation(),
+            Err(EDHOCError::ConnectionIdentifierMismatch)
+        );
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_th_4() {
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+
+        let (initiator, message_1) = new_initiator().prepare_message_1(None, &None).unwrap();
+        let (responder, message_2) = new_responder()
+            .respond_to_message_1(&message_1, CredentialTransfer::ByReference)
+            .unwrap();
+        let initiator = receive_message_2(initiator, &message_2);
+        let (initiator, responder, _message_3) = exchange_message_3(initiator, responder);
+        assert_eq!(initiator.th_4(), responder.th_4());
+
+        // th_3 is not exposed by the wrappers, so compare against it through the low-level API
+        let i: BytesP256ElemLen = I.try_into().unwrap();
+        let r: BytesP256ElemLen = R.try_into().unwrap();
+        let mut crypto = default_crypto();
+        let (x, g_x) = crypto.p256_generate_key_pair();
+        let (y, g_y) = crypto.p256_generate_key_pair();
+        let (wait_m2, message_1) = i_prepare_message_1(
+            &InitiatorStart::new(x, g_x, &EDHOC_SUPPORTED_SUITES).unwrap(),
+            &mut crypto,
+            0x37,
+            &None,
+        )
+        .unwrap();
+        let (processing_m1, _) =
+            r_process_message_1(&ResponderStart::new(y, g_y), &mut crypto, &message_1).unwrap();
+        let (mut wait_m3, message_2) = r_prepare_message_2(
+            &processing_m1,
+            &mut crypto,
+            cred_r,
+            &r,
+            0x27,
+            CredentialTransfer::ByReference,
+            &None,
+        )
+        .unwrap();
+        let (processing_m2, _, _, _) =
+            i_parse_message_2(&wait_m2, &mut crypto, &message_2).unwrap();
+        let mut processed_m2 = i_verify_message_2(&processing_m2, &mut crypto, cred_r, &i).unwrap();
+        let th_3 = processed_m2.th_3;
+        let (i_completed, message_3, _) = i_prepare_message_3(
+            &mut processed_m2,
+            &mut crypto,
+            cred_i,
+            CredentialTransfer::ByReference,
+            &None,
+        )
+        .unwrap();
+        let (mut processing_m3, _, _) =
+            r_parse_message_3(&mut wait_m3, &mut crypto, &message_3).unwrap();
+        let (r_completed, _) = r_verify_message_3(&mut processing_m3, &mut crypto, cred_i).unwrap();
+
+        assert_eq!(i_completed.th_4, r_completed.th_4);
+        assert_ne!(i_completed.th_4, th_3);
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_handshake_stage_and_progress_mark() {
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+
+        let mut initiator = new_initiator();
+        let mut responder = new_responder();
+        assert_eq!(initiator.stage(), HandshakeStage::Start);
+        assert_eq!(responder.stage(), HandshakeStage::Start);
+        assert_eq!(initiator.progress_mark(), 0);
+        assert_eq!(responder.progress_mark(), 0);
+        initiator.set_progress_mark(1);
+        responder.set_progress_mark(100);
+
+        let (mut initiator, message_1) = initiator.prepare_message_1(None, &None).unwrap();
+        assert_eq!(initiator.stage(), HandshakeStage::WaitM2);
+        assert_eq!(initiator.progress_mark(), 1);
+        initiator.set_progress_mark(2);
+
+        let (mut responder, _ead_1) = responder.process_message_1(&message_1).unwrap();
+        assert_eq!(responder.stage(), HandshakeStage::ProcessedM1);
+        assert_eq!(responder.progress_mark(), 100);
+        responder.set_progress_mark(101);
+
+        let (mut responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+            .unwrap();
+        assert_eq!(responder.stage(), HandshakeStage::WaitM3);
+        assert_eq!(responder.progress_mark(), 101);
+        responder.set_progress_mark(102);
+
+        let (mut initiator, _c_r, id_cred_r, _ead_2) =
+            initiator.parse_message_2(&message_2).unwrap();
+        assert_eq!(initiator.stage(), HandshakeStage::ProcessingM2);
+        assert_eq!(initiator.progress_mark(), 2);
+        initiator.set_progress_mark(3);
+
+        let valid_cred_r = credential_check_or_fetch(Some(cred_r), id_cred_r).unwrap();
+        let mut initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+        assert_eq!(initiator.stage(), HandshakeStage::ProcessedM2);
+        assert_eq!(initiator.progress_mark(), 3);
+        initiator.set_progress_mark(4);
+
+        let (initiator, message_3, _i_prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+        assert_eq!(initiator.stage(), HandshakeStage::Done);
+        assert_eq!(initiator.progress_mark(), 4);
+
+        let (mut responder, id_cred_i, _ead_3) = responder.parse_message_3(&message_3).unwrap();
+        assert_eq!(responder.stage(), HandshakeStage::ProcessingM3);
+        assert_eq!(responder.progress_mark(), 102);
+        responder.set_progress_mark(103);
+
+        let valid_cred_i = credential_check_or_fetch(Some(cred_i), id_cred_i).unwrap();
+        let (responder, _r_prk_out) = responder.verify_message_3(valid_cred_i).unwrap();
+        assert_eq!(responder.stage(), HandshakeStage::Done);
+        assert_eq!(responder.progress_mark(), 103);
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_retransmit_message_1() {
+        let initiator = new_initiator();
+        let responder = new_responder();
+
+        let (initiator, message_1) = initiator.prepare_message_1(None, &None).unwrap();
+        // sending message_1 fails, so the retained copy is sent again
+        let resent = *initiator.message_1();
+        assert_eq!(resent, message_1);
+
+        let (responder, _ead_1) = responder.process_message_1(&resent).unwrap();
+        let (_responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+            .unwrap();
+        assert!(initiator.parse_message_2(&message_2).is_ok());
+    }
+
+    #[test]
+    fn test_retransmit_message_2() {
+        let ec_operations = std::rc::Rc::new(core::cell::Cell::new(0));
+        let responder = EdhocResponder::new(
+            CountingEc(default_crypto(), ec_operations.clone()),
+            R,
+            cred_r(),
+        )
+        .unwrap();
+
+        let (initiator, responder, _message_1) = exchange_message_1(new_initiator(), responder);
+        let (mut responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+            .unwrap();
+        let operations = ec_operations.get();
+
+        // message_2 got lost, so the Initiator sends message_1 again
+        let resent = *initiator.message_1();
+        assert_eq!(responder.retained_message_2_for(&resent), Some(&message_2));
+        assert_eq!(responder.retained_message_2(), Some(&message_2));
+        assert_eq!(ec_operations.get(), operations);
+        assert!(initiator.parse_message_2(&message_2).is_ok());
+
+        // a message_1 of another handshake is not answered with it
+        let (_initiator, other_message_1) = new_initiator().prepare_message_1(None, &None).unwrap();
+        assert_eq!(responder.retained_message_2_for(&other_message_1), None);
+
+        responder.discard_retained();
+        assert_eq!(responder.retained_message_2(), None);
+        assert_eq!(responder.retained_message_2_for(&resent), None);
+    }
+
+    #[test]
+    fn test_early_message_3() {
+        let (initiator, responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+        // message_2 is handed to the transport, which has not yet confirmed sending it
+        let (initiator, responder, message_2) = exchange_message_2(initiator, responder);
+        let (_initiator, message_3, i_prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+
+        // message_3 arrives first, and is processed right away
+        assert_eq!(responder.retained_message_2(), Some(&message_2));
+        let (_responder, r_prk_out) = receive_message_3(responder, &message_3);
+        assert_eq!(i_prk_out, r_prk_out);
+    }
+
+    #[test]
+    fn test_initiator_g_x() {
+        let (initiator, message_1) = new_initiator().prepare_message_1(None, &None).unwrap();
+        let (_method, _suites_i, _suites_i_len, g_x, _c_i, _ead_1) =
+            parse_message_1(&message_1).unwrap();
+        assert_eq!(initiator.g_x(), &g_x);
+        let g_x_start = message_1.len - 1 - P256_ELEM_LEN; // G_X is followed by the one-byte C_I
+        assert_eq!(
+            &message_1.as_slice()[g_x_start..g_x_start + P256_ELEM_LEN],
+            initiator.g_x()
+        );
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_external_decryption_of_ciphertext_2() {
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+
+        let (initiator, responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+        let (responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, Some(0x05), &None)
+            .unwrap();
+        let (mut initiator, c_r, id_cred_r, _ead_2) =
+            initiator.parse_message_2(&message_2).unwrap();
+
+        // decrypt ciphertext_2 as a separate environment would
+        let mut plaintext_2 = *initiator.ciphertext_2();
+        let keystream_2 = initiator.keystream_2();
+        for (byte, key) in plaintext_2.content[..plaintext_2.len]
+            .iter_mut()
+            .zip(keystream_2)
+        {
+            *byte ^= key;
+        }
+        assert_eq!(&plaintext_2, initiator.plaintext_2());
+
+        let (c_r_external, id_cred_r_external, ead_2) =
+            initiator.set_plaintext_2(&plaintext_2).unwrap();
+        assert_eq!(c_r_external, c_r);
+        assert_eq!(id_cred_r_external.kid, id_cred_r.kid);
+        assert!(ead_2.is_empty());
+
+        // a plaintext_2 that does not decode is rejected
+        let garbage = BufferPlaintext2::new_from_slice(&[0xff]).unwrap();
+        assert_eq!(
+            initiator.set_plaintext_2(&garbage).unwrap_err().step,
+            HandshakeStep::ParseMessage2
+        );
+
+        let valid_cred_r = credential_check_or_fetch(Some(cred_r), id_cred_r_external).unwrap();
+        let initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+        exchange_message_3(initiator, responder);
+    }
+
+    #[test]
+    fn test_credential_rejection() {
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+        let reference_to = |kid| CredentialRPK {
+            value: Default::default(),
+            public_key: Default::default(),
+            kid,
+            has_kid: true,
+        };
+
+        assert_eq!(
+            credential_check_or_fetch_detailed(Some(cred_r), reference_to(cred_i.kid)).unwrap_err(),
+            CredentialRejection {
+                kind: RejectionKind::KidMismatch,
+                received_kid: Some(cred_i.kid),
+                expected_kid: Some(cred_r.kid),
+            }
+        );
+        assert_eq!(
+            credential_check_or_fetch_detailed(Some(cred_r), cred_i).unwrap_err(),
+            CredentialRejection {
+                kind: RejectionKind::ValueMismatch,
+                received_kid: Some(cred_i.kid),
+                expected_kid: Some(cred_r.kid),
+            }
+        );
+        assert_eq!(
+            credential_check_or_fetch_detailed(None, reference_to(cred_r.kid)).unwrap_err(),
+            CredentialRejection {
+                kind: RejectionKind::ReferenceWithoutStore,
+                received_kid: Some(cred_r.kid),
+                expected_kid: None,
+            }
+        );
+
+        // the plain variant does not tell the rejections apart
+        assert_eq!(
+            credential_check_or_fetch(Some(cred_r), cred_i).unwrap_err(),
+            EDHOCError::UnknownPeer
+        );
+        assert_eq!(
+            credential_check_or_fetch(None, reference_to(cred_r.kid)).unwrap_err(),
+            EDHOCError::UnknownPeer
+        );
+        assert!(credential_check_or_fetch_detailed(Some(cred_r), reference_to(cred_r.kid)).is_ok());
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_fetch_credential_by_hint() {
+        const REPOSITORY_URI: &[u8] = b"coap://repository.example/creds";
+        // a credential repository that only knows CRED_R
+        let fetch = |uri: &[u8], kid: &[u8]| (uri == REPOSITORY_URI && kid == [0x0a]).then(cred_r);
+
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+
+        let (initiator, responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+        let ead_2 = CredentialHint::to_ead_2(REPOSITORY_URI).unwrap();
+        let (responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &Some(ead_2))
+            .unwrap();
+
+        let (initiator, _c_r, id_cred_r, ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        let hint = CredentialHint::from_ead_2(ead_2.first().unwrap());
+        assert_eq!(hint.unwrap().uri(), REPOSITORY_URI);
+
+        let CredentialLookup::NeedsFetch(request) =
+            credential_check_or_request_fetch(None, id_cred_r, hint).unwrap()
+        else {
+            panic!("a credential referenced by kid needs to be fetched");
+        };
+        let fetched = fetch(request.hint.unwrap().uri(), request.kid()).unwrap();
+        let valid_cred_r = request.complete(fetched).unwrap();
+
+        let initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+        exchange_message_3(initiator, responder);
+
+        // a fetched credential with another kid is rejected
+        assert_eq!(
+            request.complete(cred_i).unwrap_err().kind,
+            RejectionKind::KidMismatch
+        );
+        // an expected credential makes fetching unnecessary
+        assert!(matches!(
+            credential_check_or_request_fetch(Some(cred_r), id_cred_r, hint),
+            Ok(CredentialLookup::Valid(_))
+        ));
+        // other EAD items are no hints
+        let other = EADItem {
+            label: EAD_RA_LABEL,
+            ..CredentialHint::to_ead_2(REPOSITORY_URI).unwrap()
+        };
+        assert_eq!(CredentialHint::from_ead_2(&other), None);
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_ead_freshness() {
+        const NOW: u64 = 1_760_000_000;
+        let freshness = EadFreshness::new(30);
+        let received_at = |timestamp: Option<u64>| {
+            let ead_1 = timestamp.map(EadFreshness::to_ead_1);
+            let (_initiator, message_1) = new_initiator().prepare_message_1(None, &ead_1).unwrap();
+            let (_responder, ead_1) = new_responder().process_message_1(&message_1).unwrap();
+            freshness.check_ead_1(&ead_1, NOW)
+        };
+
+        // in the window, also with a clock that is slightly ahead
+        assert_eq!(received_at(Some(NOW - 10)), Ok(()));
+        assert_eq!(received_at(Some(NOW + 30)), Ok(()));
+        // expired, too far ahead, or missing
+        assert_eq!(received_at(Some(NOW - 31)), Err(EDHOCError::EADError));
+        assert_eq!(received_at(Some(NOW + 3600)), Err(EDHOCError::EADError));
+        assert_eq!(received_at(None), Err(EDHOCError::EADError));
+
+        assert_eq!(
+            EadFreshness::timestamp(&EadFreshness::to_ead_1(NOW)),
+            Some(NOW)
+        );
+        let other = EADItem {
+            label: EAD_CRED_HINT_LABEL,
+            ..EadFreshness::to_ead_1(NOW)
+        };
+        assert_eq!(EadFreshness::timestamp(&other), None);
+    }
+
+    #[test]
+    fn test_selected_suite_acceptable() {
+        use SuitePolicy::*;
+
+        // SUITES_I offers 3 before the selected 2
+        for policy in [AcceptAnySupported, RequireMostPreferred] {
+            assert!(selected_suite_acceptable(policy, &[3, 2], &[2]));
+            assert!(!selected_suite_acceptable(policy, &[3, 2], &[3]));
+            assert!(!selected_suite_acceptable(policy, &[], &[2]));
+        }
+        // a responder that supports and prefers 3
+        assert!(selected_suite_acceptable(
+            AcceptAnySupported,
+            &[3, 2],
+            &[3, 2]
+        ));
+        assert!(!selected_suite_acceptable(
+            RequireMostPreferred,
+            &[3, 2],
+            &[3, 2]
+        ));
+        // a responder that prefers the selected suite
+        assert!(selected_suite_acceptable(
+            RequireMostPreferred,
+            &[3, 2],
+            &[2, 3]
+        ));
+    }
+
+    #[test]
+    fn test_suite_policy() {
+        let initiator_offering = |offered: &[u8]| {
+            let (x, g_x) = default_crypto().p256_generate_key_pair();
+            EdhocInitiator::from_start(
+                default_crypto(),
+                InitiatorStart::new(x, g_x, offered).unwrap(),
+            )
+        };
+
+        let responder = |policy| new_responder().with_suite_policy(policy);
+        // the Initiator offers 3 but selects 2
+        let (_initiator, message_1) = initiator_offering(&[3, 2])
+            .prepare_message_1(None, &None)
+            .unwrap();
+        let suites_i = prevalidate_message_1(message_1.as_slice())
+            .unwrap()
+            .suites_i()
+            .to_vec();
+
+        // a Responder that supports and prefers 3 accepts 2 only by default, and asks for 3 else
+        assert_eq!(
+            responder(SuitePolicy::AcceptAnySupported).check_selected_suite(&suites_i, &[3, 2]),
+            Ok(())
+        );
+        assert_eq!(
+            responder(SuitePolicy::RequireMostPreferred).check_selected_suite(&suites_i, &[3, 2]),
+            Err(EDHOCError::UnsupportedCipherSuite)
+        );
+        // one that prefers 2 accepts it either way
+        assert_eq!(
+            responder(SuitePolicy::RequireMostPreferred).check_selected_suite(&suites_i, &[2, 3]),
+            Ok(())
+        );
+
+        // 3 is not supported here, so the selected 2 is the most preferred one
+        for policy in [
+            SuitePolicy::AcceptAnySupported,
+            SuitePolicy::RequireMostPreferred,
+        ] {
+            assert!(responder(policy).process_message_1(&message_1).is_ok());
+        }
+        let (_initiator, message_1) = initiator_offering(&[2, 3])
+            .prepare_message_1(None, &None)
+            .unwrap();
+        assert_eq!(
+            responder(SuitePolicy::RequireMostPreferred)
+                .process_message_1(&message_1)
+                .unwrap_err(),
+            EdhocFailure::new(
+                EDHOCError::UnsupportedCipherSuite,
+                HandshakeStep::ProcessMessage1
+            )
+        );
+        // ERR_CODE 2, SUITES_R 2
+        assert_eq!(
+            prepare_error_wrong_selected_suite().as_slice(),
+            [0x02, 0x02]
+        );
+    }
+
+    #[test]
+    fn test_offered_suites_count() {
+        let (x, g_x) = default_crypto().p256_generate_key_pair();
+        assert_eq!(
+            InitiatorStart::new(x, g_x, &[]).unwrap_err(),
+            EDHOCError::InvalidParameter
+        );
+        assert_eq!(
+            InitiatorStart::new(x, g_x, &[2; SUITES_LEN + 1]).unwrap_err(),
+            EDHOCError::InvalidParameter
+        );
+        let start = InitiatorStart::new(x, g_x, &[2; SUITES_LEN]).unwrap();
+        assert_eq!(start.suites_i_len, SUITES_LEN);
+    }
+
+    #[test]
+    fn test_offered_suites() {
+        let (x, g_x) = default_crypto().p256_generate_key_pair();
+        let initiator = EdhocInitiator::from_start(
+            default_crypto(),
+            InitiatorStart::new(x, g_x, &[6, 3, 2]).unwrap(),
+        );
+        let (_initiator, responder, _message_1) = exchange_message_1(initiator, new_responder());
+        // including the suites preferred over the selected 2
+        assert_eq!(responder.offered_suites(), [6, 3, 2]);
+
+        let message_1 = EdhocMessageBuffer::from_hex(MESSAGE_1_TV);
+        let (responder, _ead_1) = new_responder().process_message_1(&message_1).unwrap();
+        assert_eq!(responder.offered_suites(), [6, 2]);
+
+        // a single suite is sent as an int rather than an array
+        let initiator = EdhocInitiator::from_start(
+            default_crypto(),
+            InitiatorStart::new(x, g_x, &[2]).unwrap(),
+        );
+        let (_initiator, responder, _message_1) = exchange_message_1(initiator, new_responder());
+        assert_eq!(responder.offered_suites(), [2]);
+    }
+
+    #[test]
+    fn test_restart_with_suite() {
+        let (x, g_x) = default_crypto().p256_generate_key_pair();
+        let initiator = EdhocInitiator::from_start(
+            default_crypto(),
+            InitiatorStart::new(x, g_x, &[3]).unwrap(),
+        );
+        let (initiator, message_1) = initiator.prepare_message_1(None, &None).unwrap();
+        let responder = new_responder();
+        assert_eq!(
+            responder.process_message_1(&message_1).unwrap_err().error,
+            EDHOCError::UnsupportedCipherSuite
+        );
+
+        // the Responder sent SUITES_R 2
+        let first_g_x = *initiator.g_x();
+        let initiator = initiator.recycle();
+        assert_eq!(initiator.selected_cipher_suite(), 3);
+        let initiator = initiator.restart_with_suite(2).unwrap();
+        assert_eq!(initiator.attempted_suites(), [3]);
+        let (initiator, message_1) = initiator.prepare_message_1(None, &None).unwrap();
+        assert_ne!(*initiator.g_x(), first_g_x);
+        assert_eq!(peek_message_1_header(message_1.as_slice()), Ok((3, 2)));
+        let responder = new_responder();
+        let (responder, _ead_1) = responder.process_message_1(&message_1).unwrap();
+        assert_eq!(responder.offered_suites(), [3, 2]);
+
+        // the history survives another recycle, and is only cleared on request
+        let mut initiator = initiator.recycle();
+        assert_eq!(initiator.attempted_suites(), [3]);
+        let (_initiator, responder, _message_1) = exchange_message_1(initiator, new_responder());
+        assert_eq!(responder.offered_suites(), [3, 2]);
+
+        let (x, g_x) = default_crypto().p256_generate_key_pair();
+        initiator = EdhocInitiator::from_start(
+            default_crypto(),
+            InitiatorStart::new(x, g_x, &[3]).unwrap(),
+        );
+        initiator = initiator.restart_with_suite(2).unwrap();
+        initiator.reset_negotiation_history();
+        assert!(initiator.attempted_suites().is_empty());
+        // the configured suite 3 is still preferred over the selected one
+        let (_initiator, responder, _message_1) = exchange_message_1(initiator, new_responder());
+        assert_eq!(responder.offered_suites(), [3, 2]);
+    }
+
+    #[test]
+    fn test_restart_keeps_preference_order() {
+        let offered = |initiator: EdhocInitiator<_>| {
+            let (_initiator, message_1) = initiator.prepare_message_1(None, &None).unwrap();
+            let (_method, suites_i, suites_i_len, _g_x, _c_i, _ead_1) =
+                parse_message_1(&message_1).unwrap();
+            suites_i[..suites_i_len].to_vec()
+        };
+        let (x, g_x) = default_crypto().p256_generate_key_pair();
+        let start = || {
+            EdhocInitiator::from_start(
+                default_crypto(),
+                InitiatorStart::new(x, g_x, &[6, 3, 2]).unwrap(),
+            )
+        };
+
+        // only the retried suite moves to the end
+        let initiator = start().restart_with_suite(3).unwrap();
+        assert_eq!(initiator.attempted_suites(), [2]);
+        assert_eq!(offered(initiator), [6, 2, 3]);
+
+        // a suite that was not configured stays once attempted, behind the configured ones
+        let initiator = start()
+            .restart_with_suite(4)
+            .unwrap()
+            .restart_with_suite(6)
+            .unwrap();
+        assert_eq!(initiator.attempted_suites(), [2, 4]);
+        assert_eq!(offered(initiator), [3, 2, 4, 6]);
+
+        let mut initiator = start()
+            .restart_with_suite(4)
+            .unwrap()
+            .restart_with_suite(6)
+            .unwrap();
+        initiator.reset_negotiation_history();
+        assert_eq!(offered(initiator), [3, 2, 6]);
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_respond_to_message_1() {
+        let cred_r = cred_r();
+
+        let (initiator, message_1) = new_initiator().prepare_message_1(None, &None).unwrap();
+        let (_responder, message_2) = new_responder()
+            .respond_to_message_1(&message_1, CredentialTransfer::ByReference)
+            .unwrap();
+
+        let (_initiator, _c_r, id_cred_r, ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        assert!(id_cred_r.reference_only());
+        assert_eq!(id_cred_r.kid, cred_r.kid);
+        assert!(ead_2.is_empty());
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_id_cred_r_full_map() {
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+
+        let (initiator, message_1) = new_initiator().prepare_message_1(None, &None).unwrap();
+        let (_responder, message_2) = new_responder()
+            .respond_to_message_1(&message_1, CredentialTransfer::ByReferenceFullMap)
+            .unwrap();
+
+        let (initiator, _c_r, id_cred_r, _ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        // plaintext_2 = ( C_R, {4: h'0a'}, MAC_2 )
+        assert_eq!(
+            initiator.plaintext_2().content[1..1 + ID_CRED_LEN],
+            cred_r.get_id_cred()
+        );
+        assert_eq!(id_cred_r.kid, cred_r.kid);
+
+        let valid_cred_r = credential_check_or_fetch(Some(cred_r), id_cred_r).unwrap();
+        assert!(initiator.verify_message_2(I, cred_i, valid_cred_r).is_ok());
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_export_cose_key() {
+        use ciborium::value::Value;
+
+        let (c_i, c_r) = (0x37, 0x27); // -24 and -8
+
+        let (initiator, message_1) = new_initiator().prepare_message_1(Some(c_i), &None).unwrap();
+        let (responder, _ead_1) = new_responder().process_message_1(&message_1).unwrap();
+        let (responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, Some(c_r), &None)
+            .unwrap();
+        let initiator = receive_message_2(initiator, &message_2);
+        let (mut initiator, mut responder, _message_3) = exchange_message_3(initiator, responder);
+
+        let master_secret = initiator.edhoc_exporter(0, &[], 16).unwrap();
+        let expected_cose_key = |kid: u8| {
+            Value::Map(vec![
+                (Value::from(1), Value::from(4)),
+                (Value::from(2), Value::Bytes(vec![kid])),
+                (Value::from(-1), Value::Bytes(master_secret[..16].to_vec())),
+            ])
+        };
+
+        let mut out = [0u8; 32];
+        let len = initiator
+            .export_cose_key(
+                ExporterLabel::OSCORE_MASTER_SECRET,
+                CoseKty::Symmetric,
+                16,
+                &mut out,
+            )
+            .unwrap();
+        let cose_key: Value = ciborium::de::from_reader(&out[..len]).unwrap();
+        assert_eq!(cose_key, expected_cose_key(c_r));
+
+        let len = responder
+            .export_cose_key(
+                ExporterLabel::OSCORE_MASTER_SECRET,
+                CoseKty::Symmetric,
+                16,
+                &mut out,
+            )
+            .unwrap();
+        let cose_key: Value = ciborium::de::from_reader(&out[..len]).unwrap();
+        assert_eq!(cose_key, expected_cose_key(c_i));
+
+        // output buffer too small for the key
+        let mut out = [0u8; 16];
+        assert_eq!(
+            initiator.export_cose_key(
+                ExporterLabel::OSCORE_MASTER_SECRET,
+                CoseKty::Symmetric,
+                16,
+                &mut out
+            ),
+            Err(EDHOCError::InvalidParameter)
+        );
+    }
+
+    /// Lengths to try a slice parameter with `max_len` at: the edges, and spread beyond them
+    fn parameter_lengths(max_len: usize) -> impl Iterator<Item = usize> {
+        // xorshift, so that the lengths differ between limits but not between runs
+        let mut state = max_len as u32 | 1;
+        let spread = core::iter::repeat_with(move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as usize % (2 * max_len + 2)
+        });
+        [0, 1, max_len - 1, max_len, max_len + 1, 2 * max_len]
+            .into_iter()
+            .chain(spread.take(16))
+    }
+
+    // drives the high-level API with parameters of arbitrary lengths, which must be accepted up to
+    // their limit and rejected with a typed error beyond it, never panicking or being truncated
+    #[test]
+    fn test_parameter_limits() {
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+        let bytes = [0xa5u8; 2 * MAX_BUFFER_LEN + 2];
+
+        for len in parameter_lengths(P256_ELEM_LEN) {
+            let responder = EdhocResponder::new(default_crypto(), &bytes[..len], cred_r);
+            match len {
+                P256_ELEM_LEN => assert!(responder.is_ok()),
+                _ => assert_eq!(responder.unwrap_err(), EDHOCError::InvalidKey),
+            }
+        }
+
+        for len in parameter_lengths(MAX_EAD_VALUE_LEN) {
+            let item = EADItem::with_value(1, false, &bytes[..len]);
+            match len {
+                ..=MAX_EAD_VALUE_LEN => assert_eq!(item.unwrap().value.unwrap().len, len),
+                _ => assert_eq!(item.unwrap_err(), EDHOCError::EadTooLongError),
+            }
+        }
+
+        let exchange = || {
+            let (initiator, responder, _message_1) =
+                exchange_message_1(new_initiator(), new_responder());
+            (initiator, responder)
+        };
+
+        let (_initiator, mut responder) = exchange();
+        for len in parameter_lengths(MAX_EXPORTER_CONTEXT_LEN) {
+            let key = responder.derive_ead_key(0, &bytes[..len], 16);
+            match len {
+                ..=MAX_EXPORTER_CONTEXT_LEN => assert!(key.is_ok()),
+                _ => assert_eq!(key.unwrap_err().error, EDHOCError::InvalidParameter),
+            }
+        }
+        for len in parameter_lengths(MAX_EXPORTER_OUTPUT_LEN) {
+            let key = responder.derive_ead_key(0, &[], len);
+            match len {
+                ..=MAX_EXPORTER_OUTPUT_LEN => assert!(key.is_ok()),
+                _ => assert_eq!(key.unwrap_err().error, EDHOCError::InvalidParameter),
+            }
+        }
+
+        // the private key is checked before it is used
+        for len in parameter_lengths(P256_ELEM_LEN).filter(|len| *len != P256_ELEM_LEN) {
+            let (initiator, responder) = exchange();
+            let (_responder, message_2) = responder
+                .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+                .unwrap();
+            let (initiator, _c_r, _id_cred_r, _ead_2) =
+                initiator.parse_message_2(&message_2).unwrap();
+            assert_eq!(
+                initiator
+                    .verify_message_2(&bytes[..len], cred_i, cred_r)
+                    .unwrap_err()
+                    .error,
+                EDHOCError::InvalidKey
+            );
+        }
+
+        let (initiator, responder) = exchange();
+        let (mut responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+            .unwrap();
+        let (mut initiator, _c_r, _id_cred_r, _ead_2) =
+            initiator.parse_message_2(&message_2).unwrap();
+        let context = &bytes[..MAX_EXPORTER_CONTEXT_LEN + 1];
+        assert_eq!(
+            responder.early_exporter(0, context, 16).unwrap_err(),
+            EDHOCError::InvalidParameter
+        );
+        assert_eq!(
+            initiator.derive_ead_key(0, context, 16).unwrap_err(),
+            EDHOCError::InvalidParameter
+        );
+        let initiator = initiator.verify_message_2(I, cred_i, cred_r).unwrap();
+        let (mut initiator, message_3, _prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+        let (mut responder, _id_cred_i, _ead_3) = responder.parse_message_3(&message_3).unwrap();
+        assert_eq!(
+            responder.derive_ead_key(0, context, 16).unwrap_err(),
+            EDHOCError::InvalidParameter
+        );
+        let (mut responder, _prk_out) = responder.verify_message_3(cred_i).unwrap();
+
+        for len in parameter_lengths(MAX_EXPORTER_CONTEXT_LEN) {
+            let context = &bytes[..len];
+            let i_key = initiator.edhoc_exporter(0, context, 16);
+            let r_key = responder.edhoc_exporter(0, context, 16);
+            match len {
+                ..=MAX_EXPORTER_CONTEXT_LEN => assert_eq!(i_key.unwrap(), r_key.unwrap()),
+                _ => {
+                    assert_eq!(i_key.unwrap_err(), EDHOCError::InvalidParameter);
+                    assert_eq!(r_key.unwrap_err(), EDHOCError::InvalidParameter);
+                }
+            }
+        }
+        for len in parameter_lengths(MAX_EXPORTER_OUTPUT_LEN) {
+            let i_key = initiator.edhoc_exporter(0, &[], len);
+            let r_key = responder.edhoc_exporter(0, &[], len);
+            match len {
+                ..=MAX_EXPORTER_OUTPUT_LEN => {
+                    assert_eq!(i_key.unwrap()[..len], r_key.unwrap()[..len])
+                }
+                _ => {
+                    assert_eq!(i_key.unwrap_err(), EDHOCError::InvalidParameter);
+                    assert_eq!(r_key.unwrap_err(), EDHOCError::InvalidParameter);
+                }
+            }
+        }
+        for len in parameter_lengths(MAX_EXPORTER_CONTEXT_LEN) {
+            let context = &bytes[..len];
+            let i_prk_out = initiator.edhoc_key_update(context);
+            let r_prk_out = responder.edhoc_key_update(context);
+            match len {
+                ..=MAX_EXPORTER_CONTEXT_LEN => assert_eq!(i_prk_out.unwrap(), r_prk_out.unwrap()),
+                _ => {
+                    assert_eq!(i_prk_out.unwrap_err(), EDHOCError::InvalidParameter);
+                    assert_eq!(r_prk_out.unwrap_err(), EDHOCError::InvalidParameter);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_empty_connection_identifier() {
+        use ciborium::value::Value;
+
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+
+        for (c_i, c_r) in [(CONN_ID_EMPTY, 0x05), (0x05, CONN_ID_EMPTY)] {
+            let (initiator, message_1) =
+                new_initiator().prepare_message_1(Some(c_i), &None).unwrap();
+            // without EAD_1, C_I ends message_1
+            assert_eq!(message_1.as_slice().last(), Some(&c_i));
+            let (responder, _ead_1) = new_responder().process_message_1(&message_1).unwrap();
+            let (responder, message_2) = responder
+                .prepare_message_2(CredentialTransfer::ByReference, Some(c_r), &None)
+                .unwrap();
+            assert_eq!(responder.c_i, c_i);
+            let (initiator, c_r_rcvd, id_cred_r, _ead_2) =
+                initiator.parse_message_2(&message_2).unwrap();
+            assert_eq!(c_r_rcvd, c_r);
+            let valid_cred_r = credential_check_or_fetch(Some(cred_r), id_cred_r).unwrap();
+            let initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+            let (mut initiator, mut responder, _message_3) =
+                exchange_message_3(initiator, responder);
+
+            // the empty identifier is a zero-length OSCORE ID
+            let kid_of = |out: &[u8]| {
+                let cose_key: Value = ciborium::de::from_reader(out).unwrap();
+                cose_key.as_map().unwrap()[1].1.as_bytes().unwrap().clone()
+            };
+            let mut out = [0u8; 32];
+            let len = initiator
+                .export_cose_key(
+                    ExporterLabel::OSCORE_MASTER_SECRET,
+                    CoseKty::Symmetric,
+                    16,
+                    &mut out,
+                )
+                .unwrap();
+            assert_eq!(kid_of(&out[..len]), oscore_id(&c_r));
+            let len = responder
+                .export_cose_key(
+                    ExporterLabel::OSCORE_MASTER_SECRET,
+                    CoseKty::Symmetric,
+                    16,
+                    &mut out,
+                )
+                .unwrap();
+            assert_eq!(kid_of(&out[..len]), oscore_id(&c_i));
+        }
+        assert!(oscore_id(&CONN_ID_EMPTY).is_empty());
+        assert_eq!(oscore_id(&0x05), [0x05]);
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_handshake_message_3_lost() {
+        let (initiator, responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+        let (initiator, responder, _message_2) = exchange_message_2(initiator, responder);
+
+        // message_3 is produced, but lost on its way to the responder
+        let (mut initiator, _message_3, i_prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+        assert_eq!(initiator.completion_hint(), CompletionHint::Unconfirmed);
+
+        // nothing heard from the responder, so resend the retained message_3
+        let message_3 = *initiator.message_3().unwrap();
+        let (_responder, r_prk_out) = receive_message_3(responder, &message_3);
+        assert_eq!(i_prk_out, r_prk_out);
+
+        // first protected message from the responder is processed
+        initiator.confirm_peer_completion();
+        assert_eq!(initiator.completion_hint(), CompletionHint::Confirmed);
+        assert!(initiator.message_3().is_some());
+
+        initiator.discard_retained();
+        assert!(initiator.message_3().is_none());
+    }
+
+    /// Outcome of feeding a tampered message to a peer
+    #[cfg(feature = "test-ead-none")]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum TamperOutcome {
+        ParsingError,
+        MacVerificationFailed,
+        Unsupported,
+        OtherError,
+        Panic,
+        Accepted,
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    fn tamper_classify(res: std::thread::Result<Result<(), EDHOCError>>) -> TamperOutcome {
+        match res {
+            Err(_) => TamperOutcome::Panic,
+            Ok(Ok(())) => TamperOutcome::Accepted,
+            Ok(Err(EDHOCError::ParsingError)) => TamperOutcome::ParsingError,
+            Ok(Err(EDHOCError::MacVerificationFailed)) => TamperOutcome::MacVerificationFailed,
+            Ok(Err(EDHOCError::UnsupportedMethod | EDHOCError::UnsupportedCipherSuite)) => {
+                TamperOutcome::Unsupported
+            }
+            Ok(Err(_)) => TamperOutcome::OtherError,
+        }
+    }
+
+    /// Flips every byte of `message` in turn and classifies how `replay` handles the result,
+    /// returning the outcome for each position
+    #[cfg(feature = "test-ead-none")]
+    fn tamper_each_byte(
+        message: &EdhocMessageBuffer,
+        replay: impl Fn(&EdhocMessageBuffer) -> Result<(), EDHOCError>,
+    ) -> Vec<(usize, TamperOutcome)> {
+        (0..message.len)
+            .map(|pos| {
+                let mut tampered = *message;
+                tampered.content[pos] ^= 0xff;
+                let res =
+                    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| replay(&tampered)));
+                (pos, tamper_classify(res))
+            })
+            .collect()
+    }
+
+    /// Runs with `cargo test --release -- --ignored tamper`
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    #[ignore]
+    fn test_tamper_handshake_messages() {
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+        let i: BytesP256ElemLen = I.try_into().unwrap();
+        let r: BytesP256ElemLen = R.try_into().unwrap();
+        let mut crypto = default_crypto();
+
+        // honest run, keeping every intermediate state around for the replays
+        let (x, g_x) = crypto.p256_generate_key_pair();
+        let (y, g_y) = crypto.p256_generate_key_pair();
+        let initiator_start = InitiatorStart::new(x, g_x, &EDHOC_SUPPORTED_SUITES).unwrap();
+        let responder_start = ResponderStart::new(y, g_y);
+        let (wait_m2, message_1) =
+            i_prepare_message_1(&initiator_start, &mut crypto, 0x37, &None).unwrap();
+        let (processing_m1, _) =
+            r_process_message_1(&responder_start, &mut crypto, &message_1).unwrap();
+        let (wait_m3, message_2) = r_prepare_message_2(
+            &processing_m1,
+            &mut crypto,
+            cred_r,
+            &r,
+            0x27,
+            CredentialTransfer::ByReference,
+            &None,
+        )
+        .unwrap();
+        let (processing_m2, _, _, _) =
+            i_parse_message_2(&wait_m2, &mut crypto, &message_2).unwrap();
+        let mut processed_m2 = i_verify_message_2(&processing_m2, &mut crypto, cred_r, &i).unwrap();
+        let (_, message_3, _) = i_prepare_message_3(
+            &mut processed_m2,
+            &mut crypto,
+            cred_i,
+            CredentialTransfer::ByReference,
+            &None,
+        )
+        .unwrap();
+
+        // message_1 is not protected on its own, but the Initiator must reject what follows
+        let outcomes_1 = tamper_each_byte(&message_1, |message_1| {
+            let mut crypto = default_crypto();
+            let (processing_m1, _) = r_process_message_1(&responder_start, &mut crypto, message_1)?;
+            let (_, message_2) = r_prepare_message_2(
+                &processing_m1,
+                &mut crypto,
+                cred_r,
+                &r,
+                0x27,
+                CredentialTransfer::ByReference,
+                &None,
+            )?;
+            let (processing_m2, _, _, _) = i_parse_message_2(&wait_m2, &mut crypto, &message_2)?;
+            i_verify_message_2(&processing_m2, &mut crypto, cred_r, &i).map(|_| ())
+        });
+        let outcomes_2 = tamper_each_byte(&message_2, |message_2| {
+            let mut crypto = default_crypto();
+            let (processing_m2, _, _, _) = i_parse_message_2(&wait_m2, &mut crypto, message_2)?;
+            i_verify_message_2(&processing_m2, &mut crypto, cred_r, &i).map(|_| ())
+        });
+        let outcomes_3 = tamper_each_byte(&message_3, |message_3| {
+            let mut crypto = default_crypto();
+            let mut wait_m3 =
+                WaitM3::new(wait_m3.y, wait_m3.prk_3e2m, wait_m3.th_3, wait_m3.method);
+            let (mut processing_m3, _, _) =
+                r_parse_message_3(&mut wait_m3, &mut crypto, message_3)?;
+            r_verify_message_3(&mut processing_m3, &mut crypto, cred_i).map(|_| ())
+        });
+
+        // message_3 is all ciphertext but for its header
+        for &(pos, outcome) in &outcomes_3 {
+            assert!(
+                matches!(
+                    outcome,
+                    TamperOutcome::ParsingError | TamperOutcome::MacVerificationFailed
+                ),
+                "message_3: flipping byte {pos} resulted in {outcome:?}"
+            );
+        }
+        for (name, outcomes) in [
+            ("message_1", outcomes_1),
+            ("message_2", outcomes_2),
+            ("message_3", outcomes_3),
+        ] {
+            for (pos, outcome) in outcomes {
+                assert!(
+                    outcome != TamperOutcome::Panic && outcome != TamperOutcome::Accepted,
+                    "{name}: flipping byte {pos} resulted in {outcome:?}"
+                );
+            }
+        }
+    }
+
+    fn p256_cose_key(x: Option<&[u8]>, d: Option<&[u8]>) -> Vec<u8> {
+        use ciborium::value::Value;
+
+        let mut map = vec![
+            (Value::from(1), Value::from(2)),  // kty: EC2
+            (Value::from(-1), Value::from(1)), // crv: P-256
+        ];
+        if let Some(x) = x {
+            map.push((Value::from(-2), Value::Bytes(x.to_vec())));
+        }
+        if let Some(d) = d {
+            map.push((Value::from(-4), Value::Bytes(d.to_vec())));
+        }
+        let mut encoded = Vec::new();
+        ciborium::ser::into_writer(&Value::Map(map), &mut encoded).unwrap();
+        encoded
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_handshake_cose_key() {
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+
+        let i = P256PrivateKey::from_cose_key(
+            &mut default_crypto(),
+            &p256_cose_key(Some(&cred_i.public_key), Some(I)),
+        )
+        .unwrap();
+        let r = P256PrivateKey::from_cose_key(
+            &mut default_crypto(),
+            &p256_cose_key(Some(&cred_r.public_key), Some(R)),
+        )
+        .unwrap();
+        assert_eq!(i.as_slice(), I);
+
+        let responder = EdhocResponder::new(default_crypto(), &r, cred_r).unwrap();
+        let (initiator, responder, _message_1) = exchange_message_1(new_initiator(), responder);
+        let (responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+            .unwrap();
+        let (initiator, _c_r, id_cred_r, _ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        let valid_cred_r = credential_check_or_fetch(Some(cred_r), id_cred_r).unwrap();
+        let initiator = initiator
+            .verify_message_2(&i, cred_i, valid_cred_r)
+            .unwrap();
+        let (_initiator, _responder, _message_3) = exchange_message_3(initiator, responder);
+    }
+
+    #[test]
+    fn test_private_key_from_cose_key() {
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+
+        // the public key is optional
+        let key =
+            P256PrivateKey::from_cose_key(&mut default_crypto(), &p256_cose_key(None, Some(R)))
+                .unwrap();
+        assert_eq!(key.as_slice(), R);
+
+        // public key of R paired with the private key of I
+        assert_eq!(
+            P256PrivateKey::from_cose_key(
+                &mut default_crypto(),
+                &p256_cose_key(Some(&cred_r.public_key), Some(I)),
+            )
+            .err(),
+            Some(EDHOCError::InvalidParameter)
+        );
+
+        // a public key alone is not enough
+        assert_eq!(
+            P256PrivateKey::from_cose_key(
+                &mut default_crypto(),
+                &p256_cose_key(Some(&cred_i.public_key), None),
+            )
+            .err(),
+            Some(EDHOCError::ParsingError)
+        );
+
+        let mut oversized = p256_cose_key(None, Some(R)).to_vec();
+        oversized.resize(MAX_MESSAGE_SIZE_LEN + 1, 0x00);
+        assert_eq!(
+            P256PrivateKey::from_cose_key(&mut default_crypto(), &oversized).err(),
+            Some(EDHOCError::MessageTooLong)
+        );
+    }
+
+    #[test]
+    fn test_preview_message_3() {
+        let cred_i = cred_i();
+        let ead_3 = Some(EADItem {
+            label: 23,
+            is_critical: false,
+            value: Some(EdhocMessageBuffer::new_from_slice(&[0x41, 0xcc]).unwrap()),
+        });
+
+        let (initiator, responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+        let (mut initiator, responder, _message_2) = exchange_message_2(initiator, responder);
+
+        // previewing repeatedly, and with other arguments, does not use up the state
+        let (preview, preview_len) = initiator
+            .preview_message_3(CredentialTransfer::ByReference, &ead_3)
+            .unwrap();
+        let (other, _other_len) = initiator
+            .preview_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+        assert_ne!(preview, other);
+        assert_eq!(
+            initiator
+                .preview_message_3(CredentialTransfer::ByReference, &ead_3)
+                .unwrap(),
+            (preview, preview_len)
+        );
+
+        let (_initiator, message_3, i_prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &ead_3)
+            .unwrap();
+        assert_eq!(message_3.len, preview_len);
+
+        let (responder, id_cred_i, _ead_3) = responder.parse_message_3(&message_3).unwrap();
+        assert_eq!(responder.state.plaintext_3, preview);
+        let valid_cred_i = credential_check_or_fetch(Some(cred_i), id_cred_i).unwrap();
+        let (_responder, r_prk_out) = responder.verify_message_3(valid_cred_i).unwrap();
+        assert_eq!(i_prk_out, r_prk_out);
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[cfg(not(feature = "limits-large"))]
+    #[test]
+    fn test_message_3_by_value_too_long() {
+        let (initiator, responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+        let (initiator, _responder, _message_2) = exchange_message_2(initiator, responder);
+
+        assert_eq!(
+            initiator
+                .prepare_message_3(CredentialTransfer::ByValue, &None)
+                .unwrap_err()
+                .error,
+            EDHOCError::InvalidParameter
+        );
+    }
+
+    // both credentials by value only fit the buffers of limits-large
+    #[cfg(feature = "limits-large")]
+    #[test]
+    fn test_handshake_by_value() {
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+
+        let (initiator, responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+        let (responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByValue, None, &None)
+            .unwrap();
+        let (initiator, _c_r, id_cred_r, _ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        let valid_cred_r = credential_check_or_fetch(None, id_cred_r).unwrap();
+        let initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+        let (_initiator, message_3, i_prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByValue, &None)
+            .unwrap();
+        let (responder, id_cred_i, _ead_3) = responder.parse_message_3(&message_3).unwrap();
+        let valid_cred_i = credential_check_or_fetch(None, id_cred_i).unwrap();
+        let (_responder, r_prk_out) = responder.verify_message_3(valid_cred_i).unwrap();
+        assert_eq!(i_prk_out, r_prk_out);
+    }
+
+    #[test]
+    fn test_done_credentials() {
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+
+        let (initiator, responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+        let (_responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+            .unwrap();
+        let (initiator, _c_r, id_cred_r, _ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        let valid_cred_r = credential_check_or_fetch(Some(cred_r), id_cred_r).unwrap();
+        let initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+        let (initiator, _message_3, _i_prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+
+        assert_eq!(initiator.peer_credential().value, cred_r.value);
+        assert_eq!(initiator.peer_credential().value, valid_cred_r.value);
+        assert_eq!(initiator.peer_credential().kid, valid_cred_r.kid);
+        assert_eq!(initiator.own_credential().value, cred_i.value);
+    }
+
+    #[test]
+    fn test_edhoc_exporter_into() {
+        // stands in for a buffer in protected memory, and records how it was written
+        #[derive(Default)]
+        struct Capture {
+            bytes: Vec<u8>,
+            writes: usize,
+        }
+        impl KeyOutput for Capture {
+            fn write(&mut self, bytes: &[u8]) -> Result<(), EDHOCError> {
+                self.bytes.extend_from_slice(bytes);
+                self.writes += 1;
+                Ok(())
+            }
+        }
+
+        let (mut initiator, mut responder) = handshake(new_initiator(), new_responder());
+
+        let context = [0x01, 0x02, 0x03];
+        let mut i_key = Capture::default();
+        initiator
+            .edhoc_exporter_into(0, &context, 16, &mut i_key)
+            .unwrap();
+        assert_eq!(
+            i_key.bytes,
+            initiator.edhoc_exporter(0, &context, 16).unwrap()[..16]
+        );
+        assert_eq!(i_key.writes, 1);
+        let mut r_key = Capture::default();
+        responder
+            .edhoc_exporter_into(0, &context, 16, &mut r_key)
+            .unwrap();
+        assert_eq!(r_key.bytes, i_key.bytes);
+
+        // into the front of a longer slice
+        let mut salt = [0xff; 10];
+        responder
+            .edhoc_exporter_into(1, &[], 8, &mut &mut salt[..])
+            .unwrap();
+        assert_eq!(salt[..8], initiator.edhoc_exporter(1, &[], 8).unwrap()[..8]);
+        assert_eq!(salt[8..], [0xff, 0xff]);
+
+        // longer than a hash block, so the back-end hands it over in parts
+        let mut long_key = Capture::default();
+        initiator
+            .edhoc_exporter_into(2, &context, 40, &mut long_key)
+            .unwrap();
+        assert_eq!(
+            long_key.bytes,
+            initiator.edhoc_exporter(2, &context, 40).unwrap()[..40]
+        );
+        assert_eq!(long_key.writes, 2);
+
+        // a slice that is too short is an error, not a panic
+        let mut short = [0xff; 10];
+        assert_eq!(
+            responder.edhoc_exporter_into(2, &context, 40, &mut &mut short[..]),
+            Err(EDHOCError::InvalidParameter)
+        );
+
+        let mut unused = Capture::default();
+        assert_eq!(
+            initiator.edhoc_exporter_into(0, &[], MAX_EXPORTER_OUTPUT_LEN + 1, &mut unused),
+            Err(EDHOCError::InvalidParameter)
+        );
+        assert_eq!(unused.writes, 0);
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_early_exporter() {
+        let (initiator, responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+        let (mut responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+            .unwrap();
+
+        // data is sent to whoever is at the other end right after message_2
+        let early_secret = responder.early_exporter(0, &[], 16).unwrap();
+
+        let initiator = receive_message_2(initiator, &message_2);
+        let (mut initiator, mut responder, _message_3) = exchange_message_3(initiator, responder);
+        assert!(responder.early_exported());
+
+        let secret = responder.edhoc_exporter(0, &[], 16).unwrap();
+        assert_eq!(secret, initiator.edhoc_exporter(0, &[], 16).unwrap());
+        assert_ne!(early_secret[..16], secret[..16]);
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_derive_ead_key() {
+        const EAD_LABEL: u16 = 23;
+        const PAYLOAD: &[u8] = b"hi";
+
+        // encrypts PAYLOAD into the value of an EAD item, as a bstr holding the AES-CCM ciphertext
+        fn encrypt_ead(key: &[u8], iv: &[u8]) -> EADItem {
+            let ciphertext = default_crypto().aes_ccm_encrypt_tag_8(
+                key[..AES_CCM_KEY_LEN].try_into().unwrap(),
+                iv[..AES_CCM_IV_LEN].try_into().unwrap(),
+                &[],
+                &PAYLOAD.try_into().unwrap(),
+            );
+            let mut value = EdhocMessageBuffer::new();
+            value
+                .push(CBOR_MAJOR_BYTE_STRING | ciphertext.len as u8)
+                .unwrap();
+            value.extend_from_slice(ciphertext.as_slice()).unwrap();
+            EADItem {
+                label: EAD_LABEL,
+                is_critical: false,
+                value: Some(value),
+            }
+        }
+
+        fn decrypt_ead(key: &[u8], iv: &[u8], ead: EadItems) -> BufferPlaintext3 {
+            let value = ead.first().unwrap().value.unwrap();
+            let ciphertext = CBORDecoder::new(value.as_slice()).bytes().unwrap();
+            default_crypto()
+                .aes_ccm_decrypt_tag_8(
+                    key[..AES_CCM_KEY_LEN].try_into().unwrap(),
+                    iv[..AES_CCM_IV_LEN].try_into().unwrap(),
+                    &[],
+                    &ciphertext.try_into().unwrap(),
+                )
+                .unwrap()
+        }
+
+        // CRED_R doubles as the initiator's credential, with CRED_I the EAD_3 item would not fit
+        // into the context of MAC_3
+        let cred_i = cred_r();
+
+        let (initiator, mut responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+
+        let key = responder
+            .derive_ead_key(0, b"my-ead", AES_CCM_KEY_LEN)
+            .unwrap();
+        let iv = responder
+            .derive_ead_key(1, b"my-ead", AES_CCM_IV_LEN)
+            .unwrap();
+        let ead_2 = encrypt_ead(&key, &iv);
+        let (responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &Some(ead_2))
+            .unwrap();
+
+        let (mut initiator, _c_r, id_cred_r, ead_2) =
+            initiator.parse_message_2(&message_2).unwrap();
+        let key = initiator
+            .derive_ead_key(0, b"my-ead", AES_CCM_KEY_LEN)
+            .unwrap();
+        let iv = initiator
+            .derive_ead_key(1, b"my-ead", AES_CCM_IV_LEN)
+            .unwrap();
+        assert_eq!(decrypt_ead(&key, &iv, ead_2).as_slice(), PAYLOAD);
+
+        let valid_cred_r = credential_check_or_fetch(Some(cred_r()), id_cred_r).unwrap();
+        let mut initiator = initiator.verify_message_2(R, cred_i, valid_cred_r).unwrap();
+
+        let key_3 = initiator
+            .derive_ead_key(0, b"my-ead", AES_CCM_KEY_LEN)
+            .unwrap();
+        let iv_3 = initiator
+            .derive_ead_key(1, b"my-ead", AES_CCM_IV_LEN)
+            .unwrap();
+        assert_ne!(key_3, key);
+        let ead_3 = encrypt_ead(&key_3, &iv_3);
+        let (_initiator, message_3, _) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &Some(ead_3))
+            .unwrap();
+
+        let (mut responder, _id_cred_i, ead_3) = responder.parse_message_3(&message_3).unwrap();
+        let key = responder
+            .derive_ead_key(0, b"my-ead", AES_CCM_KEY_LEN)
+            .unwrap();
+        let iv = responder
+            .derive_ead_key(1, b"my-ead", AES_CCM_IV_LEN)
+            .unwrap();
+        assert_eq!(decrypt_ead(&key, &iv, ead_3).as_slice(), PAYLOAD);
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_handshake_several_ead_3_items() {
+        // ( 1, h'01', -65000, 300, h'cafe' ), sent as a single item whose value carries the others,
+        // as only one item can be sent
+        let ead_3 = EADItem::with_value(
+            1,
+            false,
+            &[
+                0x41, 0x01, 0x39, 0xfd, 0xe7, 0x19, 0x01, 0x2c, 0x42, 0xca, 0xfe,
+            ],
+        )
+        .unwrap();
+        // CRED_R doubles as the initiator's credential, with CRED_I the EAD_3 items would not fit
+        // into the context of MAC_3
+        let cred_i = cred_r();
+
+        let (initiator, responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+        let (responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+            .unwrap();
+        let (initiator, _c_r, id_cred_r, _ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        let valid_cred_r = credential_check_or_fetch(Some(cred_r()), id_cred_r).unwrap();
+        let initiator = initiator.verify_message_2(R, cred_i, valid_cred_r).unwrap();
+        let (_initiator, message_3, i_prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &Some(ead_3))
+            .unwrap();
+
+        let (responder, id_cred_i, ead_3) = responder.parse_message_3(&message_3).unwrap();
+        let items: Vec<_> = ead_3
+            .iter()
+            .map(|item| {
+                (
+                    item.label,
+                    item.is_critical,
+                    item.value.map(|value| value.len),
+                )
+            })
+            .collect();
+        assert_eq!(
+            items,
+            [
+                (1, false, Some(2)),
+                (65000, true, None),
+                (300, false, Some(3))
+            ]
+        );
+        assert_eq!(ead_3.first().unwrap().label, 1);
+
+        // MAC_3 covers all of them
+        let valid_cred_i = credential_check_or_fetch(Some(cred_i), id_cred_i).unwrap();
+        let (_responder, r_prk_out) = responder.verify_message_3(valid_cred_i).unwrap();
+        assert_eq!(i_prk_out, r_prk_out);
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    // an EAD_2 value of a whole TH_2 exceeds MAX_EAD_SIZE_LEN of limits-small
+    #[cfg(not(feature = "limits-small"))]
+    #[test]
+    fn test_prepare_message_2_with() {
+        const EAD_LABEL: u16 = 23;
+
+        let (initiator, responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+
+        let mut seen = None;
+        let (_responder, message_2) = responder
+            .prepare_message_2_with(CredentialTransfer::ByReference, None, |context| {
+                seen = Some(*context);
+                let mut value = EdhocMessageBuffer::new();
+                let mut encoder = CBOREncoder::new(&mut value.content);
+                encoder.bytes(&context.th_2).unwrap();
+                value.len = encoder.position();
+                Some(EADItem {
+                    label: EAD_LABEL,
+                    is_critical: false,
+                    value: Some(value),
+                })
+            })
+            .unwrap();
+        let seen = seen.unwrap();
+
+        let (initiator, c_r, _id_cred_r, ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        assert_eq!(c_r, seen.c_r);
+        assert_eq!(initiator.th_2(), &seen.th_2);
+        let ead_2 = ead_2.first().cloned().unwrap();
+        assert_eq!(ead_2.label, EAD_LABEL);
+        let value = ead_2.value.unwrap();
+        assert_eq!(
+            CBORDecoder::new(value.as_slice()).bytes().unwrap(),
+            initiator.th_2()
+        );
+    }
+
+    #[cfg(feature = "test-ead-none")]
+    #[test]
+    fn test_failure_step() {
+        let (initiator, responder, _message_1) =
+            exchange_message_1(new_initiator(), new_responder());
+        let (initiator, responder, _message_2) = exchange_message_2(initiator, responder);
+        let (_initiator, message_3, _) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+
+        // the responder verifies MAC_3 against the wrong credential
+        let (responder, _id_cred_i, _ead_3) = responder.parse_message_3(&message_3).unwrap();
+        let failure = responder.verify_message_3(cred_r()).unwrap_err();
+        assert_eq!(failure.error, EDHOCError::MacVerificationFailed);
+        assert_eq!(failure.step, HandshakeStep::VerifyMessage3);
+    }
+
+    // these tests send credentials by value, which do not fit the buffers of limits-small
+    #[cfg(not(feature = "limits-small"))]
+    mod by_value {
+        use super::*;
+
+        #[test]
+        fn test_signed_cwt() {
+            let cred_r = cred_r();
+
+            let cwt = CredentialSignedCWT::parse(SIGNED_CWT_R).unwrap();
+            let cred = cwt
+                .verify(&mut default_crypto(), &CWT_ISSUER_X, &CWT_ISSUER_Y)
+                .unwrap();
+            assert_eq!(cred.public_key, cred_r.public_key);
+            assert_eq!(cred.kid, cred_r.kid);
+            assert_eq!(cred.value.as_slice(), SIGNED_CWT_R);
+
+            // a peer referring to the credential by its kid is matched against the verified CWT
+            let id_cred_r = CredentialRPK {
+                value: EdhocMessageBuffer::new(),
+                public_key: [0; P256_ELEM_LEN],
+                kid: cred_r.kid,
+                has_kid: true,
+            };
+            let valid_cred_r = credential_check_or_fetch(Some(cred), id_cred_r).unwrap();
+            assert_eq!(valid_cred_r.value.as_slice(), SIGNED_CWT_R);
 
-        edhoc_key_update(
-            &mut self.state,
-            &mut self.crypto,
-            &context_buf,
-            context.len(),
-        )
-    }
-}
+            // tampered signature
+            let mut tampered = [0u8; SIGNED_CWT_R.len()];
+            tampered.copy_from_slice(SIGNED_CWT_R);
+            tampered[SIGNED_CWT_R.len() - 1] ^= 0x01;
+            let cwt = CredentialSignedCWT::parse(&tampered).unwrap();
+            assert_eq!(
+                cwt.verify(&mut default_crypto(), &CWT_ISSUER_X, &CWT_ISSUER_Y)
+                    .unwrap_err(),
+                EDHOCError::CredentialVerificationFailed
+            );
 
-pub fn generate_connection_identifier_cbor<Crypto: CryptoTrait>(crypto: &mut Crypto) -> u8 {
-    let c_i = generate_connection_identifier(crypto);
-    if c_i >= 0 && c_i <= 23 {
-        c_i as u8 // verbatim encoding of single byte integer
-    } else if c_i < 0 && c_i >= -24 {
-        // negative single byte integer encoding
-        CBOR_NEG_INT_1BYTE_START - 1 + c_i.unsigned_abs()
-    } else {
-        0
-    }
-}
+            // signed by someone else
+            let cwt = CredentialSignedCWT::parse(SIGNED_CWT_R).unwrap();
+            assert_eq!(
+                cwt.verify(&mut default_crypto(), &CWT_ISSUER_Y, &CWT_ISSUER_X)
+                    .unwrap_err(),
+                EDHOCError::CredentialVerificationFailed
+            );
 
-/// generates an identifier that can be serialized as a single CBOR integer, i.e. -24 <= x <= 23
-pub fn generate_connection_identifier<Crypto: CryptoTrait>(crypto: &mut Crypto) -> i8 {
-    let mut conn_id = crypto.get_random_byte() as i8;
-    while conn_id < -24 || conn_id > 23 {
-        conn_id = crypto.get_random_byte() as i8;
-    }
-    conn_id
-}
+            // a bare CCS is not a signed CWT
+            assert!(CredentialSignedCWT::parse(CRED_R).is_err());
+        }
 
-// Implements auth credential checking according to draft-tiloca-lake-implem-cons
-pub fn credential_check_or_fetch<'a>(
-    cred_expected: Option<CredentialRPK>,
-    id_cred_received: CredentialRPK,
-) -> Result<CredentialRPK, EDHOCError> {
-    // Processing of auth credentials according to draft-tiloca-lake-implem-cons
-    // Comments tagged with a number refer to steps in Section 4.3.1. of draft-tiloca-lake-implem-cons
-    if let Some(cred_expected) = cred_expected {
-        // 1. Does ID_CRED_X point to a stored authentication credential? YES
-        // IMPL: compare cred_i_expected with id_cred
-        //   IMPL: assume cred_i_expected is well formed
-        let credentials_match = if id_cred_received.reference_only() {
-            id_cred_received.kid == cred_expected.kid
-        } else {
-            id_cred_received.value == cred_expected.value
-        };
+        #[test]
+        fn test_reference_to_credential_received_by_value() {
+            let cred_i = cred_i();
+            let x = cred_r().public_key;
+            let y: BytesP256ElemLen = CRED_R[CRED_R.len() - P256_ELEM_LEN..].try_into().unwrap();
 
-        // 2. Is this authentication credential still valid?
-        // IMPL,TODO: check cred_r_expected is still valid
+            // kid h'2e' is sent as -15 when sent by reference, kid h'40' as a byte string
+            for kid in [0x2e, 0x40] {
+                let cred_r = CredentialRPK::build_ccs(None, Some(kid), &x, Some(&y)).unwrap();
+                let mut store: Vec<CredentialRPK> = Vec::new();
 
-        // Continue by considering CRED_X as the authentication credential of the other peer.
-        // IMPL: ready to proceed, including process ead_2
+                for cred_transfer in [CredentialTransfer::ByValue, CredentialTransfer::ByReference]
+                {
+                    let responder = EdhocResponder::new(default_crypto(), R, cred_r).unwrap();
+                    let (initiator, responder, _message_1) =
+                        exchange_message_1(new_initiator(), responder);
+                    let (_responder, message_2) = responder
+                        .prepare_message_2(cred_transfer, None, &None)
+                        .unwrap();
+                    let (initiator, _c_r, id_cred_r, _ead_2) =
+                        initiator.parse_message_2(&message_2).unwrap();
 
-        if credentials_match {
-            Ok(cred_expected)
-        } else {
-            Err(EDHOCError::UnknownPeer)
+                    let stored = store
+                        .iter()
+                        .find(|cred| cred.kid_bytes() == id_cred_r.kid_bytes())
+                        .copied();
+                    // only the first session is trust on first use
+                    assert_eq!(
+                        stored.is_some(),
+                        cred_transfer == CredentialTransfer::ByReference
+                    );
+                    let valid_cred_r = credential_check_or_fetch(stored, id_cred_r).unwrap();
+                    if stored.is_none() {
+                        store.push(valid_cred_r);
+                    }
+                    initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+                }
+            }
         }
-    } else {
-        // 1. Does ID_CRED_X point to a stored authentication credential? NO
-        // IMPL: cred_i_expected provided by application is None
-        //       id_cred must be a full credential
-        // 3. Is the trust model Pre-knowledge-only? NO (hardcoded to NO for now)
-        // 4. Is the trust model Pre-knowledge + TOFU? YES (hardcoded to YES for now)
-        // 6. Validate CRED_X. Generally a CCS has to be validated only syntactically and semantically, unlike a certificate or a CWT.
-        //    Is the validation successful?
-        // IMPL,NOTE: the credential has already been parsed with CredentialRPK::new in the *_parse_message_* function
-        // 5. Is the authentication credential authorized for use in the context of this EDHOC session?
-        // IMPL,TODO: we just skip this step for now
-        // 7. Store CRED_X as valid and trusted.
-        //   Pair it with consistent credential identifiers, for each supported type of credential identifier.
 
-        assert!(!id_cred_received.reference_only());
-        Ok(id_cred_received)
-    }
+        #[cfg(feature = "test-ead-none")]
+        #[test]
+        fn test_application_profile() {
+            let by_reference_only =
+                ApplicationProfile::default().with_credential_transfers(true, false);
 
-    // 8. Is this authentication credential good to use in the context of this EDHOC session?
-    // IMPL,TODO: we just skip this step for now
-}
+            let profile = ApplicationProfile::default();
+            assert!(profile.allows_method(Method::default() as u8));
+            assert!(profile.allows_cipher_suite(EDHOC_SUPPORTED_SUITES[0]));
+            assert!(!profile.allows_credential_transfer(CredentialTransfer::Auto));
+            assert!(!profile.uses_message_4());
+            let profile = profile.with_methods(&[0, 1]).with_cipher_suites(&[2, 40]);
+            assert!(!profile.allows_method(3));
+            assert!(profile.allows_cipher_suite(2));
+            assert!(!profile.allows_cipher_suite(6));
+            assert!(!profile.allows_cipher_suite(40));
+            assert!(!by_reference_only.allows_credential_transfer(CredentialTransfer::ByValue));
+            assert!(by_reference_only
+                .allows_credential_transfer(CredentialTransfer::ByReferenceFullMap));
 
-#[cfg(test)]
-mod test_vectors_common {
-    use hexlit::hex;
+            // the Initiator rejects a by-value message_2 that the Responder was allowed to send
+            let (initiator, responder, _message_1) = exchange_message_1(
+                new_initiator().with_application_profile(by_reference_only),
+                new_responder(),
+            );
+            let (_responder, message_2) = responder
+                .prepare_message_2(CredentialTransfer::ByValue, None, &None)
+                .unwrap();
+            assert_eq!(
+                initiator.parse_message_2(&message_2).unwrap_err(),
+                EdhocFailure::new(EDHOCError::ProfileViolation, HandshakeStep::ParseMessage2)
+            );
 
-    pub const ID_CRED_I: &[u8] = &hex!("a104412b");
-    pub const ID_CRED_R: &[u8] = &hex!("a104410a");
-    pub const CRED_I: &[u8] = &hex!("A2027734322D35302D33312D46462D45462D33372D33322D333908A101A5010202412B2001215820AC75E9ECE3E50BFC8ED60399889522405C47BF16DF96660A41298CB4307F7EB62258206E5DE611388A4B8A8211334AC7D37ECB52A387D257E6DB3C2A93DF21FF3AFFC8");
-    pub const I: &[u8] = &hex!("fb13adeb6518cee5f88417660841142e830a81fe334380a953406a1305e8706b");
-    pub const R: &[u8] = &hex!("72cc4761dbd4c78f758931aa589d348d1ef874a7e303ede2f140dcf3e6aa4aac");
-    pub const _G_I_Y_COORD: &[u8] =
-        &hex!("6e5de611388a4b8a8211334ac7d37ecb52a387d257e6db3c2a93df21ff3affc8"); // not used
-    pub const CRED_R: &[u8] = &hex!("A2026008A101A5010202410A2001215820BBC34960526EA4D32E940CAD2A234148DDC21791A12AFBCBAC93622046DD44F02258204519E257236B2A0CE2023F0931F1F386CA7AFDA64FCDE0108C224C51EABF6072");
+            // a Responder with the same profile does not send it in the first place
+            let (_initiator, responder, message_1) = exchange_message_1(
+                new_initiator(),
+                new_responder().with_application_profile(by_reference_only),
+            );
+            assert_eq!(
+                responder
+                    .prepare_message_2(CredentialTransfer::ByValue, None, &None)
+                    .unwrap_err(),
+                EdhocFailure::new(EDHOCError::ProfileViolation, HandshakeStep::PrepareMessage2)
+            );
 
-    pub const MESSAGE_1_TV_FIRST_TIME: &str =
-        "03065820741a13d7ba048fbb615e94386aa3b61bea5b3d8f65f32620b749bee8d278efa90e";
-    pub const MESSAGE_1_TV: &str =
-        "0382060258208af6f430ebe18d34184017a9a11bf511c8dff8f834730b96c1b7c8dbca2fc3b637";
-}
+            // methods and cipher suites are checked on both sides
+            assert_eq!(
+                new_initiator()
+                    .with_application_profile(ApplicationProfile::default().with_methods(&[0]))
+                    .prepare_message_1(None, &None)
+                    .unwrap_err(),
+                EdhocFailure::new(
+                    EDHOCError::UnsupportedMethod,
+                    HandshakeStep::PrepareMessage1
+                )
+            );
+            let responder = new_responder()
+                .with_application_profile(ApplicationProfile::default().with_cipher_suites(&[0]));
+            assert_eq!(
+                responder.process_message_1(&message_1).unwrap_err(),
+                EdhocFailure::new(
+                    EDHOCError::UnsupportedCipherSuite,
+                    HandshakeStep::ProcessMessage1
+                )
+            );
+        }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use lakers_crypto::default_crypto;
-    use test_vectors_common::*;
+        #[cfg(feature = "test-ead-none")]
+        #[test]
+        fn test_profile_with_message_4() {
+            let profile = ApplicationProfile::default().with_message_4(true);
 
-    #[test]
-    fn test_new_initiator() {
-        let _initiator = EdhocInitiator::new(default_crypto());
-    }
+            let (mut initiator, mut responder) = handshake(
+                new_initiator().with_application_profile(profile),
+                new_responder().with_application_profile(profile),
+            );
 
-    #[test]
-    fn test_new_responder() {
-        let _responder = EdhocResponder::new(
-            default_crypto(),
-            R,
-            CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap(),
-        );
-    }
+            // no keys before message_4 went from the Responder to the Initiator
+            assert_eq!(
+                responder.edhoc_exporter(0, &[], 16).unwrap_err(),
+                EDHOCError::ProfileViolation
+            );
+            assert_eq!(
+                initiator.edhoc_key_update(&[]).unwrap_err(),
+                EDHOCError::ProfileViolation
+            );
 
-    #[test]
-    fn test_prepare_message_1() {
-        let initiator = EdhocInitiator::new(default_crypto());
+            let ead_4 = EADItem::with_value(0x20, false, &[0x42, 0x01, 0x02]).unwrap();
+            let message_4 = responder.prepare_message_4(&Some(ead_4)).unwrap();
+            let r_key = responder.edhoc_exporter(0, &[], 16).unwrap();
 
-        let c_i = generate_connection_identifier_cbor(&mut default_crypto());
-        let result = initiator.prepare_message_1(Some(c_i), &None);
-        assert!(result.is_ok());
-    }
+            let mut tampered = message_4;
+            tampered.content[tampered.len - 1] ^= 0x01;
+            assert_eq!(
+                initiator.process_message_4(&tampered).unwrap_err(),
+                EDHOCError::MacVerificationFailed
+            );
+            initiator.confirm_peer_completion();
+            assert_eq!(
+                initiator.edhoc_exporter(0, &[], 16).unwrap_err(),
+                EDHOCError::ProfileViolation
+            );
 
-    #[test]
-    fn test_process_message_1() {
-        let message_1_tv_first_time = EdhocMessageBuffer::from_hex(MESSAGE_1_TV_FIRST_TIME);
-        let message_1_tv = EdhocMessageBuffer::from_hex(MESSAGE_1_TV);
-        let responder = EdhocResponder::new(
-            default_crypto(),
-            R,
-            CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap(),
-        );
+            let ead_4 = initiator.process_message_4(&message_4).unwrap();
+            assert_eq!(ead_4.len(), 1);
+            assert_eq!(ead_4.as_slice()[0].label, 0x20);
+            assert_eq!(
+                ead_4.as_slice()[0].value.unwrap().as_slice(),
+                [0x42, 0x01, 0x02]
+            );
+            assert_eq!(initiator.completion_hint(), CompletionHint::Confirmed);
+            assert_eq!(initiator.edhoc_exporter(0, &[], 16).unwrap(), r_key);
+        }
 
-        // process message_1 first time, when unsupported suite is selected
-        let error = responder.process_message_1(&message_1_tv_first_time);
-        assert!(error.is_err());
-        assert_eq!(error.unwrap_err(), EDHOCError::UnsupportedCipherSuite);
+        #[cfg(feature = "test-ead-none")]
+        #[test]
+        fn test_require_peer_credential_by_value() {
+            let cred_r = cred_r();
 
-        // We need to create a new responder -- no message is supposed to be processed twice by a
-        // responder or initiator
-        let responder = EdhocResponder::new(
-            default_crypto(),
-            R,
-            CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap(),
-        );
+            for required in [false, true] {
+                for sent in [CredentialTransfer::ByReference, CredentialTransfer::ByValue] {
+                    let violation = required && sent == CredentialTransfer::ByReference;
 
-        // process message_1 second time
-        let error = responder.process_message_1(&message_1_tv);
-        assert!(error.is_ok());
-    }
+                    // the Initiator's policy on message_2
+                    let (initiator, responder, _message_1) = exchange_message_1(
+                        new_initiator().require_peer_credential_by_value(required),
+                        new_responder(),
+                    );
+                    let (_responder, message_2) =
+                        responder.prepare_message_2(sent, None, &None).unwrap();
+                    let parsed = initiator.parse_message_2(&message_2);
+                    if violation {
+                        assert_eq!(
+                            parsed.unwrap_err(),
+                            EdhocFailure::new(
+                                EDHOCError::CredentialTransferPolicyViolation,
+                                HandshakeStep::ParseMessage2
+                            )
+                        );
+                    } else {
+                        let (_initiator, _c_r, id_cred_r, _ead_2) = parsed.unwrap();
+                        assert_eq!(
+                            id_cred_r.reference_only(),
+                            sent == CredentialTransfer::ByReference
+                        );
+                    }
 
-    #[test]
-    fn test_generate_connection_identifier() {
-        let conn_id = generate_connection_identifier(&mut default_crypto());
-        assert!(conn_id >= -24 && conn_id <= 23);
-    }
+                    // the Responder's policy on message_3
+                    let (initiator, responder, _message_1) = exchange_message_1(
+                        new_initiator(),
+                        new_responder().require_peer_credential_by_value(required),
+                    );
+                    let (responder, message_2) = responder
+                        .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+                        .unwrap();
+                    let (initiator, _c_r, id_cred_r, _ead_2) =
+                        initiator.parse_message_2(&message_2).unwrap();
+                    let valid_cred_r = credential_check_or_fetch(Some(cred_r), id_cred_r).unwrap();
+                    // CRED_I is too long to be sent by value, so the Initiator uses R's identity
+                    let initiator = initiator.verify_message_2(R, cred_r, valid_cred_r).unwrap();
+                    let (_initiator, message_3, _prk_out) =
+                        initiator.prepare_message_3(sent, &None).unwrap();
+                    let parsed = responder.parse_message_3(&message_3);
+                    if violation {
+                        assert_eq!(
+                            parsed.unwrap_err(),
+                            EdhocFailure::new(
+                                EDHOCError::CredentialTransferPolicyViolation,
+                                HandshakeStep::ParseMessage3
+                            )
+                        );
+                    } else {
+                        let (_responder, id_cred_i, _ead_3) = parsed.unwrap();
+                        assert_eq!(
+                            id_cred_i.reference_only(),
+                            sent == CredentialTransfer::ByReference
+                        );
+                    }
+                }
+            }
+        }
 
-    #[cfg(feature = "test-ead-none")]
-    #[test]
-    fn test_handshake() {
-        let cred_i = CredentialRPK::new(CRED_I.try_into().unwrap()).unwrap();
-        let cred_r = CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap();
+        #[cfg(feature = "test-ead-none")]
+        #[test]
+        fn test_message_2_over_ble() {
+            use ble::*;
 
-        let initiator = EdhocInitiator::new(default_crypto()); // can choose which identity to use after learning R's identity
-        let responder = EdhocResponder::new(default_crypto(), R, cred_r.clone()); // has to select an identity before learning who is I
+            let (initiator, responder, _message_1) =
+                exchange_message_1(new_initiator(), new_responder());
+            let (_responder, message_2) = responder
+                .prepare_message_2(CredentialTransfer::ByValue, None, &None)
+                .unwrap();
 
-        // ---- begin initiator handling
-        // if needed: prepare ead_1
-        let (initiator, message_1) = initiator.prepare_message_1(None, &None).unwrap();
-        // ---- end initiator handling
+            let fragmenter = GattFragmenter::new(&message_2);
+            assert!(fragmenter.fragment_count() > 4);
+            let mut reassembler = GattReassembler::new();
+            let mut received = None;
+            for fragment in fragmenter.clone() {
+                assert!(fragment.as_slice().len() <= GATT_FRAGMENT_LEN);
+                match fragment.sequence_number() {
+                    // dropped on the air
+                    2 => continue,
+                    3 => {
+                        assert_eq!(
+                            reassembler.receive(fragment.as_slice()),
+                            Err(GattError::OutOfOrder(2))
+                        );
+                        // the peripheral re-sends what is missing, and then the rest again
+                        for resent in 1..=3 {
+                            let resent = fragmenter.fragment(resent).unwrap();
+                            assert_eq!(reassembler.receive(resent.as_slice()), Ok(None));
+                        }
+                    }
+                    _ => received = reassembler.receive(fragment.as_slice()).unwrap(),
+                }
+            }
+            let received = received.unwrap();
+            assert_eq!(received, message_2);
 
-        // ---- begin responder handling
-        let (responder, _ead_1) = responder.process_message_1(&message_1).unwrap();
-        // if ead_1: process ead_1
-        // if needed: prepare ead_2
-        let (responder, message_2) = responder
-            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
-            .unwrap();
-        // ---- end responder handling
+            assert!(initiator.parse_message_2(&received).is_ok());
+        }
 
-        // ---- being initiator handling
-        let (initiator, _c_r, id_cred_r, _ead_2) = initiator.parse_message_2(&message_2).unwrap();
-        let valid_cred_r = credential_check_or_fetch(Some(cred_r), id_cred_r).unwrap();
-        let initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+        #[cfg(feature = "test-ead-none")]
+        #[test]
+        fn test_estimate_message_3_size() {
+            // CRED_R doubles as the initiator's credential, CRED_I is too long to be sent by value
+            let cred_i = cred_r();
 
-        // if needed: prepare ead_3
-        let (mut initiator, message_3, i_prk_out) = initiator
-            .prepare_message_3(CredentialTransfer::ByReference, &None)
-            .unwrap();
-        // ---- end initiator handling
+            for cred_transfer in [CredentialTransfer::ByReference, CredentialTransfer::ByValue] {
+                let (initiator, responder, _message_1) =
+                    exchange_message_1(new_initiator(), new_responder());
+                let (responder, message_2) = responder
+                    .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+                    .unwrap();
+                let (initiator, _c_r, id_cred_r, _ead_2) =
+                    initiator.parse_message_2(&message_2).unwrap();
+                let valid_cred_r = credential_check_or_fetch(Some(cred_r()), id_cred_r).unwrap();
+                let initiator = initiator.verify_message_2(R, cred_i, valid_cred_r).unwrap();
 
-        // ---- begin responder handling
-        let (responder, id_cred_i, _ead_3) = responder.parse_message_3(&message_3).unwrap();
-        let valid_cred_i = credential_check_or_fetch(Some(cred_i), id_cred_i).unwrap();
-        // if ead_3: process ead_3
-        let (mut responder, r_prk_out) = responder.verify_message_3(valid_cred_i).unwrap();
-        // ---- end responder handling
+                let by_reference = initiator
+                    .estimate_message_3_size(CredentialTransfer::ByReference, &None)
+                    .unwrap();
+                let by_value = initiator
+                    .estimate_message_3_size(CredentialTransfer::ByValue, &None)
+                    .unwrap();
+                // the credential plus its bstr header replaces the single byte kid
+                assert!(by_value - by_reference >= CRED_R.len());
+                assert!(by_value - by_reference <= CRED_R.len() + 3);
 
-        // check that prk_out is equal at initiator and responder side
-        assert_eq!(i_prk_out, r_prk_out);
+                let estimate = initiator
+                    .estimate_message_3_size(cred_transfer, &None)
+                    .unwrap();
+                let (_initiator, message_3, i_prk_out) =
+                    initiator.prepare_message_3(cred_transfer, &None).unwrap();
+                assert_eq!(message_3.len, estimate);
 
-        // derive OSCORE secret and salt at both sides and compare
-        let i_oscore_secret = initiator.edhoc_exporter(0u8, &[], 16); // label is 0
-        let i_oscore_salt = initiator.edhoc_exporter(1u8, &[], 8); // label is 1
+                let (responder, id_cred_i, _ead_3) = responder.parse_message_3(&message_3).unwrap();
+                let valid_cred_i = credential_check_or_fetch(Some(cred_i), id_cred_i).unwrap();
+                let (_responder, r_prk_out) = responder.verify_message_3(valid_cred_i).unwrap();
+                assert_eq!(i_prk_out, r_prk_out);
+            }
+        }
 
-        let r_oscore_secret = responder.edhoc_exporter(0u8, &[], 16); // label is 0
-        let r_oscore_salt = responder.edhoc_exporter(1u8, &[], 8); // label is 1
+        #[cfg(feature = "test-ead-none")]
+        #[test]
+        fn test_credential_transfer_without_kid() {
+            let cred_i = cred_i();
+            let cred_r = CredentialRPK::new(CRED_R_NO_KID.try_into().unwrap()).unwrap();
+            assert!(!cred_r.has_kid);
+            let responder_without_kid =
+                || EdhocResponder::new(default_crypto(), R, cred_r).unwrap();
 
-        assert_eq!(i_oscore_secret, r_oscore_secret);
-        assert_eq!(i_oscore_salt, r_oscore_salt);
+            let (_initiator, responder, _message_1) =
+                exchange_message_1(new_initiator(), responder_without_kid());
+            let res = responder.prepare_message_2(CredentialTransfer::ByReference, None, &None);
+            assert_eq!(
+                res.unwrap_err(),
+                EdhocFailure::new(
+                    EDHOCError::CredentialCannotBeReferenced,
+                    HandshakeStep::PrepareMessage2
+                )
+            );
 
-        // test key update with context from draft-ietf-lake-traces
-        let i_prk_out_new = initiator.edhoc_key_update(&[
-            0xa0, 0x11, 0x58, 0xfd, 0xb8, 0x20, 0x89, 0x0c, 0xd6, 0xbe, 0x16, 0x96, 0x02, 0xb8,
-            0xbc, 0xea,
-        ]);
-        let r_prk_out_new = responder.edhoc_key_update(&[
-            0xa0, 0x11, 0x58, 0xfd, 0xb8, 0x20, 0x89, 0x0c, 0xd6, 0xbe, 0x16, 0x96, 0x02, 0xb8,
-            0xbc, 0xea,
-        ]);
+            for cred_transfer in [CredentialTransfer::ByValue, CredentialTransfer::Auto] {
+                let (initiator, responder, _message_1) =
+                    exchange_message_1(new_initiator(), responder_without_kid());
+                let (responder, message_2) = responder
+                    .prepare_message_2(cred_transfer, None, &None)
+                    .unwrap();
+                assert_eq!(responder.credential_transfer(), CredentialTransfer::ByValue);
 
-        assert_eq!(i_prk_out_new, r_prk_out_new);
+                let (initiator, _c_r, id_cred_r, _ead_2) =
+                    initiator.parse_message_2(&message_2).unwrap();
+                assert_eq!(id_cred_r.value.as_slice(), CRED_R_NO_KID);
+                let valid_cred_r = credential_check_or_fetch(None, id_cred_r).unwrap();
+                let initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+
+                // CRED_I has a kid, so it is sent by reference
+                let (initiator, message_3, i_prk_out) = initiator
+                    .prepare_message_3(CredentialTransfer::Auto, &None)
+                    .unwrap();
+                assert_eq!(
+                    initiator.credential_transfer(),
+                    CredentialTransfer::ByReference
+                );
+
+                let (responder, id_cred_i, _ead_3) = responder.parse_message_3(&message_3).unwrap();
+                assert!(id_cred_i.reference_only());
+                let valid_cred_i = credential_check_or_fetch(Some(cred_i), id_cred_i).unwrap();
+                let (_responder, r_prk_out) = responder.verify_message_3(valid_cred_i).unwrap();
+                assert_eq!(i_prk_out, r_prk_out);
+            }
+        }
     }
 }
 
 #[cfg(feature = "test-ead-authz")]
 #[cfg(test)]
 mod test_authz {
+    use super::test::cred_i;
     use super::*;
     use hexlit::hex;
     use lakers_crypto::default_crypto;
@@ -640,6 +6176,27 @@ mod test_authz {
     const G_W_TV: &[u8] = &hex!("FFA4F102134029B3B156890B88C9D9619501196574174DCB68A07DB0588E4D41");
     const LOC_W_TV: &[u8] = &hex!("636F61703A2F2F656E726F6C6C6D656E742E736572766572");
 
+    // Wire format vectors, pinned to detect changes in the encoding of the authz messages.
+    //
+    // The inputs are those of the lakers-ead-authz test vectors, and so is EAD1_VALUE_TV. The
+    // vectors there encode the EAD_1 label as 1 (non-critical), while ZeroTouchDevice marks the
+    // item critical (label -1, 0x20): MESSAGE_1 and everything that includes or hashes it were
+    // therefore pinned from the output of this implementation.
+    const X_TV: BytesP256ElemLen =
+        hex!("368ec1f69aeb659ba37d5a8d45b21bdc0299dceaa8ef235f3ca42ce3530f9525");
+    const G_X_TV: BytesP256ElemLen =
+        hex!("8af6f430ebe18d34184017a9a11bf511c8dff8f834730b96c1b7c8dbca2fc3b6");
+    const SUITES_I_TV: &[u8] = &hex!("0602");
+    const C_I_TV: u8 = 0x37;
+    const CRED_V_TV: &[u8] = &hex!("a2026b6578616d706c652e65647508a101a501020241322001215820bbc34960526ea4d32e940cad2a234148ddc21791a12afbcbac93622046dd44f02258204519e257236b2a0ce2023f0931f1f386ca7afda64fcde0108c224c51eabf6072");
+    const EAD1_VALUE_TV: &[u8] = &hex!(
+        "58287818636f61703a2f2f656e726f6c6c6d656e742e7365727665724dda9784962883c96ed01ff122c3"
+    );
+    const MESSAGE_1_WITH_EAD_TV: &[u8] = &hex!("0382060258208af6f430ebe18d34184017a9a11bf511c8dff8f834730b96c1b7c8dbca2fc3b6372058287818636f61703a2f2f656e726f6c6c6d656e742e7365727665724dda9784962883c96ed01ff122c3");
+    const VOUCHER_REQUEST_TV: &[u8] = &hex!("8158520382060258208af6f430ebe18d34184017a9a11bf511c8dff8f834730b96c1b7c8dbca2fc3b6372058287818636f61703a2f2f656e726f6c6c6d656e742e7365727665724dda9784962883c96ed01ff122c3");
+    const VOUCHER_RESPONSE_TV: &[u8] = &hex!("8258520382060258208af6f430ebe18d34184017a9a11bf511c8dff8f834730b96c1b7c8dbca2fc3b6372058287818636f61703a2f2f656e726f6c6c6d656e742e7365727665724dda9784962883c96ed01ff122c34948e68b9d82f28f9c51");
+    const EAD2_VALUE_TV: &[u8] = &hex!("48e68b9d82f28f9c51");
+
     // TODO: have a setup_test function that prepares the common objects for the ead tests
     #[test]
     fn test_handshake_authz() {
@@ -648,14 +6205,15 @@ mod test_authz {
 
         // ==== initialize edhoc ====
         let mut initiator = EdhocInitiator::new(default_crypto());
-        let responder = EdhocResponder::new(default_crypto(), R, cred_r);
+        let responder = EdhocResponder::new(default_crypto(), R, cred_r).unwrap();
 
         // ==== initialize ead-authz ====
         let device = ZeroTouchDevice::new(
             ID_U_TV.try_into().unwrap(),
             G_W_TV.try_into().unwrap(),
             LOC_W_TV.try_into().unwrap(),
-        );
+        )
+        .unwrap();
         let authenticator = ZeroTouchAuthenticator::default();
 
         let acl = EdhocMessageBuffer::new_from_slice(&[cred_i.kid]).unwrap();
@@ -667,27 +6225,32 @@ mod test_authz {
 
         // ==== begin edhoc with ead-authz ====
 
-        let (mut device, ead_1) = device.prepare_ead_1(
-            &mut default_crypto(),
-            initiator.compute_ephemeral_secret(&device.g_w),
-            initiator.selected_cipher_suite(),
-        );
+        let (device, ead_1) = device
+            .prepare_ead_1(
+                &mut default_crypto(),
+                initiator.compute_ephemeral_secret(&device.g_w),
+                initiator.selected_cipher_suite(),
+            )
+            .unwrap();
         let (initiator, message_1) = initiator.prepare_message_1(None, &Some(ead_1)).unwrap();
-        device.set_h_message_1(initiator.state.h_message_1.clone());
 
         let (responder, ead_1) = responder.process_message_1(&message_1).unwrap();
-        let ead_2 = if let Some(ead_1) = ead_1 {
+        let ead_2 = if let Some(ead_1) = ead_1.first() {
             let (authenticator, _loc_w, voucher_request) =
-                authenticator.process_ead_1(&ead_1, &message_1).unwrap();
+                authenticator.process_ead_1(ead_1, &message_1).unwrap();
 
             // the line below mocks a request to the server: let voucher_response = auth_client.post(loc_w, voucher_request)?
-            let voucher_response = server
+            let (voucher_response, _opaque_state) = server
                 .handle_voucher_request(&mut default_crypto(), &voucher_request)
                 .unwrap();
+            let voucher_response = VoucherResponse::decode(voucher_response.as_slice()).unwrap();
 
             let res = authenticator.prepare_ead_2(&voucher_response);
             assert!(res.is_ok());
-            authenticator.prepare_ead_2(&voucher_response).ok()
+            authenticator
+                .prepare_ead_2(&voucher_response)
+                .ok()
+                .map(|(ead_2, _opaque_state)| ead_2)
         } else {
             None
         };
@@ -697,8 +6260,8 @@ mod test_authz {
 
         let (initiator, _c_r, id_cred_r, ead_2) = initiator.parse_message_2(&message_2).unwrap();
         let valid_cred_r = credential_check_or_fetch(None, id_cred_r).unwrap();
-        if let Some(ead_2) = ead_2 {
-            let result = device.process_ead_2(&mut default_crypto(), ead_2, CRED_R);
+        if let Some(ead_2) = ead_2.first().cloned() {
+            let result = device.process_ead_2(&mut default_crypto(), ead_2, CRED_R, &message_1);
             assert!(result.is_ok());
         }
         let initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
@@ -713,5 +6276,211 @@ mod test_authz {
 
         // check that prk_out is equal at initiator and responder side
         assert_eq!(i_prk_out, r_prk_out);
+
+        #[cfg(feature = "report")]
+        {
+            let i_report = _initiator.into_report().unwrap();
+            let r_report = _responder.into_report().unwrap();
+            for report in [&i_report, &r_report] {
+                assert_eq!(report.message_1_len, message_1.len);
+                assert_eq!(report.message_2_len, message_2.len);
+                assert_eq!(
+                    report.ead,
+                    [
+                        EadReport {
+                            message: 1,
+                            label: EAD_ZEROCONF_LABEL,
+                            critical: true
+                        },
+                        EadReport {
+                            message: 2,
+                            label: EAD_ZEROCONF_LABEL,
+                            critical: true
+                        }
+                    ]
+                );
+                assert_eq!(report.exporter_labels, []);
+            }
+            assert_eq!(
+                i_report.peer_credential,
+                CredentialReport {
+                    kid: Some(0x0a),
+                    transfer: CredentialTransfer::ByValue
+                }
+            );
+            assert_eq!(r_report.own_credential, i_report.peer_credential);
+        }
+    }
+
+    /// Runs the handshake with the initiator's ephemeral key and C_I of the authz vectors, so that
+    /// every authz item on the wire can be compared against pinned values
+    #[test]
+    fn test_handshake_authz_wire_format() {
+        let cred_i = cred_i();
+        let cred_r = CredentialRPK::new(CRED_V_TV.try_into().unwrap()).unwrap();
+
+        let initiator = EdhocInitiator::from_start(
+            default_crypto(),
+            InitiatorStart::new(X_TV, G_X_TV, SUITES_I_TV).unwrap(),
+        );
+        let responder = EdhocResponder::new(default_crypto(), R, cred_r).unwrap();
+
+        let device = ZeroTouchDevice::new(
+            ID_U_TV.try_into().unwrap(),
+            G_W_TV.try_into().unwrap(),
+            LOC_W_TV.try_into().unwrap(),
+        )
+        .unwrap();
+        let authenticator = ZeroTouchAuthenticator::default();
+        let acl = EdhocMessageBuffer::new_from_slice(&[cred_i.kid]).unwrap();
+        let server = ZeroTouchServer::new(W_TV.try_into().unwrap(), CRED_V_TV, Some(acl));
+
+        let (device, ead_1) = device
+            .prepare_ead_1(
+                &mut default_crypto(),
+                default_crypto().p256_ecdh(&X_TV, &G_W_TV.try_into().unwrap()),
+                initiator.selected_cipher_suite(),
+            )
+            .unwrap();
+        assert_eq!(ead_1.value.unwrap().as_slice(), EAD1_VALUE_TV);
+        let (initiator, message_1) = initiator
+            .prepare_message_1(Some(C_I_TV), &Some(ead_1))
+            .unwrap();
+        assert_eq!(message_1.as_slice(), MESSAGE_1_WITH_EAD_TV);
+
+        let (responder, ead_1) = responder.process_message_1(&message_1).unwrap();
+        let (authenticator, _loc_w, voucher_request) = authenticator
+            .process_ead_1(ead_1.first().unwrap(), &message_1)
+            .unwrap();
+        assert_eq!(voucher_request.as_slice(), VOUCHER_REQUEST_TV);
+
+        let (voucher_response, _opaque_state) = server
+            .handle_voucher_request(&mut default_crypto(), &voucher_request)
+            .unwrap();
+        assert_eq!(voucher_response.as_slice(), VOUCHER_RESPONSE_TV);
+        let voucher_response = VoucherResponse::decode(voucher_response.as_slice()).unwrap();
+
+        let (ead_2, _opaque_state) = authenticator.prepare_ead_2(&voucher_response).unwrap();
+        assert_eq!(ead_2.value.unwrap().as_slice(), EAD2_VALUE_TV);
+        let (responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByValue, None, &Some(ead_2))
+            .unwrap();
+
+        let (initiator, _c_r, id_cred_r, ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        assert_eq!(
+            ead_2.first().unwrap().value.unwrap().as_slice(),
+            EAD2_VALUE_TV
+        );
+        assert!(device
+            .process_ead_2(
+                &mut default_crypto(),
+                ead_2.first().cloned().unwrap(),
+                CRED_V_TV,
+                &message_1,
+            )
+            .is_ok());
+        let valid_cred_r = credential_check_or_fetch(None, id_cred_r).unwrap();
+        let initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+
+        let (_initiator, message_3, i_prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+        let (responder, id_cred_i, _ead_3) = responder.parse_message_3(&message_3).unwrap();
+        let valid_cred_i = credential_check_or_fetch(Some(cred_i), id_cred_i).unwrap();
+        let (_responder, r_prk_out) = responder.verify_message_3(valid_cred_i).unwrap();
+        assert_eq!(i_prk_out, r_prk_out);
+    }
+}
+
+#[cfg(feature = "test-ead-ra")]
+#[cfg(test)]
+mod test_ra {
+    use super::test::{cred_i, cred_r, new_initiator, new_responder};
+    use super::*;
+    use lakers_crypto::default_crypto;
+    use lakers_ead::*;
+    use test_vectors_common::*;
+
+    // application/eat+cwt, application/eat+jwt
+    const FORMATS_TV: &[u16] = &[263, 60];
+    const EVIDENCE_TV: &[u8] = b"stub evidence";
+
+    #[test]
+    fn test_handshake_ra() {
+        let cred_i = cred_i();
+        let cred_r = cred_r();
+
+        let initiator = new_initiator();
+        let responder = new_responder();
+
+        let requester = AttestationRequester::new(FORMATS_TV).unwrap();
+        let verifier = AttestationVerifier::new(&[263]).unwrap();
+
+        let (requester, ead_1_sent) = requester.prepare_ead_1(&mut default_crypto()).unwrap();
+        let (initiator, message_1) = initiator
+            .prepare_message_1(None, &Some(ead_1_sent.clone()))
+            .unwrap();
+
+        let (responder, ead_1) = responder.process_message_1(&message_1).unwrap();
+        let verifier = verifier.process_ead_1(ead_1.first().unwrap()).unwrap();
+        let (responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByValue, None, &None)
+            .unwrap();
+
+        let (initiator, _c_r, id_cred_r, _ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        let valid_cred_r = credential_check_or_fetch(Some(cred_r), id_cred_r).unwrap();
+        let initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+
+        let ead_3 = requester
+            .prepare_ead_3(&mut default_crypto(), initiator.th_3(), 263, EVIDENCE_TV)
+            .unwrap();
+        let (_initiator, message_3, i_prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &Some(ead_3.clone()))
+            .unwrap();
+
+        let (responder, id_cred_i, ead_3_received) = responder.parse_message_3(&message_3).unwrap();
+        let attestation = verifier
+            .process_ead_3(
+                &mut default_crypto(),
+                responder.th_3(),
+                ead_3_received.first().unwrap(),
+            )
+            .unwrap();
+        assert_eq!(attestation.format, 263);
+        assert_eq!(attestation.evidence.as_slice(), EVIDENCE_TV);
+
+        let valid_cred_i = credential_check_or_fetch(Some(cred_i), id_cred_i).unwrap();
+        let (_responder, r_prk_out) = responder.verify_message_3(valid_cred_i).unwrap();
+        assert_eq!(i_prk_out, r_prk_out);
+
+        // an Initiator that sends the EAD_1 and EAD_3 of the first session again in a second one
+        // (as if it relayed a genuine attester) is caught, as TH_3 differs
+        let (initiator, message_1) = new_initiator()
+            .prepare_message_1(None, &Some(ead_1_sent))
+            .unwrap();
+        let (responder, ead_1) = new_responder().process_message_1(&message_1).unwrap();
+        let verifier = AttestationVerifier::new(&[263])
+            .unwrap()
+            .process_ead_1(ead_1.first().unwrap())
+            .unwrap();
+        let (responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByValue, None, &None)
+            .unwrap();
+        let (initiator, _c_r, _id_cred_r, _ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        let initiator = initiator.verify_message_2(I, cred_i, cred_r).unwrap();
+        let (_initiator, message_3, _i_prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &Some(ead_3))
+            .unwrap();
+        let (responder, _id_cred_i, ead_3) = responder.parse_message_3(&message_3).unwrap();
+        assert_eq!(
+            verifier
+                .process_ead_3(
+                    &mut default_crypto(),
+                    responder.th_3(),
+                    ead_3.first().unwrap()
+                )
+                .unwrap_err(),
+            AttestationError::BindingMismatch
+        );
     }
 }
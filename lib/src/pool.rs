@@ -0,0 +1,409 @@
+//! Fixed-capacity table of the Responder sessions of a gateway, with counters of their outcomes
+use super::*;
+
+/// Counters of the outcomes of the messages handled by a [ResponderPool]
+///
+/// Each message that fails is counted in exactly one error class, which is derived from its
+/// [EDHOCError] variant. The counters only ever increase
+/// until [ResponderPool::reset_stats], as expected of e.g. Prometheus counters.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Handshakes completed by a message_3
+    pub completed: u64,
+    /// Messages that were malformed, too short or too long, or followed by trailing data
+    pub parse_errors: u64,
+    /// Messages whose MAC or AEAD tag did not verify
+    pub mac_failures: u64,
+    /// Messages of an unknown session, or with a credential that is not known
+    pub unknown_peers: u64,
+    /// Messages of type 1 that did not select a supported cipher suite
+    pub suite_mismatches: u64,
+    /// Messages that failed for any other reason (e.g. policy violations)
+    pub other_errors: u64,
+    /// Sessions dropped to make room for a new one before they expired
+    pub evicted: u64,
+    /// Sessions dropped because their message_3 did not arrive in time
+    pub expired: u64,
+    /// Resent message_1 answered with the retained message_2
+    pub retransmissions: u64,
+}
+
+impl PoolStats {
+    fn record_error(&mut self, error: EDHOCError) {
+        let counter = match error {
+            EDHOCError::ParsingError
+            | EDHOCError::TrailingData
+            | EDHOCError::MessageTooLong
+            | EDHOCError::NonCanonicalEncoding
+            | EDHOCError::MessageTooShort => &mut self.parse_errors,
+            EDHOCError::MacVerificationFailed => &mut self.mac_failures,
+            EDHOCError::UnknownPeer => &mut self.unknown_peers,
+            EDHOCError::UnsupportedCipherSuite => &mut self.suite_mismatches,
+            _ => &mut self.other_errors,
+        };
+        *counter += 1;
+    }
+}
+
+/// Sessions of a Responder that serves many Initiators, e.g. a gateway
+///
+/// Holds up to `N` handshakes that are waiting for their message_3, keyed by C_R, and runs the
+/// one-call handlers [respond_to_message_1] and [finish_with_message_3] on them. A session that
+/// did not receive its message_3 within `lifetime` (in the unit of the `now` the handlers are
+/// given, e.g. seconds) expires; when all slots are taken, the session closest to expiry makes
+/// room for a new one. The C_R of a new session is chosen among those not in use by another one,
+/// and a message_1 for which none is left fails with [EDHOCError::InvalidParameter].
+///
+/// Every outcome is counted in the [PoolStats] returned by [ResponderPool::stats].
+#[derive(Debug)]
+pub struct ResponderPool<Crypto: CryptoTrait, const N: usize> {
+    sessions: [Option<(EdhocResponderWaitM3<Crypto>, u64)>; N], // with their expiry
+    lifetime: u64,
+    stats: PoolStats,
+}
+
+impl<Crypto: CryptoTrait, const N: usize> ResponderPool<Crypto, N> {
+    /// An empty pool whose sessions expire `lifetime` after their message_1
+    pub fn new(lifetime: u64) -> Self {
+        ResponderPool {
+            sessions: core::array::from_fn(|_| None),
+            lifetime,
+            stats: PoolStats::default(),
+        }
+    }
+
+    /// Number of sessions waiting for their message_3
+    pub fn len(&self) -> usize {
+        self.sessions.iter().flatten().count()
+    }
+
+    /// Whether no session is waiting for its message_3
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Snapshot of the counters
+    pub fn stats(&self) -> PoolStats {
+        self.stats
+    }
+
+    /// Sets all counters back to zero, e.g. after they were exported
+    pub fn reset_stats(&mut self) {
+        self.stats = PoolStats::default();
+    }
+
+    /// Drops the sessions that expired by `now`
+    ///
+    /// This is also done by the handlers, so calling it is only needed to reclaim memory while
+    /// no messages arrive.
+    pub fn expire(&mut self, now: u64) {
+        for session in self.sessions.iter_mut() {
+            if matches!(session, Some((_, expiry)) if *expiry <= now) {
+                *session = None;
+                self.stats.expired += 1;
+            }
+        }
+    }
+
+    /// Answers the message_1 in `payload` like [respond_to_message_1], and keeps the session
+    /// until its message_3
+    ///
+    /// A message_1 that is a resent copy of one of a pending session is answered with the
+    /// message_2 retained there, see [EdhocResponderWaitM3::retained_message_2_for]; the EAD_1
+    /// returned then is empty, as it was already returned the first time.
+    pub fn handle_message_1(
+        &mut self,
+        identity: &ResponderIdentity,
+        crypto: Crypto,
+        payload: &[u8],
+        policy: &ResponderPolicy,
+        now: u64,
+    ) -> Result<(BufferMessage2, EadItems), EDHOCError> {
+        self.expire(now);
+        let result = self.respond(identity, crypto, payload, policy, now);
+        if let Err(error) = result {
+            self.stats.record_error(error);
+        }
+        result
+    }
+
+    /// Completes the session with connection identifier `c_r` with the message_3 in `payload`,
+    /// like [finish_with_message_3]
+    ///
+    /// The session is removed whether or not this succeeds. Fails with
+    /// [EDHOCError::UnknownPeer] if there is no such session, e.g. because it expired.
+    pub fn handle_message_3(
+        &mut self,
+        c_r: u8,
+        payload: &[u8],
+        lookup: impl FnOnce(&CredentialRPK) -> Option<CredentialRPK>,
+        policy: &ResponderPolicy,
+        now: u64,
+    ) -> Result<
+        (
+            EdhocResponderDone<Crypto>,
+            BytesHashLen,
+            CredentialRPK,
+            EadItems,
+        ),
+        EDHOCError,
+    > {
+        self.expire(now);
+        let result = match self.take(c_r) {
+            Some(responder) => finish_with_message_3(responder, payload, lookup, policy),
+            None => Err(EDHOCError::UnknownPeer),
+        };
+        match &result {
+            Ok(_) => self.stats.completed += 1,
+            Err(error) => self.stats.record_error(*error),
+        }
+        result
+    }
+
+    fn respond(
+        &mut self,
+        identity: &ResponderIdentity,
+        crypto: Crypto,
+        payload: &[u8],
+        policy: &ResponderPolicy,
+        now: u64,
+    ) -> Result<(BufferMessage2, EadItems), EDHOCError> {
+        let message_1 =
+            BufferMessage1::new_from_slice(payload).map_err(|_| EDHOCError::MessageTooLong)?;
+        let retained = self
+            .sessions
+            .iter()
+            .flatten()
+            .find_map(|(responder, _)| responder.retained_message_2_for(&message_1))
+            .copied();
+        if let Some(message_2) = retained {
+            self.stats.retransmissions += 1;
+            return Ok((message_2, EadItems::new()));
+        }
+
+        let (responder, message_2, ead_1) =
+            respond_to_message_1(identity, crypto, payload, policy, |c_r| {
+                self.position(c_r).is_none()
+            })?;
+        self.insert(responder, now);
+        Ok((message_2, ead_1))
+    }
+
+    /// Parks `responder`, whose C_R is not in use by another session, replacing the session
+    /// closest to expiry if all slots are taken
+    fn insert(&mut self, responder: EdhocResponderWaitM3<Crypto>, now: u64) {
+        debug_assert!(self.position(responder.c_r()).is_none());
+        let slot = self.sessions.iter().position(Option::is_none).or_else(|| {
+            self.sessions
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, session)| session.as_ref().map(|(_, expiry)| *expiry))
+                .map(|(slot, _)| slot)
+        });
+        let Some(slot) = slot else {
+            // N is 0, so the session is dropped right away
+            self.stats.evicted += 1;
+            return;
+        };
+        if self.sessions[slot].is_some() {
+            self.stats.evicted += 1;
+        }
+        self.sessions[slot] = Some((responder, now.saturating_add(self.lifetime)));
+    }
+
+    fn position(&self, c_r: u8) -> Option<usize> {
+        self.sessions
+            .iter()
+            .position(|session| matches!(session, Some((responder, _)) if responder.c_r() == c_r))
+    }
+
+    fn take(&mut self, c_r: u8) -> Option<EdhocResponderWaitM3<Crypto>> {
+        let slot = self.position(c_r)?;
+        self.sessions[slot].take().map(|(responder, _)| responder)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test_vectors_common::*;
+    use super::*;
+    use lakers_crypto::{default_crypto, Crypto};
+
+    const LIFETIME: u64 = 30;
+
+    fn identity() -> ResponderIdentity {
+        let cred_r = CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap();
+        ResponderIdentity::new(
+            &mut default_crypto(),
+            P256PrivateKey::new(R.try_into().unwrap()),
+            cred_r,
+        )
+        .unwrap()
+    }
+
+    fn known(id_cred_i: &CredentialRPK) -> Option<CredentialRPK> {
+        let cred_i = CredentialRPK::new(CRED_I.try_into().unwrap()).unwrap();
+        (id_cred_i.kid == cred_i.kid).then_some(cred_i)
+    }
+
+    /// Runs the Initiator up to message_3, returning C_R and message_3
+    fn message_3_for(
+        initiator: EdhocInitiatorWaitM2<Crypto>,
+        message_2: &BufferMessage2,
+    ) -> (u8, BufferMessage3) {
+        let cred_i = CredentialRPK::new(CRED_I.try_into().unwrap()).unwrap();
+        let cred_r = CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap();
+        let (initiator, c_r, id_cred_r, _ead_2) = initiator.parse_message_2(message_2).unwrap();
+        let valid_cred_r = credential_check_or_fetch(Some(cred_r), id_cred_r).unwrap();
+        let initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+        let (_initiator, message_3, _prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+        (c_r, message_3)
+    }
+
+    fn initiate() -> (EdhocInitiatorWaitM2<'static, Crypto>, BufferMessage1) {
+        EdhocInitiator::new(default_crypto())
+            .prepare_message_1(None, &None)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_pool_stats() {
+        let identity = identity();
+        let policy = ResponderPolicy::default();
+        let mut pool = ResponderPool::<Crypto, 2>::new(LIFETIME);
+        let message_1 = |pool: &mut ResponderPool<Crypto, 2>, payload: &[u8], now| {
+            pool.handle_message_1(&identity, default_crypto(), payload, &policy, now)
+        };
+
+        // two handshakes that complete, one of them after its message_1 was resent
+        let mut handshakes = Vec::new();
+        for _ in 0..2 {
+            let (initiator, payload) = initiate();
+            let (message_2, _ead_1) = message_1(&mut pool, payload.as_slice(), 0).unwrap();
+            handshakes.push((initiator, payload, message_2));
+        }
+        let (_, resent, message_2) = &handshakes[1];
+        let (resent_message_2, ead_1) = message_1(&mut pool, resent.as_slice(), 1).unwrap();
+        assert_eq!(resent_message_2, *message_2);
+        assert!(ead_1.is_empty());
+        assert_eq!(pool.len(), 2);
+        for (initiator, _, message_2) in handshakes {
+            let (c_r, message_3) = message_3_for(initiator, &message_2);
+            assert!(pool
+                .handle_message_3(c_r, message_3.as_slice(), known, &policy, 2)
+                .is_ok());
+        }
+        assert!(pool.is_empty());
+
+        // malformed message_1s, and one that selects a suite that is not supported
+        assert_eq!(
+            message_1(&mut pool, &[0xff], 3).unwrap_err(),
+            EDHOCError::ParsingError
+        );
+        assert_eq!(
+            message_1(&mut pool, &[0x00; MAX_MESSAGE_SIZE_LEN + 1], 3).unwrap_err(),
+            EDHOCError::MessageTooLong
+        );
+        let (_initiator, mut payload) = initiate();
+        payload.content[1] = 0x06; // SUITES_I
+        assert_eq!(
+            message_1(&mut pool, payload.as_slice(), 3).unwrap_err(),
+            EDHOCError::UnsupportedCipherSuite
+        );
+
+        // a message_3 with a bad tag, which ends its session, so that it is then unknown
+        let (initiator, payload) = initiate();
+        let (message_2, _ead_1) = message_1(&mut pool, payload.as_slice(), 4).unwrap();
+        let (c_r, mut message_3) = message_3_for(initiator, &message_2);
+        message_3.content[message_3.len - 1] ^= 0x01;
+        for error in [EDHOCError::MacVerificationFailed, EDHOCError::UnknownPeer] {
+            assert_eq!(
+                pool.handle_message_3(c_r, message_3.as_slice(), known, &policy, 5)
+                    .unwrap_err(),
+                error
+            );
+        }
+
+        // three sessions in two slots evict one, and the others expire
+        for now in [10, 11, 12] {
+            let (_initiator, payload) = initiate();
+            assert!(message_1(&mut pool, payload.as_slice(), now).is_ok());
+        }
+        assert_eq!(pool.len(), 2);
+        pool.expire(12 + LIFETIME);
+        assert!(pool.is_empty());
+
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                completed: 2,
+                parse_errors: 2,
+                mac_failures: 1,
+                unknown_peers: 1,
+                suite_mismatches: 1,
+                other_errors: 0,
+                evicted: 1,
+                expired: 2,
+                retransmissions: 1,
+            }
+        );
+        pool.reset_stats();
+        assert_eq!(pool.stats(), PoolStats::default());
+    }
+
+    #[test]
+    fn test_pool_connection_identifiers() {
+        // as many slots as there are single-byte identifiers
+        const SLOTS: usize = 48;
+        let identity = identity();
+        let policy = ResponderPolicy::default();
+        let mut pool = ResponderPool::<Crypto, SLOTS>::new(LIFETIME);
+
+        let mut c_rs = Vec::new();
+        for _ in 0..SLOTS {
+            let (initiator, payload) = initiate();
+            let (message_2, _ead_1) = pool
+                .handle_message_1(&identity, default_crypto(), payload.as_slice(), &policy, 0)
+                .unwrap();
+            let (_initiator, c_r, _id_cred_r, _ead_2) =
+                initiator.parse_message_2(&message_2).unwrap();
+            assert!(!c_rs.contains(&c_r));
+            c_rs.push(c_r);
+        }
+
+        // no session is evicted for lack of an identifier
+        let (_initiator, payload) = initiate();
+        assert_eq!(
+            pool.handle_message_1(&identity, default_crypto(), payload.as_slice(), &policy, 1)
+                .unwrap_err(),
+            EDHOCError::InvalidParameter
+        );
+        assert_eq!(pool.len(), SLOTS);
+        let stats = pool.stats();
+        assert_eq!((stats.evicted, stats.other_errors), (0, 1));
+    }
+
+    #[test]
+    fn test_pool_expiry() {
+        let identity = identity();
+        let policy = ResponderPolicy::default();
+        let mut pool = ResponderPool::<Crypto, 4>::new(LIFETIME);
+
+        let (initiator, payload) = initiate();
+        let (message_2, _ead_1) = pool
+            .handle_message_1(&identity, default_crypto(), payload.as_slice(), &policy, 0)
+            .unwrap();
+        let (c_r, message_3) = message_3_for(initiator, &message_2);
+
+        // message_3 arrives too late
+        assert_eq!(
+            pool.handle_message_3(c_r, message_3.as_slice(), known, &policy, LIFETIME)
+                .unwrap_err(),
+            EDHOCError::UnknownPeer
+        );
+        let stats = pool.stats();
+        assert_eq!((stats.expired, stats.unknown_peers), (1, 1));
+    }
+}
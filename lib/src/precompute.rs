@@ -0,0 +1,272 @@
+//! Preparation of message_1 ahead of sending it, e.g. while a battery-powered device is on
+//! external power, and storage of the Initiator state across a reboot
+use super::*;
+
+/// Longest output of [EdhocInitiatorWaitM2::save]
+pub const MAX_SAVED_INITIATOR_LEN: usize = MAX_MESSAGE_SIZE_LEN + 2 * SUITES_LEN + 64;
+
+// version of the format of EdhocInitiatorWaitM2::save, to be increased on any change of it
+const SAVED_INITIATOR_VERSION: u8 = 2;
+
+/// A message_1 that was prepared by [EdhocInitiator::precompute_message_1], to be sent exactly
+/// once
+///
+/// Its ephemeral key is the one of the [EdhocInitiatorWaitM2] it was prepared with, so it must not
+/// be sent again after a new handshake was started with a different state. Nothing in it is
+/// secret.
+#[derive(Debug)]
+pub struct PreparedMessage1 {
+    message_1: BufferMessage1,
+}
+
+impl PreparedMessage1 {
+    /// Restores a message_1 that was stored as [PreparedMessage1::as_slice]
+    ///
+    /// Fails with [EDHOCError::MessageTooLong] or [EDHOCError::ParsingError] if `bytes` is not a
+    /// well-formed message_1.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, EDHOCError> {
+        let message_1 =
+            BufferMessage1::new_from_slice(bytes).map_err(|_| EDHOCError::MessageTooLong)?;
+        parse_message_1(&message_1)?;
+        Ok(PreparedMessage1 { message_1 })
+    }
+
+    /// The encoded message_1, e.g. to be stored
+    pub fn as_slice(&self) -> &[u8] {
+        self.message_1.as_slice()
+    }
+
+    /// The message_1 to be sent, which uses up this
+    pub fn into_message(self) -> BufferMessage1 {
+        self.message_1
+    }
+}
+
+impl<'a, Crypto: CryptoTrait> EdhocInitiator<'a, Crypto> {
+    /// Like [EdhocInitiator::prepare_message_1], but for sending message_1 later, e.g. right after
+    /// the device woke up
+    ///
+    /// The ephemeral key generation and the encoding happen here, so that sending only takes the
+    /// [PreparedMessage1]. Both parts may be stored until then, see [EdhocInitiatorWaitM2::save].
+    /// The state does not depend on when message_1 is sent, so it may also be used before.
+    pub fn precompute_message_1(
+        self,
+        c_i: Option<u8>,
+        ead_1: &Option<EADItem>,
+    ) -> Result<(PreparedMessage1, EdhocInitiatorWaitM2<'a, Crypto>), EdhocFailure> {
+        let (initiator, message_1) = self.prepare_message_1(c_i, ead_1)?;
+        Ok((PreparedMessage1 { message_1 }, initiator))
+    }
+}
+
+impl<'a, Crypto: CryptoTrait> EdhocInitiatorWaitM2<'a, Crypto> {
+    /// Writes this state to `buf`, from which [EdhocInitiatorWaitM2::restore] restores it, and
+    /// returns the number of bytes written
+    ///
+    /// The output includes the ephemeral private key, so it has to be stored as confidentially as
+    /// the device keys. It also has to be deleted once the handshake continued: restoring it twice
+    /// would let two handshakes use the same ephemeral key. [MAX_SAVED_INITIATOR_LEN] bytes are
+    /// always sufficient; a shorter `buf` may fail with [EDHOCError::InvalidParameter].
+    ///
+    /// The credential resolver and the report are not saved.
+    pub fn save(&self, buf: &mut [u8]) -> Result<usize, EDHOCError> {
+        let mut encoder = CBOREncoder::new(buf);
+        let encoded: Result<(), MessageBufferError> = (|| {
+            encoder.array(12)?;
+            encoder.u8(SAVED_INITIATOR_VERSION)?;
+            encoder.bytes(&self.state.x)?;
+            encoder.bytes(self.message_1.as_slice())?;
+            encoder.bytes(&self.preferred_suites[..self.preferred_suites_len])?;
+            encoder.bytes(&self.attempted_suites[..self.attempted_suites_len])?;
+            encoder.u8(self.profile.methods)?;
+            encoder.u32(self.profile.suites)?;
+            encoder.bool(self.profile.by_reference)?;
+            encoder.bool(self.profile.by_value)?;
+            encoder.bool(self.profile.message_4)?;
+            encoder.bool(self.peer_cred_by_value)?;
+            encoder.u32(self.progress_mark)
+        })();
+        encoded.map_err(|_| EDHOCError::InvalidParameter)?;
+        Ok(encoder.position())
+    }
+
+    /// Restores a state that was written by [EdhocInitiatorWaitM2::save], e.g. after a reboot
+    ///
+    /// Everything but the ephemeral private key is derived from the saved message_1. Fails with
+    /// [EDHOCError::ParsingError] if `saved` was not written by this version of the library, and
+    /// with [EDHOCError::InconsistentState] if the private key does not belong to the G_X of
+    /// message_1, e.g. because the stored state was corrupted.
+    pub fn restore(mut crypto: Crypto, saved: &[u8]) -> Result<Self, EDHOCError> {
+        let mut decoder = CBORDecoder::new(saved);
+        if decoder.array()? != 12 || decoder.u8()? != SAVED_INITIATOR_VERSION {
+            return Err(EDHOCError::ParsingError);
+        }
+        let x: BytesP256ElemLen = decoder.bytes_sized(P256_ELEM_LEN)?.try_into().unwrap();
+        let message_1 = BufferMessage1::new_from_slice(decoder.bytes()?)
+            .map_err(|_| EDHOCError::ParsingError)?;
+        let preferred = decoder.bytes()?;
+        let attempted = decoder.bytes()?;
+        if preferred.len() > SUITES_LEN || attempted.len() > SUITES_LEN {
+            return Err(EDHOCError::ParsingError);
+        }
+        let mut preferred_suites: BytesSuites = [0x00; SUITES_LEN];
+        preferred_suites[..preferred.len()].copy_from_slice(preferred);
+        let mut attempted_suites: BytesSuites = [0x00; SUITES_LEN];
+        attempted_suites[..attempted.len()].copy_from_slice(attempted);
+        let profile = ApplicationProfile {
+            methods: decoder.u8()?,
+            suites: decoder.u32()?,
+            by_reference: decoder.bool()?,
+            by_value: decoder.bool()?,
+            message_4: decoder.bool()?,
+        };
+        let peer_cred_by_value = decoder.bool()?;
+        let progress_mark = decoder.u32()?;
+        decoder.ensure_finished()?;
+
+        let (method, suites_i, suites_i_len, g_x, c_i, _ead_1) = parse_message_1(&message_1)?;
+        let method = Method::from_u8(method).ok_or(EDHOCError::ParsingError)?;
+        let mut message_1_buf: BytesMaxBuffer = [0x00; MAX_BUFFER_LEN];
+        message_1_buf[..message_1.len].copy_from_slice(message_1.as_slice());
+        let h_message_1 = crypto.sha256_digest(&message_1_buf, message_1.len);
+
+        let state = WaitM2::new(x, g_x, h_message_1, suites_i[suites_i_len - 1], method);
+        state.check_consistency()?;
+        if crypto.p256_public_from_private(&x)? != g_x {
+            return Err(EDHOCError::InconsistentState);
+        }
+
+        let mut report = ReportRecorder::new();
+        report.message_1(&message_1, None);
+        Ok(EdhocInitiatorWaitM2 {
+            state,
+            message_1,
+            suites_i,
+            suites_i_len,
+            preferred_suites,
+            preferred_suites_len: preferred.len(),
+            attempted_suites,
+            attempted_suites_len: attempted.len(),
+            bytes_exchanged: message_1.len,
+            c_i: ConnectionId::c_i(c_i),
+            profile,
+            peer_cred_by_value,
+            credential_resolver: None,
+            report,
+            progress_mark,
+            crypto,
+        })
+    }
+
+    /// Sets how [EdhocInitiatorWaitM2::parse_and_verify_message_2] looks up the credential of the
+    /// Responder, e.g. after [EdhocInitiatorWaitM2::restore]
+    pub fn with_credential_resolver(mut self, resolver: &'a dyn CredentialResolver) -> Self {
+        self.credential_resolver = Some(resolver);
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test_vectors_common::*;
+    use super::*;
+    use lakers_crypto::default_crypto;
+
+    #[test]
+    fn test_precompute_message_1() {
+        let cred_i = CredentialRPK::new(CRED_I.try_into().unwrap()).unwrap();
+        let cred_r = CredentialRPK::new(CRED_R.try_into().unwrap()).unwrap();
+
+        // while on external power; only what is saved outlives this block
+        let mut saved_state = [0x00; MAX_SAVED_INITIATOR_LEN];
+        let (saved_state_len, saved_message_1, c_i) = {
+            let (prepared, initiator) = EdhocInitiator::new(default_crypto())
+                .with_application_profile(ApplicationProfile::default().with_cipher_suites(&[2]))
+                .precompute_message_1(None, &None)
+                .unwrap();
+            (
+                initiator.save(&mut saved_state).unwrap(),
+                prepared.as_slice().to_vec(),
+                initiator.c_i.id,
+            )
+        };
+
+        // after waking up
+        let initiator =
+            EdhocInitiatorWaitM2::restore(default_crypto(), &saved_state[..saved_state_len])
+                .unwrap();
+        assert_eq!(initiator.c_i, ConnectionId::c_i(c_i));
+        assert_eq!(initiator.profile.suites, 1 << 2);
+        assert_eq!(
+            initiator.preferred_suites[..initiator.preferred_suites_len],
+            EDHOC_SUPPORTED_SUITES
+        );
+        let message_1 = PreparedMessage1::from_slice(&saved_message_1)
+            .unwrap()
+            .into_message();
+        assert_eq!(initiator.message_1(), &message_1);
+
+        let responder = EdhocResponder::new(default_crypto(), R, cred_r).unwrap();
+        let (responder, _ead_1) = responder.process_message_1(&message_1).unwrap();
+        let (responder, message_2) = responder
+            .prepare_message_2(CredentialTransfer::ByReference, None, &None)
+            .unwrap();
+
+        let (initiator, _c_r, id_cred_r, _ead_2) = initiator.parse_message_2(&message_2).unwrap();
+        let valid_cred_r = credential_check_or_fetch(Some(cred_r), id_cred_r).unwrap();
+        let initiator = initiator.verify_message_2(I, cred_i, valid_cred_r).unwrap();
+        let (_initiator, message_3, i_prk_out) = initiator
+            .prepare_message_3(CredentialTransfer::ByReference, &None)
+            .unwrap();
+
+        let (responder, id_cred_i, _ead_3) = responder.parse_message_3(&message_3).unwrap();
+        let valid_cred_i = credential_check_or_fetch(Some(cred_i), id_cred_i).unwrap();
+        let (_responder, r_prk_out) = responder.verify_message_3(valid_cred_i).unwrap();
+        assert_eq!(i_prk_out, r_prk_out);
+    }
+
+    #[test]
+    fn test_restore_invalid() {
+        let (_prepared, initiator) = EdhocInitiator::new(default_crypto())
+            .precompute_message_1(None, &None)
+            .unwrap();
+        let mut saved = [0x00; MAX_SAVED_INITIATOR_LEN];
+        let saved_len = initiator.save(&mut saved).unwrap();
+        assert_eq!(
+            initiator.save(&mut saved[..saved_len - 1]),
+            Err(EDHOCError::InvalidParameter)
+        );
+
+        let restore = |saved: &[u8]| EdhocInitiatorWaitM2::restore(default_crypto(), saved);
+        assert!(restore(&saved[..saved_len]).is_ok());
+        assert!(restore(&saved[..saved_len - 1]).is_err());
+        assert!(restore(&[]).is_err());
+        let mut other_version = saved;
+        other_version[1] = SAVED_INITIATOR_VERSION + 1;
+        assert_eq!(
+            restore(&other_version[..saved_len]).unwrap_err(),
+            EDHOCError::ParsingError
+        );
+
+        // x follows the array and version headers and its own bstr header
+        assert_eq!(saved[2..4], [0x58, 0x20]);
+        assert_eq!(saved[4..4 + P256_ELEM_LEN], initiator.state.x);
+        let mut other_x = saved;
+        other_x[4 + P256_ELEM_LEN - 1] ^= 0x01;
+        assert_eq!(
+            restore(&other_x[..saved_len]).unwrap_err(),
+            EDHOCError::InconsistentState
+        );
+        let mut zero_x = saved;
+        zero_x[4..4 + P256_ELEM_LEN].fill(0x00);
+        assert_eq!(
+            restore(&zero_x[..saved_len]).unwrap_err(),
+            EDHOCError::InconsistentState
+        );
+
+        assert_eq!(
+            PreparedMessage1::from_slice(&[0x03, 0x02]).unwrap_err(),
+            EDHOCError::ParsingError
+        );
+    }
+}
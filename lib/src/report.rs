@@ -0,0 +1,251 @@
+//! Machine-readable report of a completed handshake, e.g. for compliance testing
+//!
+//! With the `report` feature, the wrapper structs record what was negotiated and exchanged while
+//! the handshake progresses, and [EdhocInitiatorDone::into_report] and
+//! [EdhocResponderDone::into_report] hand it out as a [HandshakeReport] that can be serialized
+//! with serde (e.g. to JSON or CBOR).
+//!
+//! Only public parameters of the handshake are recorded: no key, secret or message content ever
+//! ends up in a report. Without the feature, recording does nothing and takes no space.
+//!
+//! [EdhocInitiatorDone::into_report]: crate::EdhocInitiatorDone::into_report
+//! [EdhocResponderDone::into_report]: crate::EdhocResponderDone::into_report
+
+use lakers_shared::*;
+
+#[cfg(feature = "report")]
+use serde::{Serialize, Serializer};
+
+/// Side of the handshake a [HandshakeReport] was produced by
+#[cfg(feature = "report")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// The peer that sent message_1
+    Initiator,
+    /// The peer that sent message_2
+    Responder,
+}
+
+/// A credential as it was identified in the handshake
+#[cfg(feature = "report")]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CredentialReport {
+    /// kid of the credential, if it has one
+    pub kid: Option<u8>,
+    /// How the credential was sent in message_2 or message_3
+    #[serde(serialize_with = "serialize_transfer")]
+    pub transfer: CredentialTransfer,
+}
+
+/// An EAD item that was sent or received
+#[cfg(feature = "report")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct EadReport {
+    /// Number of the message that carried the item
+    pub message: u8,
+    /// Absolute value of the label
+    pub label: u16,
+    /// Whether the item was critical
+    pub critical: bool,
+}
+
+/// A label the EDHOC exporter was used with
+#[cfg(feature = "report")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ExporterReport {
+    /// The exporter label
+    pub label: u8,
+    /// Whether the label was used before message_3 was verified (see
+    /// [EdhocResponderWaitM3::early_exporter](crate::EdhocResponderWaitM3::early_exporter))
+    pub early: bool,
+}
+
+/// Unknown items that were skipped at the end of message_1 (see
+/// [UnknownTrailingData::IgnoreAndReport](crate::UnknownTrailingData::IgnoreAndReport))
+#[cfg(feature = "report")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TrailingItemsReport {
+    /// Number of skipped items
+    pub items: usize,
+    /// Number of bytes the skipped items took
+    pub bytes: usize,
+}
+
+/// Parameters of a completed handshake, as seen by one of the peers
+#[cfg(feature = "report")]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HandshakeReport {
+    /// Side of the handshake the report was produced by
+    pub role: Role,
+    /// The authentication method
+    pub method: u8,
+    /// The selected cipher suite
+    pub cipher_suite: u8,
+    /// The credential this peer authenticated with
+    pub own_credential: CredentialReport,
+    /// The credential the other peer authenticated with
+    pub peer_credential: CredentialReport,
+    /// EAD items, in the order of the messages that carried them
+    pub ead: Vec<EadReport>,
+    /// Length of message_1 in bytes
+    pub message_1_len: usize,
+    /// Length of message_2 in bytes
+    pub message_2_len: usize,
+    /// Length of message_3 in bytes
+    pub message_3_len: usize,
+    /// Unknown items the Responder skipped at the end of message_1, if there were any
+    pub message_1_trailing_items: Option<TrailingItemsReport>,
+    /// Labels the exporter was used with, in the order of their first use
+    pub exporter_labels: Vec<ExporterReport>,
+}
+
+#[cfg(feature = "report")]
+fn serialize_transfer<S: Serializer>(
+    transfer: &CredentialTransfer,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(match transfer {
+        CredentialTransfer::ByReference => "by_reference",
+        CredentialTransfer::ByValue => "by_value",
+        CredentialTransfer::ByReferenceFullMap => "by_reference_full_map",
+        CredentialTransfer::Auto => "auto",
+    })
+}
+
+/// What the wrapper structs record while the handshake progresses
+#[cfg(feature = "report")]
+#[derive(Debug, Default)]
+pub(crate) struct ReportRecorder {
+    method: u8,
+    cipher_suite: u8,
+    own_credential: Option<CredentialReport>,
+    peer_credential: Option<CredentialReport>,
+    ead: Vec<EadReport>,
+    message_lens: [usize; 3],
+    trailing_items: Option<TrailingItemsReport>,
+    exporter_labels: Vec<ExporterReport>,
+}
+
+#[cfg(feature = "report")]
+impl ReportRecorder {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records method, cipher suite and EAD_1 of a well-formed message_1, along with the unknown
+    /// items that were skipped at its end
+    pub(crate) fn message_1(
+        &mut self,
+        message_1: &BufferMessage1,
+        trailing: Option<TrailingItems>,
+    ) {
+        let known_len = message_1.len - trailing.map_or(0, |trailing| trailing.bytes);
+        if let Ok(known) = BufferMessage1::new_from_slice(&message_1.as_slice()[..known_len]) {
+            if let Ok((method, suites_i, suites_i_len, _g_x, _c_i, ead_1)) = parse_message_1(&known)
+            {
+                self.method = method;
+                self.cipher_suite = suites_i[suites_i_len - 1];
+                self.ead(1, ead_1.as_slice());
+            }
+        }
+        self.message_lens[0] = message_1.len;
+        self.trailing_items = trailing.map(|trailing| TrailingItemsReport {
+            items: trailing.items,
+            bytes: trailing.bytes,
+        });
+    }
+
+    pub(crate) fn message_2(&mut self, message_2: &BufferMessage2, ead_2: &[EADItem]) {
+        self.message_lens[1] = message_2.len;
+        self.ead(2, ead_2);
+    }
+
+    pub(crate) fn message_3(&mut self, message_3: &BufferMessage3, ead_3: &[EADItem]) {
+        self.message_lens[2] = message_3.len;
+        self.ead(3, ead_3);
+    }
+
+    /// Records the EAD items of a message, replacing what was recorded for it before
+    pub(crate) fn ead(&mut self, message: u8, ead: &[EADItem]) {
+        self.ead.retain(|item| item.message != message);
+        for ead in ead {
+            self.ead.push(EadReport {
+                message,
+                label: ead.label,
+                critical: ead.is_critical,
+            });
+        }
+    }
+
+    pub(crate) fn own_credential(&mut self, cred: &CredentialRPK, transfer: CredentialTransfer) {
+        self.own_credential = Some(CredentialReport {
+            kid: cred.has_kid.then_some(cred.kid),
+            transfer,
+        });
+    }
+
+    pub(crate) fn peer_credential(&mut self, id_cred: &CredentialRPK) {
+        let transfer = if id_cred.reference_only() {
+            CredentialTransfer::ByReference
+        } else {
+            CredentialTransfer::ByValue
+        };
+        self.peer_credential = Some(CredentialReport {
+            kid: id_cred.has_kid.then_some(id_cred.kid),
+            transfer,
+        });
+    }
+
+    pub(crate) fn exporter(&mut self, label: u8, early: bool) {
+        if !self.exporter_labels.iter().any(|used| used.label == label) {
+            self.exporter_labels.push(ExporterReport { label, early });
+        }
+    }
+
+    /// Builds the report; fails with [EDHOCError::InconsistentState] if no credentials were
+    /// recorded, i.e. if the handshake did not complete
+    pub(crate) fn finish(self, role: Role) -> Result<HandshakeReport, EDHOCError> {
+        let (Some(own_credential), Some(peer_credential)) =
+            (self.own_credential, self.peer_credential)
+        else {
+            return Err(EDHOCError::InconsistentState);
+        };
+        Ok(HandshakeReport {
+            role,
+            method: self.method,
+            cipher_suite: self.cipher_suite,
+            own_credential,
+            peer_credential,
+            ead: self.ead,
+            message_1_len: self.message_lens[0],
+            message_2_len: self.message_lens[1],
+            message_3_len: self.message_lens[2],
+            message_1_trailing_items: self.trailing_items,
+            exporter_labels: self.exporter_labels,
+        })
+    }
+}
+
+#[cfg(not(feature = "report"))]
+#[derive(Debug)]
+pub(crate) struct ReportRecorder;
+
+#[cfg(not(feature = "report"))]
+impl ReportRecorder {
+    pub(crate) fn new() -> Self {
+        ReportRecorder
+    }
+    pub(crate) fn message_1(
+        &mut self,
+        _message_1: &BufferMessage1,
+        _trailing: Option<TrailingItems>,
+    ) {
+    }
+    pub(crate) fn message_2(&mut self, _message_2: &BufferMessage2, _ead_2: &[EADItem]) {}
+    pub(crate) fn message_3(&mut self, _message_3: &BufferMessage3, _ead_3: &[EADItem]) {}
+    pub(crate) fn ead(&mut self, _message: u8, _ead: &[EADItem]) {}
+    pub(crate) fn own_credential(&mut self, _cred: &CredentialRPK, _transfer: CredentialTransfer) {}
+    pub(crate) fn peer_credential(&mut self, _id_cred: &CredentialRPK) {}
+    pub(crate) fn exporter(&mut self, _label: u8, _early: bool) {}
+}
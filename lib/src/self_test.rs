@@ -0,0 +1,269 @@
+//! Known-answer tests of the cryptographic primitives used in the handshake
+//!
+//! Certified devices are often required to check their crypto back-end at boot before using it.
+//! [self_test] runs each primitive that EDHOC uses on a fixed input, in the sizes EDHOC uses it,
+//! and compares the result to a known answer. Most vectors are taken from the traces of
+//! [RFC 9529](https://www.rfc-editor.org/rfc/rfc9529) (Chapter 3).
+
+use lakers_shared::{Crypto as CryptoTrait, *};
+
+/// Primitive that did not produce the known answer in [self_test]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum SelfTestFailure {
+    /// [CryptoTrait::sha256_digest]
+    Sha256,
+    /// [CryptoTrait::hkdf_extract]
+    HkdfExtract,
+    /// [CryptoTrait::hkdf_expand]
+    HkdfExpand,
+    /// [CryptoTrait::aes_ccm_encrypt_tag_8]
+    AesCcmEncrypt,
+    /// [CryptoTrait::aes_ccm_decrypt_tag_8]
+    AesCcmDecrypt,
+    /// [CryptoTrait::p256_ecdh]
+    P256Ecdh,
+}
+
+impl core::fmt::Display for SelfTestFailure {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "crypto self-test failed for {:?}", self)
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl std::error::Error for SelfTestFailure {}
+
+// SHA-256("abc") from FIPS 180-2
+const SHA256_INPUT: &[u8] = b"abc";
+const SHA256_OUTPUT: BytesHashLen = [
+    0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d, 0xae, 0x22, 0x23,
+    0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10, 0xff, 0x61, 0xf2, 0x00, 0x15, 0xad,
+];
+
+// PRK_2e = HKDF-Extract(TH_2, G_XY)
+const TH_2: BytesHashLen = [
+    0x35, 0x6e, 0xfd, 0x53, 0x77, 0x14, 0x25, 0xe0, 0x08, 0xf3, 0xfe, 0x3a, 0x86, 0xc8, 0x3f, 0xf4,
+    0xc6, 0xb1, 0x6e, 0x57, 0x02, 0x8f, 0xf3, 0x9d, 0x52, 0x36, 0xc1, 0x82, 0xb2, 0x02, 0x08, 0x4b,
+];
+const G_XY: BytesP256ElemLen = [
+    0x2f, 0x0c, 0xb7, 0xe8, 0x60, 0xba, 0x53, 0x8f, 0xbf, 0x5c, 0x8b, 0xde, 0xd0, 0x09, 0xf6, 0x25,
+    0x9b, 0x4b, 0x62, 0x8f, 0xe1, 0xeb, 0x7d, 0xbe, 0x93, 0x78, 0xe5, 0xec, 0xf7, 0xa8, 0x24, 0xba,
+];
+const PRK_2E: BytesHashLen = [
+    0x5a, 0xa0, 0xd6, 0x9f, 0x3e, 0x3d, 0x1e, 0x0c, 0x47, 0x9f, 0x0b, 0x8a, 0x48, 0x66, 0x90, 0xc9,
+    0x80, 0x26, 0x30, 0xc3, 0x46, 0x6b, 0x1d, 0xc9, 0x23, 0x71, 0xc9, 0x82, 0x56, 0x31, 0x70, 0xb5,
+];
+
+// HKDF-Expand of test case 1 of RFC 5869
+const HKDF_EXPAND_PRK: BytesHashLen = [
+    0x07, 0x77, 0x09, 0x36, 0x2c, 0x2e, 0x32, 0xdf, 0x0d, 0xdc, 0x3f, 0x0d, 0xc4, 0x7b, 0xba, 0x63,
+    0x90, 0xb6, 0xc7, 0x3b, 0xb5, 0x0f, 0x9c, 0x31, 0x22, 0xec, 0x84, 0x4a, 0xd7, 0xc2, 0xb3, 0xe5,
+];
+const HKDF_EXPAND_INFO: &[u8] = &[0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9];
+const HKDF_EXPAND_OKM: &[u8] = &[
+    0x3c, 0xb2, 0x5f, 0x25, 0xfa, 0xac, 0xd5, 0x7a, 0x90, 0x43, 0x4f, 0x64, 0xd0, 0x36, 0x2f, 0x2a,
+    0x2d, 0x2d, 0x0a, 0x90, 0xcf, 0x1a, 0x5a, 0x4c, 0x5d, 0xb0, 0x2d, 0x56, 0xec, 0xc4, 0xc5, 0xbf,
+    0x34, 0x00, 0x72, 0x08, 0xd5, 0xb8, 0x87, 0x18, 0x58, 0x65,
+];
+
+// CIPHERTEXT_3 = AES-CCM-16-64-128(K_3, IV_3, A_3, PLAINTEXT_3)
+const K_3: BytesCcmKeyLen = [
+    0x8e, 0x7a, 0x30, 0x04, 0x20, 0x00, 0xf7, 0x90, 0x0e, 0x81, 0x74, 0x13, 0x1f, 0x75, 0xf3, 0xed,
+];
+const IV_3: BytesCcmIvLen = [
+    0x6d, 0x83, 0x00, 0xc1, 0xe2, 0x3b, 0x56, 0x15, 0x3a, 0xe7, 0x0e, 0xe4, 0x57,
+];
+// ["Encrypt0", h'', TH_3]
+const A_3: &[u8] = &[
+    0x83, 0x68, 0x45, 0x6e, 0x63, 0x72, 0x79, 0x70, 0x74, 0x30, 0x40, 0x58, 0x20, 0xad, 0xaf, 0x67,
+    0xa7, 0x8a, 0x4b, 0xcc, 0x91, 0xe0, 0x18, 0xf8, 0x88, 0x27, 0x62, 0xa7, 0x22, 0x00, 0x0b, 0x25,
+    0x07, 0x03, 0x9d, 0xf0, 0xbc, 0x1b, 0xbf, 0x0c, 0x16, 0x1b, 0xb3, 0x15, 0x5c,
+];
+const PLAINTEXT_3: &[u8] = &[0x2b, 0x48, 0x62, 0x3c, 0x91, 0xdf, 0x41, 0xe3, 0x4c, 0x2f];
+const CIPHERTEXT_3: &[u8] = &[
+    0xe5, 0x62, 0x09, 0x7b, 0xc4, 0x17, 0xdd, 0x59, 0x19, 0x48, 0x5a, 0xc7, 0x89, 0x1f, 0xfd, 0x90,
+    0xa9, 0xfc,
+];
+
+// G_XY = ECDH(X, G_Y)
+const X: BytesP256ElemLen = [
+    0x36, 0x8e, 0xc1, 0xf6, 0x9a, 0xeb, 0x65, 0x9b, 0xa3, 0x7d, 0x5a, 0x8d, 0x45, 0xb2, 0x1b, 0xdc,
+    0x02, 0x99, 0xdc, 0xea, 0xa8, 0xef, 0x23, 0x5f, 0x3c, 0xa4, 0x2c, 0xe3, 0x53, 0x0f, 0x95, 0x25,
+];
+const G_Y: BytesP256ElemLen = [
+    0x41, 0x97, 0x01, 0xd7, 0xf0, 0x0a, 0x26, 0xc2, 0xdc, 0x58, 0x7a, 0x36, 0xdd, 0x75, 0x25, 0x49,
+    0xf3, 0x37, 0x63, 0xc8, 0x93, 0x42, 0x2c, 0x8e, 0xa0, 0xf9, 0x55, 0xa1, 0x3a, 0x4f, 0xf5, 0xd5,
+];
+
+/// Runs known-answer tests of all primitives of `crypto` that the handshake uses
+///
+/// This is meant to be called once at boot, before any handshake. It stops at the first primitive
+/// that does not produce its known answer.
+pub fn self_test(crypto: &mut impl CryptoTrait) -> Result<(), SelfTestFailure> {
+    let mut message: BytesMaxBuffer = [0x00; MAX_BUFFER_LEN];
+    message[..SHA256_INPUT.len()].copy_from_slice(SHA256_INPUT);
+    if crypto.sha256_digest(&message, SHA256_INPUT.len()) != SHA256_OUTPUT {
+        return Err(SelfTestFailure::Sha256);
+    }
+
+    if crypto.hkdf_extract(&TH_2, &G_XY) != PRK_2E {
+        return Err(SelfTestFailure::HkdfExtract);
+    }
+
+    let mut info: BytesMaxInfoBuffer = [0x00; MAX_INFO_LEN];
+    info[..HKDF_EXPAND_INFO.len()].copy_from_slice(HKDF_EXPAND_INFO);
+    let okm = crypto.hkdf_expand(
+        &HKDF_EXPAND_PRK,
+        &info,
+        HKDF_EXPAND_INFO.len(),
+        HKDF_EXPAND_OKM.len(),
+    );
+    if okm[..HKDF_EXPAND_OKM.len()] != *HKDF_EXPAND_OKM {
+        return Err(SelfTestFailure::HkdfExpand);
+    }
+
+    // the vectors fit, so neither of these can fail
+    let plaintext = BufferPlaintext3::new_from_slice(PLAINTEXT_3).unwrap();
+    let mut ciphertext = BufferCiphertext3::new_from_slice(CIPHERTEXT_3).unwrap();
+    if crypto.aes_ccm_encrypt_tag_8(&K_3, &IV_3, A_3, &plaintext) != ciphertext {
+        return Err(SelfTestFailure::AesCcmEncrypt);
+    }
+    if crypto.aes_ccm_decrypt_tag_8(&K_3, &IV_3, A_3, &ciphertext) != Ok(plaintext) {
+        return Err(SelfTestFailure::AesCcmDecrypt);
+    }
+    // the tag has to be checked as well
+    ciphertext.content[ciphertext.len - 1] ^= 0x01;
+    if crypto
+        .aes_ccm_decrypt_tag_8(&K_3, &IV_3, A_3, &ciphertext)
+        .is_ok()
+    {
+        return Err(SelfTestFailure::AesCcmDecrypt);
+    }
+
+    if crypto.p256_ecdh(&X, &G_Y) != G_XY {
+        return Err(SelfTestFailure::P256Ecdh);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lakers_crypto::default_crypto;
+
+    /// A backend that corrupts the output of one primitive
+    #[derive(Debug)]
+    struct Corrupted<C: CryptoTrait> {
+        crypto: C,
+        primitive: SelfTestFailure,
+    }
+
+    impl<C: CryptoTrait> CryptoTrait for Corrupted<C> {
+        fn sha256_digest(&mut self, message: &BytesMaxBuffer, message_len: usize) -> BytesHashLen {
+            let mut output = self.crypto.sha256_digest(message, message_len);
+            if self.primitive == SelfTestFailure::Sha256 {
+                output[0] ^= 0x01;
+            }
+            output
+        }
+
+        fn hkdf_expand(
+            &mut self,
+            prk: &BytesHashLen,
+            info: &BytesMaxInfoBuffer,
+            info_len: usize,
+            length: usize,
+        ) -> BytesMaxBuffer {
+            let mut output = self.crypto.hkdf_expand(prk, info, info_len, length);
+            if self.primitive == SelfTestFailure::HkdfExpand {
+                output[length - 1] ^= 0x01;
+            }
+            output
+        }
+
+        fn hkdf_extract(&mut self, salt: &BytesHashLen, ikm: &BytesP256ElemLen) -> BytesHashLen {
+            let mut output = self.crypto.hkdf_extract(salt, ikm);
+            if self.primitive == SelfTestFailure::HkdfExtract {
+                output[0] ^= 0x01;
+            }
+            output
+        }
+
+        fn aes_ccm_encrypt_tag_8(
+            &mut self,
+            key: &BytesCcmKeyLen,
+            iv: &BytesCcmIvLen,
+            ad: &[u8],
+            plaintext: &BufferPlaintext3,
+        ) -> BufferCiphertext3 {
+            let mut output = self.crypto.aes_ccm_encrypt_tag_8(key, iv, ad, plaintext);
+            if self.primitive == SelfTestFailure::AesCcmEncrypt {
+                output.content[0] ^= 0x01;
+            }
+            output
+        }
+
+        fn aes_ccm_decrypt_tag_8(
+            &mut self,
+            key: &BytesCcmKeyLen,
+            iv: &BytesCcmIvLen,
+            ad: &[u8],
+            ciphertext: &BufferCiphertext3,
+        ) -> Result<BufferPlaintext3, EDHOCError> {
+            if self.primitive == SelfTestFailure::AesCcmDecrypt {
+                // skips the tag check
+                let mut plaintext = BufferPlaintext3::new();
+                plaintext.len = ciphertext.len - AES_CCM_TAG_LEN;
+                return Ok(plaintext);
+            }
+            self.crypto.aes_ccm_decrypt_tag_8(key, iv, ad, ciphertext)
+        }
+
+        fn p256_ecdh(
+            &mut self,
+            private_key: &BytesP256ElemLen,
+            public_key: &BytesP256ElemLen,
+        ) -> BytesP256ElemLen {
+            let mut output = self.crypto.p256_ecdh(private_key, public_key);
+            if self.primitive == SelfTestFailure::P256Ecdh {
+                output[0] ^= 0x01;
+            }
+            output
+        }
+
+        forward_crypto!(
+            |this| this.crypto,
+            except sha256_digest,
+            hkdf_expand,
+            hkdf_extract,
+            aes_ccm_encrypt_tag_8,
+            aes_ccm_decrypt_tag_8,
+            p256_ecdh
+        );
+    }
+
+    #[test]
+    fn test_self_test() {
+        assert_eq!(self_test(&mut default_crypto()), Ok(()));
+    }
+
+    #[test]
+    fn test_self_test_corrupted() {
+        for primitive in [
+            SelfTestFailure::Sha256,
+            SelfTestFailure::HkdfExtract,
+            SelfTestFailure::HkdfExpand,
+            SelfTestFailure::AesCcmEncrypt,
+            SelfTestFailure::AesCcmDecrypt,
+            SelfTestFailure::P256Ecdh,
+        ] {
+            let mut crypto = Corrupted {
+                crypto: default_crypto(),
+                primitive,
+            };
+            assert_eq!(self_test(&mut crypto), Err(primitive));
+        }
+    }
+}
@@ -1,71 +1,957 @@
 use super::*;
 
+/// A raw public key credential, i.e. a CCS holding a P-256 public key, or only the kid of one
+#[cfg_attr(feature = "python-bindings", pyclass)]
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct CredentialRPK {
+    /// The encoded CCS, empty if the credential is only known by its kid
     pub value: EdhocMessageBuffer,
+    /// The x coordinate of the public key
     pub public_key: BytesP256ElemLen, // could be a reference, but safe Rust doesn't allow self-referencing structs
+    /// The one-byte kid, only meaningful if `has_kid` is set
     pub kid: u8,
+    /// Whether the credential has a kid
+    pub has_kid: bool, // a credential without kid can not be referenced, its kid is then 0
 }
 
 impl CredentialRPK {
+    /// Parses a CCS, i.e. a CWT Claims Set with the public key in a COSE_Key
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hexlit::hex;
+    /// use lakers_shared::*;
+    ///
+    /// # fn main() -> Result<(), EDHOCError> {
+    /// // CRED_R of RFC 9529, Section 3
+    /// let ccs = hex!("A2026008A101A5010202410A2001215820BBC34960526EA4D32E940CAD2A234148DDC21791A12AFBCBAC93622046DD44F02258204519E257236B2A0CE2023F0931F1F386CA7AFDA64FCDE0108C224C51EABF6072");
+    /// let value =
+    ///     EdhocMessageBuffer::new_from_slice(&ccs).map_err(|_| EDHOCError::MessageTooLong)?;
+    /// let cred_r = CredentialRPK::new(value)?;
+    /// assert_eq!(cred_r.kid_bytes(), Some(&[0x0a][..]));
+    /// assert_eq!(cred_r.public_key[..4], hex!("BBC34960"));
+    ///
+    /// let truncated = EdhocMessageBuffer::from_hex("A2026008A101A5010202410A2001215820");
+    /// assert!(CredentialRPK::new(truncated).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
     pub fn new(value: EdhocMessageBuffer) -> Result<Self, EDHOCError> {
         let (public_key, kid) = Self::parse(value.as_slice())?;
         Ok(Self {
             value,
             public_key,
-            kid,
+            kid: kid.unwrap_or_default(),
+            has_kid: kid.is_some(),
         })
     }
 
+    /// A credential that is only known by its kid, as received in ID_CRED by reference
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lakers_shared::CredentialRPK;
+    ///
+    /// let id_cred_r = CredentialRPK::from_kid(0x0a);
+    /// assert!(id_cred_r.reference_only());
+    /// assert_eq!(id_cred_r.kid_bytes(), Some(&[0x0a][..]));
+    /// ```
+    pub fn from_kid(kid: u8) -> Self {
+        Self {
+            value: Default::default(),
+            public_key: Default::default(),
+            kid,
+            has_kid: true,
+        }
+    }
+
+    /// Whether only the kid of the credential is known, as its value was not received
+    ///
+    /// See [CredentialRPK::from_kid] for an example.
     pub fn reference_only(&self) -> bool {
         self.value.len == 0
     }
 
+    /// The kid as the byte string of the COSE_Key, or None if the credential has no kid
+    ///
+    /// This is what a credential is to be looked up by, whichever form the kid was received in:
+    /// e.g. kid h'2e' is sent as the integer -15 in compact ID_CRED, but as h'2e' in a CCS.
+    pub fn kid_bytes(&self) -> Option<&[u8]> {
+        self.has_kid.then_some(core::slice::from_ref(&self.kid))
+    }
+
+    /// The y coordinate of the public key, if the CCS includes it
+    ///
+    /// Only x is kept in [CredentialRPK::public_key], which suffices for ECDH; verifying a
+    /// signature needs both.
+    pub fn public_key_y(&self) -> Option<BytesP256ElemLen> {
+        let ccs = Self::parse_ccs(self.value.as_slice()).ok()?;
+        ccs.y?.try_into().ok()
+    }
+
+    /// ID_CRED by reference, i.e. the map `{4: kid}`
     pub fn get_id_cred(&self) -> BytesIdCred {
         [0xa1, 0x04, 0x41, self.kid] // cbor map = {4: kid}
     }
 
-    fn parse(cred: &[u8]) -> Result<(BytesP256ElemLen, u8), EDHOCError> {
-        // NOTE: this routine is only guaranteed to work with credentials from lake-traces
-        const CCS_PREFIX_LEN: usize = 3;
-        const CNF_AND_COSE_KEY_PREFIX_LEN: usize = 8;
-        const COSE_KEY_FIRST_ITEMS_LEN: usize = 6;
+    /// Re-encodes the credential as a CCS in deterministic CBOR encoding
+    ///
+    /// Map entries are emitted in the order of RFC 8949 Section 4.2.1, so credentials that only
+    /// differ in the order of their keys produce identical bytes. A reference-only credential has
+    /// no value to encode and returns an empty buffer.
+    pub fn to_canonical_ccs(&self) -> Result<EdhocMessageBuffer, EDHOCError> {
+        let mut buffer = EdhocMessageBuffer::new();
+        if self.reference_only() {
+            return Ok(buffer);
+        }
+        let ccs = Self::parse_ccs(self.value.as_slice())?;
+
+        buffer.len = ccs
+            .encode(&mut buffer.content)
+            .map_err(|_| EDHOCError::ParsingError)?;
 
-        if cred.len()
-            < 3 + CCS_PREFIX_LEN
-                + 1
-                + CNF_AND_COSE_KEY_PREFIX_LEN
-                + COSE_KEY_FIRST_ITEMS_LEN
-                + P256_ELEM_LEN
-        {
+        Ok(buffer)
+    }
+
+    /// Builds a CCS `{2: subject, 8: {1: COSE_Key}}` for a P-256 public key, e.g. for provisioning
+    ///
+    /// The encoding is the deterministic one of [Self::to_canonical_ccs]. Returns
+    /// [EDHOCError::InvalidParameter] if the CCS does not fit into an [EdhocMessageBuffer].
+    pub fn build_ccs(
+        subject: Option<&str>,
+        kid: Option<u8>,
+        x: &BytesP256ElemLen,
+        y: Option<&BytesP256ElemLen>,
+    ) -> Result<Self, EDHOCError> {
+        let ccs = CcsParts {
+            subject: subject.map(str::as_bytes),
+            kid,
+            alg: None,
+            x,
+            y: y.map(|y| &y[..]),
+        };
+        let mut value = EdhocMessageBuffer::new();
+        value.len = ccs
+            .encode(&mut value.content)
+            .map_err(|_| EDHOCError::InvalidParameter)?;
+
+        Self::new(value)
+    }
+
+    fn parse(cred: &[u8]) -> Result<(BytesP256ElemLen, Option<u8>), EDHOCError> {
+        let ccs = Self::parse_ccs(cred)?;
+        Ok((ccs.x.try_into().expect("Wrong key length"), ccs.kid))
+    }
+
+    /// Walks a CCS `{? 2: subject, 8: {1: COSE_Key}}` holding a P-256 EC2 key with an optional
+    /// one-byte kid
+    fn parse_ccs(cred: &[u8]) -> Result<CcsParts<'_>, EDHOCError> {
+        let mut decoder = CBORDecoder::new(cred);
+        let mut subject = None;
+        let mut cose_key = None;
+        for _ in 0..decoder.map()? {
+            let label = decoder.u8()?;
+            if label == CCS_SUB && subject.is_none() {
+                subject = Some(decoder.str()?);
+            } else if label == CCS_CNF && cose_key.is_none() {
+                if decoder.map()? != 1 || decoder.u8()? != CNF_COSE_KEY {
+                    return Err(EDHOCError::ParsingError);
+                }
+                cose_key = Some(Self::parse_cose_key(&mut decoder)?);
+            } else {
+                return Err(EDHOCError::ParsingError);
+            }
+        }
+        decoder.ensure_finished()?;
+
+        let Some(ccs) = cose_key else {
+            return Err(EDHOCError::ParsingError);
+        };
+        Ok(CcsParts { subject, ..ccs })
+    }
+
+    fn parse_cose_key<'a>(decoder: &mut CBORDecoder<'a>) -> Result<CcsParts<'a>, EDHOCError> {
+        let mut kty = None;
+        let mut kid = None;
+        let mut alg = None;
+        let mut crv = None;
+        let mut x = None;
+        let mut y = None;
+        for _ in 0..decoder.map()? {
+            let label = decoder.i8()?;
+            if label == COSE_KEY_KTY && kty.is_none() {
+                kty = Some(decoder.u8()?);
+            } else if label == COSE_KEY_KID && kid.is_none() {
+                kid = Some(decoder.bytes_sized(1)?[0]);
+            } else if label == COSE_KEY_ALG && alg.is_none() {
+                alg = Some(decoder.i32()?);
+            } else if label == COSE_KEY_CRV && crv.is_none() {
+                crv = Some(decoder.u8()?);
+            } else if label == COSE_KEY_X && x.is_none() {
+                x = Some(decoder.bytes_sized(P256_ELEM_LEN)?);
+            } else if label == COSE_KEY_Y && y.is_none() {
+                y = Some(decoder.bytes_sized(P256_ELEM_LEN)?);
+            } else {
+                return Err(EDHOCError::ParsingError);
+            }
+        }
+
+        match (kty, crv, x) {
+            (Some(COSE_KTY_EC2), Some(COSE_CRV_P256), Some(x)) => Ok(CcsParts {
+                subject: None,
+                kid,
+                alg,
+                x,
+                y,
+            }),
+            _ => Err(EDHOCError::ParsingError),
+        }
+    }
+}
+
+const CCS_SUB: u8 = 2;
+const CCS_CNF: u8 = 8;
+const CNF_COSE_KEY: u8 = 1;
+const COSE_KEY_KTY: i8 = 1;
+const COSE_KEY_KID: i8 = 2;
+const COSE_KEY_ALG: i8 = 3;
+const COSE_KEY_CRV: i8 = -1;
+const COSE_KEY_X: i8 = -2;
+const COSE_KEY_Y: i8 = -3;
+const COSE_KTY_EC2: u8 = 2;
+const COSE_CRV_P256: u8 = 1;
+
+/// The parts of a CCS that [CredentialRPK] understands, borrowed from its encoded value
+struct CcsParts<'a> {
+    subject: Option<&'a [u8]>,
+    kid: Option<u8>,
+    alg: Option<i32>,
+    x: &'a [u8],
+    y: Option<&'a [u8]>,
+}
+
+impl CcsParts<'_> {
+    /// Writes the CCS into `out` with its map entries in deterministic order, returning its length
+    fn encode(&self, out: &mut [u8]) -> Result<usize, MessageBufferError> {
+        let mut encoder = CBOREncoder::new(out);
+        encoder.map(if self.subject.is_some() { 2 } else { 1 })?;
+        if let Some(subject) = self.subject {
+            encoder.u8(CCS_SUB)?;
+            encoder.str(subject)?;
+        }
+        encoder.u8(CCS_CNF)?;
+        encoder.map(1)?;
+        encoder.u8(CNF_COSE_KEY)?;
+        encoder.map(
+            3 + self.kid.is_some() as usize
+                + self.alg.is_some() as usize
+                + self.y.is_some() as usize,
+        )?;
+        encoder.i8(COSE_KEY_KTY)?;
+        encoder.u8(COSE_KTY_EC2)?;
+        if let Some(kid) = self.kid {
+            encoder.i8(COSE_KEY_KID)?;
+            encoder.bytes(&[kid])?;
+        }
+        if let Some(alg) = self.alg {
+            encoder.i8(COSE_KEY_ALG)?;
+            encoder.i32(alg)?;
+        }
+        encoder.i8(COSE_KEY_CRV)?;
+        encoder.u8(COSE_CRV_P256)?;
+        encoder.i8(COSE_KEY_X)?;
+        encoder.bytes(self.x)?;
+        if let Some(y) = self.y {
+            encoder.i8(COSE_KEY_Y)?;
+            encoder.bytes(y)?;
+        }
+        Ok(encoder.position())
+    }
+}
+
+/// A P-256 private key, as used for static DH authentication
+///
+/// It is deliberately not `Copy` nor `Debug`, so that it is neither duplicated implicitly nor
+/// printed; its bytes are wiped when it is dropped.
+#[derive(Clone)]
+pub struct P256PrivateKey {
+    d: BytesP256ElemLen,
+}
+
+impl Drop for P256PrivateKey {
+    fn drop(&mut self) {
+        crate::wipe(&mut self.d);
+    }
+}
+
+impl P256PrivateKey {
+    /// Wraps the private scalar `d`
+    pub fn new(d: BytesP256ElemLen) -> Self {
+        Self { d }
+    }
+
+    /// Extracts the private key from a COSE_Key of kty EC2 on curve P-256
+    ///
+    /// The 'd' parameter is mandatory. When the public 'x' parameter is present, it is checked
+    /// against the one derived from 'd', and [EDHOCError::InvalidParameter] is returned if they
+    /// do not match. Keys longer than [MAX_MESSAGE_SIZE_LEN] are rejected with
+    /// [EDHOCError::MessageTooLong].
+    pub fn from_cose_key<Crypto: crate::Crypto>(
+        crypto: &mut Crypto,
+        cose_key: &[u8],
+    ) -> Result<Self, EDHOCError> {
+        const COSE_KEY_D: i8 = -4;
+
+        if cose_key.len() > MAX_MESSAGE_SIZE_LEN {
+            return Err(EDHOCError::MessageTooLong);
+        }
+
+        let mut decoder = CBORDecoder::new(cose_key);
+        let mut kty = None;
+        let mut crv = None;
+        let mut x = None;
+        let mut d = None;
+        for _ in 0..decoder.map()? {
+            let label = decoder.i8()?;
+            if label == COSE_KEY_KTY {
+                kty = Some(decoder.u8()?);
+            } else if label == COSE_KEY_CRV {
+                crv = Some(decoder.u8()?);
+            } else if label == COSE_KEY_X {
+                x = Some(decoder.bytes_sized(P256_ELEM_LEN)?);
+            } else if label == COSE_KEY_D {
+                d = Some(decoder.bytes_sized(P256_ELEM_LEN)?);
+            } else if label == COSE_KEY_KID || label == COSE_KEY_Y {
+                decoder.bytes()?;
+            } else if label == COSE_KEY_ALG {
+                decoder.i8()?;
+            } else {
+                return Err(EDHOCError::ParsingError);
+            }
+        }
+        decoder.ensure_finished()?;
+
+        if kty != Some(COSE_KTY_EC2) || crv != Some(COSE_CRV_P256) {
+            return Err(EDHOCError::ParsingError);
+        }
+        let Some(d) = d else {
+            return Err(EDHOCError::ParsingError);
+        };
+        let key = Self::new(d.try_into().expect("Wrong key length"));
+
+        if let Some(x) = x {
+            if crypto.p256_public_from_private(&key.d)? != x {
+                return Err(EDHOCError::InvalidParameter);
+            }
+        }
+
+        Ok(key)
+    }
+
+    /// The private scalar `d`
+    pub fn as_slice(&self) -> &[u8] {
+        &self.d[..]
+    }
+}
+
+impl AsRef<[u8]> for P256PrivateKey {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+/// Maximum number of certificates accepted in an x5chain
+pub const MAX_X5CHAIN_LEN: usize = 4;
+
+/// A chain of X.509 certificates as carried in an `x5chain` credential identifier
+///
+/// Certificates are DER encoded and ordered from the leaf to the root, each one being signed by
+/// the next. Only ECDSA P-256 with SHA-256 certificates are supported.
+#[derive(Clone, Copy, Debug)]
+pub struct CredentialX509Chain<'a> {
+    certs: [&'a [u8]; MAX_X5CHAIN_LEN],
+    len: usize,
+}
+
+impl<'a> CredentialX509Chain<'a> {
+    /// Builds a chain from DER encoded certificates, leaf first
+    pub fn new(certs: &[&'a [u8]]) -> Result<Self, EDHOCError> {
+        if certs.len() > MAX_X5CHAIN_LEN {
+            return Err(EDHOCError::ParsingError);
+        }
+        let mut chain = Self {
+            certs: [&[]; MAX_X5CHAIN_LEN],
+            len: certs.len(),
+        };
+        chain.certs[..certs.len()].copy_from_slice(certs);
+        Ok(chain)
+    }
+
+    /// Builds a chain from the CBOR value of an x5chain: either a single bstr or an array of bstr
+    ///
+    /// Values longer than [MAX_CREDENTIAL_INPUT_LEN] are rejected with
+    /// [EDHOCError::MessageTooLong].
+    pub fn parse(x5chain: &'a [u8]) -> Result<Self, EDHOCError> {
+        if x5chain.len() > MAX_CREDENTIAL_INPUT_LEN {
+            return Err(EDHOCError::MessageTooLong);
+        }
+        let mut decoder = CBORDecoder::new(x5chain);
+        let mut chain = Self {
+            certs: [&[]; MAX_X5CHAIN_LEN],
+            len: 0,
+        };
+        if CBORDecoder::type_of(decoder.current()?) == CBOR_MAJOR_ARRAY {
+            let len = decoder.array()?;
+            if len > MAX_X5CHAIN_LEN {
+                return Err(EDHOCError::ParsingError);
+            }
+            for i in 0..len {
+                chain.certs[i] = decoder.bytes()?;
+            }
+            chain.len = len;
+        } else {
+            chain.certs[0] = decoder.bytes()?;
+            chain.len = 1;
+        }
+        if decoder.finished() {
+            Ok(chain)
+        } else {
             Err(EDHOCError::ParsingError)
+        }
+    }
+
+    /// Number of certificates in the chain
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the chain holds no certificate
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The x coordinate of the leaf certificate's public key, as used for static DH
+    pub fn leaf_public_key(&self) -> Result<BytesP256ElemLen, EDHOCError> {
+        if self.is_empty() {
+            return Err(EDHOCError::CredentialVerificationFailed);
+        }
+        Ok(X509Certificate::parse(self.certs[0])?.public_key_x)
+    }
+
+    /// Checks that each certificate is issued and signed by the next one, and that the last one is
+    /// either one of the trust anchors or issued and signed by one of them.
+    ///
+    /// Trust anchors are DER encoded certificates. Validity periods and extensions are not checked.
+    pub fn validate<Crypto: crate::Crypto>(
+        &self,
+        crypto: &mut Crypto,
+        trust_anchors: &[&[u8]],
+    ) -> Result<(), EDHOCError> {
+        if self.is_empty() {
+            return Err(EDHOCError::CredentialVerificationFailed);
+        }
+
+        for i in 0..self.len - 1 {
+            let cert = X509Certificate::parse(self.certs[i])?;
+            let issuer = X509Certificate::parse(self.certs[i + 1])?;
+            if !issuer.has_issued(crypto, &cert) {
+                return Err(EDHOCError::CredentialVerificationFailed);
+            }
+        }
+
+        let last = self.certs[self.len - 1];
+        let last_cert = X509Certificate::parse(last)?;
+        for anchor in trust_anchors {
+            if *anchor == last {
+                return Ok(());
+            }
+            if let Ok(anchor) = X509Certificate::parse(anchor) {
+                if anchor.has_issued(crypto, &last_cert) {
+                    return Ok(());
+                }
+            }
+        }
+        Err(EDHOCError::CredentialVerificationFailed)
+    }
+}
+
+/// The parts of a DER encoded X.509 certificate that are needed for chain validation
+struct X509Certificate<'a> {
+    tbs: &'a [u8], // full TBSCertificate, including its header
+    issuer: &'a [u8],
+    subject: &'a [u8],
+    public_key_x: BytesP256ElemLen,
+    public_key_y: BytesP256ElemLen,
+    signature: BytesP256Signature,
+}
+
+const DER_SEQUENCE: u8 = 0x30;
+const DER_INTEGER: u8 = 0x02;
+const DER_BIT_STRING: u8 = 0x03;
+const DER_EXPLICIT_0: u8 = 0xa0;
+// DER encoded OID 1.2.840.10045.4.3.2
+const DER_ECDSA_WITH_SHA256: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+
+impl<'a> X509Certificate<'a> {
+    fn parse(der: &'a [u8]) -> Result<Self, EDHOCError> {
+        let (certificate, rest) = der_expect(der, DER_SEQUENCE)?;
+        if !rest.is_empty() {
+            return Err(EDHOCError::ParsingError);
+        }
+
+        let (_, tbs_contents, after_tbs) = der_tlv(certificate)?;
+        let tbs = &certificate[..certificate.len() - after_tbs.len()];
+        let (signature_algorithm, after_algorithm) = der_expect(after_tbs, DER_SEQUENCE)?;
+        if signature_algorithm != DER_ECDSA_WITH_SHA256 {
+            return Err(EDHOCError::ParsingError);
+        }
+        let (signature_value, _) = der_expect(after_algorithm, DER_BIT_STRING)?;
+
+        // TBSCertificate: version, serialNumber, signature, issuer, validity, subject, subjectPublicKeyInfo
+        let mut rest = tbs_contents;
+        if rest.first() == Some(&DER_EXPLICIT_0) {
+            (_, rest) = der_expect(rest, DER_EXPLICIT_0)?;
+        }
+        (_, rest) = der_expect(rest, DER_INTEGER)?;
+        (_, rest) = der_expect(rest, DER_SEQUENCE)?;
+        let issuer_start = rest;
+        (_, rest) = der_expect(rest, DER_SEQUENCE)?;
+        let issuer = &issuer_start[..issuer_start.len() - rest.len()];
+        (_, rest) = der_expect(rest, DER_SEQUENCE)?;
+        let subject_start = rest;
+        (_, rest) = der_expect(rest, DER_SEQUENCE)?;
+        let subject = &subject_start[..subject_start.len() - rest.len()];
+        let (spki, _) = der_expect(rest, DER_SEQUENCE)?;
+        let (_, spki) = der_expect(spki, DER_SEQUENCE)?;
+        let (public_key, _) = der_expect(spki, DER_BIT_STRING)?;
+
+        // no unused bits, uncompressed point
+        if public_key.len() != 2 + 2 * P256_ELEM_LEN || public_key[0] != 0 || public_key[1] != 0x04
+        {
+            return Err(EDHOCError::ParsingError);
+        }
+
+        Ok(Self {
+            tbs,
+            issuer,
+            subject,
+            public_key_x: public_key[2..2 + P256_ELEM_LEN].try_into().unwrap(),
+            public_key_y: public_key[2 + P256_ELEM_LEN..].try_into().unwrap(),
+            signature: der_decode_ecdsa_signature(signature_value)?,
+        })
+    }
+
+    fn has_issued<Crypto: crate::Crypto>(&self, crypto: &mut Crypto, cert: &Self) -> bool {
+        cert.issuer == self.subject
+            && crypto.p256_ecdsa_verify(
+                &self.public_key_x,
+                &self.public_key_y,
+                cert.tbs,
+                &cert.signature,
+            )
+    }
+}
+
+/// Splits a DER item into its tag, its contents and whatever follows it
+///
+/// DER requires lengths in their shortest encoding, so a long form that could have been shorter
+/// fails with [EDHOCError::ParsingError].
+fn der_tlv(input: &[u8]) -> Result<(u8, &[u8], &[u8]), EDHOCError> {
+    let (tag, first_len) = match input {
+        [tag, first_len, ..] => (*tag, *first_len),
+        _ => return Err(EDHOCError::ParsingError),
+    };
+    let (len, header_len) = match (first_len, input.get(2..)) {
+        (0x81, Some([len, ..])) if *len >= 0x80 => (*len as usize, 3),
+        (0x82, Some([len_high, len_low, ..])) if *len_high > 0 => {
+            (u16::from_be_bytes([*len_high, *len_low]) as usize, 4)
+        }
+        _ if first_len < 0x80 => (first_len as usize, 2),
+        _ => return Err(EDHOCError::ParsingError),
+    };
+    if header_len + len > input.len() {
+        return Err(EDHOCError::ParsingError);
+    }
+    Ok((
+        tag,
+        &input[header_len..header_len + len],
+        &input[header_len + len..],
+    ))
+}
+
+/// Like [der_tlv], but fails if the item does not have the expected tag
+fn der_expect(input: &[u8], expected_tag: u8) -> Result<(&[u8], &[u8]), EDHOCError> {
+    let (tag, contents, rest) = der_tlv(input)?;
+    if tag == expected_tag {
+        Ok((contents, rest))
+    } else {
+        Err(EDHOCError::ParsingError)
+    }
+}
+
+/// Converts the contents of a signatureValue BIT STRING into the raw r || s form
+fn der_decode_ecdsa_signature(bit_string: &[u8]) -> Result<BytesP256Signature, EDHOCError> {
+    let Some((0, encoded)) = bit_string.split_first() else {
+        return Err(EDHOCError::ParsingError);
+    };
+    let (sequence, _) = der_expect(encoded, DER_SEQUENCE)?;
+    let (r, rest) = der_expect(sequence, DER_INTEGER)?;
+    let (s, _) = der_expect(rest, DER_INTEGER)?;
+
+    let mut signature: BytesP256Signature = [0; P256_SIGNATURE_LEN];
+    for (integer, out) in [r, s].iter().zip(signature.chunks_mut(P256_ELEM_LEN)) {
+        // strip the sign byte of positive integers with the high bit set
+        let integer = match integer {
+            [0, rest @ ..] if !rest.is_empty() => rest,
+            _ => integer,
+        };
+        if integer.len() > P256_ELEM_LEN {
+            return Err(EDHOCError::ParsingError);
+        }
+        out[P256_ELEM_LEN - integer.len()..].copy_from_slice(integer);
+    }
+    Ok(signature)
+}
+
+/// What [X509CertificateParseStream] extracted from a certificate
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamedX509Certificate {
+    /// SHA-256 of the DER encoded certificate, i.e. the hash value of an `x5t` with alg -16
+    pub thumbprint: BytesHashLen,
+    /// The x coordinate of the public key
+    pub public_key_x: BytesP256ElemLen,
+    /// The y coordinate of the public key
+    pub public_key_y: BytesP256ElemLen,
+}
+
+impl StreamedX509Certificate {
+    /// The x coordinate of the public key, as used for static DH, if the certificate is one of
+    /// the `trusted` ones, given by their thumbprints
+    ///
+    /// As the signature of a streamed certificate is not verified, pinning the certificate is
+    /// what makes its public key trustworthy; other certificates fail with
+    /// [EDHOCError::CredentialVerificationFailed].
+    pub fn check_thumbprint(
+        &self,
+        trusted: &[BytesHashLen],
+    ) -> Result<BytesP256ElemLen, EDHOCError> {
+        if trusted.contains(&self.thumbprint) {
+            Ok(self.public_key_x)
         } else {
-            let subject_len = CBORDecoder::info_of(cred[2]) as usize;
-
-            let id_cred_offset: usize = CCS_PREFIX_LEN
-                .checked_add(subject_len)
-                .and_then(|x| x.checked_add(CNF_AND_COSE_KEY_PREFIX_LEN))
-                .ok_or(EDHOCError::ParsingError)?;
-
-            let g_a_x_offset: usize = id_cred_offset
-                .checked_add(COSE_KEY_FIRST_ITEMS_LEN)
-                .ok_or(EDHOCError::ParsingError)?;
-
-            if g_a_x_offset
-                .checked_add(P256_ELEM_LEN)
-                .map_or(false, |end| end <= cred.len())
-            {
-                Ok((
-                    cred[g_a_x_offset..g_a_x_offset + P256_ELEM_LEN]
-                        .try_into()
-                        .expect("Wrong key length"),
-                    cred[id_cred_offset],
-                ))
-            } else {
-                Err(EDHOCError::ParsingError)
+            Err(EDHOCError::CredentialVerificationFailed)
+        }
+    }
+}
+
+// tag and a length of up to two bytes, as accepted by der_tlv
+const DER_MAX_HEADER_LEN: usize = 4;
+// the public key is nested in the Certificate, the TBSCertificate and the SubjectPublicKeyInfo
+const X509_STREAM_MAX_DEPTH: usize = 3;
+// subjectPublicKey BIT STRING: no unused bits, then an uncompressed point
+const P256_SPKI_KEY_LEN: usize = 2 + 2 * P256_ELEM_LEN;
+// position of subjectPublicKeyInfo in the TBSCertificate, after an optional version
+const TBS_SPKI_INDEX: usize = 5;
+
+/// Single-pass parsing of a DER encoded X.509 certificate that arrives in chunks, e.g. a large
+/// certificate sent by value that is decrypted piecewise
+///
+/// Each chunk is hashed into the thumbprint and walked through without being retained: only the
+/// hash state, the current DER header and the public key are held, so memory does not grow with
+/// the certificate. Like [CredentialX509Chain::leaf_public_key], only uncompressed P-256 keys are
+/// supported.
+///
+/// This only parses: the signature of the certificate is not verified, as that would need the
+/// whole TBSCertificate. The result is to be checked against pinned certificates with
+/// [StreamedX509Certificate::check_thumbprint].
+#[derive(Debug)]
+pub struct X509CertificateParseStream<H: Sha256Stream> {
+    hasher: H,
+    position: usize,                       // bytes consumed so far
+    ends: [usize; X509_STREAM_MAX_DEPTH],  // where each entered SEQUENCE ends
+    items: [usize; X509_STREAM_MAX_DEPTH], // items seen in each entered SEQUENCE
+    depth: usize,                          // SEQUENCEs entered and not yet ended
+    header: [u8; DER_MAX_HEADER_LEN],      // header of the next item, as far as it was read
+    header_len: usize,
+    skipping: usize, // bytes left of an item that is not looked into
+    public_key: [u8; P256_SPKI_KEY_LEN],
+    public_key_len: usize,
+    complete: bool,
+    failed: bool,
+}
+
+impl<H: Sha256Stream> X509CertificateParseStream<H> {
+    /// Starts parsing a certificate, hashing it into the thumbprint with `hasher`
+    pub fn new(hasher: H) -> Self {
+        Self {
+            hasher,
+            position: 0,
+            ends: [0; X509_STREAM_MAX_DEPTH],
+            items: [0; X509_STREAM_MAX_DEPTH],
+            depth: 0,
+            header: [0; DER_MAX_HEADER_LEN],
+            header_len: 0,
+            skipping: 0,
+            public_key: [0; P256_SPKI_KEY_LEN],
+            public_key_len: 0,
+            complete: false,
+            failed: false,
+        }
+    }
+
+    /// Processes the next chunk of the certificate
+    ///
+    /// Fails with [EDHOCError::ParsingError] on malformed DER and on data after the certificate;
+    /// the stream is then unusable.
+    pub fn update(&mut self, chunk: &[u8]) -> Result<(), EDHOCError> {
+        if self.failed {
+            return Err(EDHOCError::ParsingError);
+        }
+        for byte in chunk {
+            if let Err(error) = self.consume(*byte) {
+                self.failed = true;
+                return Err(error);
+            }
+        }
+        self.hasher.update(chunk);
+        Ok(())
+    }
+
+    /// The thumbprint and public key, once the whole certificate was processed
+    pub fn finish(self) -> Result<StreamedX509Certificate, EDHOCError> {
+        // the Certificate holds tbsCertificate, signatureAlgorithm and signatureValue
+        if self.failed || !self.complete || self.items[0] != 3 {
+            return Err(EDHOCError::ParsingError);
+        }
+        if self.public_key_len != P256_SPKI_KEY_LEN
+            || self.public_key[0] != 0
+            || self.public_key[1] != 0x04
+        {
+            return Err(EDHOCError::ParsingError);
+        }
+        Ok(StreamedX509Certificate {
+            thumbprint: self.hasher.finish(),
+            public_key_x: self.public_key[2..2 + P256_ELEM_LEN].try_into().unwrap(),
+            public_key_y: self.public_key[2 + P256_ELEM_LEN..].try_into().unwrap(),
+        })
+    }
+
+    fn consume(&mut self, byte: u8) -> Result<(), EDHOCError> {
+        if self.complete {
+            return Err(EDHOCError::ParsingError);
+        }
+        self.position += 1;
+        // items are checked to fit into their SEQUENCE, but headers are only known once read
+        if self.depth > 0 && self.position > self.ends[self.depth - 1] {
+            return Err(EDHOCError::ParsingError);
+        }
+        if self.capturing() {
+            self.public_key[self.public_key_len] = byte;
+            self.public_key_len += 1;
+        } else if self.skipping > 0 {
+            self.skipping -= 1;
+        } else {
+            self.header[self.header_len] = byte;
+            self.header_len += 1;
+            let Some(len) = der_header_len(&self.header[..self.header_len])? else {
+                return Ok(()); // more of the header is to come
+            };
+            self.header_len = 0;
+            self.start_item(self.header[0], len)?;
+        }
+        self.end_sequences();
+        Ok(())
+    }
+
+    // while the subjectPublicKey BIT STRING is read
+    fn capturing(&self) -> bool {
+        self.depth == X509_STREAM_MAX_DEPTH
+            && self.items[X509_STREAM_MAX_DEPTH - 1] == 2
+            && self.public_key_len < P256_SPKI_KEY_LEN
+    }
+
+    /// Decides, from its position in the certificate, whether an item is entered, read or skipped
+    fn start_item(&mut self, tag: u8, len: usize) -> Result<(), EDHOCError> {
+        if self.depth > 0 && self.position + len > self.ends[self.depth - 1] {
+            return Err(EDHOCError::ParsingError);
+        }
+        let index = match self.depth {
+            0 => 0,
+            depth => self.items[depth - 1],
+        };
+        match (self.depth, index) {
+            // Certificate, tbsCertificate and subjectPublicKeyInfo
+            (0, _) | (1, 0) | (2, TBS_SPKI_INDEX) => {
+                if tag != DER_SEQUENCE {
+                    return Err(EDHOCError::ParsingError);
+                }
+                if self.depth > 0 {
+                    self.items[self.depth - 1] += 1;
+                }
+                self.ends[self.depth] = self.position + len;
+                self.items[self.depth] = 0;
+                self.depth += 1;
+            }
+            // signatureAlgorithm and signatureValue
+            (1, 1..=2) => {
+                self.items[0] += 1;
+                self.skipping = len;
+            }
+            (1, _) => return Err(EDHOCError::ParsingError),
+            // the version is not counted, so that the fields after it have fixed positions
+            (2, 0) if tag == DER_EXPLICIT_0 => self.skipping = len,
+            (2, _) => {
+                self.items[1] += 1;
+                self.skipping = len;
+            }
+            // algorithm of subjectPublicKeyInfo
+            (3, 0) => {
+                self.items[2] += 1;
+                self.skipping = len;
+            }
+            (3, 1) if tag == DER_BIT_STRING && len == P256_SPKI_KEY_LEN => {
+                self.items[2] += 1;
+            }
+            _ => return Err(EDHOCError::ParsingError),
+        }
+        Ok(())
+    }
+
+    fn end_sequences(&mut self) {
+        while self.depth > 0
+            && self.position == self.ends[self.depth - 1]
+            && self.skipping == 0
+            && !self.capturing()
+        {
+            self.depth -= 1;
+            self.complete = self.depth == 0;
+        }
+    }
+}
+
+/// Length of the contents of a DER item from its header, or None if the header is incomplete
+///
+/// Like [der_tlv], this only accepts lengths in their shortest encoding.
+fn der_header_len(header: &[u8]) -> Result<Option<usize>, EDHOCError> {
+    match header {
+        [_] | [_, 0x81] | [_, 0x82] | [_, 0x82, _] => Ok(None),
+        [_, first_len] if *first_len < 0x80 => Ok(Some(*first_len as usize)),
+        [_, 0x81, len] if *len >= 0x80 => Ok(Some(*len as usize)),
+        [_, 0x82, len_high, len_low] if *len_high > 0 => {
+            Ok(Some(u16::from_be_bytes([*len_high, *len_low]) as usize))
+        }
+        _ => Err(EDHOCError::ParsingError),
+    }
+}
+
+/// A CWT that is signed by an issuer, i.e. a CCS wrapped in a COSE_Sign1
+///
+/// Only ES256 signatures are supported, and the payload has to be a CCS as understood by
+/// [CredentialRPK]. The envelope may or may not carry the COSE_Sign1 tag.
+#[derive(Clone, Copy, Debug)]
+pub struct CredentialSignedCWT<'a> {
+    value: &'a [u8],     // the full COSE_Sign1, which is CRED_x
+    protected: &'a [u8], // serialized protected header
+    payload: &'a [u8],   // the CCS
+    signature: BytesP256Signature,
+}
+
+const COSE_SIGN1_TAG: u8 = 18;
+const COSE_HEADER_ALG: u8 = 1;
+const COSE_HEADER_KID: u8 = 4;
+const COSE_ALG_ES256: i8 = -7;
+const SIG_STRUCTURE_CONTEXT: &[u8] = b"Signature1";
+
+impl<'a> CredentialSignedCWT<'a> {
+    /// Parses a COSE_Sign1 without checking its signature
+    ///
+    /// Values longer than [MAX_CREDENTIAL_INPUT_LEN] are rejected with
+    /// [EDHOCError::MessageTooLong].
+    pub fn parse(value: &'a [u8]) -> Result<Self, EDHOCError> {
+        if value.len() > MAX_CREDENTIAL_INPUT_LEN {
+            return Err(EDHOCError::MessageTooLong);
+        }
+        let mut decoder = CBORDecoder::new(value);
+        if CBORDecoder::type_of(decoder.current()?) == CBOR_MAJOR_TAG
+            && decoder.tag()? != COSE_SIGN1_TAG
+        {
+            return Err(EDHOCError::ParsingError);
+        }
+        if decoder.array()? != 4 {
+            return Err(EDHOCError::ParsingError);
+        }
+        let protected = decoder.bytes()?;
+        // the unprotected header may only hold a kid
+        for _ in 0..decoder.map()? {
+            if decoder.u8()? != COSE_HEADER_KID {
+                return Err(EDHOCError::ParsingError);
             }
+            decoder.bytes()?;
+        }
+        let payload = decoder.bytes()?;
+        let signature = decoder.bytes_sized(P256_SIGNATURE_LEN)?;
+        decoder.ensure_finished()?;
+
+        let mut header = CBORDecoder::new(protected);
+        if header.map()? != 1 || header.u8()? != COSE_HEADER_ALG || header.i8()? != COSE_ALG_ES256 {
+            return Err(EDHOCError::ParsingError);
         }
+        header.ensure_finished()?;
+
+        Ok(Self {
+            value,
+            protected,
+            payload,
+            signature: signature.try_into().unwrap(),
+        })
+    }
+
+    /// Checks the signature with the issuer's public key, given by both its coordinates, and
+    /// returns the credential that authenticates with the confirmation key of the CWT.
+    ///
+    /// The value of the returned credential is the whole COSE_Sign1, so that it can be used as the
+    /// expected credential in `credential_check_or_fetch`.
+    pub fn verify<Crypto: crate::Crypto>(
+        &self,
+        crypto: &mut Crypto,
+        issuer_x: &BytesP256ElemLen,
+        issuer_y: &BytesP256ElemLen,
+    ) -> Result<CredentialRPK, EDHOCError> {
+        let mut sig_structure = [0u8; MAX_BUFFER_LEN];
+        let sig_structure_len = self
+            .encode_sig_structure(&mut sig_structure)
+            .map_err(|_| EDHOCError::ParsingError)?;
+
+        if !crypto.p256_ecdsa_verify(
+            issuer_x,
+            issuer_y,
+            &sig_structure[..sig_structure_len],
+            &self.signature,
+        ) {
+            return Err(EDHOCError::CredentialVerificationFailed);
+        }
+
+        let (public_key, kid) = CredentialRPK::parse(self.payload)?;
+        Ok(CredentialRPK {
+            value: EdhocMessageBuffer::new_from_slice(self.value)
+                .map_err(|_| EDHOCError::ParsingError)?,
+            public_key,
+            kid: kid.unwrap_or_default(),
+            has_kid: kid.is_some(),
+        })
+    }
+
+    /// Writes `["Signature1", protected, external_aad: h'', payload]` into `out`, returning its length
+    fn encode_sig_structure(&self, out: &mut [u8]) -> Result<usize, MessageBufferError> {
+        let mut encoder = CBOREncoder::new(out);
+        encoder.array(4)?;
+        encoder.str(SIG_STRUCTURE_CONTEXT)?;
+        encoder.bytes(self.protected)?;
+        encoder.bytes(&[])?;
+        encoder.bytes(self.payload)?;
+        Ok(encoder.position())
     }
 }
 
@@ -89,5 +975,126 @@ mod test {
         assert_eq!(cred.public_key, G_A_TV);
         assert_eq!(cred.kid, ID_CRED_TV[3]);
         assert_eq!(cred.get_id_cred(), ID_CRED_TV);
+        assert!(cred.has_kid);
+    }
+
+    #[test]
+    fn test_kid_bytes() {
+        let cred = CredentialRPK::new(CRED_TV.try_into().unwrap()).unwrap();
+        assert_eq!(cred.kid_bytes(), Some(&ID_CRED_TV[3..]));
+        assert_eq!(
+            CredentialRPK::from_kid(ID_CRED_TV[3]).kid_bytes(),
+            cred.kid_bytes()
+        );
+
+        let x: BytesP256ElemLen = G_A_TV.try_into().unwrap();
+        let cred = CredentialRPK::build_ccs(None, None, &x, None).unwrap();
+        assert_eq!(cred.kid_bytes(), None);
+    }
+
+    #[test]
+    fn test_new_cred_without_kid() {
+        const CRED_NO_KID_TV: &[u8] = &hex!("a2026b6578616d706c652e65647508a101a401022001215820bbc34960526ea4d32e940cad2a234148ddc21791a12afbcbac93622046dd44f02258204519e257236b2a0ce2023f0931f1f386ca7afda64fcde0108c224c51eabf6072");
+
+        let cred = CredentialRPK::new(CRED_NO_KID_TV.try_into().unwrap()).unwrap();
+        assert_eq!(cred.public_key, G_A_TV);
+        assert!(!cred.has_kid);
+        assert_eq!(cred.to_canonical_ccs().unwrap().as_slice(), CRED_NO_KID_TV);
+    }
+
+    #[test]
+    fn test_canonical_ccs() {
+        // CRED_TV with the CCS claims and the COSE_Key parameters in reverse order
+        const CRED_REORDERED_TV: &[u8] = &hex!("a208a101a52258204519e257236b2a0ce2023f0931f1f386ca7afda64fcde0108c224c51eabf6072215820bbc34960526ea4d32e940cad2a234148ddc21791a12afbcbac93622046dd44f020010241320102026b6578616d706c652e656475");
+
+        let cred = CredentialRPK::new(CRED_TV.try_into().unwrap()).unwrap();
+        let cred_reordered = CredentialRPK::new(CRED_REORDERED_TV.try_into().unwrap()).unwrap();
+        assert_eq!(cred_reordered.public_key, G_A_TV);
+        assert_eq!(cred_reordered.kid, ID_CRED_TV[3]);
+        assert_ne!(cred.value, cred_reordered.value);
+
+        let canonical = cred.to_canonical_ccs().unwrap();
+        assert_eq!(canonical, cred_reordered.to_canonical_ccs().unwrap());
+        // the lake-traces credential already is in deterministic encoding
+        assert_eq!(canonical.as_slice(), CRED_TV);
+
+        let reference = CredentialRPK {
+            value: EdhocMessageBuffer::new(),
+            ..cred
+        };
+        assert_eq!(reference.to_canonical_ccs().unwrap().len, 0);
+    }
+
+    #[test]
+    fn test_canonical_ccs_alg() {
+        // CRED_TV with alg ES256 (-7), which sorts between kid (2) and crv (-1)
+        const CRED_ALG_TV: &[u8] = &hex!("a2026b6578616d706c652e65647508a101a6010202413203262001215820bbc34960526ea4d32e940cad2a234148ddc21791a12afbcbac93622046dd44f02258204519e257236b2a0ce2023f0931f1f386ca7afda64fcde0108c224c51eabf6072");
+
+        let cred = CredentialRPK::new(CRED_ALG_TV.try_into().unwrap()).unwrap();
+        assert_eq!(cred.public_key, G_A_TV);
+        assert_eq!(cred.to_canonical_ccs().unwrap().as_slice(), CRED_ALG_TV);
+
+        // ES384 (-35) and RS256 (-257) take more than one byte to encode
+        let y = &CRED_TV[CRED_TV.len() - P256_ELEM_LEN..];
+        for alg in [-7, -35, -257, 1, 24] {
+            let ccs = CcsParts {
+                subject: Some(b"example.edu"),
+                kid: Some(ID_CRED_TV[3]),
+                alg: Some(alg),
+                x: G_A_TV,
+                y: Some(y),
+            };
+            let mut value = EdhocMessageBuffer::new();
+            value.len = ccs.encode(&mut value.content).unwrap();
+
+            let cred = CredentialRPK::new(value).unwrap();
+            assert_eq!(cred.public_key, G_A_TV);
+            assert_eq!(cred.public_key_y().unwrap(), y);
+            assert_eq!(
+                CredentialRPK::parse_ccs(cred.value.as_slice()).unwrap().alg,
+                Some(alg)
+            );
+            assert_eq!(cred.to_canonical_ccs().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_build_ccs() {
+        let x: BytesP256ElemLen = G_A_TV.try_into().unwrap();
+        let y: BytesP256ElemLen = CRED_TV[CRED_TV.len() - P256_ELEM_LEN..].try_into().unwrap();
+
+        let cred = CredentialRPK::build_ccs(Some("example.edu"), Some(ID_CRED_TV[3]), &x, Some(&y))
+            .unwrap();
+        assert_eq!(cred.value.as_slice(), CRED_TV);
+        assert_eq!(cred.kid, ID_CRED_TV[3]);
+
+        let cred = CredentialRPK::build_ccs(None, None, &x, None).unwrap();
+        assert_eq!(cred.public_key, x);
+        assert!(!cred.has_kid);
+
+        let subject = core::str::from_utf8(&[b'a'; MAX_MESSAGE_SIZE_LEN]).unwrap();
+        assert_eq!(
+            CredentialRPK::build_ccs(Some(subject), None, &x, None).unwrap_err(),
+            EDHOCError::InvalidParameter
+        );
+    }
+
+    #[test]
+    fn test_parse_x5chain() {
+        let chain = CredentialX509Chain::parse(&hex!("824201024103")).unwrap();
+        assert_eq!(chain.len(), 2);
+
+        let chain = CredentialX509Chain::parse(&hex!("420102")).unwrap();
+        assert_eq!(chain.len(), 1);
+
+        let chain = CredentialX509Chain::parse(&hex!("80")).unwrap();
+        assert!(chain.is_empty());
+        assert_eq!(
+            chain.leaf_public_key().unwrap_err(),
+            EDHOCError::CredentialVerificationFailed
+        );
+
+        // more certificates than supported
+        assert!(CredentialX509Chain::parse(&hex!("85400000000000")).is_err());
     }
 }
@@ -2,6 +2,64 @@
 
 use super::*;
 
+/// Hash algorithm of a cipher suite, which is also used in its HKDF
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlg {
+    /// SHA-256, as in cipher suites 0 to 6
+    Sha256,
+    /// SHA-384, as in cipher suites 24 and 25
+    Sha384,
+}
+
+impl HashAlg {
+    /// Hash algorithm of a cipher suite from the EDHOC Cipher Suites registry
+    pub fn for_suite(suite: u8) -> Option<Self> {
+        if suite <= 6 {
+            Some(HashAlg::Sha256)
+        } else if suite == 24 || suite == 25 {
+            Some(HashAlg::Sha384)
+        } else {
+            None
+        }
+    }
+
+    /// Length of the digests, which is also that of the PRKs
+    pub fn digest_len(self) -> usize {
+        match self {
+            HashAlg::Sha256 => SHA256_DIGEST_LEN,
+            HashAlg::Sha384 => SHA384_DIGEST_LEN,
+        }
+    }
+}
+
+/// A digest or PRK of any supported hash algorithm
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HashOutput {
+    content: [u8; MAX_HASH_LEN],
+    len: usize,
+}
+
+impl HashOutput {
+    /// Copies a digest, failing with [EDHOCError::InvalidParameter] if it is longer than any
+    /// supported one
+    pub fn new_from_slice(slice: &[u8]) -> Result<Self, EDHOCError> {
+        let mut content = [0u8; MAX_HASH_LEN];
+        let Some(dest) = content.get_mut(..slice.len()) else {
+            return Err(EDHOCError::InvalidParameter);
+        };
+        dest.copy_from_slice(slice);
+        Ok(HashOutput {
+            content,
+            len: slice.len(),
+        })
+    }
+
+    /// The digest, as long as the hash algorithm produces it
+    pub fn as_slice(&self) -> &[u8] {
+        &self.content[..self.len]
+    }
+}
+
 /// Interface between the lakers crate and any implementations of the required crypto primitives.
 ///
 /// Sending cryptographic operations through a trait gives the library the flexibility to use
@@ -16,8 +74,13 @@ use super::*;
 /// remaining options are to wrap a Crypto implementation into interior mutability using the
 /// platform's mutex, or to refactor the main initiator and responder objects into a form where the
 /// cryptography implementation can be taken out and stored separately.
+///
+/// The trait is object safe, so back-ends can also be chosen at runtime through
+/// [ErasedCrypto].
 pub trait Crypto: core::fmt::Debug {
+    /// SHA-256 of the first `message_len` bytes of `message`
     fn sha256_digest(&mut self, message: &BytesMaxBuffer, message_len: usize) -> BytesHashLen;
+    /// HKDF-Expand with SHA-256, of which the first `length` bytes are valid
     fn hkdf_expand(
         &mut self,
         prk: &BytesHashLen,
@@ -25,7 +88,27 @@ pub trait Crypto: core::fmt::Debug {
         info_len: usize,
         length: usize,
     ) -> BytesMaxBuffer;
+    /// HKDF-Extract with SHA-256
     fn hkdf_extract(&mut self, salt: &BytesHashLen, ikm: &BytesP256ElemLen) -> BytesHashLen;
+    /// Like [Crypto::hkdf_expand], but passes the output to `output` in parts, in order, instead
+    /// of returning it in a buffer.
+    ///
+    /// The default implementation expands into a scratch buffer and wipes it afterwards. Back-ends
+    /// that can compute HKDF-Expand one block at a time should override it, so that no more than
+    /// a block of the output is kept in their own memory.
+    fn hkdf_expand_into(
+        &mut self,
+        prk: &BytesHashLen,
+        info: &BytesMaxInfoBuffer,
+        info_len: usize,
+        length: usize,
+        output: &mut dyn FnMut(&[u8]),
+    ) {
+        let mut okm = self.hkdf_expand(prk, info, info_len, length);
+        output(&okm[..length]);
+        wipe(&mut okm);
+    }
+    /// AES-CCM-16-64-128 encryption, returning the ciphertext with its 8 byte tag
     fn aes_ccm_encrypt_tag_8(
         &mut self,
         key: &BytesCcmKeyLen,
@@ -33,6 +116,8 @@ pub trait Crypto: core::fmt::Debug {
         ad: &[u8],
         plaintext: &BufferPlaintext3,
     ) -> BufferCiphertext3;
+    /// AES-CCM-16-64-128 decryption, failing with [EDHOCError::MacVerificationFailed] if the tag
+    /// does not match
     fn aes_ccm_decrypt_tag_8(
         &mut self,
         key: &BytesCcmKeyLen,
@@ -40,11 +125,526 @@ pub trait Crypto: core::fmt::Debug {
         ad: &[u8],
         ciphertext: &BufferCiphertext3,
     ) -> Result<BufferPlaintext3, EDHOCError>;
+    /// ECDH on P-256, returning the x coordinate of the shared point
     fn p256_ecdh(
         &mut self,
         private_key: &BytesP256ElemLen,
         public_key: &BytesP256ElemLen,
     ) -> BytesP256ElemLen;
+    /// Whether AES-CCM currently runs on a hardware engine.
+    ///
+    /// This can change at runtime, e.g. when a crypto peripheral is powered down; see
+    /// [AesCcmDispatch]. Either way, the AES-CCM methods have to produce the same output. The
+    /// default implementation reports software.
+    fn aes_ccm_hardware_available(&mut self) -> bool {
+        false
+    }
+    /// A byte from a cryptographically secure random number generator
     fn get_random_byte(&mut self) -> u8;
+    /// Generate an ephemeral P-256 key pair, returning the private key and the x coordinate of
+    /// the public key.
+    ///
+    /// A back-end that fails to produce a key pair returns all zeros, which lakers rejects with
+    /// [EDHOCError::RngFailure].
     fn p256_generate_key_pair(&mut self) -> (BytesP256ElemLen, BytesP256ElemLen);
+    /// Check whether an x coordinate received from the peer belongs to a point on the P-256 curve.
+    ///
+    /// This has to hold before the key is passed to [Crypto::p256_ecdh]. The default
+    /// implementation accepts every key, leaving the check to back-ends that can perform it.
+    fn p256_validate_public_key(&mut self, public_key: &BytesP256ElemLen) -> bool {
+        let _ = public_key;
+        true
+    }
+    /// Compute the x coordinate of the public key belonging to a P-256 private key.
+    ///
+    /// The default implementation performs ECDH with the curve's generator, whose result is the
+    /// x coordinate of the public key.
+    fn p256_public_from_private(
+        &mut self,
+        private_key: &BytesP256ElemLen,
+    ) -> Result<BytesP256ElemLen, EDHOCError> {
+        const P256_GENERATOR_X: BytesP256ElemLen = [
+            0x6b, 0x17, 0xd1, 0xf2, 0xe1, 0x2c, 0x42, 0x47, 0xf8, 0xbc, 0xe6, 0xe5, 0x63, 0xa4,
+            0x40, 0xf2, 0x77, 0x03, 0x7d, 0x81, 0x2d, 0xeb, 0x33, 0xa0, 0xf4, 0xa1, 0x39, 0x45,
+            0xd8, 0x98, 0xc2, 0x96,
+        ];
+        Ok(self.p256_ecdh(private_key, &P256_GENERATOR_X))
+    }
+    /// Verify an ECDSA P-256 signature (in raw r || s form) with SHA-256 over message.
+    ///
+    /// The public key is given by both its coordinates. Back-ends without signature support can
+    /// rely on the default implementation, which rejects every signature.
+    fn p256_ecdsa_verify(
+        &mut self,
+        public_key_x: &BytesP256ElemLen,
+        public_key_y: &BytesP256ElemLen,
+        message: &[u8],
+        signature: &BytesP256Signature,
+    ) -> bool {
+        let _ = (public_key_x, public_key_y, message, signature);
+        false
+    }
+    /// Sign a message with ECDSA P-256 and SHA-256, returning the signature in raw r || s form.
+    ///
+    /// This is only needed for authentication with signatures (see [Method::initiator_signs]).
+    /// The default implementation fails with [EDHOCError::UnsupportedMethod].
+    fn p256_ecdsa_sign(
+        &mut self,
+        private_key: &BytesP256ElemLen,
+        message: &[u8],
+    ) -> Result<BytesP256Signature, EDHOCError> {
+        let _ = (private_key, message);
+        Err(EDHOCError::UnsupportedMethod)
+    }
+    /// Hash a message with the given algorithm.
+    ///
+    /// The default implementation only supports SHA-256 through [Crypto::sha256_digest], and
+    /// returns [EDHOCError::UnsupportedCipherSuite] for other algorithms.
+    fn hash(&mut self, alg: HashAlg, message: &[u8]) -> Result<HashOutput, EDHOCError> {
+        if alg != HashAlg::Sha256 {
+            return Err(EDHOCError::UnsupportedCipherSuite);
+        }
+        let mut buffer: BytesMaxBuffer = [0x00; MAX_BUFFER_LEN];
+        let Some(dest) = buffer.get_mut(..message.len()) else {
+            return Err(EDHOCError::InvalidParameter);
+        };
+        dest.copy_from_slice(message);
+        HashOutput::new_from_slice(&self.sha256_digest(&buffer, message.len()))
+    }
+    /// HKDF-Extract with the given hash algorithm.
+    ///
+    /// The default implementation only supports SHA-256 with the salt and IKM sizes of
+    /// [Crypto::hkdf_extract].
+    fn hkdf_extract_with(
+        &mut self,
+        alg: HashAlg,
+        salt: &[u8],
+        ikm: &[u8],
+    ) -> Result<HashOutput, EDHOCError> {
+        if alg != HashAlg::Sha256 {
+            return Err(EDHOCError::UnsupportedCipherSuite);
+        }
+        let (Ok(salt), Ok(ikm)) = (salt.try_into(), ikm.try_into()) else {
+            return Err(EDHOCError::InvalidParameter);
+        };
+        HashOutput::new_from_slice(&self.hkdf_extract(salt, ikm))
+    }
+    /// HKDF-Expand with the given hash algorithm, filling all of `okm`.
+    ///
+    /// The default implementation only supports SHA-256 with the sizes of [Crypto::hkdf_expand].
+    fn hkdf_expand_with(
+        &mut self,
+        alg: HashAlg,
+        prk: &[u8],
+        info: &[u8],
+        okm: &mut [u8],
+    ) -> Result<(), EDHOCError> {
+        if alg != HashAlg::Sha256 {
+            return Err(EDHOCError::UnsupportedCipherSuite);
+        }
+        let mut info_buf: BytesMaxInfoBuffer = [0x00; MAX_INFO_LEN];
+        let (Ok(prk), Some(info_dest)) = (prk.try_into(), info_buf.get_mut(..info.len())) else {
+            return Err(EDHOCError::InvalidParameter);
+        };
+        if okm.len() > MAX_BUFFER_LEN {
+            return Err(EDHOCError::InvalidParameter);
+        }
+        info_dest.copy_from_slice(info);
+        let output = self.hkdf_expand(prk, &info_buf, info.len(), okm.len());
+        okm.copy_from_slice(&output[..okm.len()]);
+        Ok(())
+    }
+}
+
+/// Forwards the methods of [Crypto] to another back-end, for wrappers that change the behavior
+/// of a few primitives only
+///
+/// Invoked inside an `impl Crypto for` block, `forward_crypto!(|this| this.inner)` implements
+/// every method as a call to the same method on `this.inner`, where `this` is `self`.
+/// `forward_crypto!(|this| this.inner, except p256_ecdh)` leaves out the listed methods, which
+/// the block implements itself; `only` instead of `except` forwards just the listed ones, which
+/// also serves to implement [AesCcmEngine] from a back-end.
+///
+/// # Examples
+///
+/// ```
+/// use lakers_shared::{forward_crypto, BytesP256ElemLen, Crypto};
+///
+/// /// A back-end whose ephemeral key pairs are all zeros, as if its RNG failed
+/// #[derive(Debug)]
+/// struct FailingRng<C: Crypto>(C);
+///
+/// impl<C: Crypto> Crypto for FailingRng<C> {
+///     fn p256_generate_key_pair(&mut self) -> (BytesP256ElemLen, BytesP256ElemLen) {
+///         ([0; 32], [0; 32])
+///     }
+///     forward_crypto!(|this| this.0, except p256_generate_key_pair);
+/// }
+/// ```
+#[macro_export]
+macro_rules! forward_crypto {
+    (|$this:ident| $inner:expr) => {
+        $crate::forward_crypto!(|$this| $inner, except);
+    };
+    (|$this:ident| $inner:expr, except $($skip:ident),* $(,)?) => {
+        $crate::forward_crypto!(@maybe $this, $inner, sha256_digest, [$($skip)*]);
+        $crate::forward_crypto!(@maybe $this, $inner, hkdf_expand, [$($skip)*]);
+        $crate::forward_crypto!(@maybe $this, $inner, hkdf_extract, [$($skip)*]);
+        $crate::forward_crypto!(@maybe $this, $inner, hkdf_expand_into, [$($skip)*]);
+        $crate::forward_crypto!(@maybe $this, $inner, aes_ccm_encrypt_tag_8, [$($skip)*]);
+        $crate::forward_crypto!(@maybe $this, $inner, aes_ccm_decrypt_tag_8, [$($skip)*]);
+        $crate::forward_crypto!(@maybe $this, $inner, aes_ccm_hardware_available, [$($skip)*]);
+        $crate::forward_crypto!(@maybe $this, $inner, p256_ecdh, [$($skip)*]);
+        $crate::forward_crypto!(@maybe $this, $inner, get_random_byte, [$($skip)*]);
+        $crate::forward_crypto!(@maybe $this, $inner, p256_generate_key_pair, [$($skip)*]);
+        $crate::forward_crypto!(@maybe $this, $inner, p256_validate_public_key, [$($skip)*]);
+        $crate::forward_crypto!(@maybe $this, $inner, p256_public_from_private, [$($skip)*]);
+        $crate::forward_crypto!(@maybe $this, $inner, p256_ecdsa_verify, [$($skip)*]);
+        $crate::forward_crypto!(@maybe $this, $inner, p256_ecdsa_sign, [$($skip)*]);
+        $crate::forward_crypto!(@maybe $this, $inner, hash, [$($skip)*]);
+        $crate::forward_crypto!(@maybe $this, $inner, hkdf_extract_with, [$($skip)*]);
+        $crate::forward_crypto!(@maybe $this, $inner, hkdf_expand_with, [$($skip)*]);
+    };
+    (|$this:ident| $inner:expr, only $($method:ident),+ $(,)?) => {
+        $($crate::forward_crypto!(@method $this, $inner, $method);)+
+    };
+    // a method is forwarded unless it is the head of the list of skipped ones
+    (@maybe $this:ident, $inner:expr, sha256_digest, [sha256_digest $($rest:ident)*]) => {};
+    (@maybe $this:ident, $inner:expr, hkdf_expand, [hkdf_expand $($rest:ident)*]) => {};
+    (@maybe $this:ident, $inner:expr, hkdf_extract, [hkdf_extract $($rest:ident)*]) => {};
+    (@maybe $this:ident, $inner:expr, hkdf_expand_into, [hkdf_expand_into $($rest:ident)*]) => {};
+    (
+        @maybe $this:ident,
+        $inner:expr,
+        aes_ccm_encrypt_tag_8,
+        [aes_ccm_encrypt_tag_8 $($rest:ident)*]
+    ) => {};
+    (
+        @maybe $this:ident,
+        $inner:expr,
+        aes_ccm_decrypt_tag_8,
+        [aes_ccm_decrypt_tag_8 $($rest:ident)*]
+    ) => {};
+    (
+        @maybe $this:ident,
+        $inner:expr,
+        aes_ccm_hardware_available,
+        [aes_ccm_hardware_available $($rest:ident)*]
+    ) => {};
+    (@maybe $this:ident, $inner:expr, p256_ecdh, [p256_ecdh $($rest:ident)*]) => {};
+    (@maybe $this:ident, $inner:expr, get_random_byte, [get_random_byte $($rest:ident)*]) => {};
+    (
+        @maybe $this:ident,
+        $inner:expr,
+        p256_generate_key_pair,
+        [p256_generate_key_pair $($rest:ident)*]
+    ) => {};
+    (
+        @maybe $this:ident,
+        $inner:expr,
+        p256_validate_public_key,
+        [p256_validate_public_key $($rest:ident)*]
+    ) => {};
+    (
+        @maybe $this:ident,
+        $inner:expr,
+        p256_public_from_private,
+        [p256_public_from_private $($rest:ident)*]
+    ) => {};
+    (@maybe $this:ident, $inner:expr, p256_ecdsa_verify, [p256_ecdsa_verify $($rest:ident)*]) => {};
+    (@maybe $this:ident, $inner:expr, p256_ecdsa_sign, [p256_ecdsa_sign $($rest:ident)*]) => {};
+    (@maybe $this:ident, $inner:expr, hash, [hash $($rest:ident)*]) => {};
+    (@maybe $this:ident, $inner:expr, hkdf_extract_with, [hkdf_extract_with $($rest:ident)*]) => {};
+    (@maybe $this:ident, $inner:expr, hkdf_expand_with, [hkdf_expand_with $($rest:ident)*]) => {};
+    (@maybe $this:ident, $inner:expr, $method:ident, [$skip:ident $($rest:ident)*]) => {
+        $crate::forward_crypto!(@maybe $this, $inner, $method, [$($rest)*]);
+    };
+    (@maybe $this:ident, $inner:expr, $method:ident, []) => {
+        $crate::forward_crypto!(@method $this, $inner, $method);
+    };
+    (@method $this:ident, $inner:expr, sha256_digest) => {
+        fn sha256_digest(
+            &mut self,
+            message: &$crate::BytesMaxBuffer,
+            message_len: usize,
+        ) -> $crate::BytesHashLen {
+            let $this = self;
+            $inner.sha256_digest(message, message_len)
+        }
+    };
+    (@method $this:ident, $inner:expr, hkdf_expand) => {
+        fn hkdf_expand(
+            &mut self,
+            prk: &$crate::BytesHashLen,
+            info: &$crate::BytesMaxInfoBuffer,
+            info_len: usize,
+            length: usize,
+        ) -> $crate::BytesMaxBuffer {
+            let $this = self;
+            $inner.hkdf_expand(prk, info, info_len, length)
+        }
+    };
+    (@method $this:ident, $inner:expr, hkdf_extract) => {
+        fn hkdf_extract(
+            &mut self,
+            salt: &$crate::BytesHashLen,
+            ikm: &$crate::BytesP256ElemLen,
+        ) -> $crate::BytesHashLen {
+            let $this = self;
+            $inner.hkdf_extract(salt, ikm)
+        }
+    };
+    (@method $this:ident, $inner:expr, hkdf_expand_into) => {
+        fn hkdf_expand_into(
+            &mut self,
+            prk: &$crate::BytesHashLen,
+            info: &$crate::BytesMaxInfoBuffer,
+            info_len: usize,
+            length: usize,
+            output: &mut dyn FnMut(&[u8]),
+        ) {
+            let $this = self;
+            $inner.hkdf_expand_into(prk, info, info_len, length, output)
+        }
+    };
+    (@method $this:ident, $inner:expr, aes_ccm_encrypt_tag_8) => {
+        fn aes_ccm_encrypt_tag_8(
+            &mut self,
+            key: &$crate::BytesCcmKeyLen,
+            iv: &$crate::BytesCcmIvLen,
+            ad: &[u8],
+            plaintext: &$crate::BufferPlaintext3,
+        ) -> $crate::BufferCiphertext3 {
+            let $this = self;
+            $inner.aes_ccm_encrypt_tag_8(key, iv, ad, plaintext)
+        }
+    };
+    (@method $this:ident, $inner:expr, aes_ccm_decrypt_tag_8) => {
+        fn aes_ccm_decrypt_tag_8(
+            &mut self,
+            key: &$crate::BytesCcmKeyLen,
+            iv: &$crate::BytesCcmIvLen,
+            ad: &[u8],
+            ciphertext: &$crate::BufferCiphertext3,
+        ) -> Result<$crate::BufferPlaintext3, $crate::EDHOCError> {
+            let $this = self;
+            $inner.aes_ccm_decrypt_tag_8(key, iv, ad, ciphertext)
+        }
+    };
+    (@method $this:ident, $inner:expr, aes_ccm_hardware_available) => {
+        fn aes_ccm_hardware_available(&mut self) -> bool {
+            let $this = self;
+            $inner.aes_ccm_hardware_available()
+        }
+    };
+    (@method $this:ident, $inner:expr, p256_ecdh) => {
+        fn p256_ecdh(
+            &mut self,
+            private_key: &$crate::BytesP256ElemLen,
+            public_key: &$crate::BytesP256ElemLen,
+        ) -> $crate::BytesP256ElemLen {
+            let $this = self;
+            $inner.p256_ecdh(private_key, public_key)
+        }
+    };
+    (@method $this:ident, $inner:expr, get_random_byte) => {
+        fn get_random_byte(&mut self) -> u8 {
+            let $this = self;
+            $inner.get_random_byte()
+        }
+    };
+    (@method $this:ident, $inner:expr, p256_generate_key_pair) => {
+        fn p256_generate_key_pair(
+            &mut self,
+        ) -> ($crate::BytesP256ElemLen, $crate::BytesP256ElemLen) {
+            let $this = self;
+            $inner.p256_generate_key_pair()
+        }
+    };
+    (@method $this:ident, $inner:expr, p256_validate_public_key) => {
+        fn p256_validate_public_key(&mut self, public_key: &$crate::BytesP256ElemLen) -> bool {
+            let $this = self;
+            $inner.p256_validate_public_key(public_key)
+        }
+    };
+    (@method $this:ident, $inner:expr, p256_public_from_private) => {
+        fn p256_public_from_private(
+            &mut self,
+            private_key: &$crate::BytesP256ElemLen,
+        ) -> Result<$crate::BytesP256ElemLen, $crate::EDHOCError> {
+            let $this = self;
+            $inner.p256_public_from_private(private_key)
+        }
+    };
+    (@method $this:ident, $inner:expr, p256_ecdsa_verify) => {
+        fn p256_ecdsa_verify(
+            &mut self,
+            public_key_x: &$crate::BytesP256ElemLen,
+            public_key_y: &$crate::BytesP256ElemLen,
+            message: &[u8],
+            signature: &$crate::BytesP256Signature,
+        ) -> bool {
+            let $this = self;
+            $inner.p256_ecdsa_verify(public_key_x, public_key_y, message, signature)
+        }
+    };
+    (@method $this:ident, $inner:expr, p256_ecdsa_sign) => {
+        fn p256_ecdsa_sign(
+            &mut self,
+            private_key: &$crate::BytesP256ElemLen,
+            message: &[u8],
+        ) -> Result<$crate::BytesP256Signature, $crate::EDHOCError> {
+            let $this = self;
+            $inner.p256_ecdsa_sign(private_key, message)
+        }
+    };
+    (@method $this:ident, $inner:expr, hash) => {
+        fn hash(
+            &mut self,
+            alg: $crate::HashAlg,
+            message: &[u8],
+        ) -> Result<$crate::HashOutput, $crate::EDHOCError> {
+            let $this = self;
+            $inner.hash(alg, message)
+        }
+    };
+    (@method $this:ident, $inner:expr, hkdf_extract_with) => {
+        fn hkdf_extract_with(
+            &mut self,
+            alg: $crate::HashAlg,
+            salt: &[u8],
+            ikm: &[u8],
+        ) -> Result<$crate::HashOutput, $crate::EDHOCError> {
+            let $this = self;
+            $inner.hkdf_extract_with(alg, salt, ikm)
+        }
+    };
+    (@method $this:ident, $inner:expr, hkdf_expand_with) => {
+        fn hkdf_expand_with(
+            &mut self,
+            alg: $crate::HashAlg,
+            prk: &[u8],
+            info: &[u8],
+            okm: &mut [u8],
+        ) -> Result<(), $crate::EDHOCError> {
+            let $this = self;
+            $inner.hkdf_expand_with(alg, prk, info, okm)
+        }
+    };
+}
+
+/// A [Crypto] back-end whose type is erased, e.g. to keep initiators and responders with different
+/// back-ends in one collection.
+///
+/// Every [Crypto] implements this, and with the `std` feature, `Box<dyn ErasedCrypto>` implements
+/// [Crypto] again, so that it can be used as in `EdhocInitiator<Box<dyn ErasedCrypto>>`. This
+/// costs a dynamic dispatch for every cryptographic operation.
+pub trait ErasedCrypto: Crypto {}
+
+impl<C: Crypto> ErasedCrypto for C {}
+
+// Crypto has to stay object safe for ErasedCrypto to be usable
+const _: Option<&dyn ErasedCrypto> = None;
+
+#[cfg(feature = "std")]
+impl<C: Crypto + ?Sized> Crypto for std::boxed::Box<C> {
+    forward_crypto!(|this| **this);
+}
+
+/// Overwrites secret bytes with zeros, in a way that the compiler does not optimize out even when
+/// the bytes are not read anymore
+pub fn wipe(secret: &mut [u8]) {
+    zeroize::Zeroize::zeroize(secret);
+}
+
+/// Incremental SHA-256, for inputs that are processed in chunks rather than from one buffer
+///
+/// This is provided by back-ends next to their [Crypto] implementation (as `Sha256Hasher` by the
+/// RustCrypto and PSA ones), and used by
+/// [X509CertificateParseStream](crate::X509CertificateParseStream).
+pub trait Sha256Stream {
+    /// Hashes the next chunk of the input
+    fn update(&mut self, data: &[u8]);
+    /// The digest of all chunks passed so far
+    fn finish(self) -> BytesHashLen;
+}
+
+/// An AES-CCM engine that may or may not be usable at runtime, e.g. a crypto peripheral that is
+/// not enabled on every device
+pub trait AesCcmEngine: core::fmt::Debug {
+    /// Whether the engine can be used right now
+    fn available(&mut self) -> bool;
+    /// Like [Crypto::aes_ccm_encrypt_tag_8]
+    fn aes_ccm_encrypt_tag_8(
+        &mut self,
+        key: &BytesCcmKeyLen,
+        iv: &BytesCcmIvLen,
+        ad: &[u8],
+        plaintext: &BufferPlaintext3,
+    ) -> BufferCiphertext3;
+    /// Like [Crypto::aes_ccm_decrypt_tag_8]
+    fn aes_ccm_decrypt_tag_8(
+        &mut self,
+        key: &BytesCcmKeyLen,
+        iv: &BytesCcmIvLen,
+        ad: &[u8],
+        ciphertext: &BufferCiphertext3,
+    ) -> Result<BufferPlaintext3, EDHOCError>;
+}
+
+/// A [Crypto] back-end that runs AES-CCM on a hardware `engine` whenever it is available, and
+/// falls back to the `software` back-end otherwise
+///
+/// Availability is checked for every operation. All other primitives are taken from `software`.
+#[derive(Debug)]
+pub struct AesCcmDispatch<C: Crypto, E: AesCcmEngine> {
+    /// The back-end of all primitives, and of AES-CCM while the engine is unavailable
+    pub software: C,
+    /// The engine of AES-CCM while it is available
+    pub engine: E,
+}
+
+impl<C: Crypto, E: AesCcmEngine> AesCcmDispatch<C, E> {
+    /// Dispatches AES-CCM between `software` and `engine`
+    pub fn new(software: C, engine: E) -> Self {
+        AesCcmDispatch { software, engine }
+    }
+}
+
+impl<C: Crypto, E: AesCcmEngine> Crypto for AesCcmDispatch<C, E> {
+    fn aes_ccm_encrypt_tag_8(
+        &mut self,
+        key: &BytesCcmKeyLen,
+        iv: &BytesCcmIvLen,
+        ad: &[u8],
+        plaintext: &BufferPlaintext3,
+    ) -> BufferCiphertext3 {
+        if self.aes_ccm_hardware_available() {
+            self.engine.aes_ccm_encrypt_tag_8(key, iv, ad, plaintext)
+        } else {
+            self.software.aes_ccm_encrypt_tag_8(key, iv, ad, plaintext)
+        }
+    }
+    fn aes_ccm_decrypt_tag_8(
+        &mut self,
+        key: &BytesCcmKeyLen,
+        iv: &BytesCcmIvLen,
+        ad: &[u8],
+        ciphertext: &BufferCiphertext3,
+    ) -> Result<BufferPlaintext3, EDHOCError> {
+        if self.aes_ccm_hardware_available() {
+            self.engine.aes_ccm_decrypt_tag_8(key, iv, ad, ciphertext)
+        } else {
+            self.software.aes_ccm_decrypt_tag_8(key, iv, ad, ciphertext)
+        }
+    }
+    fn aes_ccm_hardware_available(&mut self) -> bool {
+        self.engine.available()
+    }
+    forward_crypto!(
+        |this| this.software,
+        except aes_ccm_encrypt_tag_8,
+        aes_ccm_decrypt_tag_8,
+        aes_ccm_hardware_available
+    );
 }
@@ -7,16 +7,23 @@
 //!
 //! [lakers]: https://docs.rs/lakers/
 //! [lakers-ead]: https://docs.rs/lakers-ead/latest/lakers_ead/
-// NOTE: if there is no python-bindings feature, which will be the case for embedded builds,
-//       then the crate will be no_std
-#![cfg_attr(not(feature = "python-bindings"), no_std)]
+// NOTE: if there is neither the python-bindings nor the std feature, which will be the case for
+//       embedded builds, then the crate will be no_std
+#![cfg_attr(not(any(feature = "python-bindings", feature = "std")), no_std)]
+#![warn(missing_docs)]
+
+#[cfg(test)]
+extern crate std;
 
 pub use cbor_decoder::*;
+pub use cbor_encoder::*;
 pub use edhoc_parser::*;
 pub use helpers::*;
 
 mod crypto;
-pub use crypto::Crypto;
+pub use crypto::{
+    wipe, AesCcmDispatch, AesCcmEngine, Crypto, ErasedCrypto, HashAlg, HashOutput, Sha256Stream,
+};
 
 mod cred;
 pub use cred::*;
@@ -25,206 +32,1050 @@ pub use cred::*;
 use pyo3::prelude::*;
 #[cfg(feature = "python-bindings")]
 mod python_bindings;
+#[cfg(feature = "python-bindings")]
+pub use python_bindings::EdhocError;
+
+mod limits;
+pub use limits::*;
 
-// TODO: find a way to configure the buffer size
-// need 128 to handle EAD fields, and 192 for the EAD_1 voucher
-pub const MAX_MESSAGE_SIZE_LEN: usize = 128 + 64;
+/// Longest credential (x5chain or CWT) that is parsed from a slice
+pub const MAX_CREDENTIAL_INPUT_LEN: usize = 2048;
 
+/// Length of an encoded ID_CRED map that holds a one-byte kid, e.g. `{4: h'2b'}`
 pub const ID_CRED_LEN: usize = 4;
+/// Most cipher suites an Initiator can offer in SUITES_I
 pub const SUITES_LEN: usize = 9;
+/// Number of cipher suites this implementation supports, see [EDHOC_SUPPORTED_SUITES]
 pub const SUPPORTED_SUITES_LEN: usize = 1;
-pub const EDHOC_METHOD: u8 = 3u8; // stat-stat is the only supported method
+const _: () = assert!(SUPPORTED_SUITES_LEN > 0 && SUPPORTED_SUITES_LEN <= SUITES_LEN);
+/// The static-static authentication method, as sent in METHOD
+#[deprecated(note = "methods other than static-static are supported, use Method::default()")]
+pub const EDHOC_METHOD: u8 = Method::StaticStatic as u8;
+/// ERR_CODE of an EDHOC error message that carries the cipher suites the Responder supports
+pub const ERR_CODE_WRONG_SELECTED_SUITE: u8 = 2;
+/// Length of a P-256 private key or of the x coordinate of a public key
 pub const P256_ELEM_LEN: usize = 32;
+/// Length of an ECDSA P-256 signature, which is the concatenation of r and s
+pub const P256_SIGNATURE_LEN: usize = 2 * P256_ELEM_LEN;
+/// Prime p of the field P-256 is defined over; coordinates are always less than p
+pub const P256_FIELD_PRIME: BytesP256ElemLen = [
+    0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+];
+/// Length of a SHA-256 digest, which is the hash length of the supported cipher suites
 pub const SHA256_DIGEST_LEN: usize = 32;
+/// Length of a SHA-384 digest
+pub const SHA384_DIGEST_LEN: usize = 48;
+/// Size of the output buffer for any supported hash algorithm
+pub const MAX_HASH_LEN: usize = 64;
+/// Length of an AES-CCM-16-64-128 key
 pub const AES_CCM_KEY_LEN: usize = 16;
+/// Length of an AES-CCM-16-64-128 nonce
 pub const AES_CCM_IV_LEN: usize = 13;
+/// Length of an AES-CCM-16-64-128 authentication tag
 pub const AES_CCM_TAG_LEN: usize = 8;
-pub const MAC_LENGTH: usize = 8; // used for EAD Zeroconf
+/// Length of a MAC in the static DH methods, also used for the voucher of EAD Zeroconf
+pub const MAC_LENGTH: usize = 8;
+/// Length of MAC_2 when the Responder authenticates with a static DH key
 pub const MAC_LENGTH_2: usize = MAC_LENGTH;
+/// Length of MAC_3 when the Initiator authenticates with a static DH key
 pub const MAC_LENGTH_3: usize = MAC_LENGTH_2;
-pub const ENCODED_VOUCHER_LEN: usize = 1 + MAC_LENGTH; // 1 byte for the length of the bstr-encoded voucher
-
-// maximum supported length of connection identifier for R
-pub const MAX_KDF_CONTEXT_LEN: usize = 150;
-pub const MAX_KDF_LABEL_LEN: usize = 15; // for "KEYSTREAM_2"
-pub const MAX_BUFFER_LEN: usize = 256;
+/// Shortest CIPHERTEXT_2: single-byte C_R and ID_CRED_R, and MAC_2 as bstr
+pub const MIN_CIPHERTEXT_2_LEN: usize = 1 + 1 + 1 + MAC_LENGTH_2;
+/// Shortest CIPHERTEXT_3: single-byte ID_CRED_I, MAC_3 as bstr, and the AEAD tag
+pub const MIN_CIPHERTEXT_3_LEN: usize = 1 + 1 + MAC_LENGTH_3 + AES_CCM_TAG_LEN;
+/// Longest Signature_or_MAC_2 and Signature_or_MAC_3, which is that of an ECDSA P-256 signature
+pub const MAX_SIGNATURE_OR_MAC_LEN: usize = P256_SIGNATURE_LEN;
+/// Length of the bstr-encoded voucher: 1 byte for the length, and the MAC
+pub const ENCODED_VOUCHER_LEN: usize = 1 + MAC_LENGTH;
+
+/// Longest label of the EDHOC key derivation, which is "KEYSTREAM_2"
+pub const MAX_KDF_LABEL_LEN: usize = 15;
+
+/// Bound of every loop that draws random values until one is acceptable, after which
+/// [EDHOCError::RngFailure] is returned
+pub const RNG_MAX_RETRIES: usize = 256;
+/// Initial byte of a byte string with a one-byte length
 pub const CBOR_BYTE_STRING: u8 = 0x58u8;
+/// Initial byte of a text string with a one-byte length
 pub const CBOR_TEXT_STRING: u8 = 0x78u8;
+/// Initial byte of an unsigned integer in one following byte
 pub const CBOR_UINT_1BYTE: u8 = 0x18u8;
+/// Initial byte of a negative integer in one following byte
+pub const CBOR_NEG_INT_1BYTE: u8 = 0x38u8;
+/// Encoding of -1, the first negative integer that fits the initial byte
 pub const CBOR_NEG_INT_1BYTE_START: u8 = 0x20u8;
+/// Encoding of -24, the last negative integer that fits the initial byte
 pub const CBOR_NEG_INT_1BYTE_END: u8 = 0x37u8;
+/// Encoding of 0, the first unsigned integer that fits the initial byte
 pub const CBOR_UINT_1BYTE_START: u8 = 0x0u8;
+/// Encoding of 23, the last unsigned integer that fits the initial byte
 pub const CBOR_UINT_1BYTE_END: u8 = 0x17u8;
+/// Major type 3, text string
 pub const CBOR_MAJOR_TEXT_STRING: u8 = 0x60u8;
+/// Major type 2, byte string
 pub const CBOR_MAJOR_BYTE_STRING: u8 = 0x40u8;
+/// Initial byte of a byte string of length 1
+pub const CBOR_BYTE_STRING_1BYTE: u8 = 0x41u8;
+/// Initial byte of a byte string of length 23, the longest whose length fits the initial byte
 pub const CBOR_MAJOR_BYTE_STRING_MAX: u8 = 0x57u8;
+/// Major type 4, array
 pub const CBOR_MAJOR_ARRAY: u8 = 0x80u8;
+/// Initial byte of an array of 23 items, the longest whose length fits the initial byte
 pub const CBOR_MAJOR_ARRAY_MAX: u8 = 0x97u8;
-pub const MAX_INFO_LEN: usize = 2 + SHA256_DIGEST_LEN + // 32-byte digest as bstr
-				            1 + MAX_KDF_LABEL_LEN +     // label <24 bytes as tstr
-						    1 + MAX_KDF_CONTEXT_LEN +   // context <24 bytes as bstr
-						    1; // length as u8
-
-pub const ENC_STRUCTURE_LEN: usize = 8 + 5 + SHA256_DIGEST_LEN; // 8 for ENCRYPT0
+/// Major type 5, map
+pub const CBOR_MAJOR_MAP: u8 = 0xA0u8;
+/// Major type 6, tag
+pub const CBOR_MAJOR_TAG: u8 = 0xC0u8;
+/// Major type 7, simple values and floats
+pub const CBOR_MAJOR_SIMPLE: u8 = 0xE0u8;
+/// The simple value false
+pub const CBOR_FALSE: u8 = 0xF4u8;
+/// The simple value true
+pub const CBOR_TRUE: u8 = 0xF5u8;
+/// The simple value null
+pub const CBOR_NULL: u8 = 0xF6u8;
+/// The simple value undefined
+pub const CBOR_UNDEFINED: u8 = 0xF7u8;
+/// Connection identifier that is the empty byte string, kept in its encoded form like the
+/// single-byte integers used otherwise (which it can not be mistaken for)
+pub const CONN_ID_EMPTY: u8 = CBOR_MAJOR_BYTE_STRING;
+/// Length of the Enc_structure of the EDHOC AEAD, with 8 bytes for "Encrypt0"
+pub const ENC_STRUCTURE_LEN: usize = 8 + 5 + SHA256_DIGEST_LEN;
+
+/// Version of the EDHOC specification that is implemented, which is also draft-ietf-lake-edhoc-23
+pub const EDHOC_SPEC_VERSION: &str = "RFC 9528";
+/// Version of the zero-touch authorization draft that [EAD_ZEROCONF_LABEL] and the voucher
+/// follow; until it is published, its labels are not final
+pub const AUTHZ_DRAFT_VERSION: &str = "draft-ietf-lake-authz-02";
+
+/// The specification versions that are implemented, as `(EDHOC_SPEC_VERSION,
+/// AUTHZ_DRAFT_VERSION)`, e.g. for a device to report them when debugging interoperability
+///
+/// # Examples
+///
+/// ```
+/// let (edhoc, authz) = lakers_shared::spec_versions();
+/// assert_eq!(edhoc, "RFC 9528");
+/// assert!(authz.starts_with("draft-ietf-lake-authz-"));
+/// ```
+pub fn spec_versions() -> (&'static str, &'static str) {
+    (EDHOC_SPEC_VERSION, AUTHZ_DRAFT_VERSION)
+}
 
-pub const MAX_EAD_SIZE_LEN: usize = 64;
-pub const EAD_ZEROCONF_LABEL: u8 = 0x1; // NOTE: in lake-authz-draft-02 it is still TBD1
+/// EAD label of the zero-touch authorization items; in lake-authz-draft-02 it is still TBD1
+pub const EAD_ZEROCONF_LABEL: u16 = 0x1;
+/// Label of K_1 in the info of the zero-touch key derivation
 pub const EAD_ZEROCONF_INFO_K_1_LABEL: u8 = 0x0;
+/// Label of IV_1 in the info of the zero-touch key derivation
 pub const EAD_ZEROCONF_INFO_IV_1_LABEL: u8 = 0x1;
+/// Length of the Enc_structure that encrypts ID_U in EAD_1
 pub const EAD_ZEROCONF_ENC_STRUCTURE_LEN: usize = 2 + 8 + 3;
-
+/// EAD label of the remote attestation items; in draft-ietf-lake-ra it is still TBD
+pub const EAD_RA_LABEL: u16 = 0x2;
+/// EAD label of a credential hint; unregistered, only understood by lakers peers
+pub const EAD_CRED_HINT_LABEL: u16 = 0x17;
+/// EAD label of a freshness timestamp; unregistered, only understood by lakers peers
+pub const EAD_FRESHNESS_LABEL: u16 = 0x18;
+
+/// SUITES_I as offered in message_1, of which the first `suites_i_len` are used
 pub type BytesSuites = [u8; SUITES_LEN];
+/// The cipher suites this implementation supports
 pub type BytesSupportedSuites = [u8; SUPPORTED_SUITES_LEN];
-pub const EDHOC_SUITES: BytesSuites = [0, 1, 2, 3, 4, 5, 6, 24, 25]; // all but private cipher suites
+/// All cipher suites registered for EDHOC, i.e. all but the private ones
+pub const EDHOC_SUITES: BytesSuites = [0, 1, 2, 3, 4, 5, 6, 24, 25];
+/// The cipher suites this implementation supports, in order of preference
 pub const EDHOC_SUPPORTED_SUITES: BytesSupportedSuites = [0x2u8];
 
+/// An empty EAD_2
 pub type BytesEad2 = [u8; 0];
+/// An encoded ID_CRED map with a one-byte kid
 pub type BytesIdCred = [u8; ID_CRED_LEN];
+/// Eight bytes, e.g. the "Encrypt0" context of an Enc_structure
 pub type Bytes8 = [u8; 8];
+/// An AES-CCM key
 pub type BytesCcmKeyLen = [u8; AES_CCM_KEY_LEN];
+/// An AES-CCM nonce
 pub type BytesCcmIvLen = [u8; AES_CCM_IV_LEN];
+/// PLAINTEXT_2, the decrypted content of CIPHERTEXT_2
 pub type BufferPlaintext2 = EdhocMessageBuffer;
+/// PLAINTEXT_3, the decrypted content of CIPHERTEXT_3
 pub type BufferPlaintext3 = EdhocMessageBuffer;
+/// MAC_2 of the static DH methods
 pub type BytesMac2 = [u8; MAC_LENGTH_2];
+/// MAC_3 of the static DH methods
 pub type BytesMac3 = [u8; MAC_LENGTH_3];
+/// An encoded message_1
 pub type BufferMessage1 = EdhocMessageBuffer;
+/// An encoded message_3
 pub type BufferMessage3 = EdhocMessageBuffer;
+/// An encoded message_4
+pub type BufferMessage4 = EdhocMessageBuffer;
+/// CIPHERTEXT_2, PLAINTEXT_2 encrypted with KEYSTREAM_2
 pub type BufferCiphertext2 = EdhocMessageBuffer;
+/// CIPHERTEXT_3, PLAINTEXT_3 encrypted with AES-CCM
 pub type BufferCiphertext3 = EdhocMessageBuffer;
+/// A SHA-256 digest, e.g. a transcript hash or a PRK
 pub type BytesHashLen = [u8; SHA256_DIGEST_LEN];
+/// A P-256 private key or the x coordinate of a public key
 pub type BytesP256ElemLen = [u8; P256_ELEM_LEN];
+/// An ECDSA P-256 signature
+pub type BytesP256Signature = [u8; P256_SIGNATURE_LEN];
+/// An encoded message_2
 pub type BufferMessage2 = EdhocMessageBuffer;
-pub type BytesMaxBuffer = [u8; MAX_BUFFER_LEN];
-pub type BytesMaxContextBuffer = [u8; MAX_KDF_CONTEXT_LEN];
-pub type BytesMaxInfoBuffer = [u8; MAX_INFO_LEN];
+/// A label of the EDHOC key derivation
 pub type BytesMaxLabelBuffeer = [u8; MAX_KDF_LABEL_LEN];
+/// The Enc_structure of the AEAD
 pub type BytesEncStructureLen = [u8; ENC_STRUCTURE_LEN];
 
+/// A MAC of [MAC_LENGTH]
 pub type BytesMac = [u8; MAC_LENGTH];
+/// The bstr-encoded voucher of EAD Zeroconf
 pub type BytesEncodedVoucher = [u8; ENCODED_VOUCHER_LEN];
+/// The value of an EAD item
 pub type EADMessageBuffer = EdhocMessageBuffer; // TODO: make it of size MAX_EAD_SIZE_LEN
 
+/// Errors of the EDHOC implementation
+///
+/// The discriminants are the stable codes of [EDHOCError::code], which are passed through the C
+/// API and the python bindings: existing variants must keep their value, and new variants get a
+/// new one.
 #[repr(C)]
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[non_exhaustive]
 pub enum EDHOCError {
+    /// The credential of the peer is not known, or does not match the expected one
     UnknownPeer = 1,
+    /// MAC_2, MAC_3, a signature or the tag of the AEAD did not verify
     MacVerificationFailed = 2,
+    /// The authentication method is not supported or not allowed
     UnsupportedMethod = 3,
+    /// The selected cipher suite is not supported
     UnsupportedCipherSuite = 4,
+    /// A message or one of its fields is malformed
     ParsingError = 5,
+    /// An EAD label does not fit the encoding
     EadLabelTooLongError = 6,
+    /// An EAD item or field is longer than the buffers hold
     EadTooLongError = 7,
+    /// An EAD item was rejected, e.g. an unknown critical one
     EADError = 8,
+    /// An error without a more specific variant
     UnknownError = 9,
+    /// A credential could not be parsed or verified
+    CredentialVerificationFailed = 10,
+    /// A state was used twice where it may only be used once
+    StateAlreadyUsed = 11,
+    /// An argument is outside of what the function accepts
+    InvalidParameter = 12,
+    /// A key or a public key received from the peer is not valid
+    InvalidKey = 13,
+    /// A credential without kid can not be sent by reference
+    CredentialCannotBeReferenced = 14,
+    /// The random number generator did not produce an acceptable value
+    RngFailure = 15,
+    /// A message was well-formed but followed by more data than the protocol allows
+    TrailingData = 16,
+    /// The peer used a feature the [application profile](https://www.rfc-editor.org/rfc/rfc9528#section-3.9) does not allow
+    ProfileViolation = 17,
+    /// An input was longer than the entry point it was passed to accepts
+    MessageTooLong = 18,
+    /// The peer referenced its credential where local policy requires it to be sent by value
+    CredentialTransferPolicyViolation = 19,
+    /// C_I and C_R were found swapped in the state of a peer
+    ConnectionIdentifierMismatch = 20,
+    /// A connection identifier was not encoded in its canonical form
+    NonCanonicalEncoding = 21,
+    /// A state (e.g. one restored from storage) is not one a handshake can be in
+    InconsistentState = 22,
+    /// A ciphertext was shorter than the shortest plaintext it can carry (and its tag)
+    MessageTooShort = 23,
+}
+
+/// Lower level error an [EDHOCError] originates from
+///
+/// As EDHOCError is a plain C-like enum (it is passed through the C API as an integer), the cause
+/// is not stored but derived from the variant, see [EDHOCError::cause].
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ErrorCause {
+    /// An error of the CBOR decoder
+    Cbor(CBORError),
+    /// An error of an [EdhocMessageBuffer]
+    MessageBuffer(MessageBufferError),
+}
+
+impl From<CBORError> for ErrorCause {
+    fn from(error: CBORError) -> Self {
+        ErrorCause::Cbor(error)
+    }
+}
+
+impl From<MessageBufferError> for ErrorCause {
+    fn from(error: MessageBufferError) -> Self {
+        ErrorCause::MessageBuffer(error)
+    }
+}
+
+impl From<ErrorCause> for EDHOCError {
+    fn from(cause: ErrorCause) -> Self {
+        match cause {
+            ErrorCause::Cbor(error) => error.into(),
+            ErrorCause::MessageBuffer(error) => error.into(),
+        }
+    }
+}
+
+impl EDHOCError {
+    /// Stable numeric code of the error
+    pub fn code(&self) -> u16 {
+        *self as u16
+    }
+
+    /// The error with the given [EDHOCError::code], if any
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lakers_shared::EDHOCError;
+    ///
+    /// let code = EDHOCError::UnknownPeer.code();
+    /// assert_eq!(EDHOCError::from_code(code), Some(EDHOCError::UnknownPeer));
+    /// assert_eq!(EDHOCError::from_code(0), None);
+    /// ```
+    pub fn from_code(code: u16) -> Option<Self> {
+        match code {
+            1 => Some(EDHOCError::UnknownPeer),
+            2 => Some(EDHOCError::MacVerificationFailed),
+            3 => Some(EDHOCError::UnsupportedMethod),
+            4 => Some(EDHOCError::UnsupportedCipherSuite),
+            5 => Some(EDHOCError::ParsingError),
+            6 => Some(EDHOCError::EadLabelTooLongError),
+            7 => Some(EDHOCError::EadTooLongError),
+            8 => Some(EDHOCError::EADError),
+            9 => Some(EDHOCError::UnknownError),
+            10 => Some(EDHOCError::CredentialVerificationFailed),
+            11 => Some(EDHOCError::StateAlreadyUsed),
+            12 => Some(EDHOCError::InvalidParameter),
+            13 => Some(EDHOCError::InvalidKey),
+            14 => Some(EDHOCError::CredentialCannotBeReferenced),
+            15 => Some(EDHOCError::RngFailure),
+            16 => Some(EDHOCError::TrailingData),
+            17 => Some(EDHOCError::ProfileViolation),
+            18 => Some(EDHOCError::MessageTooLong),
+            19 => Some(EDHOCError::CredentialTransferPolicyViolation),
+            20 => Some(EDHOCError::ConnectionIdentifierMismatch),
+            21 => Some(EDHOCError::NonCanonicalEncoding),
+            22 => Some(EDHOCError::InconsistentState),
+            23 => Some(EDHOCError::MessageTooShort),
+            _ => None,
+        }
+    }
+
+    /// The lower level error this error originates from, for variants that have no other origin
+    ///
+    /// Variants that are also raised directly (e.g. [EDHOCError::ParsingError], which is what a
+    /// [CBORError::DecodingError] turns into, but also what many checks of the messages fail
+    /// with) have no cause.
+    pub fn cause(&self) -> Option<ErrorCause> {
+        match self {
+            EDHOCError::NonCanonicalEncoding => {
+                Some(ErrorCause::Cbor(CBORError::NonCanonicalEncoding))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl core::fmt::Display for EDHOCError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "EDHOCError::{:?}", self)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EDHOCError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        // the source has to live as long as the error type, so it is picked from constants
+        match self.cause()? {
+            ErrorCause::Cbor(CBORError::DecodingError) => Some(&CBORError::DecodingError),
+            ErrorCause::Cbor(CBORError::NonCanonicalEncoding) => {
+                Some(&CBORError::NonCanonicalEncoding)
+            }
+            ErrorCause::MessageBuffer(MessageBufferError::SliceTooLong) => {
+                Some(&MessageBufferError::SliceTooLong)
+            }
+            ErrorCause::MessageBuffer(MessageBufferError::BufferAlreadyFull) => {
+                Some(&MessageBufferError::BufferAlreadyFull)
+            }
+            ErrorCause::MessageBuffer(MessageBufferError::InvalidValue) => {
+                Some(&MessageBufferError::InvalidValue)
+            }
+        }
+    }
 }
 
+/// State of the Initiator before message_1
 #[derive(Debug)]
 #[repr(C)]
+#[non_exhaustive]
 pub struct InitiatorStart {
+    /// SUITES_I, of which the first `suites_i_len` are offered, the selected one last
     pub suites_i: BytesSuites,
+    /// Number of offered suites in `suites_i`
     pub suites_i_len: usize,
-    pub x: BytesP256ElemLen,   // ephemeral private key of myself
-    pub g_x: BytesP256ElemLen, // ephemeral public key of myself
+    /// Own ephemeral private key
+    pub x: BytesP256ElemLen,
+    /// Own ephemeral public key
+    pub g_x: BytesP256ElemLen,
+    /// Authentication method sent in message_1
+    pub method: Method,
+}
+
+impl InitiatorStart {
+    /// Fails with [EDHOCError::InvalidParameter] if no or more than [SUITES_LEN] suites are
+    /// offered.
+    pub fn new(
+        x: BytesP256ElemLen,
+        g_x: BytesP256ElemLen,
+        suites_i: &[u8],
+    ) -> Result<Self, EDHOCError> {
+        if suites_i.is_empty() || suites_i.len() > SUITES_LEN {
+            return Err(EDHOCError::InvalidParameter);
+        }
+        let mut suites: BytesSuites = [0x0; SUITES_LEN];
+        suites[..suites_i.len()].copy_from_slice(suites_i);
+        Ok(InitiatorStart {
+            suites_i: suites,
+            suites_i_len: suites_i.len(),
+            x,
+            g_x,
+            method: Method::default(),
+        })
+    }
+
+    /// Offers the [EDHOC_SUPPORTED_SUITES]
+    pub fn with_supported_suites(x: BytesP256ElemLen, g_x: BytesP256ElemLen) -> Self {
+        let mut suites: BytesSuites = [0x0; SUITES_LEN];
+        suites[..SUPPORTED_SUITES_LEN].copy_from_slice(&EDHOC_SUPPORTED_SUITES);
+        InitiatorStart {
+            suites_i: suites,
+            suites_i_len: SUPPORTED_SUITES_LEN,
+            x,
+            g_x,
+            method: Method::default(),
+        }
+    }
 }
 
+/// State of the Responder before message_1
 #[derive(Debug)]
+#[non_exhaustive]
 pub struct ResponderStart {
-    pub y: BytesP256ElemLen,   // ephemeral private key of myself
-    pub g_y: BytesP256ElemLen, // ephemeral public key of myself
+    /// Own ephemeral private key
+    pub y: BytesP256ElemLen,
+    /// Own ephemeral public key
+    pub g_y: BytesP256ElemLen,
+    /// The only method accepted in message_1
+    pub method: Method,
+}
+
+impl ResponderStart {
+    /// Starts with the ephemeral key pair `y`, `g_y` and the default method
+    pub fn new(y: BytesP256ElemLen, g_y: BytesP256ElemLen) -> Self {
+        ResponderStart {
+            y,
+            g_y,
+            method: Method::default(),
+        }
+    }
 }
 
+/// State of the Responder after processing message_1
 #[derive(Default, Debug)]
+#[non_exhaustive]
 pub struct ProcessingM1 {
+    /// Own ephemeral private key
     pub y: BytesP256ElemLen,
+    /// Own ephemeral public key
     pub g_y: BytesP256ElemLen,
+    /// C_I as received in message_1
     pub c_i: u8,
-    pub g_x: BytesP256ElemLen, // ephemeral public key of the initiator
+    /// Ephemeral public key of the Initiator
+    pub g_x: BytesP256ElemLen,
+    /// Hash of message_1, from which TH_2 is computed
     pub h_message_1: BytesHashLen,
+    /// SUITES_I as offered by the Initiator, the selected one last
+    pub suites_i: BytesSuites,
+    /// Number of offered suites in `suites_i`
+    pub suites_i_len: usize,
+    /// Authentication method as received in message_1
+    pub method: Method,
+}
+
+impl ProcessingM1 {
+    /// Assembles the state from its fields
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        y: BytesP256ElemLen,
+        g_y: BytesP256ElemLen,
+        c_i: u8,
+        g_x: BytesP256ElemLen,
+        h_message_1: BytesHashLen,
+        suites_i: BytesSuites,
+        suites_i_len: usize,
+        method: Method,
+    ) -> Self {
+        ProcessingM1 {
+            y,
+            g_y,
+            c_i,
+            g_x,
+            h_message_1,
+            suites_i,
+            suites_i_len,
+            method,
+        }
+    }
 }
 
+/// State of the Initiator after sending message_1
 #[derive(Default, Clone, Debug)]
 #[repr(C)]
+#[non_exhaustive]
 pub struct WaitM2 {
-    pub x: BytesP256ElemLen, // ephemeral private key of the initiator
+    /// Own ephemeral private key
+    pub x: BytesP256ElemLen,
+    /// Own ephemeral public key, as sent in message_1
+    pub g_x: BytesP256ElemLen,
+    /// Hash of message_1, from which TH_2 is computed
     pub h_message_1: BytesHashLen,
+    /// The last of SUITES_I, which message_2 has to be processed with
+    pub selected_suite: u8,
+    /// Authentication method as sent in message_1
+    pub method: Method,
+}
+
+impl WaitM2 {
+    /// Assembles the state from its fields
+    pub fn new(
+        x: BytesP256ElemLen,
+        g_x: BytesP256ElemLen,
+        h_message_1: BytesHashLen,
+        selected_suite: u8,
+        method: Method,
+    ) -> Self {
+        WaitM2 {
+            x,
+            g_x,
+            h_message_1,
+            selected_suite,
+            method,
+        }
+    }
+
+    /// Checks that the state is one that preparing message_1 can have produced, failing with
+    /// [EDHOCError::InconsistentState] otherwise
+    ///
+    /// States that were restored from outside of the library (e.g. through the C API) may be
+    /// corrupt or forged; a zeroed state in particular is caught here, which would otherwise be
+    /// processed as an all-zero ephemeral key and transcript.
+    pub fn check_consistency(&self) -> Result<(), EDHOCError> {
+        let is_zero = |bytes: &[u8]| bytes.iter().all(|byte| *byte == 0);
+        if is_zero(&self.x) || is_zero(&self.g_x) || is_zero(&self.h_message_1) {
+            Err(EDHOCError::InconsistentState)
+        } else {
+            Ok(())
+        }
+    }
 }
 
+/// State of the Responder after sending message_2
 #[derive(Default, Debug)]
+#[non_exhaustive]
 pub struct WaitM3 {
-    pub y: BytesP256ElemLen, // ephemeral private key of the responder
+    /// Own ephemeral private key
+    pub y: BytesP256ElemLen,
+    /// PRK_3e2m, from which the keys of message_3 are derived
     pub prk_3e2m: BytesHashLen,
+    /// TH_3
     pub th_3: BytesHashLen,
+    /// Authentication method of the handshake
+    pub method: Method,
+}
+
+impl WaitM3 {
+    /// Assembles the state from its fields
+    pub fn new(
+        y: BytesP256ElemLen,
+        prk_3e2m: BytesHashLen,
+        th_3: BytesHashLen,
+        method: Method,
+    ) -> Self {
+        WaitM3 {
+            y,
+            prk_3e2m,
+            th_3,
+            method,
+        }
+    }
 }
 
+/// State of the Initiator after parsing message_2, before its MAC or signature is verified
 #[derive(Debug, Default)]
 #[repr(C)]
+#[non_exhaustive]
 pub struct ProcessingM2 {
-    pub mac_2: BytesMac2,
+    /// Signature_or_MAC_2 as received
+    pub mac_2: SignatureOrMac,
+    /// PRK_2e, from which KEYSTREAM_2 was derived
     pub prk_2e: BytesHashLen,
+    /// TH_2
     pub th_2: BytesHashLen,
+    /// Own ephemeral private key
     pub x: BytesP256ElemLen,
+    /// Ephemeral public key of the Responder
     pub g_y: BytesP256ElemLen,
+    /// PLAINTEXT_2, as decrypted
     pub plaintext_2: EdhocMessageBuffer,
+    /// C_R as received
     pub c_r: u8,
-    pub ead_2: Option<EADItem>,
+    /// EAD_2 as received
+    pub ead_2: EadItems,
+    /// The cipher suite of the handshake
+    pub selected_suite: u8,
+    /// Authentication method of the handshake
+    pub method: Method,
 }
 
-#[derive(Default, Debug)]
+impl ProcessingM2 {
+    /// Assembles the state from its fields
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        mac_2: SignatureOrMac,
+        prk_2e: BytesHashLen,
+        th_2: BytesHashLen,
+        x: BytesP256ElemLen,
+        g_y: BytesP256ElemLen,
+        plaintext_2: EdhocMessageBuffer,
+        c_r: u8,
+        ead_2: EadItems,
+        selected_suite: u8,
+        method: Method,
+    ) -> Self {
+        ProcessingM2 {
+            mac_2,
+            prk_2e,
+            th_2,
+            x,
+            g_y,
+            plaintext_2,
+            c_r,
+            ead_2,
+            selected_suite,
+            method,
+        }
+    }
+}
+
+/// State of the Initiator after verifying message_2
+///
+/// It can be used to prepare message_3 exactly once: encrypting a second message_3 would reuse
+/// the same key and IV, so the state keeps track of whether it was used.
+///
+/// With signatures, it holds the Initiator's private key until message_3 is prepared. The key is
+/// left out of the [Debug] output and overwritten with zeros when the state is dropped.
 #[repr(C)]
+#[non_exhaustive]
 pub struct ProcessedM2 {
+    /// PRK_3e2m
     pub prk_3e2m: BytesHashLen,
+    /// PRK_4e3m, from which MAC_3 and the keys of message_3 are derived
     pub prk_4e3m: BytesHashLen,
+    /// TH_3
     pub th_3: BytesHashLen,
+    /// Authentication method of the handshake
+    pub method: Method,
+    i: BytesP256ElemLen, // I's private signature key, only set with signatures
+    used: bool,
+}
+
+impl ProcessedM2 {
+    /// State of an Initiator that authenticates with a static DH key
+    pub fn new(prk_3e2m: BytesHashLen, prk_4e3m: BytesHashLen, th_3: BytesHashLen) -> Self {
+        ProcessedM2 {
+            prk_3e2m,
+            prk_4e3m,
+            th_3,
+            method: Method::StaticStatic,
+            i: Default::default(),
+            used: false,
+        }
+    }
+
+    /// State of an Initiator that signs message_3 with its private key `i`
+    ///
+    /// PRK_4e3m is then PRK_3e2m.
+    pub fn new_signing(prk_3e2m: BytesHashLen, th_3: BytesHashLen, i: BytesP256ElemLen) -> Self {
+        ProcessedM2 {
+            prk_3e2m,
+            prk_4e3m: prk_3e2m,
+            th_3,
+            method: Method::SignatureSignature,
+            i,
+            used: false,
+        }
+    }
+
+    /// The private key message_3 is signed with, see [ProcessedM2::new_signing]
+    pub fn signature_key(&self) -> Option<&BytesP256ElemLen> {
+        self.method.initiator_signs().then_some(&self.i)
+    }
+
+    /// Records that message_3 is being prepared from this state; fails if that happened before.
+    pub fn mark_used(&mut self) -> Result<(), EDHOCError> {
+        if self.used {
+            Err(EDHOCError::StateAlreadyUsed)
+        } else {
+            self.used = true;
+            Ok(())
+        }
+    }
+}
+
+impl core::fmt::Debug for ProcessedM2 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ProcessedM2")
+            .field("prk_3e2m", &self.prk_3e2m)
+            .field("prk_4e3m", &self.prk_4e3m)
+            .field("th_3", &self.th_3)
+            .field("method", &self.method)
+            .field("used", &self.used)
+            .finish_non_exhaustive()
+    }
 }
 
+impl Drop for ProcessedM2 {
+    fn drop(&mut self) {
+        wipe(&mut self.i);
+    }
+}
+
+/// State of the Responder after parsing message_3, before its MAC or signature is verified
 #[derive(Default, Debug)]
+#[non_exhaustive]
 pub struct ProcessingM3 {
-    pub mac_3: BytesMac3,
-    pub y: BytesP256ElemLen, // ephemeral private key of the responder
+    /// Signature_or_MAC_3 as received
+    pub mac_3: SignatureOrMac,
+    /// Own ephemeral private key
+    pub y: BytesP256ElemLen,
+    /// PRK_3e2m
     pub prk_3e2m: BytesHashLen,
+    /// TH_3
     pub th_3: BytesHashLen,
+    /// PLAINTEXT_3, as decrypted
     pub plaintext_3: EdhocMessageBuffer,
-    pub ead_3: Option<EADItem>,
+    /// EAD_3 as received
+    pub ead_3: EadItems,
+    /// Authentication method of the handshake
+    pub method: Method,
+}
+
+impl ProcessingM3 {
+    /// Assembles the state from its fields
+    pub fn new(
+        mac_3: SignatureOrMac,
+        y: BytesP256ElemLen,
+        prk_3e2m: BytesHashLen,
+        th_3: BytesHashLen,
+        plaintext_3: EdhocMessageBuffer,
+        ead_3: EadItems,
+        method: Method,
+    ) -> Self {
+        ProcessingM3 {
+            mac_3,
+            y,
+            prk_3e2m,
+            th_3,
+            plaintext_3,
+            ead_3,
+            method,
+        }
+    }
 }
 
+/// Intermediate state of the Initiator while preparing message_3
 #[derive(Debug)]
+#[non_exhaustive]
 pub struct PreparingM3 {
+    /// PRK_3e2m
     pub prk_3e2m: BytesHashLen,
+    /// PRK_4e3m
     pub prk_4e3m: BytesHashLen,
+    /// TH_3
     pub th_3: BytesHashLen,
+    /// MAC_3 of the static DH methods
     pub mac_3: BytesMac3,
 }
 
+impl PreparingM3 {
+    /// Assembles the state from its fields
+    pub fn new(
+        prk_3e2m: BytesHashLen,
+        prk_4e3m: BytesHashLen,
+        th_3: BytesHashLen,
+        mac_3: BytesMac3,
+    ) -> Self {
+        PreparingM3 {
+            prk_3e2m,
+            prk_4e3m,
+            th_3,
+            mac_3,
+        }
+    }
+}
+
+/// State of either party after a completed handshake, from which keys are exported
 #[derive(Default, Debug)]
 #[repr(C)]
+#[non_exhaustive]
 pub struct Completed {
+    /// PRK_out
     pub prk_out: BytesHashLen,
+    /// PRK_exporter, derived from PRK_out
     pub prk_exporter: BytesHashLen,
+    /// TH_4
+    pub th_4: BytesHashLen,
+    /// PRK_4e3m, which protects message_4
+    pub prk_4e3m: BytesHashLen,
+    /// Whether keys were exported before the Initiator was authenticated
+    pub early_exported: bool,
+}
+
+impl Completed {
+    /// Assembles the state from its fields, with no keys exported early
+    pub fn new(
+        prk_out: BytesHashLen,
+        prk_exporter: BytesHashLen,
+        th_4: BytesHashLen,
+        prk_4e3m: BytesHashLen,
+    ) -> Self {
+        Completed {
+            prk_out,
+            prk_exporter,
+            th_4,
+            prk_4e3m,
+            early_exported: false,
+        }
+    }
+}
+
+/// Authentication method of a handshake, as sent in METHOD of message_1
+///
+/// See [RFC 9528 Section 3.2](https://www.rfc-editor.org/rfc/rfc9528#section-3.2). The names
+/// give the key of the Initiator first. Signatures are ECDSA P-256 with SHA-256 in suite 2.
+///
+/// The traces of [RFC 9529](https://www.rfc-editor.org/rfc/rfc9529) only cover signatures with
+/// EdDSA in suite 0, which is not supported. The ECDSA methods are therefore not checked against
+/// a published trace, only against each other and against test vectors of this crate.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Method {
+    /// Both peers authenticate with signatures
+    SignatureSignature = 0,
+    /// The Initiator authenticates with a signature, the Responder with a static DH key
+    SignatureStatic = 1,
+    /// The Initiator authenticates with a static DH key, the Responder with a signature
+    StaticSignature = 2,
+    /// Both peers authenticate with static DH keys
+    #[default]
+    StaticStatic = 3,
+}
+
+impl Method {
+    /// The method with the given number, if any
+    pub fn from_u8(method: u8) -> Option<Self> {
+        match method {
+            0 => Some(Method::SignatureSignature),
+            1 => Some(Method::SignatureStatic),
+            2 => Some(Method::StaticSignature),
+            3 => Some(Method::StaticStatic),
+            _ => None,
+        }
+    }
+
+    /// Whether the Initiator signs message_3, rather than authenticating with a static DH key
+    pub fn initiator_signs(self) -> bool {
+        matches!(self, Method::SignatureSignature | Method::SignatureStatic)
+    }
+
+    /// Whether the Responder signs message_2, rather than authenticating with a static DH key
+    pub fn responder_signs(self) -> bool {
+        matches!(self, Method::SignatureSignature | Method::StaticSignature)
+    }
+
+    /// Length of MAC_2, which is the hash length when it is signed
+    pub fn mac_length_2(self) -> usize {
+        Self::mac_length(self.responder_signs())
+    }
+
+    /// Length of MAC_3, which is the hash length when it is signed
+    pub fn mac_length_3(self) -> usize {
+        Self::mac_length(self.initiator_signs())
+    }
+
+    /// Length of Signature_or_MAC_2
+    pub fn signature_or_mac_length_2(self) -> usize {
+        Self::signature_or_mac_length(self.responder_signs())
+    }
+
+    /// Length of Signature_or_MAC_3
+    pub fn signature_or_mac_length_3(self) -> usize {
+        Self::signature_or_mac_length(self.initiator_signs())
+    }
+
+    fn mac_length(signed: bool) -> usize {
+        if signed {
+            SHA256_DIGEST_LEN
+        } else {
+            MAC_LENGTH
+        }
+    }
+
+    fn signature_or_mac_length(signed: bool) -> usize {
+        if signed {
+            P256_SIGNATURE_LEN
+        } else {
+            MAC_LENGTH
+        }
+    }
+}
+
+/// Signature_or_MAC_2 or Signature_or_MAC_3 as sent in a plaintext, whose length depends on the
+/// [Method]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct SignatureOrMac {
+    /// Storage, of which the first `len` bytes are valid
+    pub content: [u8; MAX_SIGNATURE_OR_MAC_LEN],
+    /// Length of the signature or MAC
+    pub len: usize,
+}
+
+impl Default for SignatureOrMac {
+    fn default() -> Self {
+        SignatureOrMac {
+            content: [0; MAX_SIGNATURE_OR_MAC_LEN],
+            len: 0,
+        }
+    }
 }
 
+impl SignatureOrMac {
+    /// Copies a signature or MAC, failing with [EDHOCError::ParsingError] if it is longer than
+    /// [MAX_SIGNATURE_OR_MAC_LEN]
+    pub fn new_from_slice(slice: &[u8]) -> Result<Self, EDHOCError> {
+        let mut signature_or_mac = SignatureOrMac::default();
+        let Some(dest) = signature_or_mac.content.get_mut(..slice.len()) else {
+            return Err(EDHOCError::ParsingError);
+        };
+        dest.copy_from_slice(slice);
+        signature_or_mac.len = slice.len();
+        Ok(signature_or_mac)
+    }
+
+    /// The valid bytes
+    pub fn as_slice(&self) -> &[u8] {
+        &self.content[..self.len]
+    }
+}
+
+/// How a party sends its credential in ID_CRED_R or ID_CRED_I
 #[cfg_attr(feature = "python-bindings", pyclass)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 #[repr(C)]
 pub enum CredentialTransfer {
+    /// Only the kid of the credential is sent
     ByReference,
+    /// The whole credential is sent
     ByValue,
+    /// Like ByReference, but always sends the full `{4: kid}` map instead of the compact kid.
+    ///
+    /// This is only meant for interoperability with peers that do not understand the compact form.
+    ByReferenceFullMap,
+    /// ByReference if the credential has a kid, ByValue otherwise.
+    Auto,
 }
 
-#[derive(PartialEq, Debug)]
+/// Errors of [EdhocMessageBuffer] and the other buffers of limited length
+#[derive(PartialEq, Debug, Clone, Copy)]
 #[repr(C)]
 pub enum MessageBufferError {
+    /// The buffer has no room for another byte
     BufferAlreadyFull,
+    /// The slice does not fit the buffer
     SliceTooLong,
+    /// The value has no encoding, e.g. a reserved CBOR simple value
+    InvalidValue,
+}
+
+impl core::fmt::Display for MessageBufferError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "MessageBufferError::{:?}", self)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MessageBufferError {}
+
+impl From<MessageBufferError> for EDHOCError {
+    fn from(error: MessageBufferError) -> Self {
+        match error {
+            MessageBufferError::BufferAlreadyFull | MessageBufferError::SliceTooLong => {
+                EDHOCError::MessageTooLong
+            }
+            MessageBufferError::InvalidValue => EDHOCError::InvalidParameter,
+        }
+    }
 }
 
 /// An owned u8 vector of a limited length
 ///
 /// It is used to represent the various messages in encrypted and in decrypted form, as well as
 /// other data items. Its maximum length is [MAX_MESSAGE_SIZE_LEN].
+///
+/// # Examples
+///
+/// Only the first `len` bytes of `content` are valid. A buffer can be reused, as filling it
+/// replaces what it held before:
+///
+/// ```
+/// use lakers_shared::*;
+///
+/// # fn main() -> Result<(), MessageBufferError> {
+/// let mut buffer = EdhocMessageBuffer::new_from_slice(&[0x03, 0x02])?;
+/// buffer.push(0x58)?;
+/// buffer.extend_from_slice(&[0x20])?;
+/// assert_eq!(buffer.as_slice(), &[0x03, 0x02, 0x58, 0x20]);
+///
+/// buffer.fill_with_slice(&[0x01])?;
+/// assert_eq!(buffer.as_slice(), &[0x01]);
+///
+/// // what does not fit is rejected, and the buffer is left as it was
+/// let too_long = [0x00; MAX_MESSAGE_SIZE_LEN];
+/// assert_eq!(
+///     buffer.extend_from_slice(&too_long),
+///     Err(MessageBufferError::SliceTooLong)
+/// );
+/// assert_eq!(buffer.as_slice(), &[0x01]);
+/// # Ok(())
+/// # }
+/// ```
 #[repr(C)]
 #[derive(PartialEq, Debug, Copy, Clone)]
 pub struct EdhocMessageBuffer {
+    /// Storage, of which the first `len` bytes are valid
     pub content: [u8; MAX_MESSAGE_SIZE_LEN],
+    /// Number of valid bytes
     pub len: usize,
 }
 
@@ -238,6 +1089,16 @@ impl Default for EdhocMessageBuffer {
 }
 
 impl EdhocMessageBuffer {
+    /// An empty buffer
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lakers_shared::EdhocMessageBuffer;
+    ///
+    /// let buffer = EdhocMessageBuffer::new();
+    /// assert!(buffer.as_slice().is_empty());
+    /// ```
     pub fn new() -> Self {
         EdhocMessageBuffer {
             content: [0u8; MAX_MESSAGE_SIZE_LEN],
@@ -245,6 +1106,22 @@ impl EdhocMessageBuffer {
         }
     }
 
+    /// A buffer that holds a copy of `slice`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lakers_shared::*;
+    ///
+    /// let buffer = EdhocMessageBuffer::new_from_slice(&[0x01, 0x02]).unwrap();
+    /// assert_eq!(buffer.len, 2);
+    ///
+    /// let too_long = [0x00; MAX_MESSAGE_SIZE_LEN + 1];
+    /// assert_eq!(
+    ///     EdhocMessageBuffer::new_from_slice(&too_long),
+    ///     Err(MessageBufferError::SliceTooLong)
+    /// );
+    /// ```
     pub fn new_from_slice(slice: &[u8]) -> Result<Self, MessageBufferError> {
         let mut buffer = Self::new();
         if buffer.fill_with_slice(slice).is_ok() {
@@ -254,10 +1131,33 @@ impl EdhocMessageBuffer {
         }
     }
 
+    /// The byte at `index` of `content`, which is not checked against `len`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lakers_shared::*;
+    ///
+    /// let buffer = EdhocMessageBuffer::new_from_slice(&[0x01, 0x02]).unwrap();
+    /// assert_eq!(buffer.get(1), Some(0x02));
+    /// assert_eq!(buffer.get(MAX_MESSAGE_SIZE_LEN), None);
+    /// ```
     pub fn get(self, index: usize) -> Option<u8> {
         self.content.get(index).copied()
     }
 
+    /// Appends a byte
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lakers_shared::*;
+    ///
+    /// let almost_full = [0x00; MAX_MESSAGE_SIZE_LEN - 1];
+    /// let mut buffer = EdhocMessageBuffer::new_from_slice(&almost_full).unwrap();
+    /// assert_eq!(buffer.push(0x01), Ok(()));
+    /// assert_eq!(buffer.push(0x02), Err(MessageBufferError::BufferAlreadyFull));
+    /// ```
     pub fn push(&mut self, item: u8) -> Result<(), MessageBufferError> {
         if self.len < self.content.len() {
             self.content[self.len] = item;
@@ -268,14 +1168,46 @@ impl EdhocMessageBuffer {
         }
     }
 
+    /// The `len` bytes of `content` from `start` on, which are not checked against `self.len`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lakers_shared::*;
+    ///
+    /// let buffer = EdhocMessageBuffer::new_from_slice(&[0x01, 0x02, 0x03]).unwrap();
+    /// assert_eq!(buffer.get_slice(1, 2), Some(&[0x02, 0x03][..]));
+    /// assert_eq!(buffer.get_slice(MAX_MESSAGE_SIZE_LEN, 1), None);
+    /// ```
     pub fn get_slice(&self, start: usize, len: usize) -> Option<&[u8]> {
         self.content.get(start..start + len)
     }
 
+    /// The valid bytes of the buffer
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lakers_shared::EdhocMessageBuffer;
+    ///
+    /// let buffer = EdhocMessageBuffer::new_from_slice(&[0x01, 0x02]).unwrap();
+    /// assert_eq!(buffer.as_slice(), &[0x01, 0x02]);
+    /// ```
     pub fn as_slice(&self) -> &[u8] {
         &self.content[0..self.len]
     }
 
+    /// Replaces the content of the buffer with a copy of `slice`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lakers_shared::EdhocMessageBuffer;
+    ///
+    /// let mut buffer = EdhocMessageBuffer::new_from_slice(&[0x01, 0x02]).unwrap();
+    /// buffer.fill_with_slice(&[0x03]).unwrap();
+    /// assert_eq!(buffer.as_slice(), &[0x03]);
+    /// ```
     pub fn fill_with_slice(&mut self, slice: &[u8]) -> Result<(), MessageBufferError> {
         if slice.len() <= self.content.len() {
             self.len = slice.len();
@@ -286,6 +1218,24 @@ impl EdhocMessageBuffer {
         }
     }
 
+    /// Appends a copy of `slice`, or nothing at all if it does not fit
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lakers_shared::*;
+    ///
+    /// let mut buffer = EdhocMessageBuffer::new_from_slice(&[0x01]).unwrap();
+    /// buffer.extend_from_slice(&[0x02, 0x03]).unwrap();
+    /// assert_eq!(buffer.as_slice(), &[0x01, 0x02, 0x03]);
+    ///
+    /// let too_long = [0x00; MAX_MESSAGE_SIZE_LEN];
+    /// assert_eq!(
+    ///     buffer.extend_from_slice(&too_long),
+    ///     Err(MessageBufferError::SliceTooLong)
+    /// );
+    /// assert_eq!(buffer.len, 3);
+    /// ```
     pub fn extend_from_slice(&mut self, slice: &[u8]) -> Result<(), MessageBufferError> {
         if self.len + slice.len() <= self.content.len() {
             self.content[self.len..self.len + slice.len()].copy_from_slice(slice);
@@ -296,6 +1246,18 @@ impl EdhocMessageBuffer {
         }
     }
 
+    /// A buffer that holds the bytes of a hex string, e.g. of test vectors
+    ///
+    /// Panics if `hex` has other than hex digits, or does not fit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lakers_shared::EdhocMessageBuffer;
+    ///
+    /// let buffer = EdhocMessageBuffer::from_hex("0382");
+    /// assert_eq!(buffer.as_slice(), &[0x03, 0x82]);
+    /// ```
     pub fn from_hex(hex: &str) -> Self {
         let mut buffer = EdhocMessageBuffer::new();
         buffer.len = hex.len() / 2;
@@ -325,16 +1287,21 @@ impl TryInto<EdhocMessageBuffer> for &[u8] {
     }
 }
 
+/// An item of External Authorization Data (EAD), as sent in the EAD field of a message
 #[cfg_attr(feature = "python-bindings", pyclass)]
 #[derive(Clone, Debug)]
 pub struct EADItem {
-    pub label: u8,
+    /// Absolute value of the label; the label is sent negated if the item is critical
+    pub label: u16,
+    /// Whether the receiver has to abort the handshake if it does not understand the item
     pub is_critical: bool,
     // TODO[ead]: have adjustable (smaller) length for this buffer
+    /// The CBOR encoded value, if the item has one
     pub value: Option<EdhocMessageBuffer>,
 }
 
 impl EADItem {
+    /// An item with label 0 and no value, e.g. to fill an array
     pub fn new() -> Self {
         EADItem {
             label: 0,
@@ -342,18 +1309,189 @@ impl EADItem {
             value: None,
         }
     }
+
+    /// Builds an item from a label and its already CBOR encoded value
+    ///
+    /// The value buffer is moved into the item rather than copied. A critical item can not have
+    /// label 0, which has no negative counterpart; [EDHOCError::EadLabelTooLongError] is returned
+    /// for it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lakers_shared::*;
+    ///
+    /// # fn main() -> Result<(), EDHOCError> {
+    /// // h'cccccc'
+    /// let value = EdhocMessageBuffer::new_from_slice(&[0x43, 0xcc, 0xcc, 0xcc])
+    ///     .map_err(|_| EDHOCError::MessageTooLong)?;
+    /// let ead_1 = EADItem::from_parts(1, true, value)?;
+    /// assert_eq!(ead_1.label, 1);
+    ///
+    /// let error = EADItem::from_parts(0, true, value).unwrap_err();
+    /// assert_eq!(error, EDHOCError::EadLabelTooLongError);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_parts(
+        label: u16,
+        is_critical: bool,
+        value: EdhocMessageBuffer,
+    ) -> Result<Self, EDHOCError> {
+        if is_critical && label == 0 {
+            return Err(EDHOCError::EadLabelTooLongError);
+        }
+
+        Ok(EADItem {
+            label,
+            is_critical,
+            value: Some(value),
+        })
+    }
+
+    /// Like [EADItem::from_parts], but copies the encoded value from a slice
+    ///
+    /// A value longer than [MAX_EAD_VALUE_LEN] is rejected with [EDHOCError::EadTooLongError].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lakers_shared::*;
+    ///
+    /// let ead_3 = EADItem::with_value(300, false, &[0x43, 0xcc, 0xcc, 0xcc]).unwrap();
+    /// assert_eq!(ead_3.value.unwrap().len, 4);
+    ///
+    /// let too_long = [0x00; MAX_EAD_VALUE_LEN + 1];
+    /// assert_eq!(
+    ///     EADItem::with_value(300, false, &too_long).unwrap_err(),
+    ///     EDHOCError::EadTooLongError
+    /// );
+    /// ```
+    pub fn with_value(label: u16, is_critical: bool, value: &[u8]) -> Result<Self, EDHOCError> {
+        if check_parameter_len(value, MAX_EAD_VALUE_LEN).is_err() {
+            return Err(EDHOCError::EadTooLongError);
+        }
+        let Ok(value) = EdhocMessageBuffer::new_from_slice(value) else {
+            return Err(EDHOCError::EadTooLongError);
+        };
+        Self::from_parts(label, is_critical, value)
+    }
+}
+
+/// The EAD items of a received EAD field, in the order they were sent
+///
+/// Holds up to [MAX_EAD_ITEMS] items; most messages carry at most one, which [EadItems::first]
+/// returns.
+///
+/// # Examples
+///
+/// ```
+/// use lakers_shared::*;
+///
+/// let mut ead_3 = EadItems::new();
+/// assert!(ead_3.first().is_none());
+/// ead_3.push(EADItem::with_value(1, true, &[0x41, 0x01]).unwrap()).unwrap();
+/// ead_3.push(EADItem::with_value(65000, false, &[0x41, 0x02]).unwrap()).unwrap();
+/// assert_eq!(ead_3.len(), 2);
+/// assert_eq!(ead_3.first().unwrap().label, 1);
+/// assert_eq!(ead_3.iter().map(|item| item.label).last(), Some(65000));
+/// ```
+#[derive(Clone, Debug)]
+pub struct EadItems {
+    items: [EADItem; MAX_EAD_ITEMS],
+    len: usize,
+}
+
+impl Default for EadItems {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EadItems {
+    /// An empty EAD field
+    pub fn new() -> Self {
+        EadItems {
+            items: core::array::from_fn(|_| EADItem::new()),
+            len: 0,
+        }
+    }
+
+    /// Appends an item, or fails with [EDHOCError::EadTooLongError] if [MAX_EAD_ITEMS] are held
+    /// already
+    pub fn push(&mut self, item: EADItem) -> Result<(), EDHOCError> {
+        if self.len == MAX_EAD_ITEMS {
+            return Err(EDHOCError::EadTooLongError);
+        }
+        self.items[self.len] = item;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// The first item, for the common case of an EAD field with a single item
+    pub fn first(&self) -> Option<&EADItem> {
+        self.as_slice().first()
+    }
+
+    /// Number of items
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the EAD field has no items
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The items, in the order they were sent
+    pub fn as_slice(&self) -> &[EADItem] {
+        &self.items[..self.len]
+    }
+
+    /// Iterates over the items, in the order they were sent
+    pub fn iter(&self) -> core::slice::Iter<'_, EADItem> {
+        self.as_slice().iter()
+    }
+}
+
+impl From<Option<EADItem>> for EadItems {
+    fn from(item: Option<EADItem>) -> Self {
+        let mut items = EadItems::new();
+        if let Some(item) = item {
+            items.items[0] = item;
+            items.len = 1;
+        }
+        items
+    }
+}
+
+impl<'a> IntoIterator for &'a EadItems {
+    type Item = &'a EADItem;
+    type IntoIter = core::slice::Iter<'a, EADItem>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
 }
 
 // FIXME: homogenize the two structs below (likey keep only the owned version)
+/// ID_CRED_R or ID_CRED_I as decoded from a plaintext, before it is resolved to a credential
 #[derive(Debug, Clone, Copy)]
 pub enum IdCred<'a> {
+    /// a kid in compact form, i.e. as a one-byte integer whose encoding is the kid, or else as a
+    /// byte string; the kid itself is held either way
     CompactKid(u8),
+    /// a credential sent by value, as its encoded CCS
     FullCredential(&'a [u8]),
+    /// a kid sent as the `{4: kid}` map rather than in compact form
+    KidMap(u8),
 }
 
 mod helpers {
     use super::*;
 
+    /// Encodes the info of the EDHOC key derivation, `(label, context, length)`, returning it and
+    /// its length
     pub fn encode_info(
         label: u8,
         context: &BytesMaxContextBuffer,
@@ -386,49 +1524,78 @@ mod helpers {
 
         (info, info_len)
     }
+
+    /// Compares `a` and `b` in time that only depends on their lengths, not their contents
+    pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+        if a.len() != b.len() {
+            return false;
+        }
+        let difference = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+        // keep the compiler from turning the fold into an early-exit comparison
+        core::hint::black_box(difference) == 0
+    }
+}
+
+/// Well-formed items of unknown meaning that were skipped at the end of a message
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TrailingItems {
+    /// Number of skipped items
+    pub items: usize,
+    /// Number of bytes the skipped items took
+    pub bytes: usize,
 }
 
 // TODO: move to own file (or even to the main crate, once EAD is extracted as an external dependency)
 mod edhoc_parser {
     use super::*;
 
-    pub fn parse_ead(buffer: &[u8]) -> Result<Option<EADItem>, EDHOCError> {
-        // assuming label is a single byte integer (negative or positive)
-        if let Some((&label, tail)) = buffer.split_first() {
-            let label_res = if CBORDecoder::is_u8(label) {
-                // CBOR unsigned integer (0..=23)
-                Ok((label, false))
-            } else if CBORDecoder::is_i8(label) {
-                // CBOR negative integer (-1..=-24)
-                Ok((label - (CBOR_NEG_INT_1BYTE_START - 1), true))
-            } else {
-                Err(EDHOCError::ParsingError)
+    /// Parses the items of an EAD field, each a label that is optionally followed by its value
+    ///
+    /// A value is taken to be a byte string, which is how the registered EAD items encode it; the
+    /// item ends where the byte string does, and the next label may follow. For a value of any
+    /// other type, the item takes the rest of the buffer, as with the single item that was
+    /// supported before. A truncated item is a [EDHOCError::ParsingError], and more than
+    /// [MAX_EAD_ITEMS] items an [EDHOCError::EadTooLongError].
+    pub fn parse_ead(buffer: &[u8]) -> Result<EadItems, EDHOCError> {
+        // the values are copied into message buffers
+        if buffer.len() > MAX_MESSAGE_SIZE_LEN {
+            return Err(EDHOCError::MessageTooLong);
+        }
+        let mut items = EadItems::new();
+        let mut decoder = CBORDecoder::new(buffer);
+        while !decoder.finished() {
+            // a negative label marks a critical item
+            let (label, is_critical) = match decoder.i32() {
+                Ok(label) if label >= 0 && label <= u16::MAX as i32 => (label as u16, false),
+                Ok(label) if label < 0 && label >= -(u16::MAX as i32) => (-label as u16, true),
+                _ => return Err(EDHOCError::ParsingError),
             };
-
-            if let Ok((label, is_critical)) = label_res {
-                let ead_value = if tail.len() > 0 {
-                    // EAD value is present
-                    let mut buffer = EdhocMessageBuffer::new();
-                    buffer.fill_with_slice(tail).unwrap(); // TODO(hax): this *should* not panic due to the buffer sizes passed from upstream functions. can we prove it with hax?
-                    buffer.len = tail.len();
-                    Some(buffer)
-                } else {
-                    None
-                };
-                let ead_item = Some(EADItem {
-                    label,
-                    is_critical,
-                    value: ead_value,
-                });
-                Ok(ead_item)
-            } else {
-                Err(EDHOCError::ParsingError)
-            }
-        } else {
-            Err(EDHOCError::ParsingError)
+            let item = |value: Option<&[u8]>| EADItem {
+                label,
+                is_critical,
+                // fits, as the whole buffer does
+                value: value.map(|value| EdhocMessageBuffer::new_from_slice(value).unwrap()),
+            };
+            let start = decoder.position();
+            let value = match decoder.current().map(CBORDecoder::type_of) {
+                // no value, or the label of the next item follows
+                Err(_) | Ok(CBOR_UINT_1BYTE_START) | Ok(CBOR_NEG_INT_1BYTE_START) => None,
+                Ok(CBOR_MAJOR_BYTE_STRING) => {
+                    decoder.bytes().map_err(|_| EDHOCError::ParsingError)?;
+                    Some(&buffer[start..decoder.position()])
+                }
+                Ok(_) => {
+                    items.push(item(Some(&buffer[start..])))?;
+                    break;
+                }
+            };
+            items.push(item(value))?;
         }
+        Ok(items)
     }
 
+    /// Decodes SUITES_I, given either as a single integer or as an array of at least two, and
+    /// returns the suites, their number and the decoder positioned after them
     pub fn parse_suites_i(
         mut decoder: CBORDecoder,
     ) -> Result<(BytesSuites, usize, CBORDecoder), EDHOCError> {
@@ -459,19 +1626,10 @@ mod edhoc_parser {
         }
     }
 
+    /// Decodes message_1 into METHOD, SUITES_I (and their number), G_X, C_I and EAD_1
     pub fn parse_message_1(
         rcvd_message_1: &BufferMessage1,
-    ) -> Result<
-        (
-            u8,
-            BytesSuites,
-            usize,
-            BytesP256ElemLen,
-            u8,
-            Option<EADItem>,
-        ),
-        EDHOCError,
-    > {
+    ) -> Result<(u8, BytesSuites, usize, BytesP256ElemLen, u8, EadItems), EDHOCError> {
         let mut decoder = CBORDecoder::new(rcvd_message_1.as_slice());
         let method = decoder.u8()?;
 
@@ -479,13 +1637,12 @@ mod edhoc_parser {
             let mut g_x: BytesP256ElemLen = [0x00; P256_ELEM_LEN];
             g_x.copy_from_slice(decoder.bytes_sized(P256_ELEM_LEN)?);
 
-            // consume c_i encoded as single-byte int (we still do not support bstr encoding)
-            let c_i = decoder.int_raw()?;
+            // consume c_i encoded as single-byte int or as the empty bstr (longer bstr identifiers
+            // are not supported)
+            let c_i = decoder.conn_id_raw()?;
 
             // if there is still more to parse, the rest will be the EAD_1
             if rcvd_message_1.len > decoder.position() {
-                // NOTE: since the current implementation only supports one EAD handler,
-                // we assume only one EAD item
                 let ead_res = parse_ead(decoder.remaining_buffer()?);
                 if let Ok(ead_1) = ead_res {
                     Ok((method, suites_i, suites_i_len, g_x, c_i, ead_1))
@@ -493,7 +1650,7 @@ mod edhoc_parser {
                     Err(ead_res.unwrap_err())
                 }
             } else if decoder.finished() {
-                Ok((method, suites_i, suites_i_len, g_x, c_i, None))
+                Ok((method, suites_i, suites_i_len, g_x, c_i, EadItems::new()))
             } else {
                 Err(EDHOCError::ParsingError)
             }
@@ -502,28 +1659,118 @@ mod edhoc_parser {
         }
     }
 
-    pub fn parse_message_2(
-        rcvd_message_2: &BufferMessage2,
-    ) -> Result<(BytesP256ElemLen, BufferCiphertext2), EDHOCError> {
-        // FIXME decode negative integers as well
-        let mut ciphertext_2: BufferCiphertext2 = BufferCiphertext2::new();
+    /// Returns the EAD region of a message_1, i.e. everything after C_I, without interpreting it
+    ///
+    /// This is for forwarding EAD verbatim, e.g. to an external processor. `None` is returned if
+    /// there is no EAD, or if the items before it do not decode.
+    pub fn extract_ead_region(message_1: &[u8]) -> Option<&[u8]> {
+        let mut decoder = CBORDecoder::new(message_1);
+        decoder.u8().ok()?;
+        let (_suites_i, _suites_i_len, mut decoder) = parse_suites_i(decoder).ok()?;
+        decoder.bytes_sized(P256_ELEM_LEN).ok()?;
+        decoder.conn_id_raw().ok()?;
+        non_empty_tail(message_1, decoder.position())
+    }
 
-        let mut decoder = CBORDecoder::new(rcvd_message_2.as_slice());
+    /// Like [extract_ead_region], but for a plaintext_2, where the EAD region follows
+    /// Signature_or_MAC_2
+    pub fn extract_ead_region_plaintext_2(plaintext_2: &[u8]) -> Option<&[u8]> {
+        let mut decoder = CBORDecoder::new(plaintext_2);
+        decoder.conn_id_raw().ok()?;
+        decode_id_cred(&mut decoder).ok()?;
+        decoder.bytes().ok()?;
+        non_empty_tail(plaintext_2, decoder.position())
+    }
 
-        // message_2 consists of 1 bstr element; this element in turn contains the concatenation of g_y and ciphertext_2
-        let decoded = decoder.bytes()?;
-        if decoder.finished() {
-            if let Some(key) = decoded.get(0..P256_ELEM_LEN) {
-                let mut g_y: BytesP256ElemLen = [0x00; P256_ELEM_LEN];
-                g_y.copy_from_slice(key);
-                if let Some(c2) = decoded.get(P256_ELEM_LEN..) {
-                    if ciphertext_2.fill_with_slice(c2).is_ok() {
-                        Ok((g_y, ciphertext_2))
-                    } else {
-                        Err(EDHOCError::ParsingError)
-                    }
+    /// Like [extract_ead_region], but for a plaintext_3, where the EAD region follows
+    /// Signature_or_MAC_3
+    pub fn extract_ead_region_plaintext_3(plaintext_3: &[u8]) -> Option<&[u8]> {
+        let mut decoder = CBORDecoder::new(plaintext_3);
+        decode_id_cred(&mut decoder).ok()?;
+        decoder.bytes().ok()?;
+        non_empty_tail(plaintext_3, decoder.position())
+    }
+
+    fn non_empty_tail(buffer: &[u8], position: usize) -> Option<&[u8]> {
+        buffer.get(position..).filter(|tail| !tail.is_empty())
+    }
+
+    /// Splits a message_1 into the items this implementation knows and well-formed unknown items
+    /// that follow them, returning the length of the known part
+    ///
+    /// Each EAD_1 item is taken to be a label with at most one bstr value here, as in [parse_ead],
+    /// as its end could not be told apart from what follows otherwise.
+    pub fn split_message_1_trailing_items(
+        message_1: &[u8],
+    ) -> Result<(usize, TrailingItems), EDHOCError> {
+        let mut decoder = CBORDecoder::new(message_1);
+        decoder.u8()?;
+        let (_suites_i, _suites_i_len, mut decoder) = parse_suites_i(decoder)?;
+        decoder.bytes_sized(P256_ELEM_LEN)?;
+        decoder.conn_id_raw()?;
+
+        while let Ok(current) = decoder.current() {
+            let major = CBORDecoder::type_of(current);
+            if major != CBOR_UINT_1BYTE_START && major != CBOR_NEG_INT_1BYTE_START {
+                break;
+            }
+            decoder.i32()?;
+            if decoder
+                .current()
+                .is_ok_and(|current| CBORDecoder::type_of(current) == CBOR_MAJOR_BYTE_STRING)
+            {
+                decoder.bytes()?;
+            }
+        }
+        let known_len = decoder.position();
+
+        let mut items = 0;
+        while !decoder.finished() {
+            decoder.skip()?;
+            items += 1;
+        }
+        Ok((
+            known_len,
+            TrailingItems {
+                items,
+                bytes: message_1.len() - known_len,
+            },
+        ))
+    }
+
+    /// Decodes message_2 into G_Y and CIPHERTEXT_2, failing if CIPHERTEXT_2 does not fit a
+    /// [BufferCiphertext2]
+    pub fn parse_message_2(
+        rcvd_message_2: &BufferMessage2,
+    ) -> Result<(BytesP256ElemLen, BufferCiphertext2), EDHOCError> {
+        let mut ciphertext_2: BufferCiphertext2 = BufferCiphertext2::new();
+        let (g_y, c2) = split_message_2(rcvd_message_2.as_slice())?;
+        if ciphertext_2.fill_with_slice(c2).is_ok() {
+            Ok((g_y, ciphertext_2))
+        } else {
+            Err(EDHOCError::ParsingError)
+        }
+    }
+
+    /// Splits message_2 into G_Y and CIPHERTEXT_2, which is borrowed from `message_2` and thus not
+    /// limited by the size of a [BufferCiphertext2]
+    pub fn split_message_2(message_2: &[u8]) -> Result<(BytesP256ElemLen, &[u8]), EDHOCError> {
+        // FIXME decode negative integers as well
+        let mut decoder = CBORDecoder::new(message_2);
+
+        // message_2 consists of 1 bstr element; this element in turn contains the concatenation of g_y and ciphertext_2
+        let decoded = decoder.bytes()?;
+        if !decoder.finished() {
+            return Err(EDHOCError::TrailingData);
+        }
+        if let Some(key) = decoded.get(0..P256_ELEM_LEN) {
+            let mut g_y: BytesP256ElemLen = [0x00; P256_ELEM_LEN];
+            g_y.copy_from_slice(key);
+            if let Some(c2) = decoded.get(P256_ELEM_LEN..) {
+                if c2.len() < MIN_CIPHERTEXT_2_LEN {
+                    Err(EDHOCError::MessageTooShort)
                 } else {
-                    Err(EDHOCError::ParsingError)
+                    Ok((g_y, c2))
                 }
             } else {
                 Err(EDHOCError::ParsingError)
@@ -533,63 +1780,91 @@ mod edhoc_parser {
         }
     }
 
+    /// Removes the outer CBOR byte string that some combined encodings put around message_3
+    ///
+    /// The bare message_3 is a byte string already, so both forms cannot be told apart; the
+    /// caller has to know that `message_3` is wrapped. Fails with [EDHOCError::ParsingError] if
+    /// it is not a single byte string.
+    pub fn unwrap_message_3(message_3: &BufferMessage3) -> Result<BufferMessage3, EDHOCError> {
+        let mut outer = CBORDecoder::new(message_3.as_slice());
+        let content = outer.bytes()?;
+        if !outer.finished() {
+            return Err(EDHOCError::ParsingError);
+        }
+        BufferMessage3::new_from_slice(content).map_err(|_| EDHOCError::ParsingError)
+    }
+
+    fn decode_id_cred<'a>(decoder: &mut CBORDecoder<'a>) -> Result<IdCred<'a>, EDHOCError> {
+        let current = decoder.current()?;
+        // NOTE: if len of bstr is 1, it is a compact kid
+        if CBOR_MAJOR_BYTE_STRING == CBORDecoder::type_of(current)
+            && CBORDecoder::info_of(current) > 1
+        {
+            Ok(IdCred::FullCredential(decoder.bytes()?))
+        } else if CBOR_MAJOR_BYTE_STRING == CBORDecoder::type_of(current) {
+            // a kid that is the encoding of a one-byte integer must have been sent as that integer
+            let kid = decoder.bytes_sized(1)?[0];
+            if CBORDecoder::is_u8(kid) || CBORDecoder::is_i8(kid) {
+                Err(EDHOCError::ParsingError)
+            } else {
+                Ok(IdCred::CompactKid(kid))
+            }
+        } else if CBOR_MAJOR_MAP == CBORDecoder::type_of(current) {
+            // only {4: kid} with a single byte kid is supported
+            if decoder.map()? == 1 && decoder.u8()? == 4 {
+                Ok(IdCred::KidMap(decoder.bytes_sized(1)?[0]))
+            } else {
+                Err(EDHOCError::ParsingError)
+            }
+        } else {
+            Ok(IdCred::CompactKid(decoder.int_raw()?))
+        }
+    }
+
+    /// Decodes PLAINTEXT_2 into C_R, ID_CRED_R, Signature_or_MAC_2 and EAD_2
     pub fn decode_plaintext_2(
         plaintext_2: &BufferCiphertext2,
-    ) -> Result<(u8, IdCred, BytesMac2, Option<EADItem>), EDHOCError> {
-        let mut mac_2: BytesMac2 = [0x00; MAC_LENGTH_2];
-
+    ) -> Result<(u8, IdCred<'_>, SignatureOrMac, EadItems), EDHOCError> {
         let mut decoder = CBORDecoder::new(plaintext_2.as_slice());
 
-        let c_r = decoder.int_raw()?;
+        let c_r = decoder.conn_id_raw()?;
 
-        // NOTE: if len of bstr is 1, it is a compact kid and therefore should have been encoded as int
-        let id_cred_r = if CBOR_MAJOR_BYTE_STRING == CBORDecoder::type_of(decoder.current()?)
-            && CBORDecoder::info_of(decoder.current()?) > 1
-        {
-            IdCred::FullCredential(decoder.bytes()?)
-        } else {
-            IdCred::CompactKid(decoder.int_raw()?)
-        };
+        let id_cred_r = decode_id_cred(&mut decoder)?;
 
-        mac_2[..].copy_from_slice(decoder.bytes_sized(MAC_LENGTH_2)?);
+        // its length is only checked against the method when it is verified
+        let mac_2 = SignatureOrMac::new_from_slice(decoder.bytes()?)?;
 
         // if there is still more to parse, the rest will be the EAD_2
         if plaintext_2.len > decoder.position() {
-            // assume only one EAD item
+            // as plaintext_2 is authenticated, anything else after MAC_2 is a malformed EAD_2
+            // rather than a malformed message
             let ead_res = parse_ead(decoder.remaining_buffer()?);
             if let Ok(ead_2) = ead_res {
                 Ok((c_r, id_cred_r, mac_2, ead_2))
             } else {
-                Err(ead_res.unwrap_err())
+                Err(EDHOCError::EADError)
             }
         } else if decoder.finished() {
-            Ok((c_r, id_cred_r, mac_2, None))
+            Ok((c_r, id_cred_r, mac_2, EadItems::new()))
         } else {
             Err(EDHOCError::ParsingError)
         }
     }
 
+    /// Decodes PLAINTEXT_3 into ID_CRED_I, Signature_or_MAC_3 and EAD_3
+    ///
+    /// See [IdCred] for an example.
     pub fn decode_plaintext_3(
         plaintext_3: &BufferPlaintext3,
-    ) -> Result<(IdCred, BytesMac3, Option<EADItem>), EDHOCError> {
-        let mut mac_3: BytesMac3 = [0x00; MAC_LENGTH_3];
-
+    ) -> Result<(IdCred<'_>, SignatureOrMac, EadItems), EDHOCError> {
         let mut decoder = CBORDecoder::new(plaintext_3.as_slice());
 
-        // NOTE: if len of bstr is 1, then it is a compact kid and therefore should have been encoded as int
-        let id_cred_i = if CBOR_MAJOR_BYTE_STRING == CBORDecoder::type_of(decoder.current()?)
-            && CBORDecoder::info_of(decoder.current()?) > 1
-        {
-            IdCred::FullCredential(decoder.bytes()?)
-        } else {
-            IdCred::CompactKid(decoder.int_raw()?)
-        };
+        let id_cred_i = decode_id_cred(&mut decoder)?;
 
-        mac_3[..].copy_from_slice(decoder.bytes_sized(MAC_LENGTH_3)?);
+        let mac_3 = SignatureOrMac::new_from_slice(decoder.bytes()?)?;
 
         // if there is still more to parse, the rest will be the EAD_3
         if plaintext_3.len > decoder.position() {
-            // assume only one EAD item
             let ead_res = parse_ead(decoder.remaining_buffer()?);
             if let Ok(ead_3) = ead_res {
                 Ok((id_cred_i, mac_3, ead_3))
@@ -597,7 +1872,7 @@ mod edhoc_parser {
                 Err(ead_res.unwrap_err())
             }
         } else if decoder.finished() {
-            Ok((id_cred_i, mac_3, None))
+            Ok((id_cred_i, mac_3, EadItems::new()))
         } else {
             Err(EDHOCError::ParsingError)
         }
@@ -606,21 +1881,54 @@ mod edhoc_parser {
 
 mod cbor_decoder {
     /// Decoder inspired by the [minicbor](https://crates.io/crates/minicbor) crate.
+    ///
+    /// Every decoding method consumes input or fails, so a loop that decodes an item per
+    /// iteration ends after at most as many iterations as there are input bytes, whatever count
+    /// the input announces.
     use super::*;
 
-    #[derive(Debug)]
+    #[cfg(test)]
+    std::thread_local! {
+        /// Number of times the decoders of the current thread accessed their input
+        pub(crate) static DECODER_STEPS: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+    }
+
+    #[cfg(test)]
+    fn count_step() {
+        DECODER_STEPS.with(|steps| steps.set(steps.get() + 1));
+    }
+
+    #[cfg(not(test))]
+    fn count_step() {}
+
+    /// Error of the [CBORDecoder]
+    #[derive(PartialEq, Debug, Clone, Copy)]
     pub enum CBORError {
+        /// The input ended early or does not hold the expected item
         DecodingError,
+        /// The item has a shorter encoding that had to be used
+        NonCanonicalEncoding,
+    }
+
+    impl core::fmt::Display for CBORError {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            write!(f, "CBORError::{:?}", self)
+        }
     }
 
+    #[cfg(feature = "std")]
+    impl std::error::Error for CBORError {}
+
     impl From<CBORError> for EDHOCError {
         fn from(error: CBORError) -> Self {
             match error {
                 CBORError::DecodingError => EDHOCError::ParsingError,
+                CBORError::NonCanonicalEncoding => EDHOCError::NonCanonicalEncoding,
             }
         }
     }
 
+    /// Decoder of the CBOR items of a borrowed buffer, read one after the other
     #[derive(Debug)]
     pub struct CBORDecoder<'a> {
         buf: &'a [u8],
@@ -628,11 +1936,13 @@ mod cbor_decoder {
     }
 
     impl<'a> CBORDecoder<'a> {
+        /// A decoder at the start of `bytes`
         pub fn new(bytes: &'a [u8]) -> Self {
             CBORDecoder { buf: bytes, pos: 0 }
         }
 
         fn read(&mut self) -> Result<u8, CBORError> {
+            count_step();
             if let Some(b) = self.buf.get(self.pos) {
                 self.pos += 1;
                 Ok(*b)
@@ -643,6 +1953,7 @@ mod cbor_decoder {
 
         /// Consume and return *n* bytes starting at the current position.
         fn read_slice(&mut self, n: usize) -> Result<&'a [u8], CBORError> {
+            count_step();
             if let Some(b) = self
                 .pos
                 .checked_add(n)
@@ -655,14 +1966,17 @@ mod cbor_decoder {
             }
         }
 
+        /// Number of bytes consumed so far
         pub fn position(&self) -> usize {
             self.pos
         }
 
+        /// Whether the whole input was consumed
         pub fn finished(&self) -> bool {
             self.pos == self.buf.len()
         }
 
+        /// Fails with [CBORError::DecodingError] unless the whole input was consumed
         pub fn ensure_finished(&self) -> Result<(), CBORError> {
             if self.finished() {
                 Ok(())
@@ -671,6 +1985,7 @@ mod cbor_decoder {
             }
         }
 
+        /// The input that was not consumed yet
         pub fn remaining_buffer(&self) -> Result<&[u8], CBORError> {
             if let Some(buffer) = self.buf.get(self.pos..) {
                 Ok(buffer)
@@ -681,6 +1996,7 @@ mod cbor_decoder {
 
         /// Get the byte at the current position.
         pub fn current(&self) -> Result<u8, CBORError> {
+            count_step();
             if let Some(b) = self.buf.get(self.pos) {
                 Ok(*b)
             } else {
@@ -701,6 +2017,28 @@ mod cbor_decoder {
             }
         }
 
+        /// Decode a `u16` value.
+        pub fn u16(&mut self) -> Result<u16, CBORError> {
+            let n = self.u64()?;
+            u16::try_from(n).map_err(|_| CBORError::DecodingError)
+        }
+
+        /// Decode a `u32` value.
+        pub fn u32(&mut self) -> Result<u32, CBORError> {
+            let n = self.u64()?;
+            u32::try_from(n).map_err(|_| CBORError::DecodingError)
+        }
+
+        /// Decode a `u64` value.
+        pub fn u64(&mut self) -> Result<u64, CBORError> {
+            let b = self.read()?;
+            if CBOR_UINT_1BYTE_START != Self::type_of(b) {
+                Err(CBORError::DecodingError)
+            } else {
+                self.argument(Self::info_of(b))
+            }
+        }
+
         /// Decode an `i8` value.
         pub fn i8(&mut self) -> Result<i8, CBORError> {
             let n = self.read()?;
@@ -717,6 +2055,23 @@ mod cbor_decoder {
             }
         }
 
+        /// Decode an integer of at most 16 bits of magnitude as an `i32` value.
+        pub fn i32(&mut self) -> Result<i32, CBORError> {
+            let b = self.read()?;
+            let n = self.argument(Self::info_of(b))?;
+            if n > u64::from(u16::MAX) {
+                return Err(CBORError::DecodingError);
+            }
+            let n = n as i32;
+            if CBOR_UINT_1BYTE_START == Self::type_of(b) {
+                Ok(n)
+            } else if CBOR_NEG_INT_1BYTE_START == Self::type_of(b) {
+                Ok(-1 - n)
+            } else {
+                Err(CBORError::DecodingError)
+            }
+        }
+
         /// Get the raw `i8` or `u8` value.
         pub fn int_raw(&mut self) -> Result<u8, CBORError> {
             let n = self.read()?;
@@ -727,6 +2082,33 @@ mod cbor_decoder {
             }
         }
 
+        /// Get the raw encoding of a connection identifier: a single-byte int or the empty bstr.
+        ///
+        /// An identifier that is sent as a two-byte int or a one-byte bstr even though it has a
+        /// single-byte int encoding (RFC 9528, Section 3.3.2) fails with
+        /// [CBORError::NonCanonicalEncoding].
+        pub fn conn_id_raw(&mut self) -> Result<u8, CBORError> {
+            let current = self.current()?;
+            if current == CONN_ID_EMPTY {
+                return self.read();
+            }
+            if let (CBOR_UINT_1BYTE | CBOR_NEG_INT_1BYTE, Some(&argument)) =
+                (current, self.buf.get(self.pos + 1))
+            {
+                if argument <= CBOR_UINT_1BYTE_END {
+                    return Err(CBORError::NonCanonicalEncoding);
+                }
+            }
+            if let (CBOR_BYTE_STRING_1BYTE, Some(&byte)) = (current, self.buf.get(self.pos + 1)) {
+                if (CBOR_UINT_1BYTE_START..=CBOR_UINT_1BYTE_END).contains(&byte)
+                    || (CBOR_NEG_INT_1BYTE_START..=CBOR_NEG_INT_1BYTE_END).contains(&byte)
+                {
+                    return Err(CBORError::NonCanonicalEncoding);
+                }
+            }
+            self.int_raw()
+        }
+
         /// Decode a string slice.
         pub fn str(&mut self) -> Result<&'a [u8], CBORError> {
             let b = self.read()?;
@@ -772,12 +2154,131 @@ mod cbor_decoder {
             }
         }
 
-        /// Decode a `u8` value into usize.
+        /// Begin decoding a map, returning its number of entries.
+        pub fn map(&mut self) -> Result<usize, CBORError> {
+            let b = self.read()?;
+            if CBOR_MAJOR_MAP != Self::type_of(b) {
+                Err(CBORError::DecodingError)
+            } else {
+                match Self::info_of(b) {
+                    31 => Err(CBORError::DecodingError), // no support for unknown size maps
+                    n => Ok(self.as_usize(n)?),
+                }
+            }
+        }
+
+        /// Decode the number of a tag (major type 6) below 24; the tagged item follows.
+        pub fn tag(&mut self) -> Result<u8, CBORError> {
+            let b = self.read()?;
+            if CBOR_MAJOR_TAG != Self::type_of(b) || Self::info_of(b) > CBOR_UINT_1BYTE_END {
+                Err(CBORError::DecodingError)
+            } else {
+                Ok(Self::info_of(b))
+            }
+        }
+
+        /// Decode a simple value (major type 7), such as `false` (20) or `undefined` (23).
+        ///
+        /// Floats and the break byte are rejected.
+        pub fn simple(&mut self) -> Result<u8, CBORError> {
+            let b = self.read()?;
+            let info = Self::info_of(b);
+            if CBOR_MAJOR_SIMPLE != Self::type_of(b) {
+                Err(CBORError::DecodingError)
+            } else if info <= CBOR_UINT_1BYTE_END {
+                Ok(info)
+            } else if info == CBOR_UINT_1BYTE {
+                // values below 32 must use the single byte form
+                let n = self.read()?;
+                if n >= 32 {
+                    Ok(n)
+                } else {
+                    Err(CBORError::DecodingError)
+                }
+            } else {
+                Err(CBORError::DecodingError)
+            }
+        }
+
+        /// Decode a `bool` value.
+        pub fn bool(&mut self) -> Result<bool, CBORError> {
+            let n = self.simple()?;
+            if n == CBOR_TRUE - CBOR_MAJOR_SIMPLE {
+                Ok(true)
+            } else if n == CBOR_FALSE - CBOR_MAJOR_SIMPLE {
+                Ok(false)
+            } else {
+                Err(CBORError::DecodingError)
+            }
+        }
+
+        /// Decode a `null` value.
+        pub fn null(&mut self) -> Result<(), CBORError> {
+            if self.simple()? == CBOR_NULL - CBOR_MAJOR_SIMPLE {
+                Ok(())
+            } else {
+                Err(CBORError::DecodingError)
+            }
+        }
+
+        /// Skip one well-formed data item, including everything nested in it, returning the
+        /// number of bytes skipped.
+        ///
+        /// Items of indefinite length are rejected.
+        pub fn skip(&mut self) -> Result<usize, CBORError> {
+            let start = self.pos;
+            // every item takes at least one byte, so more pending items than remaining bytes
+            // can not be well-formed
+            let mut pending: usize = 1;
+            while pending > 0 {
+                pending -= 1;
+                let b = self.read()?;
+                let info = Self::info_of(b);
+                let argument = self.as_usize(info)?;
+                let major = Self::type_of(b);
+                if major == CBOR_MAJOR_BYTE_STRING || major == CBOR_MAJOR_TEXT_STRING {
+                    self.read_slice(argument)?;
+                } else if major == CBOR_MAJOR_ARRAY {
+                    pending = pending.saturating_add(argument);
+                } else if major == CBOR_MAJOR_MAP {
+                    pending = pending.saturating_add(argument.saturating_mul(2));
+                } else if major == CBOR_MAJOR_TAG {
+                    pending += 1;
+                }
+                // integers and simple values consist of their head only
+                if pending > self.buf.len() - self.pos {
+                    return Err(CBORError::DecodingError);
+                }
+            }
+            Ok(self.pos - start)
+        }
+
+        /// Decode the argument of a head whose additional info is `b` into usize.
+        ///
+        /// Supports the immediate, one-, two-, four- and eight-byte forms; an argument that does
+        /// not fit the `usize` of the platform fails with [CBORError::DecodingError].
         pub fn as_usize(&mut self, b: u8) -> Result<usize, CBORError> {
+            let n = self.argument(b)?;
+            usize::try_from(n).map_err(|_| CBORError::DecodingError)
+        }
+
+        /// Decode the argument of a head whose additional info is `b`.
+        fn argument(&mut self, b: u8) -> Result<u64, CBORError> {
             if (0..=0x17).contains(&b) {
-                Ok(usize::from(b))
+                Ok(u64::from(b))
             } else if 0x18 == b {
-                self.read().map(usize::from)
+                self.read().map(u64::from)
+            } else if 0x19 == b {
+                let n = self.read_slice(2)?;
+                Ok(u64::from(u16::from_be_bytes([n[0], n[1]])))
+            } else if 0x1a == b {
+                let n = self.read_slice(4)?;
+                Ok(u64::from(u32::from_be_bytes([n[0], n[1], n[2], n[3]])))
+            } else if 0x1b == b {
+                let n = self.read_slice(8)?;
+                Ok(u64::from_be_bytes([
+                    n[0], n[1], n[2], n[3], n[4], n[5], n[6], n[7],
+                ]))
             } else {
                 Err(CBORError::DecodingError)
             }
@@ -805,6 +2306,267 @@ mod cbor_decoder {
     }
 }
 
+mod cbor_encoder {
+    /// Encoder writing CBOR items into a caller provided buffer, the counterpart of [CBORDecoder].
+    use super::*;
+
+    /// Encoder of CBOR items into a borrowed buffer, written one after the other
+    #[derive(Debug)]
+    pub struct CBOREncoder<'a> {
+        buf: &'a mut [u8],
+        pos: usize,
+    }
+
+    impl<'a> CBOREncoder<'a> {
+        /// An encoder writing from the start of `buf`
+        pub fn new(buf: &'a mut [u8]) -> Self {
+            CBOREncoder { buf, pos: 0 }
+        }
+
+        fn write(&mut self, bytes: &[u8]) -> Result<(), MessageBufferError> {
+            if let Some(out) = self
+                .pos
+                .checked_add(bytes.len())
+                .and_then(|end| self.buf.get_mut(self.pos..end))
+            {
+                out.copy_from_slice(bytes);
+                self.pos += bytes.len();
+                Ok(())
+            } else {
+                Err(MessageBufferError::BufferAlreadyFull)
+            }
+        }
+
+        /// Write the initial byte(s) of an item of the given major type.
+        fn write_head(&mut self, major: u8, value: usize) -> Result<(), MessageBufferError> {
+            if value <= u16::MAX as usize {
+                self.write_argument(major, value as u64)
+            } else {
+                Err(MessageBufferError::SliceTooLong)
+            }
+        }
+
+        /// Write the initial byte(s) of an item of the given major type in the shortest form.
+        fn write_argument(&mut self, major: u8, value: u64) -> Result<(), MessageBufferError> {
+            if value <= CBOR_UINT_1BYTE_END as u64 {
+                self.write(&[major | value as u8])
+            } else if value <= u8::MAX as u64 {
+                self.write(&[major | CBOR_UINT_1BYTE, value as u8])
+            } else if value <= u16::MAX as u64 {
+                self.write(&[major | (CBOR_UINT_1BYTE + 1)])?;
+                self.write(&(value as u16).to_be_bytes())
+            } else if value <= u32::MAX as u64 {
+                self.write(&[major | (CBOR_UINT_1BYTE + 2)])?;
+                self.write(&(value as u32).to_be_bytes())
+            } else {
+                self.write(&[major | (CBOR_UINT_1BYTE + 3)])?;
+                self.write(&value.to_be_bytes())
+            }
+        }
+
+        /// Number of bytes written so far.
+        pub fn position(&self) -> usize {
+            self.pos
+        }
+
+        /// Encode a `u8` value.
+        pub fn u8(&mut self, value: u8) -> Result<(), MessageBufferError> {
+            self.write_head(CBOR_UINT_1BYTE_START, value as usize)
+        }
+
+        /// Encode a `u16` value.
+        pub fn u16(&mut self, value: u16) -> Result<(), MessageBufferError> {
+            self.write_head(CBOR_UINT_1BYTE_START, value as usize)
+        }
+
+        /// Encode a `u32` value.
+        pub fn u32(&mut self, value: u32) -> Result<(), MessageBufferError> {
+            self.write_argument(CBOR_UINT_1BYTE_START, value as u64)
+        }
+
+        /// Encode a `u64` value.
+        pub fn u64(&mut self, value: u64) -> Result<(), MessageBufferError> {
+            self.write_argument(CBOR_UINT_1BYTE_START, value)
+        }
+
+        /// Encode an `i8` value.
+        pub fn i8(&mut self, value: i8) -> Result<(), MessageBufferError> {
+            if value >= 0 {
+                self.write_head(CBOR_UINT_1BYTE_START, value as usize)
+            } else {
+                self.write_head(CBOR_NEG_INT_1BYTE_START, (-1 - value) as usize)
+            }
+        }
+
+        /// Encode an `i32` value of at most 16 bits of magnitude.
+        pub fn i32(&mut self, value: i32) -> Result<(), MessageBufferError> {
+            if value >= 0 {
+                self.write_head(CBOR_UINT_1BYTE_START, value as usize)
+            } else {
+                self.write_head(CBOR_NEG_INT_1BYTE_START, (-1 - value) as usize)
+            }
+        }
+
+        /// Encode a byte slice.
+        pub fn bytes(&mut self, value: &[u8]) -> Result<(), MessageBufferError> {
+            self.write_head(CBOR_MAJOR_BYTE_STRING, value.len())?;
+            self.write(value)
+        }
+
+        /// Begin encoding a byte string of `len` bytes, whose content is then written with
+        /// [CBOREncoder::raw]
+        pub fn bytes_header(&mut self, len: usize) -> Result<(), MessageBufferError> {
+            self.write_head(CBOR_MAJOR_BYTE_STRING, len)
+        }
+
+        /// Write bytes as they are, e.g. items that are already encoded
+        pub fn raw(&mut self, bytes: &[u8]) -> Result<(), MessageBufferError> {
+            self.write(bytes)
+        }
+
+        /// Encode a text string given as its UTF-8 bytes.
+        pub fn str(&mut self, value: &[u8]) -> Result<(), MessageBufferError> {
+            self.write_head(CBOR_MAJOR_TEXT_STRING, value.len())?;
+            self.write(value)
+        }
+
+        /// Begin encoding an array of `len` items.
+        pub fn array(&mut self, len: usize) -> Result<(), MessageBufferError> {
+            self.write_head(CBOR_MAJOR_ARRAY, len)
+        }
+
+        /// Begin encoding a map of `len` entries.
+        pub fn map(&mut self, len: usize) -> Result<(), MessageBufferError> {
+            self.write_head(CBOR_MAJOR_MAP, len)
+        }
+
+        /// Encode a simple value; the reserved values 24 to 31 can not be encoded.
+        pub fn simple(&mut self, value: u8) -> Result<(), MessageBufferError> {
+            if value > CBOR_UINT_1BYTE_END && value < 32 {
+                return Err(MessageBufferError::InvalidValue);
+            }
+            self.write_head(CBOR_MAJOR_SIMPLE, value as usize)
+        }
+
+        /// Encode a `bool` value.
+        pub fn bool(&mut self, value: bool) -> Result<(), MessageBufferError> {
+            self.write(&[if value { CBOR_TRUE } else { CBOR_FALSE }])
+        }
+
+        /// Encode a `null` value.
+        pub fn null(&mut self) -> Result<(), MessageBufferError> {
+            self.write(&[CBOR_NULL])
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_error {
+    use super::*;
+
+    /// Lists all variants, failing to compile when one is missing from the list
+    macro_rules! all_variants {
+        ($($variant:ident),* $(,)?) => {{
+            fn _exhaustive(error: EDHOCError) {
+                match error {
+                    $(EDHOCError::$variant => ()),*
+                }
+            }
+            [$(EDHOCError::$variant),*]
+        }};
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let all = all_variants![
+            UnknownPeer,
+            MacVerificationFailed,
+            UnsupportedMethod,
+            UnsupportedCipherSuite,
+            ParsingError,
+            EadLabelTooLongError,
+            EadTooLongError,
+            EADError,
+            UnknownError,
+            CredentialVerificationFailed,
+            StateAlreadyUsed,
+            InvalidParameter,
+            InvalidKey,
+            CredentialCannotBeReferenced,
+            RngFailure,
+            TrailingData,
+            ProfileViolation,
+            MessageTooLong,
+            CredentialTransferPolicyViolation,
+            ConnectionIdentifierMismatch,
+            NonCanonicalEncoding,
+            InconsistentState,
+            MessageTooShort,
+        ];
+
+        for (i, error) in all.iter().enumerate() {
+            assert_eq!(EDHOCError::from_code(error.code()), Some(*error));
+            for other in &all[i + 1..] {
+                assert_ne!(error.code(), other.code());
+            }
+        }
+        assert_eq!(EDHOCError::ParsingError.code(), 5);
+        assert_eq!(EDHOCError::from_code(0), None);
+
+        // a cause is only reported where converting it gives that very error back
+        for error in all {
+            if let Some(cause) = error.cause() {
+                assert_eq!(EDHOCError::from(cause), error);
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_conversions() {
+        assert_eq!(
+            EDHOCError::from(CBORError::DecodingError),
+            EDHOCError::ParsingError
+        );
+        assert_eq!(
+            EDHOCError::from(MessageBufferError::SliceTooLong),
+            EDHOCError::MessageTooLong
+        );
+        assert_eq!(
+            EDHOCError::from(MessageBufferError::InvalidValue),
+            EDHOCError::InvalidParameter
+        );
+        assert_eq!(
+            ErrorCause::from(CBORError::NonCanonicalEncoding),
+            ErrorCause::Cbor(CBORError::NonCanonicalEncoding)
+        );
+        assert_eq!(
+            EDHOCError::from(ErrorCause::from(MessageBufferError::BufferAlreadyFull)),
+            EDHOCError::MessageTooLong
+        );
+
+        // ParsingError is raised by checks that have nothing to do with CBOR as well
+        assert_eq!(EDHOCError::ParsingError.cause(), None);
+        assert_eq!(
+            EDHOCError::NonCanonicalEncoding.cause(),
+            Some(ErrorCause::Cbor(CBORError::NonCanonicalEncoding))
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_spec_versions {
+    use super::*;
+
+    #[test]
+    fn test_spec_versions() {
+        // EDHOC as published, and the authz draft whose TBD labels are filled with guesses
+        assert_eq!(spec_versions(), ("RFC 9528", "draft-ietf-lake-authz-02"));
+        for version in [EDHOC_SPEC_VERSION, AUTHZ_DRAFT_VERSION] {
+            assert!(!version.is_empty());
+        }
+    }
+}
+
 #[cfg(test)]
 mod test_cbor_decoder {
     use super::cbor_decoder::*;
@@ -820,4 +2582,335 @@ mod test_cbor_decoder {
         assert_eq!([0x68, 0x69], decoder.str().unwrap()); // "hi"
         assert_eq!([0xFE, 0xFE], decoder.bytes().unwrap());
     }
+
+    #[test]
+    fn test_cbor_decoder_conn_id() {
+        // CBOR sequence: 5, -24, h'', h'05'
+        let input = [0x05, 0x37, 0x40, 0x41, 0x05];
+        let mut decoder = CBORDecoder::new(&input);
+
+        assert_eq!(0x05, decoder.conn_id_raw().unwrap());
+        assert_eq!(0x37, decoder.conn_id_raw().unwrap());
+        assert_eq!(0x40, decoder.conn_id_raw().unwrap());
+        assert_eq!(decoder.conn_id_raw(), Err(CBORError::NonCanonicalEncoding));
+
+        // 5 and -24 with an argument byte, and h'37'
+        for input in [[0x18, 0x05], [0x38, 0x17], [0x41, 0x37]] {
+            assert_eq!(
+                CBORDecoder::new(&input).conn_id_raw(),
+                Err(CBORError::NonCanonicalEncoding)
+            );
+        }
+        // identifiers without a single-byte encoding are not supported, which is not a matter of
+        // canonical form
+        for input in [[0x18, 0x18], [0x38, 0x18], [0x41, 0x18]] {
+            assert_eq!(
+                CBORDecoder::new(&input).conn_id_raw(),
+                Err(CBORError::DecodingError)
+            );
+        }
+    }
+
+    #[test]
+    fn test_cbor_decoder_tag() {
+        // CBOR sequence: 18([]), 24(h'')
+        let input = [0xD2, 0x80, 0xD8, 0x18, 0x40];
+        let mut decoder = CBORDecoder::new(&input);
+
+        assert_eq!(18, decoder.tag().unwrap());
+        assert_eq!(0, decoder.array().unwrap());
+        // only tags encoded in a single byte are supported
+        assert!(decoder.tag().is_err());
+    }
+
+    #[test]
+    fn test_cbor_decoder_simple() {
+        // CBOR sequence: true, false, null, undefined, simple(255)
+        let input = [0xF5, 0xF4, 0xF6, 0xF7, 0xF8, 0xFF];
+        let mut decoder = CBORDecoder::new(&input);
+
+        assert!(decoder.bool().unwrap());
+        assert!(!decoder.bool().unwrap());
+        assert!(decoder.null().is_ok());
+        assert_eq!(23, decoder.simple().unwrap()); // undefined
+        assert_eq!(255, decoder.simple().unwrap());
+        assert!(decoder.finished());
+
+        // null is not a bool
+        assert!(CBORDecoder::new(&[0xF6]).bool().is_err());
+        // half float 1.0
+        assert!(CBORDecoder::new(&[0xF9, 0x3C, 0x00]).simple().is_err());
+        // break
+        assert!(CBORDecoder::new(&[0xFF]).simple().is_err());
+        // two-byte form of a value below 32
+        assert!(CBORDecoder::new(&[0xF8, 0x15]).simple().is_err());
+    }
+
+    #[test]
+    fn test_cbor_decoder_two_byte_length() {
+        // h'...' of 300 bytes, then 0
+        let mut input = [0xAA; 3 + 300 + 1];
+        input[..3].copy_from_slice(&[0x59, 0x01, 0x2C]);
+        input[303] = 0x00;
+        let mut decoder = CBORDecoder::new(&input);
+
+        assert_eq!(300, decoder.bytes().unwrap().len());
+        assert_eq!(0, decoder.u8().unwrap());
+        assert!(decoder.finished());
+
+        // the announced length exceeds the input
+        let mut decoder = CBORDecoder::new(&input[..302]);
+        assert!(decoder.bytes().is_err());
+    }
+
+    #[test]
+    fn test_cbor_decoder_multi_byte_uint() {
+        // RFC 8949, Appendix A: 1000, 1000000, 1000000000000, 18446744073709551615
+        let input = [
+            0x19, 0x03, 0xE8, 0x1A, 0x00, 0x0F, 0x42, 0x40, 0x1B, 0x00, 0x00, 0x00, 0xE8, 0xD4,
+            0xA5, 0x10, 0x00, 0x1B, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+        ];
+        let mut decoder = CBORDecoder::new(&input);
+        assert_eq!(1000, decoder.u16().unwrap());
+        assert_eq!(1000000, decoder.u32().unwrap());
+        assert_eq!(1000000000000, decoder.u64().unwrap());
+        assert_eq!(u64::MAX, decoder.u64().unwrap());
+        assert!(decoder.finished());
+
+        // the narrower methods fail on values they can not hold, as does u8 on anything longer
+        // than its own forms
+        assert!(CBORDecoder::new(&input[3..]).u16().is_err());
+        assert!(CBORDecoder::new(&input[8..]).u32().is_err());
+        assert!(CBORDecoder::new(&input).u8().is_err());
+        // -1, and a truncated argument
+        assert!(CBORDecoder::new(&[0x20]).u64().is_err());
+        assert!(CBORDecoder::new(&input[3..7]).u32().is_err());
+
+        // lengths in the four- and eight-byte forms
+        let mut decoder = CBORDecoder::new(&[0x5A, 0x00, 0x00, 0x00, 0x01, 0xAA]);
+        assert_eq!([0xAA], decoder.bytes().unwrap());
+        let mut decoder = CBORDecoder::new(&[0x9B, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x00]);
+        assert_eq!(1, decoder.array().unwrap());
+        assert_eq!(
+            CBORDecoder::new(&input[17..]).as_usize(0x1B).is_ok(),
+            usize::BITS == 64
+        );
+        // a length beyond the input
+        assert!(CBORDecoder::new(&[0x5A, 0x00, 0x01, 0x00, 0x00, 0xAA])
+            .bytes()
+            .is_err());
+    }
+
+    #[test]
+    fn test_cbor_decoder_skip() {
+        // CBOR sequence: 1000000, {1: [h'aa', "b"]}, 1(-1.5), 0
+        let input = [
+            0x1A, 0x00, 0x0F, 0x42, 0x40, 0xA1, 0x01, 0x82, 0x41, 0xAA, 0x61, 0x62, 0xC1, 0xF9,
+            0xBE, 0x00, 0x00,
+        ];
+        let mut decoder = CBORDecoder::new(&input);
+
+        assert_eq!(5, decoder.skip().unwrap());
+        assert_eq!(7, decoder.skip().unwrap());
+        assert_eq!(4, decoder.skip().unwrap());
+        assert_eq!(0, decoder.u8().unwrap());
+        assert!(decoder.finished());
+
+        // truncated array, map announcing more entries than there is input, indefinite length
+        assert!(CBORDecoder::new(&[0x82, 0x01]).skip().is_err());
+        assert!(CBORDecoder::new(&[0xB9, 0xFF, 0xFF, 0x01]).skip().is_err());
+        assert!(CBORDecoder::new(&[0x9F, 0x01, 0xFF]).skip().is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_cbor_encoder {
+    use super::*;
+
+    #[test]
+    fn test_cbor_encoder() {
+        let mut buf = [0u8; 16];
+        let mut encoder = CBOREncoder::new(&mut buf);
+
+        // CBOR sequence: {1: -1}, 24, [h'fefe']
+        encoder.map(1).unwrap();
+        encoder.u8(1).unwrap();
+        encoder.i8(-1).unwrap();
+        encoder.u8(24).unwrap();
+        encoder.array(1).unwrap();
+        encoder.bytes(&[0xFE, 0xFE]).unwrap();
+        let len = encoder.position();
+        assert_eq!(
+            buf[..len],
+            [0xA1, 0x01, 0x20, 0x18, 0x18, 0x81, 0x42, 0xFE, 0xFE]
+        );
+
+        let mut buf = [0u8; 2];
+        let mut encoder = CBOREncoder::new(&mut buf);
+        assert_eq!(
+            encoder.bytes(&[0xFE, 0xFE]),
+            Err(MessageBufferError::BufferAlreadyFull)
+        );
+    }
+
+    #[test]
+    fn test_cbor_u16() {
+        let mut buf = [0u8; 8];
+        let mut encoder = CBOREncoder::new(&mut buf);
+
+        // CBOR sequence: 23, 255, 263
+        encoder.u16(23).unwrap();
+        encoder.u16(255).unwrap();
+        encoder.u16(263).unwrap();
+        let len = encoder.position();
+        assert_eq!(buf[..len], [0x17, 0x18, 0xFF, 0x19, 0x01, 0x07]);
+
+        let mut decoder = CBORDecoder::new(&buf[..len]);
+        assert_eq!(decoder.u16().unwrap(), 23);
+        assert_eq!(decoder.u16().unwrap(), 255);
+        assert_eq!(decoder.u16().unwrap(), 263);
+        assert!(decoder.finished());
+        // -1
+        assert!(CBORDecoder::new(&[0x20]).u16().is_err());
+        // 2^16
+        assert!(CBORDecoder::new(&[0x1A, 0x00, 0x01, 0x00, 0x00])
+            .u16()
+            .is_err());
+    }
+
+    #[test]
+    fn test_cbor_encoder_multi_byte_uint() {
+        let mut buf = [0u8; 32];
+        let mut encoder = CBOREncoder::new(&mut buf);
+        // RFC 8949, Appendix A: 1000000, 1000000000000, and 1000 in the shortest form
+        encoder.u32(1000000).unwrap();
+        encoder.u64(1000000000000).unwrap();
+        encoder.u64(1000).unwrap();
+        let len = encoder.position();
+        assert_eq!(
+            buf[..len],
+            [
+                0x1A, 0x00, 0x0F, 0x42, 0x40, 0x1B, 0x00, 0x00, 0x00, 0xE8, 0xD4, 0xA5, 0x10, 0x00,
+                0x19, 0x03, 0xE8
+            ]
+        );
+
+        let mut decoder = CBORDecoder::new(&buf[..len]);
+        assert_eq!(decoder.u32().unwrap(), 1000000);
+        assert_eq!(decoder.u64().unwrap(), 1000000000000);
+        assert_eq!(decoder.u64().unwrap(), 1000);
+        assert!(decoder.finished());
+    }
+
+    #[test]
+    fn test_cbor_encoder_simple() {
+        let mut buf = [0u8; 8];
+        let mut encoder = CBOREncoder::new(&mut buf);
+
+        encoder.bool(true).unwrap();
+        encoder.bool(false).unwrap();
+        encoder.null().unwrap();
+        encoder.simple(23).unwrap();
+        encoder.simple(255).unwrap();
+        assert_eq!(encoder.simple(24), Err(MessageBufferError::InvalidValue));
+        assert_eq!(encoder.simple(31), Err(MessageBufferError::InvalidValue));
+        let len = encoder.position();
+        assert_eq!(buf[..len], [0xF5, 0xF4, 0xF6, 0xF7, 0xF8, 0xFF]);
+    }
+}
+
+#[cfg(test)]
+mod test_parse_bounds {
+    use super::*;
+    use std::vec::Vec;
+
+    /// Number of decoder steps `parse` takes
+    fn steps(parse: impl FnOnce()) -> usize {
+        DECODER_STEPS.with(|steps| steps.set(0));
+        parse();
+        DECODER_STEPS.with(|steps| steps.get())
+    }
+
+    /// An input of `len` bytes: `head`, followed by as many repetitions of `item` as fit
+    fn input(len: usize, head: &[u8], item: &[u8]) -> Vec<u8> {
+        let mut input = head.to_vec();
+        while input.len() + item.len() <= len {
+            input.extend_from_slice(item);
+        }
+        input.resize(len, 0x00);
+        input
+    }
+
+    /// Checks that parsing an input of maximum size takes work linear in its size
+    fn assert_linear(input: &[u8], parse: impl FnOnce(&[u8])) {
+        let steps = steps(|| parse(input));
+        assert!(
+            steps <= 2 * input.len(),
+            "{steps} steps for {} bytes",
+            input.len()
+        );
+    }
+
+    #[test]
+    fn test_message_parsers() {
+        // a map announcing 0xffff entries, in place of an item
+        let huge_map = [0xb9, 0xff, 0xff];
+        for head in [&[0x03, 0x02][..], &huge_map] {
+            let message = input(MAX_MESSAGE_SIZE_LEN, head, &[0x00]);
+            let message = EdhocMessageBuffer::new_from_slice(&message).unwrap();
+            assert_linear(message.as_slice(), |_| {
+                let _ = parse_message_1(&message);
+                let _ = parse_message_2(&message);
+                let _ = decode_plaintext_2(&message);
+                let _ = decode_plaintext_3(&message);
+            });
+        }
+
+        let ead = input(MAX_MESSAGE_SIZE_LEN, &[0x01], &[0xff]);
+        assert_linear(&ead, |ead| assert!(parse_ead(ead).is_ok()));
+        let ead = input(MAX_MESSAGE_SIZE_LEN + 1, &[0x01], &[0xff]);
+        assert_eq!(parse_ead(&ead).unwrap_err(), EDHOCError::MessageTooLong);
+    }
+
+    #[test]
+    fn test_credential_parsers() {
+        // CCS with a COSE_Key announcing 0xffff parameters, each a kid
+        let ccs = input(
+            MAX_MESSAGE_SIZE_LEN,
+            &[0xa1, 0x08, 0xa1, 0x01, 0xb9, 0xff, 0xff],
+            &[0x02, 0x40],
+        );
+        let ccs = EdhocMessageBuffer::new_from_slice(&ccs).unwrap();
+        assert_linear(
+            ccs.as_slice(),
+            |_| assert!(CredentialRPK::new(ccs).is_err()),
+        );
+
+        // x5chain announcing 0xffff certificates
+        let x5chain = input(MAX_CREDENTIAL_INPUT_LEN, &[0x99, 0xff, 0xff], &[0x40]);
+        assert_linear(&x5chain, |x5chain| {
+            assert!(CredentialX509Chain::parse(x5chain).is_err())
+        });
+        let x5chain = input(MAX_CREDENTIAL_INPUT_LEN + 1, &[0x41], &[0x00]);
+        assert_eq!(
+            CredentialX509Chain::parse(&x5chain).unwrap_err(),
+            EDHOCError::MessageTooLong
+        );
+
+        // COSE_Sign1 whose unprotected header announces 0xffff entries, each a kid
+        let cwt = input(
+            MAX_CREDENTIAL_INPUT_LEN,
+            &[0x84, 0x43, 0xa1, 0x01, 0x26, 0xb9, 0xff, 0xff],
+            &[0x04, 0x40],
+        );
+        assert_linear(
+            &cwt,
+            |cwt| assert!(CredentialSignedCWT::parse(cwt).is_err()),
+        );
+        let cwt = input(MAX_CREDENTIAL_INPUT_LEN + 1, &[0x84], &[0x40]);
+        assert_eq!(
+            CredentialSignedCWT::parse(&cwt).unwrap_err(),
+            EDHOCError::MessageTooLong
+        );
+    }
 }
@@ -0,0 +1,122 @@
+//! Sizes of the buffers that messages, EAD items and KDF inputs are processed in
+//!
+//! Only a few limits are primary; the others are derived from them. The primary limits come in
+//! three sets that are selected with the cargo features `limits-small` (e.g. for constrained
+//! sensors), `limits-default` (which also applies if none is enabled) and `limits-large` (e.g. for
+//! gateways that take credentials by value). As features are additive, the largest of the enabled
+//! sets applies, so that no crate in the dependency graph gets smaller buffers than it asked for.
+//!
+//! Lengths of messages and KDF contexts are encoded with at most a one-byte CBOR argument, which
+//! bounds all sets.
+use super::*;
+
+#[cfg(all(
+    feature = "limits-small",
+    not(any(feature = "limits-default", feature = "limits-large"))
+))]
+mod primary {
+    pub const MAX_MESSAGE_SIZE_LEN: usize = 128;
+    pub const MAX_EAD_SIZE_LEN: usize = 32;
+    pub const MAX_KDF_CONTEXT_LEN: usize = 176;
+}
+
+#[cfg(all(
+    not(feature = "limits-large"),
+    any(feature = "limits-default", not(feature = "limits-small"))
+))]
+mod primary {
+    // need 128 to handle EAD fields, and 192 for the EAD_1 voucher
+    pub const MAX_MESSAGE_SIZE_LEN: usize = 128 + 64;
+    pub const MAX_EAD_SIZE_LEN: usize = 64;
+    // fits the context of MAC_2 and MAC_3 (C_R, ID_CRED, TH, CRED and EAD) for a credential of
+    // about 110 bytes, such as those of the test vectors, together with an EAD item of
+    // MAX_EAD_SIZE_LEN
+    pub const MAX_KDF_CONTEXT_LEN: usize = 216;
+}
+
+// fits a handshake in which both credentials of the test vectors are sent by value
+#[cfg(feature = "limits-large")]
+mod primary {
+    pub const MAX_MESSAGE_SIZE_LEN: usize = 255;
+    pub const MAX_EAD_SIZE_LEN: usize = 128;
+    pub const MAX_KDF_CONTEXT_LEN: usize = 255;
+}
+
+/// Longest EDHOC message, and the capacity of an [EdhocMessageBuffer]
+pub const MAX_MESSAGE_SIZE_LEN: usize = primary::MAX_MESSAGE_SIZE_LEN;
+/// Longest EAD item that is expected to be processed
+pub const MAX_EAD_SIZE_LEN: usize = primary::MAX_EAD_SIZE_LEN;
+/// Longest context of the EDHOC-KDF, which for MAC_2 and MAC_3 includes the credential
+pub const MAX_KDF_CONTEXT_LEN: usize = primary::MAX_KDF_CONTEXT_LEN;
+
+/// Capacity of the scratch buffers, e.g. of the input of a transcript hash
+// fits the input of a transcript hash: a message (or plaintext and credential) after a hash as
+// bstr, with room for the CBOR headers
+pub const MAX_BUFFER_LEN: usize = MAX_MESSAGE_SIZE_LEN + 64;
+/// Longest encoded info of the EDHOC-KDF
+pub const MAX_INFO_LEN: usize = 2 + SHA256_DIGEST_LEN + // 32-byte digest as bstr
+				            1 + MAX_KDF_LABEL_LEN +     // label <24 bytes as tstr
+						    1 + MAX_KDF_CONTEXT_LEN +   // context <24 bytes as bstr
+						    1; // length as u8
+
+// Limits of the byte slices taken by the public API. A longer slice is rejected with
+// EDHOCError::InvalidParameter (EDHOCError::EadTooLongError for an EAD value) rather than
+// truncated, and a private key of another length with EDHOCError::InvalidKey; no entry point
+// panics on them.
+
+/// Longest `context` of the exporters, the key update and the EAD key derivations
+pub const MAX_EXPORTER_CONTEXT_LEN: usize = MAX_KDF_CONTEXT_LEN;
+/// Longest key derived by the exporters and the EAD key derivations; `length` is encoded in a
+/// one-byte CBOR argument
+pub const MAX_EXPORTER_OUTPUT_LEN: usize = if MAX_BUFFER_LEN < u8::MAX as usize {
+    MAX_BUFFER_LEN
+} else {
+    u8::MAX as usize
+};
+/// Longest encoded value of an [EADItem], such that the item with its one-byte label fits in
+/// [MAX_EAD_SIZE_LEN]
+pub const MAX_EAD_VALUE_LEN: usize = MAX_EAD_SIZE_LEN - 1;
+/// Most EAD items that are parsed from a single EAD field, see [EadItems]
+pub const MAX_EAD_ITEMS: usize = 4;
+
+/// Checks a byte slice parameter of the public API against its limit
+///
+/// # Examples
+///
+/// ```
+/// use lakers_shared::*;
+///
+/// let context = [0x00; MAX_EXPORTER_CONTEXT_LEN + 1];
+/// let longest = &context[..MAX_EXPORTER_CONTEXT_LEN];
+/// assert!(check_parameter_len(longest, MAX_EXPORTER_CONTEXT_LEN).is_ok());
+/// assert_eq!(
+///     check_parameter_len(&context, MAX_EXPORTER_CONTEXT_LEN),
+///     Err(EDHOCError::InvalidParameter)
+/// );
+/// ```
+pub fn check_parameter_len(parameter: &[u8], max_len: usize) -> Result<(), EDHOCError> {
+    if parameter.len() > max_len {
+        Err(EDHOCError::InvalidParameter)
+    } else {
+        Ok(())
+    }
+}
+
+/// A scratch buffer of [MAX_BUFFER_LEN] bytes
+pub type BytesMaxBuffer = [u8; MAX_BUFFER_LEN];
+/// A context of the EDHOC-KDF of up to [MAX_KDF_CONTEXT_LEN] bytes
+pub type BytesMaxContextBuffer = [u8; MAX_KDF_CONTEXT_LEN];
+/// An encoded info of the EDHOC-KDF of up to [MAX_INFO_LEN] bytes
+pub type BytesMaxInfoBuffer = [u8; MAX_INFO_LEN];
+
+const _: () = {
+    assert!(MAX_MESSAGE_SIZE_LEN <= u8::MAX as usize);
+    assert!(MAX_KDF_CONTEXT_LEN <= u8::MAX as usize);
+    assert!(MAX_EAD_SIZE_LEN < MAX_MESSAGE_SIZE_LEN);
+    assert!(MAX_EAD_SIZE_LEN < MAX_KDF_CONTEXT_LEN);
+    // TH_2 and H(message_1) are computed over a whole message next to a hash
+    assert!(2 + SHA256_DIGEST_LEN + MAX_MESSAGE_SIZE_LEN <= MAX_BUFFER_LEN);
+    // outputs of the KDF are handed out in BytesMaxBuffer
+    assert!(MAX_KDF_CONTEXT_LEN <= MAX_BUFFER_LEN);
+    assert!(MAX_HASH_LEN <= MAX_BUFFER_LEN);
+};
@@ -3,24 +3,30 @@
 /// can be extended, e.g, by adding new traits and methods.
 /// Note that this module is not restricted by no_std.
 use super::*;
-use core::fmt;
-use pyo3::{exceptions::PyValueError, types::PyBytes, PyErr};
+use pyo3::{create_exception, exceptions::PyValueError, types::PyBytes, PyErr};
 
-impl fmt::Display for EDHOCError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "EDHOCError::{:?}", self)
-    }
+create_exception!(
+    lakers,
+    EdhocError,
+    PyValueError,
+    "Error raised by EDHOC operations, with the stable `code` and the `name` of the error"
+);
+
+/// Creates an [EdhocError] for `error`, with `message` instead of the bare error name
+fn edhoc_error(error: EDHOCError, message: String) -> PyErr {
+    let err = EdhocError::new_err(message);
+    Python::with_gil(|py| {
+        let value = err.value(py);
+        // setting attributes on a fresh exception instance does not fail
+        let _ = value.setattr("code", error.code());
+        let _ = value.setattr("name", format!("{:?}", error));
+    });
+    err
 }
 
 impl From<EDHOCError> for PyErr {
     fn from(error: EDHOCError) -> Self {
-        PyValueError::new_err(error.to_string())
-    }
-}
-
-impl fmt::Display for MessageBufferError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "MessageBufferError::{:?}", self)
+        edhoc_error(error, error.to_string())
     }
 }
 
@@ -33,19 +39,15 @@ impl From<MessageBufferError> for PyErr {
 #[pymethods]
 impl EADItem {
     #[new]
-    fn new_py(label: u8, is_critical: bool, value: Vec<u8>) -> Self {
-        Self {
-            label,
-            is_critical,
-            value: Some(EdhocMessageBuffer::new_from_slice(value.as_slice()).unwrap()),
-        }
+    fn new_py(label: u16, is_critical: bool, value: Vec<u8>) -> PyResult<Self> {
+        Ok(Self::with_value(label, is_critical, value.as_slice())?)
     }
 
     fn value<'a>(&self, py: Python<'a>) -> Option<&'a PyBytes> {
         self.value.as_ref().map(|v| PyBytes::new(py, v.as_slice()))
     }
 
-    fn label(&self) -> u8 {
+    fn label(&self) -> u16 {
         self.label
     }
 
@@ -53,3 +55,82 @@ impl EADItem {
         self.is_critical
     }
 }
+
+#[pymethods]
+impl CredentialRPK {
+    /// Parses a CCS holding a P-256 public key
+    #[staticmethod]
+    fn from_ccs(value: Vec<u8>) -> PyResult<Self> {
+        let buffer = EdhocMessageBuffer::new_from_slice(value.as_slice()).map_err(|_| {
+            PyValueError::new_err(format!(
+                "CCS of {} bytes is longer than the maximum of {} bytes",
+                value.len(),
+                MAX_MESSAGE_SIZE_LEN
+            ))
+        })?;
+        CredentialRPK::new(buffer).map_err(|error| {
+            edhoc_error(
+                error,
+                format!(
+                    "malformed CCS, expected {{? 2: subject, 8: {{1: P-256 COSE_Key}}}}: {error}"
+                ),
+            )
+        })
+    }
+
+    /// Builds a CCS for `public_key`, which is either x or the concatenation of x and y
+    #[staticmethod]
+    #[pyo3(name = "build_ccs")]
+    fn build_ccs_py(subject: &str, kid: Vec<u8>, public_key: Vec<u8>) -> PyResult<Self> {
+        let [kid] = kid[..] else {
+            return Err(PyValueError::new_err(format!(
+                "kid must be a single byte, got {} bytes",
+                kid.len()
+            )));
+        };
+        let (x, y) = match public_key.len() {
+            P256_ELEM_LEN => (&public_key[..], None),
+            len if len == 2 * P256_ELEM_LEN => (
+                &public_key[..P256_ELEM_LEN],
+                Some(&public_key[P256_ELEM_LEN..]),
+            ),
+            len => {
+                return Err(PyValueError::new_err(format!(
+                    "public_key must be x or x || y of a P-256 key ({} or {} bytes), got {} bytes",
+                    P256_ELEM_LEN,
+                    2 * P256_ELEM_LEN,
+                    len
+                )))
+            }
+        };
+        let x: BytesP256ElemLen = x.try_into().unwrap();
+        let y: Option<BytesP256ElemLen> = y.map(|y| y.try_into().unwrap());
+        CredentialRPK::build_ccs(Some(subject), Some(kid), &x, y.as_ref()).map_err(|error| {
+            edhoc_error(
+                error,
+                format!(
+                    "CCS for subject of {} bytes does not fit: {error}",
+                    subject.len()
+                ),
+            )
+        })
+    }
+
+    /// The kid of the credential, or None if it has none
+    #[getter(kid)]
+    fn kid_py<'a>(&self, py: Python<'a>) -> Option<&'a PyBytes> {
+        self.has_kid.then(|| PyBytes::new(py, &[self.kid]))
+    }
+
+    /// The x coordinate of the P-256 public key
+    #[getter(public_key)]
+    fn public_key_py<'a>(&self, py: Python<'a>) -> &'a PyBytes {
+        PyBytes::new(py, &self.public_key)
+    }
+
+    /// The encoded CCS
+    #[getter(value)]
+    fn value_py<'a>(&self, py: Python<'a>) -> &'a PyBytes {
+        PyBytes::new(py, self.value.as_slice())
+    }
+}
@@ -0,0 +1,49 @@
+//! Development tasks of the lakers workspace, see `cargo xtask help`
+
+use std::env;
+use std::process::{exit, Command};
+
+/// Configurations of the buffer sizes (see the limits module of lakers-shared) that the tests
+/// are run in; the ByValue handshakes only fit some of them, and are skipped in the others
+const LIMITS: &[&str] = &["limits-small", "limits-default", "limits-large"];
+
+const HELP: &str = "\
+usage: cargo xtask <task>
+
+tasks:
+    test-limits    runs the tests of lakers and lakers-shared with each set of buffer sizes
+    help           prints this message
+";
+
+fn main() {
+    let task = env::args().nth(1);
+    let result = match task.as_deref() {
+        Some("test-limits") => test_limits(),
+        Some("help") | None => {
+            print!("{HELP}");
+            Ok(())
+        }
+        Some(other) => Err(format!("unknown task {other}\n\n{HELP}")),
+    };
+    if let Err(error) = result {
+        eprintln!("{error}");
+        exit(1);
+    }
+}
+
+fn test_limits() -> Result<(), String> {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    for limits in LIMITS {
+        println!("testing with {limits}");
+        let features = format!("{limits}, lakers-crypto/rustcrypto");
+        let status = Command::new(&cargo)
+            .args(["test", "-p", "lakers", "-p", "lakers-shared", "--features"])
+            .arg(&features)
+            .status()
+            .map_err(|error| format!("cannot run {cargo}: {error}"))?;
+        if !status.success() {
+            return Err(format!("tests failed with {limits}"));
+        }
+    }
+    Ok(())
+}